@@ -0,0 +1,54 @@
+//! Repodata loading, querying and graph building on the bundled fixture channel.
+//!
+//! Run with `cargo bench --features bench --bench repodata`.  See benches/version.rs for
+//! comparing against a saved baseline.  There is no solver yet, so solving isn't covered.  Add
+//! `--features bench,simd` to include simd-json parsing.
+
+use std::convert::TryFrom;
+use std::path::PathBuf;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use ronda::{populate_graph, read_repodata, read_repodata_from_slice, ComboMethod, VersionSpecOrConstraintTree};
+
+fn fixture() -> PathBuf {
+    let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    d.push("tests/data/current_repodata.json");
+    d
+}
+
+fn repodata_loading(c: &mut Criterion) {
+    let data = std::fs::read(fixture()).unwrap();
+    let mut group = c.benchmark_group("repodata_loading");
+    group.sample_size(20);
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("read_repodata", |b| b.iter(|| black_box(read_repodata(fixture()).unwrap())));
+    group.bench_function("read_repodata_from_slice", |b| b.iter(|| {
+        black_box(read_repodata_from_slice(&data).unwrap())
+    }));
+    #[cfg(feature = "simd")]
+    group.bench_function("read_repodata_simd", |b| b.iter_batched_ref(
+        || data.clone(),
+        |scratch| black_box(ronda::read_repodata_simd(scratch).unwrap()),
+        BatchSize::LargeInput,
+    ));
+    group.finish();
+}
+
+fn repodata_query(c: &mut Criterion) {
+    let repodata = read_repodata(fixture()).unwrap();
+    let spec = VersionSpecOrConstraintTree::try_from(">=1.1.1,<2").unwrap();
+    c.bench_function("repodata_query", |b| b.iter(|| black_box(repodata.query("openssl", &spec))));
+}
+
+fn graph_building(c: &mut Criterion) {
+    let repodata = read_repodata(fixture()).unwrap();
+    let mut group = c.benchmark_group("graph_building");
+    group.sample_size(20);
+    group.bench_function("populate_graph", |b| b.iter(|| {
+        black_box(populate_graph(vec![&repodata], ComboMethod::Strict).node_count())
+    }));
+    group.finish();
+}
+
+criterion_group!(benches, repodata_loading, repodata_query, graph_building);
+criterion_main!(benches);
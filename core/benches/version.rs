@@ -0,0 +1,73 @@
+//! Version parsing, comparison and spec matching.
+//!
+//! Run with `cargo bench --features bench --bench version`.  To compare a change against the
+//! current code, save a baseline first (`-- --save-baseline before`), then run again with
+//! `-- --baseline before`.
+
+use std::convert::TryFrom;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ronda::{CompiledSpec, Spec, Version, VersionSpecOrConstraintTree};
+
+const VERSIONS: &[&str] = &["1.2.3", "1.0.0.dev0", "2!1.1.0rc1", "1.1.0post1+local.1", "2019.8.28",
+    "1.16.5", "0.4.1a2", "3.7.4_2", "10.2.89-1", "1.0.2t"];
+
+const SPECS: &[&str] = &["1.2.3", ">=1.16", "1.16.*", ">=1.16,<2.0a0", "1.1|1.2|1.3", "!=1.2.3",
+    ">=1.0,(<1.5|>=1.7,<2)", "((1.5|((1.6|1.7), 1.8), 1.9 |2.0))|2.1"];
+
+fn version_parsing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("version_parsing");
+    group.throughput(Throughput::Elements(VERSIONS.len() as u64));
+    group.bench_function("conda_parser", |b| b.iter(|| {
+        for v in VERSIONS {
+            black_box(v.parse::<Version>().unwrap());
+        }
+    }));
+    group.finish();
+}
+
+fn version_comparison(c: &mut Criterion) {
+    let versions: Vec<Version> = VERSIONS.iter().map(|v| Version::from(*v)).collect();
+    let mut group = c.benchmark_group("version_comparison");
+    group.throughput(Throughput::Elements((versions.len() * versions.len()) as u64));
+    group.bench_function("partial_cmp", |b| b.iter(|| {
+        for a in versions.iter() {
+            for other in versions.iter() {
+                black_box(a.partial_cmp(other));
+            }
+        }
+    }));
+    group.bench_function("sort_key", |b| b.iter(|| {
+        for v in versions.iter() {
+            black_box(v.sort_key());
+        }
+    }));
+    group.finish();
+}
+
+fn spec_matching(c: &mut Criterion) {
+    let versions: Vec<Version> = VERSIONS.iter().map(|v| Version::from(*v)).collect();
+    let mut group = c.benchmark_group("spec_matching");
+    for spec_str in SPECS {
+        group.bench_with_input(BenchmarkId::new("parse", spec_str), spec_str, |b, s| b.iter(|| {
+            black_box(VersionSpecOrConstraintTree::try_from(*s).ok())
+        }));
+        if let Ok(spec) = VersionSpecOrConstraintTree::try_from(*spec_str) {
+            group.bench_with_input(BenchmarkId::new("match", spec_str), &spec, |b, spec| b.iter(|| {
+                for v in versions.iter() {
+                    black_box(spec.test_match_version(v));
+                }
+            }));
+            let compiled = CompiledSpec::compile(&spec);
+            group.bench_with_input(BenchmarkId::new("match_compiled", spec_str), &compiled, |b, spec| b.iter(|| {
+                for v in versions.iter() {
+                    black_box(spec.test_match_version(v));
+                }
+            }));
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, version_parsing, version_comparison, spec_matching);
+criterion_main!(benches);
@@ -0,0 +1,11 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use ronda::{conda_parser, Version};
+
+fuzz_target!(|data: &str| {
+    if let Ok(version) = Version::parse(data, &conda_parser) {
+        // everything derived from a parsed version must work too
+        let _ = version.sort_key();
+        let _ = version.to_string().parse::<Version>();
+    }
+});
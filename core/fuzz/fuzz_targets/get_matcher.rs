@@ -0,0 +1,14 @@
+#![no_main]
+use std::convert::TryFrom;
+
+use libfuzzer_sys::fuzz_target;
+use ronda::{Spec, VersionSpec};
+
+// A single spec, such as ">=1.2" or "1.7.*", goes through get_matcher
+fuzz_target!(|data: &str| {
+    if let Ok(spec) = VersionSpec::try_from(data) {
+        for version in ["0", "1.2.3", "1!2.0rc1", "1.7.1.post2+local", "2021.10.dev0"] {
+            let _ = spec.test_match(version);
+        }
+    }
+});
@@ -0,0 +1,17 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use ronda::{read_repodata_from_slice, Depend};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(repodata) = read_repodata_from_slice(data) {
+        // dependencies are untrusted strings too, and only parsed when they are used
+        for (_, record) in repodata.iter_records() {
+            for depend in &record.depends {
+                let depend = Depend::parse(depend);
+                let _ = depend.version_spec();
+                repodata.iter_records().for_each(|(_, r)| { depend.matches(r); });
+            }
+        }
+        let _ = repodata.dependency_closure(&["python"], &repodata.info.subdir);
+    }
+});
@@ -0,0 +1,18 @@
+#![no_main]
+use std::convert::TryFrom;
+
+use libfuzzer_sys::fuzz_target;
+use ronda::{simplify, treeify, untreeify, CompiledSpec, Spec, VersionSpecOrConstraintTree};
+
+fuzz_target!(|data: &str| {
+    let _ = treeify(data);
+    if let Ok(spec) = VersionSpecOrConstraintTree::try_from(data) {
+        let _ = untreeify(&spec);
+        let _ = untreeify(&simplify(&spec));
+        let compiled = CompiledSpec::compile(&spec);
+        for version in ["0", "1.2.3", "1!2.0rc1", "1.7.1.post2+local"] {
+            let _ = spec.test_match(version);
+            let _ = compiled.test_match(version);
+        }
+    }
+});
@@ -0,0 +1,204 @@
+//! Small command line front end to ronda, for debugging and shell scripting around channels.
+
+use std::convert::TryFrom;
+use std::env;
+use std::io::{self, Write};
+use std::process;
+
+use ronda::{read_repodata, CompOp, Combinator, Spec, Version,
+            VersionSpecOrConstraintTree};
+
+const USAGE: &str = "usage: ronda <command> [args]
+
+commands:
+    compare A B                  print <, == or > for versions A and B
+    match SPEC VERSION           print whether VERSION satisfies SPEC (exit status 1 if not)
+    query REPODATA 'NAME[SPEC]'  print filenames of records for NAME matching SPEC
+    treeify SPEC                 print SPEC as nested (combinator, members...) tuples";
+
+fn parse_version(input: &str) -> Result<Version, String> {
+    input.parse().map_err(|e| format!("invalid version '{}': {}", input, e))
+}
+
+fn parse_spec(input: &str) -> Result<VersionSpecOrConstraintTree, String> {
+    VersionSpecOrConstraintTree::try_from(input).map_err(|e| format!("invalid version spec '{}': {}", input, e))
+}
+
+// Same shape conda's treeify returns, e.g. (',', '>=1.0', ('|', '<2', '3.0.*'))
+fn format_tree(spec: &VersionSpecOrConstraintTree) -> String {
+    match spec {
+        VersionSpecOrConstraintTree::VersionSpec(s) => format!("'{}'", s.get_spec()),
+        VersionSpecOrConstraintTree::ConstraintTree(t) => {
+            let combinator = match t.combinator() {
+                Combinator::And => ",",
+                Combinator::Or => "|",
+            };
+            let mut items = vec![format!("'{}'", combinator)];
+            items.extend(t.parts().iter().map(format_tree));
+            format!("({})", items.join(", "))
+        }
+    }
+}
+
+// "numpy>=1.20" or "numpy 1.20.*" -> ("numpy", Some(spec)); a bare name matches every version
+fn split_name_and_spec(input: &str) -> Result<(&str, Option<VersionSpecOrConstraintTree>), String> {
+    let input = input.trim();
+    let end = input.find(|c: char| c.is_whitespace() || "<>=!~*".contains(c)).unwrap_or(input.len());
+    let (name, spec) = input.split_at(end);
+    let spec = spec.trim();
+    if name.is_empty() {
+        return Err(format!("no package name in '{}'", input));
+    }
+    match spec.is_empty() {
+        true => Ok((name, None)),
+        false => Ok((name, Some(parse_spec(spec)?))),
+    }
+}
+
+// Runs the command in `args`, printing its output to `out`.  Ok(false) means the command worked
+//    but found nothing (no match, no records), which main turns into exit status 1.
+fn run(args: &[String], out: &mut impl Write) -> Result<bool, String> {
+    let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+    let mut print = |line: &str| writeln!(out, "{}", line).map_err(|e| e.to_string());
+    match args.as_slice() {
+        ["compare", a, b] => {
+            let op = parse_version(a)?.compare_version(&parse_version(b)?);
+            print(match op {
+                CompOp::Eq => "==",
+                _ => op.sign(),
+            })?;
+            Ok(true)
+        },
+        ["match", spec, version] => {
+            let matched = parse_spec(spec)?.test_match_version(&parse_version(version)?);
+            print(&matched.to_string())?;
+            Ok(matched)
+        },
+        ["query", path, query] => {
+            let (name, spec) = split_name_and_spec(query)?;
+            let repodata = read_repodata(path).map_err(|e| format!("{}: {}", path, e))?;
+            let found: Vec<&str> = match spec {
+                Some(spec) => repodata.query(name, &spec).into_iter().map(|(f, _)| f).collect(),
+                None => {
+                    let mut all: Vec<&str> = repodata.iter_records()
+                        .filter(|(_, r)| r.name == name)
+                        .map(|(f, _)| f.as_str())
+                        .collect();
+                    all.sort();
+                    all
+                },
+            };
+            for filename in found.iter() {
+                print(filename)?;
+            }
+            Ok(!found.is_empty())
+        },
+        ["treeify", spec] => {
+            let tree = VersionSpecOrConstraintTree::try_from(*spec).map_err(|e| e.to_string())?;
+            print(&format_tree(&tree))?;
+            Ok(true)
+        },
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match run(&args, &mut io::stdout().lock()) {
+        Ok(true) => {},
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        },
+    }
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::path::PathBuf;
+
+    // The exit status main would give, and what the command printed
+    fn ronda(args: &[&str]) -> (i32, String) {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        let mut out = vec![];
+        let status = match run(&args, &mut out) {
+            Ok(true) => 0,
+            Ok(false) => 1,
+            Err(e) => return (2, e),
+        };
+        (status, String::from_utf8(out).unwrap())
+    }
+
+    fn repodata() -> String {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("tests/data/current_repodata.json");
+        d.to_string_lossy().into_owned()
+    }
+
+    #[rstest(a, b, expected,
+        case::less("1.2", "1.10", "<\n"),
+        case::equal("1.0", "1.0.0", "==\n"),
+        case::greater("1.0.post1", "1.0", ">\n")
+    )]
+    fn test_compare(a: &str, b: &str, expected: &str) {
+        assert_eq!(ronda(&["compare", a, b]), (0, expected.to_string()));
+    }
+
+    #[test]
+    fn test_match() {
+        assert_eq!(ronda(&["match", ">=1.0,<2", "1.5"]), (0, "true\n".to_string()));
+        assert_eq!(ronda(&["match", ">=1.0,<2", "2.5"]), (1, "false\n".to_string()));
+    }
+
+    #[test]
+    fn test_treeify() {
+        assert_eq!(ronda(&["treeify", ">=1.0,(<2|3.0.*)"]), (0, "(',', '>=1.0', ('|', '<2', '3.0.*'))\n".to_string()));
+    }
+
+    #[test]
+    fn test_query() {
+        let (status, out) = ronda(&["query", &repodata(), "openssl >=1.1.1d"]);
+        assert_eq!(status, 0);
+        let mut found: Vec<&str> = out.lines().collect();
+        found.sort();
+        assert_eq!(found, vec!["openssl-1.1.1d-h0c8e037_0.tar.bz2", "openssl-1.1.1d-he774522_0.tar.bz2"]);
+        // .tar.bz2 and .conda records
+        assert_eq!(ronda(&["query", &repodata(), "openssl"]).1.lines().count(), 8);
+        assert_eq!(ronda(&["query", &repodata(), "openssl>=3"]), (1, String::new()));
+    }
+
+    #[rstest(args,
+        case::no_command(&[]),
+        case::unknown_command(&["solve", "python"]),
+        case::missing_argument(&["compare", "1.0"]),
+        case::extra_argument(&["treeify", "1.0", "2.0"])
+    )]
+    fn test_usage(args: &[&str]) {
+        assert_eq!(ronda(args), (2, USAGE.to_string()));
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        let (status, err) = ronda(&["compare", "1.0", "1!2!3"]);
+        assert_eq!(status, 2);
+        assert!(err.starts_with("invalid version '1!2!3'"), "{}", err);
+        assert_eq!(ronda(&["query", &repodata(), ">=1.0"]), (2, "no package name in '>=1.0'".to_string()));
+        assert_eq!(ronda(&["query", "/nonexistent/repodata.json", "openssl"]).0, 2);
+    }
+
+    #[rstest(input, name, spec,
+        case::operator("numpy>=1.20", "numpy", Some(">=1.20")),
+        case::space("numpy 1.20.*", "numpy", Some("1.20.*")),
+        case::bare("numpy", "numpy", None),
+        case::padded(" numpy ", "numpy", None)
+    )]
+    fn test_split_name_and_spec(input: &str, name: &str, spec: Option<&str>) {
+        let (found_name, found_spec) = split_name_and_spec(input).unwrap();
+        assert_eq!(found_name, name);
+        assert_eq!(found_spec.map(|s| s.get_spec()), spec.map(|s| s.to_string()));
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+
+use serde::Deserialize;
+
+use crate::version::errors::VersionParsingError;
+use crate::{conda_parser, pep440_parser, Record, Version};
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LockManager {
+    Conda,
+    Pip,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct LockedHash {
+    #[serde(default)]
+    pub md5: Option<String>,
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
+
+/// One package pinned for one platform.
+#[derive(Debug, Clone)]
+pub struct LockedPackage {
+    pub name: String,
+    /// Parsed with conda's rules for conda packages and PEP 440 for pip packages.
+    pub version: Version,
+    pub manager: LockManager,
+    pub platform: String,
+    pub url: String,
+    pub hash: LockedHash,
+    /// Dependency name to version spec; the spec is empty when any version will do.
+    pub dependencies: BTreeMap<String, String>,
+    pub categories: Vec<String>,
+    pub optional: bool,
+}
+
+impl LockedPackage {
+    /// The package filename, i.e. the last part of its URL.  This is the key the package has in
+    ///    its channel's repodata.
+    pub fn filename(&self) -> &str {
+        self.url.rsplit('/').next().unwrap_or(&self.url)
+    }
+
+    /// Whether `record` is the artifact this lock pins: same name and version, and every hash
+    ///    present in the lock agrees with the record.
+    pub fn matches_record(&self, record: &Record) -> bool {
+        record.name == self.name
+            && self.version == record.version
+            && self.hash.md5.as_ref().is_none_or(|md5| *md5 == record.md5)
+            && self.hash.sha256.as_ref().is_none_or(|sha256| *sha256 == record.sha256)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CondaLock {
+    /// The lock file format version.
+    pub version: u32,
+    pub platforms: Vec<String>,
+    pub channels: Vec<String>,
+    pub packages: Vec<LockedPackage>,
+}
+
+impl CondaLock {
+    /// Packages locked for `platform`, in lock file order.
+    pub fn packages_for_platform(&self, platform: &str) -> Vec<&LockedPackage> {
+        self.packages.iter().filter(|p| p.platform == platform).collect()
+    }
+
+    /// All locked packages, grouped by platform.
+    pub fn by_platform(&self) -> BTreeMap<&str, Vec<&LockedPackage>> {
+        let mut grouped: BTreeMap<&str, Vec<&LockedPackage>> = BTreeMap::new();
+        for package in self.packages.iter() {
+            grouped.entry(package.platform.as_str()).or_default().push(package);
+        }
+        grouped
+    }
+}
+
+#[derive(Debug)]
+pub enum CondaLockError {
+    Yaml(serde_yaml::Error),
+    UnsupportedVersion(u32),
+    InvalidVersion { name: String, version: String, error: VersionParsingError },
+}
+
+impl fmt::Display for CondaLockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CondaLockError::Yaml(e) => write!(f, "unable to parse lock file: {}", e),
+            CondaLockError::UnsupportedVersion(v) => write!(f, "unsupported lock file version {}", v),
+            CondaLockError::InvalidVersion { name, version, error } =>
+                write!(f, "invalid version '{}' for {}: {}", version, name, error),
+        }
+    }
+}
+
+impl Error for CondaLockError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CondaLockError::Yaml(e) => Some(e),
+            CondaLockError::InvalidVersion { error, .. } => Some(error),
+            CondaLockError::UnsupportedVersion(_) => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RawChannel {
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct RawMetadata {
+    #[serde(default)]
+    platforms: Vec<String>,
+    #[serde(default)]
+    channels: Vec<RawChannel>,
+}
+
+#[derive(Deserialize)]
+struct RawPackage {
+    name: String,
+    version: String,
+    manager: LockManager,
+    platform: String,
+    url: String,
+    #[serde(default)]
+    hash: LockedHash,
+    #[serde(default)]
+    dependencies: BTreeMap<String, String>,
+    // v1 has a single category plus an optional flag, v2 a list of categories
+    #[serde(default)]
+    category: Option<String>,
+    #[serde(default)]
+    categories: Option<Vec<String>>,
+    #[serde(default)]
+    optional: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct RawLock {
+    version: u32,
+    metadata: RawMetadata,
+    #[serde(default)]
+    package: Vec<RawPackage>,
+}
+
+impl RawPackage {
+    fn into_locked(self) -> Result<LockedPackage, CondaLockError> {
+        let parser = match self.manager {
+            LockManager::Conda => conda_parser,
+            LockManager::Pip => pep440_parser,
+        };
+        let version = Version::parse(&self.version, &parser).map_err(|error| CondaLockError::InvalidVersion {
+            name: self.name.clone(), version: self.version.clone(), error })?;
+        let categories = match (self.categories, self.category) {
+            (Some(categories), _) => categories,
+            (None, Some(category)) => vec![category],
+            (None, None) => vec!["main".to_string()],
+        };
+        let optional = self.optional.unwrap_or_else(|| !categories.iter().any(|c| c == "main"));
+        Ok(LockedPackage {
+            name: self.name,
+            version,
+            manager: self.manager,
+            platform: self.platform,
+            url: self.url,
+            hash: self.hash,
+            dependencies: self.dependencies,
+            categories,
+            optional,
+        })
+    }
+}
+
+/// Parse a conda-lock (v1 or v2) YAML lock file.
+pub fn read_conda_lock(data: &str) -> Result<CondaLock, CondaLockError> {
+    let raw: RawLock = serde_yaml::from_str(data).map_err(CondaLockError::Yaml)?;
+    if raw.version != 1 && raw.version != 2 {
+        return Err(CondaLockError::UnsupportedVersion(raw.version));
+    }
+    let packages = raw.package.into_iter()
+        .map(RawPackage::into_locked)
+        .collect::<Result<Vec<LockedPackage>, CondaLockError>>()?;
+    Ok(CondaLock {
+        version: raw.version,
+        platforms: raw.metadata.platforms,
+        channels: raw.metadata.channels.into_iter().map(|c| c.url).collect(),
+        packages,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use super::*;
+    use crate::read_repodata;
+
+    fn data_file(name: &str) -> PathBuf {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("tests/data");
+        d.push(name);
+        d
+    }
+
+    fn read(name: &str) -> CondaLock {
+        read_conda_lock(&std::fs::read_to_string(data_file(name)).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_read_conda_lock_v1() {
+        let lock = read("conda-lock.yml");
+        assert_eq!(lock.version, 1);
+        assert_eq!(lock.platforms, vec!["linux-64", "win-64"]);
+        assert_eq!(lock.channels, vec!["defaults"]);
+
+        let by_platform = lock.by_platform();
+        assert_eq!(by_platform["win-64"].len(), 2);
+        assert_eq!(by_platform["linux-64"].len(), 2);
+
+        let openssl = lock.packages_for_platform("win-64")[1];
+        assert_eq!(openssl.version, "1.1.1d".into());
+        assert_eq!(openssl.filename(), "openssl-1.1.1d-he774522_0.tar.bz2");
+        assert_eq!(openssl.dependencies["vc"], ">=14.1,<15.0a0");
+        assert_eq!(openssl.dependencies["ca-certificates"], "");
+
+        let requests = lock.packages_for_platform("linux-64")[1];
+        assert_eq!(requests.manager, LockManager::Pip);
+        assert_eq!(requests.categories, vec!["dev"]);
+        assert!(requests.optional);
+        assert!(requests.hash.md5.is_none());
+    }
+
+    #[test]
+    fn test_read_conda_lock_v2() {
+        let lock = read("conda-lock-v2.yml");
+        assert_eq!(lock.version, 2);
+        let package = &lock.packages[0];
+        assert_eq!(package.categories, vec!["main", "dev"]);
+        assert!(!package.optional);
+    }
+
+    #[test]
+    fn test_validate_against_repodata() {
+        let lock = read("conda-lock.yml");
+        let repodata = read_repodata(data_file("current_repodata.json")).unwrap();
+        for package in lock.packages_for_platform("win-64") {
+            let record = repodata.record(package.filename()).unwrap();
+            assert!(package.matches_record(record));
+        }
+        // same filename in the linux-64 lock, but different hashes
+        let linux_ca = lock.packages_for_platform("linux-64")[0];
+        assert!(!linux_ca.matches_record(repodata.record(linux_ca.filename()).unwrap()));
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let err = read_conda_lock("version: 7\nmetadata: {}\npackage: []\n").unwrap_err();
+        assert!(matches!(err, CondaLockError::UnsupportedVersion(7)));
+    }
+}
@@ -0,0 +1 @@
+pub mod conda_lock;
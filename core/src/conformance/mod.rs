@@ -0,0 +1,4 @@
+pub mod vectors;
+mod verify;
+
+pub use self::verify::{verify, Backend, Failure, Report};
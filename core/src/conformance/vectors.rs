@@ -0,0 +1,149 @@
+//! conda's own version and version spec test vectors, from `tests/models/test_version.py` in
+//! conda.  They describe what conda does, quirks included, so the version orderings only hold for
+//! versions in `VersionMode::CondaCompat`.
+
+/// Versions in ascending order, as in conda's `test_version_order`.  Versions in the same group
+///    are equal.
+pub const VERSION_ORDER: &[&[&str]] = &[
+    &["0.4", "0.4.0"],
+    &["0.4.1a.vc11"],
+    &["0.4.1.rc"],
+    &["0.4.1.vc11"],
+    &["0.4.1"],
+    &["0.5*"],
+    &["0.5a1"],
+    &["0.5b3"],
+    &["0.5C1"],
+    &["0.5z"],
+    &["0.5za"],
+    &["0.5"],
+    &["0.5_5", "0.5-5"],
+    &["0.9.6"],
+    &["0.960923"],
+    &["1.0"],
+    &["1.0.4a3"],
+    &["1.0.4b1"],
+    &["1.0.4"],
+    &["1.1dev1"],
+    &["1.1_"],
+    &["1.1a1"],
+    &["1.1.dev1"],
+    &["1.1.a1"],
+    &["1.1"],
+    &["1.1.post1"],
+    &["1.1.1dev1"],
+    &["1.1.1rc1"],
+    &["1.1.1"],
+    &["1.1.1post1"],
+    &["1.1post1"],
+    &["2g6"],
+    &["2.0b1pr0"],
+    &["2.2be.ta29"],
+    &["2.2be5ta29"],
+    &["2.2beta29"],
+    &["2.2.0.1"],
+    &["3.1.1.6"],
+    &["3.2.p.r0"],
+    &["3.2.pr0"],
+    &["3.2.pr.1"],
+    &["5.5.kw"],
+    &["11g"],
+    &["14.3.1"],
+    &["14.3.1.post26.g9d75ca2"],
+    &["1996.07.12"],
+    &["1!0.4.1"],
+    &["1!3.1.1.6"],
+    &["2!0.4.1"],
+];
+
+/// OpenSSL's letter releases, which conda writes as post releases, in ascending order.
+pub const OPENSSL_VERSION_ORDER: &[&str] = &["1.0.1", "1.0.1post.a", "1.0.1post.b", "1.0.1post.z", "1.0.1post.za", "1.0.2"];
+
+/// Pairs of versions that are equal.
+pub const EQUAL_VERSIONS: &[(&str, &str)] = &[
+    ("0.4.1.rc", "  0.4.1.RC  "),
+    ("  0.4.rc  ", "0.4.RC"),
+    ("0.4", "0.4.0"),
+    ("0.4.a1", "0.4.0a1"),
+];
+
+/// Pairs of versions that are not equal.
+pub const UNEQUAL_VERSIONS: &[(&str, &str)] = &[
+    ("0.4", "0.4.1"),
+    ("0.4.a1", "0.4.1a1"),
+];
+
+/// Strings that aren't versions.
+pub const INVALID_VERSIONS: &[&str] = &["", "  ", "3.5&1", "5.5++", "5.5..mw", "!", "a!1.0", "a!b!1.0"];
+
+/// `(spec, version, matches)`, from conda's `test_match`, `test_local_identifier`,
+///    `test_not_eq_star` and `test_compatible_release_versions`.
+pub const SPEC_MATCHES: &[(&str, &str, bool)] = &[
+    ("1.7.*", "1.7.1", true),
+    ("1.7.1", "1.7.1", true),
+    ("1.7.0", "1.7.1", false),
+    ("1.7", "1.7.1", false),
+    ("1.5.*", "1.7.1", false),
+    (">=1.5", "1.7.1", true),
+    ("!=1.5", "1.7.1", true),
+    ("!=1.7.1", "1.7.1", false),
+    ("==1.7.1", "1.7.1", true),
+    ("==1.7", "1.7.1", false),
+    ("==1.7.2", "1.7.1", false),
+    ("==1.7.1.0", "1.7.1", true),
+    ("1.7.*|1.8.*", "1.7.1", true),
+    (">1.7,<1.8", "1.7.1", true),
+    (">1.7.1,<1.8", "1.7.1", false),
+    ("*", "1.7.1", true),
+    ("1.5.*|>1.7,<1.8", "1.7.1", true),
+    ("1.5.*|>1.7,<1.7.1", "1.7.1", false),
+    ("1.7.0", "1.7.0", true),
+    ("1.7.0.post123", "1.7.0.post123", true),
+    ("1.7.0.post123.gabcdef9", "1.7.0.post123.gabcdef9", true),
+    ("1.7.0.post123+gabcdef9", "1.7.0.post123+gabcdef9", true),
+    ("=3.3", "3.3.1", true),
+    ("=3.3", "3.3", true),
+    ("=3.3", "3.4", false),
+    ("3.3.*", "3.3.1", true),
+    ("3.3.*", "3.3", true),
+    ("3.3.*", "3.4", false),
+    ("=3.3.*", "3.3.1", true),
+    ("=3.3.*", "3.3", true),
+    ("=3.3.*", "3.4", false),
+    ("!=3.3.*", "3.3.1", false),
+    ("!=3.3.*", "3.4", true),
+    ("!=3.3.*", "3.4.1", true),
+    ("!=3.3", "3.3.1", true),
+    ("!=3.3", "3.3.0.0", false),
+    ("!=3.3.*", "3.3.0.0", false),
+    ("~=1.10", "1.11.0", true),
+    ("~=1.10.0", "1.11.0", false),
+    ("~=3.3.2", "3.4.0", false),
+    ("~=3.3.2", "3.3.1", false),
+    ("~=3.3.2", "3.3.2", true),
+    ("~=3.3.2", "3.3.3", true),
+    ("~=3.3.2|==2.2", "2.2.0", true),
+    ("~=3.3.2|==2.2", "3.3.3", true),
+    ("~=3.3.2|==4.4", "2.2.0", false),
+    ("~=3.3.2|==4.4", "3.4.0", false),
+];
+
+/// `(spec, version, matches)` for specs that conda turns into regular expressions: the ones
+///    written as `^...$`, and the ones with a `*` before their end.
+pub const REGEX_SPEC_MATCHES: &[(&str, &str, bool)] = &[
+    ("^1.7.1$", "1.7.1", true),
+    (r"^1\.7\.1$", "1.7.1", true),
+    (r"^1\.7\.[0-9]+$", "1.7.1", true),
+    (r"^1\.8.*$", "1.7.1", false),
+    (r"^1\.[5-8]\.1$", "1.7.1", true),
+    (r"^[^1].*$", "1.7.1", false),
+    (r"^[0-9+]+\.[0-9+]+\.[0-9]+$", "1.7.1", true),
+    ("^$", "1.7.1", false),
+    ("^.*$", "1.7.1", true),
+    ("1.7.*|^0.*$", "1.7.1", true),
+    ("1.6.*|^0.*$", "1.7.1", false),
+    ("1.6.*|^0.*$|1.7.1", "1.7.1", true),
+    ("^0.*$|1.7.1", "1.7.1", true),
+    (r"1.6.*|^.*\.7\.1$|0.7.1", "1.7.1", true),
+    ("1.*.1", "1.7.1", true),
+];
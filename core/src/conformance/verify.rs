@@ -0,0 +1,212 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+
+use super::vectors::{
+    EQUAL_VERSIONS, INVALID_VERSIONS, OPENSSL_VERSION_ORDER, REGEX_SPEC_MATCHES, SPEC_MATCHES, UNEQUAL_VERSIONS,
+    VERSION_ORDER,
+};
+use crate::version::errors::VersionParsingError;
+use crate::{Spec, Version, VersionSpecOrConstraintTree};
+
+/// A version ordering and spec matching implementation to check against conda's test vectors.
+///
+/// Any function that parses a `Version` is one, matching specs with libronda's own spec matcher.
+///    Other implementations (a different parser, a port to another backend) can implement it
+///    directly.
+pub trait Backend {
+    /// Check that `version` is a valid version.
+    fn validate(&self, version: &str) -> Result<(), String>;
+
+    /// Compare two versions.
+    fn compare(&self, a: &str, b: &str) -> Result<Ordering, String>;
+
+    /// Whether `version` matches `spec`.
+    fn matches(&self, spec: &str, version: &str) -> Result<bool, String>;
+}
+
+impl<F> Backend for F
+where
+    F: Fn(&str) -> Result<Version, VersionParsingError>,
+{
+    fn validate(&self, version: &str) -> Result<(), String> {
+        self(version).map(|_| ()).map_err(|e| e.to_string())
+    }
+
+    fn compare(&self, a: &str, b: &str) -> Result<Ordering, String> {
+        let a = self(a).map_err(|e| e.to_string())?;
+        let b = self(b).map_err(|e| e.to_string())?;
+        a.partial_cmp(&b).ok_or_else(|| format!("{} and {} can't be compared", a, b))
+    }
+
+    fn matches(&self, spec: &str, version: &str) -> Result<bool, String> {
+        let spec = VersionSpecOrConstraintTree::try_from(spec).map_err(|e| e.to_string())?;
+        Ok(spec.test_match_version(&self(version).map_err(|e| e.to_string())?))
+    }
+}
+
+/// A test vector the backend got wrong.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Failure {
+    /// What was checked, e.g. `0.4.1 < 0.5*` or `1.7.1 matches 1.7.*`
+    pub case: String,
+    /// What the backend said instead, or the error it returned
+    pub found: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "expected {}, found {}", self.case, self.found)
+    }
+}
+
+/// The outcome of `verify`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Report {
+    /// How many test vectors were checked
+    pub checked: usize,
+    pub failures: Vec<Failure>,
+}
+
+impl Report {
+    /// Whether the backend got every test vector right.
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    // `found` is the backend's answer written like `expected`, or its error
+    fn check(&mut self, expected: String, found: Result<String, String>) {
+        self.checked += 1;
+        match found {
+            Ok(found) if found == expected => {},
+            Ok(found) | Err(found) => self.failures.push(Failure { case: expected, found }),
+        }
+    }
+
+    fn check_order(&mut self, backend: &(impl Backend + ?Sized), a: &str, b: &str, expected: Ordering) {
+        let sign = |ord: Ordering| match ord {
+            Ordering::Less => "<",
+            Ordering::Equal => "==",
+            Ordering::Greater => ">",
+        };
+        let found = backend.compare(a, b).map(|ord| format!("{:?} {} {:?}", a, sign(ord), b));
+        self.check(format!("{:?} {} {:?}", a, sign(expected), b), found);
+    }
+
+    fn check_match(&mut self, backend: &(impl Backend + ?Sized), spec: &str, version: &str, expected: bool) {
+        let describe = |matches: bool| {
+            format!("{:?} {} {:?}", version, if matches { "matches" } else { "doesn't match" }, spec)
+        };
+        self.check(describe(expected), backend.matches(spec, version).map(describe));
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} of {} test vectors failed", self.failures.len(), self.checked)?;
+        for failure in &self.failures {
+            write!(f, "\n  {}", failure)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check `backend` against conda's version ordering and spec matching test vectors (see
+/// `vectors`), and report every one it gets wrong.
+///
+/// Versions are only ordered like conda orders them in `VersionMode::CondaCompat`, so that is the
+/// mode to check libronda's parser in.  Specs written as regular expressions need the `regex`
+/// feature.
+///
+/// ```
+/// use ronda::{conformance, Version, VersionMode};
+///
+/// let report = conformance::verify(&|v: &str| Version::with_mode(v, VersionMode::CondaCompat));
+/// if cfg!(feature = "regex") {
+///     assert!(report.passed(), "{}", report);
+/// }
+/// ```
+pub fn verify<B: Backend + ?Sized>(backend: &B) -> Report {
+    let mut report = Report::default();
+    for (index, group) in VERSION_ORDER.iter().enumerate() {
+        for other in &group[1..] {
+            report.check_order(backend, group[0], other, Ordering::Equal);
+        }
+        if let Some(next) = VERSION_ORDER.get(index + 1) {
+            report.check_order(backend, group[group.len() - 1], next[0], Ordering::Less);
+        }
+    }
+    for pair in OPENSSL_VERSION_ORDER.windows(2) {
+        report.check_order(backend, pair[0], pair[1], Ordering::Less);
+    }
+    for (a, b) in EQUAL_VERSIONS {
+        report.check_order(backend, a, b, Ordering::Equal);
+    }
+    for (a, b) in UNEQUAL_VERSIONS {
+        report.checked += 1;
+        if let Ok(Ordering::Equal) | Err(_) = backend.compare(a, b) {
+            report.failures.push(Failure { case: format!("{:?} != {:?}", a, b), found: "equal or invalid".to_string() });
+        }
+    }
+    for version in INVALID_VERSIONS {
+        report.checked += 1;
+        if backend.validate(version).is_ok() {
+            report.failures.push(Failure { case: format!("{:?} is invalid", version), found: "valid".to_string() });
+        }
+    }
+    for (spec, version, expected) in SPEC_MATCHES.iter().chain(REGEX_SPEC_MATCHES) {
+        report.check_match(backend, spec, version, *expected);
+    }
+    report
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VersionMode;
+
+    fn conda_compat(version: &str) -> Result<Version, VersionParsingError> {
+        Version::with_mode(version, VersionMode::CondaCompat)
+    }
+
+    #[test]
+    fn test_conda_compat_passes() {
+        let report = verify(&conda_compat);
+        // without the regex feature, regex specs are errors
+        let regex_spec = |f: &Failure| REGEX_SPEC_MATCHES.iter().any(|(spec, _, _)| f.case.contains(&format!("{:?}", spec)));
+        assert!(report.failures.iter().all(|f| !cfg!(feature = "regex") && regex_spec(f)), "{}", report);
+    }
+
+    #[test]
+    fn test_clean_mode_differs() {
+        let report = verify(&|v: &str| v.parse::<Version>());
+        assert!(report.failures.iter().any(|f| f.case == r#""1.1a1" < "1.1.dev1""#), "{}", report);
+    }
+
+    struct Strings;
+
+    // Compares versions as plain strings, which gets most vectors wrong
+    impl Backend for Strings {
+        fn validate(&self, _: &str) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn compare(&self, a: &str, b: &str) -> Result<Ordering, String> {
+            Ok(a.cmp(b))
+        }
+
+        fn matches(&self, spec: &str, version: &str) -> Result<bool, String> {
+            Ok(spec == version)
+        }
+    }
+
+    #[test]
+    fn test_report() {
+        let report = verify(&Strings);
+        assert_eq!(report.checked, verify(&conda_compat).checked);
+        assert!(!report.passed());
+        assert!(report.failures.contains(&Failure { case: r#""" is invalid"#.to_string(), found: "valid".to_string() }));
+        assert!(report.to_string().starts_with(&format!("{} of {} test vectors failed", report.failures.len(), report.checked)));
+    }
+}
@@ -0,0 +1,150 @@
+//! The error type shared by the whole crate.
+//!
+//! Each module keeps an error type that says precisely what went wrong there
+//! (`VersionParsingError`, `PrefixReadError`, `CondaLockError`, ...).  `RondaError` wraps all of
+//! them, so code calling into several parts of libronda can use `?` throughout and still get at
+//! the original error through `source()`.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::cancel::cancel::Cancelled;
+use crate::conda_lock::conda_lock::CondaLockError;
+use crate::matchspec::matchspec::MatchSpecError;
+#[cfg(feature = "package")]
+use crate::package::package::ExtractError;
+use crate::prefix::prefix::PrefixReadError;
+use crate::repodata::filename::PackageFilenameError;
+use crate::repodata::repodata::RepodataReadError;
+use crate::version::errors::VersionParsingError;
+
+#[derive(Debug)]
+pub enum RondaError {
+    /// A version or spec that couldn't be parsed
+    Version(VersionParsingError),
+    MatchSpec(MatchSpecError),
+    Repodata(RepodataReadError),
+    Filename(PackageFilenameError),
+    /// Malformed JSON, e.g. in PyPI metadata
+    Json(serde_json::Error),
+    Prefix(PrefixReadError),
+    CondaLock(CondaLockError),
+    Cancelled,
+    #[cfg(feature = "package")]
+    Extract(ExtractError),
+}
+
+impl fmt::Display for RondaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RondaError::Version(e) => write!(f, "{}", e),
+            RondaError::MatchSpec(e) => write!(f, "{}", e),
+            RondaError::Repodata(e) => write!(f, "{}", e),
+            RondaError::Filename(e) => write!(f, "invalid package filename: {}", e),
+            RondaError::Json(e) => write!(f, "invalid JSON: {}", e),
+            RondaError::Prefix(e) => write!(f, "{}", e),
+            RondaError::CondaLock(e) => write!(f, "{}", e),
+            RondaError::Cancelled => write!(f, "{}", Cancelled),
+            #[cfg(feature = "package")]
+            RondaError::Extract(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for RondaError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RondaError::Version(e) => Some(e),
+            RondaError::MatchSpec(e) => Some(e),
+            RondaError::Repodata(e) => Some(e),
+            RondaError::Filename(e) => Some(e),
+            RondaError::Json(e) => Some(e),
+            RondaError::Prefix(e) => Some(e),
+            RondaError::CondaLock(e) => Some(e),
+            RondaError::Cancelled => None,
+            #[cfg(feature = "package")]
+            RondaError::Extract(e) => Some(e),
+        }
+    }
+}
+
+impl From<VersionParsingError> for RondaError {
+    fn from(e: VersionParsingError) -> Self {
+        RondaError::Version(e)
+    }
+}
+
+impl From<MatchSpecError> for RondaError {
+    fn from(e: MatchSpecError) -> Self {
+        RondaError::MatchSpec(e)
+    }
+}
+
+impl From<RepodataReadError> for RondaError {
+    fn from(e: RepodataReadError) -> Self {
+        RondaError::Repodata(e)
+    }
+}
+
+impl From<PackageFilenameError> for RondaError {
+    fn from(e: PackageFilenameError) -> Self {
+        RondaError::Filename(e)
+    }
+}
+
+impl From<serde_json::Error> for RondaError {
+    fn from(e: serde_json::Error) -> Self {
+        RondaError::Json(e)
+    }
+}
+
+impl From<PrefixReadError> for RondaError {
+    fn from(e: PrefixReadError) -> Self {
+        RondaError::Prefix(e)
+    }
+}
+
+impl From<CondaLockError> for RondaError {
+    fn from(e: CondaLockError) -> Self {
+        RondaError::CondaLock(e)
+    }
+}
+
+impl From<Cancelled> for RondaError {
+    fn from(_: Cancelled) -> Self {
+        RondaError::Cancelled
+    }
+}
+
+#[cfg(feature = "package")]
+impl From<ExtractError> for RondaError {
+    fn from(e: ExtractError) -> Self {
+        RondaError::Extract(e)
+    }
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{treeify, read_conda_lock, CompOp};
+
+    fn parse_all(spec: &str, op: &str, lock: &str) -> Result<(), RondaError> {
+        treeify(spec)?;
+        CompOp::from_sign(op)?;
+        read_conda_lock(lock)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_question_mark_converts() {
+        match parse_all("(1.0", "==", "") {
+            Err(RondaError::Version(VersionParsingError::InvalidSpec(_))) => {},
+            other => panic!("unexpected {:?}", other),
+        }
+        let e = parse_all("1.0", "=>", "").unwrap_err();
+        assert_eq!(e.to_string(), "unknown comparison operator '=>'");
+        assert!(e.source().is_some());
+        assert!(matches!(parse_all("1.0", "==", "version: ["), Err(RondaError::CondaLock(_))));
+    }
+}
@@ -0,0 +1,258 @@
+//! The state files conda and mamba keep next to cached repodata, so that libronda can share their
+//! caches rather than download everything again.
+//!
+//! The repodata of a subdir is cached as `<stem>.json`, where the stem is the start of the MD5 of
+//! the subdir's URL (see `cache_file_stem`).  Next to it, `<stem>.info.json` (conda) or
+//! `<stem>.state.json` (mamba) records how it was downloaded: the URL, the `ETag`,
+//! `Last-Modified` and `Cache-Control` headers to revalidate it with, and the modification time
+//! and size of the repodata file, so that a state left over from an older file isn't trusted.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+#[derive(Debug)]
+pub enum CacheStateError {
+    Io(PathBuf, io::Error),
+    Json(PathBuf, serde_json::Error),
+}
+
+impl fmt::Display for CacheStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CacheStateError::Io(path, e) => write!(f, "unable to access {}: {}", path.display(), e),
+            CacheStateError::Json(path, e) => write!(f, "unable to parse {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl Error for CacheStateError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CacheStateError::Io(_, e) => Some(e),
+            CacheStateError::Json(_, e) => Some(e),
+        }
+    }
+}
+
+/// The stem of the cache files of the repodata at `url`, a subdir URL such as
+///    `https://conda.anaconda.org/conda-forge/linux-64`: the first 8 hex digits of the MD5 of the
+///    URL with a trailing slash, as conda and mamba name them.
+///
+/// # Examples
+///
+/// ```
+/// use ronda::cache_file_stem;
+///
+/// assert_eq!(cache_file_stem("https://conda.anaconda.org/conda-forge/linux-64"), "497deca9");
+/// ```
+pub fn cache_file_stem(url: &str) -> String {
+    let url = format!("{}/", url.trim_end_matches('/'));
+    format!("{:x}", Md5::digest(url.as_bytes()))[..8].to_string()
+}
+
+/// Whether the server has a variant of the repodata, and when that was last checked.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct CheckedFlag {
+    pub value: bool,
+    /// An ISO 8601 timestamp, as conda writes it
+    pub last_checked: String,
+}
+
+/// The contents of a `.info.json` or `.state.json` file.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct CacheState {
+    /// The subdir URL the repodata was downloaded from
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// The `Last-Modified` header
+    #[serde(rename = "mod", default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<String>,
+    /// The modification time of the cached repodata file, in nanoseconds since the epoch
+    #[serde(default)]
+    pub mtime_ns: u64,
+    /// The size of the cached repodata file
+    #[serde(default)]
+    pub size: u64,
+    /// Whether the server has `repodata.json.zst`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_zst: Option<CheckedFlag>,
+    /// Whether the server has `repodata.jlap`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_jlap: Option<CheckedFlag>,
+    /// Everything else conda or mamba wrote, kept so that writing the state loses nothing
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl CacheState {
+    pub fn new(url: &str) -> CacheState {
+        CacheState { url: url.to_string(), ..CacheState::default() }
+    }
+
+    /// The cached repodata of `url` in `cache_dir`, e.g. `pkgs/cache`.
+    pub fn repodata_path(cache_dir: &Path, url: &str) -> PathBuf {
+        cache_dir.join(format!("{}.json", cache_file_stem(url)))
+    }
+
+    /// Read the state of the cached repodata of `url` in `cache_dir`, from conda's state file or
+    ///    else mamba's.  `None` if there is none, or if it describes a different URL or a
+    ///    repodata file other than the one in the cache (a different modification time or size).
+    pub fn read(cache_dir: &Path, url: &str) -> Result<Option<CacheState>, CacheStateError> {
+        let stem = cache_file_stem(url);
+        let repodata = CacheState::repodata_path(cache_dir, url);
+        let (mtime_ns, size) = match file_stamp(&repodata) {
+            Ok(stamp) => stamp,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(CacheStateError::Io(repodata, e)),
+        };
+        for suffix in ["info", "state"] {
+            let path = cache_dir.join(format!("{}.{}.json", stem, suffix));
+            let text = match fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(CacheStateError::Io(path, e)),
+            };
+            let state: CacheState = serde_json::from_str(&text).map_err(|e| CacheStateError::Json(path, e))?;
+            let same_url = state.url.trim_end_matches('/') == url.trim_end_matches('/');
+            return Ok(Some(state).filter(|s| same_url && s.mtime_ns == mtime_ns && s.size == size));
+        }
+        Ok(None)
+    }
+
+    /// Write the state for the cached repodata in `cache_dir`, which must already be there, as
+    ///    both conda's and mamba's state file.  The modification time and size are taken from
+    ///    the repodata file.
+    pub fn write(&mut self, cache_dir: &Path) -> Result<(), CacheStateError> {
+        let repodata = CacheState::repodata_path(cache_dir, &self.url);
+        let (mtime_ns, size) = file_stamp(&repodata).map_err(|e| CacheStateError::Io(repodata, e))?;
+        self.mtime_ns = mtime_ns;
+        self.size = size;
+        let stem = cache_file_stem(&self.url);
+        // serializing plain strings, numbers and JSON values can't fail
+        let json = serde_json::to_string_pretty(self).unwrap();
+        for suffix in ["info", "state"] {
+            let path = cache_dir.join(format!("{}.{}.json", stem, suffix));
+            fs::write(&path, &json).map_err(|e| CacheStateError::Io(path, e))?;
+        }
+        Ok(())
+    }
+
+    /// The headers that make a request for the repodata conditional on it having changed:
+    ///    `If-None-Match` with the `ETag` and `If-Modified-Since` with the `Last-Modified`.
+    pub fn conditional_headers(&self) -> Vec<(&'static str, &str)> {
+        let mut headers = vec![];
+        if let Some(etag) = &self.etag {
+            headers.push(("If-None-Match", etag.as_str()));
+        }
+        if let Some(modified) = &self.modified {
+            headers.push(("If-Modified-Since", modified.as_str()));
+        }
+        headers
+    }
+}
+
+// The modification time in nanoseconds and the size of `path`
+fn file_stamp(path: &Path) -> io::Result<(u64, u64)> {
+    let metadata = fs::metadata(path)?;
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+    Ok((modified, metadata.len()))
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://conda.anaconda.org/conda-forge/noarch";
+
+    // As conda 23 writes it
+    const CONDA_STATE: &str = r#"{
+      "url": "https://conda.anaconda.org/conda-forge/noarch",
+      "etag": "W/\"5e3a1a7c0e1f3b2d\"",
+      "mod": "Tue, 01 Aug 2023 10:00:00 GMT",
+      "cache_control": "public, max-age=30",
+      "mtime_ns": 1,
+      "size": 2,
+      "has_zst": {"value": true, "last_checked": "2023-08-01T10:00:01.123456Z"},
+      "has_jlap": {"value": false, "last_checked": "2023-08-01T10:00:01.123456Z"},
+      "refresh_ns": 1690884001123456000
+    }"#;
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ronda-cache-state-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[rstest(url,
+        case("https://conda.anaconda.org/conda-forge/linux-64"),
+        case("https://conda.anaconda.org/conda-forge/linux-64/")
+    )]
+    fn test_stem(url: &str) {
+        assert_eq!(cache_file_stem(url), "497deca9");
+    }
+
+    #[test]
+    fn test_parse_conda_state() {
+        let state: CacheState = serde_json::from_str(CONDA_STATE).unwrap();
+        assert_eq!(state.modified.as_deref(), Some("Tue, 01 Aug 2023 10:00:00 GMT"));
+        assert_eq!(state.has_zst.as_ref().map(|f| f.value), Some(true));
+        assert_eq!(state.conditional_headers(), [
+            ("If-None-Match", "W/\"5e3a1a7c0e1f3b2d\""),
+            ("If-Modified-Since", "Tue, 01 Aug 2023 10:00:00 GMT"),
+        ]);
+        // unknown fields survive a round trip
+        let written: Value = serde_json::to_value(&state).unwrap();
+        assert_eq!(written, serde_json::from_str::<Value>(CONDA_STATE).unwrap());
+    }
+
+    #[test]
+    fn test_write_and_read() {
+        let dir = cache_dir("round-trip");
+        fs::write(CacheState::repodata_path(&dir, URL), "{}").unwrap();
+        let mut state = CacheState { etag: Some("abc".to_string()), ..CacheState::new(URL) };
+        state.write(&dir).unwrap();
+        assert_eq!(state.size, 2);
+        let stem = cache_file_stem(URL);
+        assert!(dir.join(format!("{}.info.json", stem)).exists());
+        assert!(dir.join(format!("{}.state.json", stem)).exists());
+        assert_eq!(CacheState::read(&dir, URL).unwrap(), Some(state));
+        // only mamba's state file
+        fs::remove_file(dir.join(format!("{}.info.json", stem))).unwrap();
+        assert!(CacheState::read(&dir, URL).unwrap().is_some());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_stale_state_is_ignored() {
+        let dir = cache_dir("stale");
+        assert_eq!(CacheState::read(&dir, URL).unwrap(), None);
+        fs::write(CacheState::repodata_path(&dir, URL), "{}").unwrap();
+        assert_eq!(CacheState::read(&dir, URL).unwrap(), None);
+        CacheState::new(URL).write(&dir).unwrap();
+        fs::write(CacheState::repodata_path(&dir, URL), "{\"packages\": {}}").unwrap();
+        assert_eq!(CacheState::read(&dir, URL).unwrap(), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_bad_state_is_an_error() {
+        let dir = cache_dir("bad");
+        fs::write(CacheState::repodata_path(&dir, URL), "{}").unwrap();
+        fs::write(dir.join(format!("{}.info.json", cache_file_stem(URL))), "not json").unwrap();
+        assert!(matches!(CacheState::read(&dir, URL), Err(CacheStateError::Json(_, _))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,367 @@
+//! Downloading from channels over HTTP.
+//!
+//! A channel with mirrors is downloaded from its sources in order, moving on to the next whenever
+//! one fails, so an unreachable CDN falls back to an internal mirror (or the other way round)
+//! without any logic in the caller.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::cancel::cancel::CancellationToken;
+use crate::fetch::channel::{host, source_url, Channel};
+use crate::fetch::limits::{Limiter, Throttle};
+use crate::fetch::shards::ShardDecoder;
+use crate::repodata::repodata::{read_repodata_from_slice, Repodata};
+
+#[derive(Debug)]
+pub enum FetchError {
+    /// The server answered with an error status
+    Status(String, u16),
+    /// No answer: DNS, connection, TLS, ...
+    Transport(String, String),
+    Io(String, io::Error),
+    Json(String, serde_json::Error),
+    /// Stopped through a `CancellationToken`
+    Cancelled(String),
+}
+
+// Each variant carries the URL it was fetching, from Channel::url, so never with credentials
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FetchError::Status(url, status) => write!(f, "{} returned HTTP {}", url, status),
+            FetchError::Transport(url, e) => write!(f, "unable to fetch {}: {}", url, e),
+            FetchError::Io(url, e) => write!(f, "unable to read {}: {}", url, e),
+            FetchError::Json(url, e) => write!(f, "unable to parse {}: {}", url, e),
+            FetchError::Cancelled(url) => write!(f, "fetching {} was cancelled", url),
+        }
+    }
+}
+
+impl Error for FetchError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FetchError::Io(_, e) => Some(e),
+            FetchError::Json(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Download `path` (e.g. `linux-64/repodata.json`) from `channel`, authenticating as it says.
+///    Each of the channel's sources is tried in turn until one answers with the file; if none
+///    does, the error from the first is returned.  Use a `FetchClient` to limit downloads.
+pub fn fetch_bytes(channel: &Channel, path: &str) -> Result<Vec<u8>, FetchError> {
+    FetchClient::new().fetch_bytes(channel, path)
+}
+
+/// Like `fetch_bytes`, giving up with `FetchError::Cancelled` soon after `token` is cancelled.
+pub fn fetch_bytes_cancellable(channel: &Channel, path: &str, token: &CancellationToken) -> Result<Vec<u8>, FetchError> {
+    FetchClient::new().fetch_bytes_cancellable(channel, path, token)
+}
+
+// Large enough not to matter for throughput, small enough to notice a cancel quickly
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Downloads with limits, so that installers built on libronda are polite on shared
+///    infrastructure: how many downloads run at once, overall and per host, and how many bytes
+///    per second they read between them.  No limit is set by default.  Clones share the limits,
+///    so set them first and then clone the client for each thread.
+///
+/// ```no_run
+/// use ronda::{Channel, FetchClient};
+///
+/// let client = FetchClient::new().max_concurrent(8).max_per_host(2).max_bytes_per_second(10_000_000);
+/// let repodata = client.fetch_repodata(&Channel::parse("conda-forge"), "noarch").unwrap();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct FetchClient {
+    max_concurrent: Option<usize>,
+    max_per_host: Option<usize>,
+    max_bytes_per_second: Option<u64>,
+    // the downloads running and the bandwidth used by this client and its clones
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    limiter: Limiter,
+    throttle: Option<Throttle>,
+}
+
+impl FetchClient {
+    pub fn new() -> FetchClient {
+        FetchClient::default()
+    }
+
+    /// Run at most `max` downloads at once (at least 1); the others wait for one to finish.
+    pub fn max_concurrent(mut self, max: usize) -> FetchClient {
+        self.max_concurrent = Some(max.max(1));
+        self.reset()
+    }
+
+    /// Run at most `max` downloads at once from any one host (at least 1).
+    pub fn max_per_host(mut self, max: usize) -> FetchClient {
+        self.max_per_host = Some(max.max(1));
+        self.reset()
+    }
+
+    /// Read at most `max` bytes per second, across all downloads.
+    pub fn max_bytes_per_second(mut self, max: u64) -> FetchClient {
+        self.max_bytes_per_second = Some(max);
+        self.reset()
+    }
+
+    // Limits changed: start counting afresh, apart from any earlier clones
+    fn reset(mut self) -> FetchClient {
+        self.shared = Arc::new(Shared {
+            limiter: Limiter::new(self.max_concurrent, self.max_per_host),
+            throttle: self.max_bytes_per_second.map(Throttle::new),
+        });
+        self
+    }
+
+    /// Like `fetch_bytes`, within this client's limits.
+    pub fn fetch_bytes(&self, channel: &Channel, path: &str) -> Result<Vec<u8>, FetchError> {
+        self.fetch_bytes_cancellable(channel, path, &CancellationToken::new())
+    }
+
+    /// Like `fetch_bytes_cancellable`, within this client's limits.  Waiting for a download slot
+    ///    is cancelled too.
+    pub fn fetch_bytes_cancellable(&self, channel: &Channel, path: &str, token: &CancellationToken) -> Result<Vec<u8>, FetchError> {
+        let mut first_error = None;
+        for source in channel.sources() {
+            match self.fetch_from(channel, source, path, token) {
+                Ok(body) => return Ok(body),
+                Err(e @ FetchError::Cancelled(_)) => return Err(e),
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        // a channel always has at least its base URL as a source
+        Err(first_error.unwrap())
+    }
+
+    /// Like `fetch_repodata`, within this client's limits.
+    pub fn fetch_repodata(&self, channel: &Channel, subdir: &str) -> Result<Repodata, FetchError> {
+        let path = format!("{}/repodata.json", subdir);
+        let body = self.fetch_bytes(channel, &path)?;
+        read_repodata_from_slice(&body).map_err(|e| FetchError::Json(channel.url(&path), e))
+    }
+
+    /// Download a zstd compressed shard at `path` and decompress it with `decoder`, which is
+    ///    best shared by all the shards of a channel.
+    pub fn fetch_shard(&self, channel: &Channel, path: &str, decoder: &ShardDecoder) -> Result<Vec<u8>, FetchError> {
+        let compressed = self.fetch_bytes(channel, path)?;
+        decoder.decompress(&compressed).map_err(|e| FetchError::Io(channel.url(path), e))
+    }
+
+    fn fetch_from(&self, channel: &Channel, source: &str, path: &str, token: &CancellationToken) -> Result<Vec<u8>, FetchError> {
+        let url = source_url(source, path);
+        let _permit = self.shared.limiter.acquire(host(source), token).map_err(|_| FetchError::Cancelled(url.clone()))?;
+        let mut request = ureq::get(&channel.source_request_url(source, path));
+        if let Some(authorization) = channel.source_authorization_header(source) {
+            request = request.set("Authorization", &authorization);
+        }
+        let response = match request.call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(status, _)) => return Err(FetchError::Status(url, status)),
+            // ureq's transport errors include the request URL, which may hold a token
+            Err(ureq::Error::Transport(e)) => return Err(FetchError::Transport(url, e.kind().to_string())),
+        };
+        let throttle = self.shared.throttle.as_ref();
+        let chunk_size = throttle.map_or(CHUNK_SIZE, |t| t.chunk_size(CHUNK_SIZE));
+        let mut body = vec![];
+        let mut reader = response.into_reader();
+        loop {
+            if token.is_cancelled() {
+                return Err(FetchError::Cancelled(url));
+            }
+            let read = reader.by_ref().take(chunk_size as u64).read_to_end(&mut body).map_err(|e| FetchError::Io(url.clone(), e))?;
+            if read == 0 {
+                return Ok(body);
+            }
+            if let Some(throttle) = throttle {
+                throttle.pay(read);
+            }
+        }
+    }
+}
+
+/// Time a `HEAD` request for `path` to each of the channel's sources, all at once, and return the
+///    channel with them ordered fastest first.  Sources that don't answer within `timeout`, or
+///    answer with an error, go last in their original order.  The channel keeps its base URL, so
+///    it is still the same channel.
+pub fn fastest_sources_first(channel: &Channel, path: &str, timeout: Duration) -> Channel {
+    let mut timings: Vec<(Duration, &str)> = thread::scope(|scope| {
+        let probes: Vec<_> = channel.sources().into_iter().map(|source| scope.spawn(move || {
+            let start = Instant::now();
+            let mut request = ureq::head(&channel.source_request_url(source, path)).timeout(timeout);
+            if let Some(authorization) = channel.source_authorization_header(source) {
+                request = request.set("Authorization", &authorization);
+            }
+            request.call().ok().map(|_| (start.elapsed(), source))
+        })).collect();
+        probes.into_iter().filter_map(|probe| probe.join().ok().flatten()).collect()
+    });
+    timings.sort_by_key(|(elapsed, _)| *elapsed);
+    let fastest: Vec<String> = timings.into_iter().map(|(_, source)| source.to_string()).collect();
+    channel.clone().with_sources_first(&fastest)
+}
+
+/// Download and parse the repodata of one subdir of `channel`.
+pub fn fetch_repodata(channel: &Channel, subdir: &str) -> Result<Repodata, FetchError> {
+    FetchClient::new().fetch_repodata(channel, subdir)
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use super::*;
+    use crate::fetch::channel::Credentials;
+
+    /// Serve one request on localhost, answering with `status` and `body`.  Returns the port and
+    ///    a handle yielding the request line and headers, lowercased.
+    pub(crate) fn serve_once(status: u16, body: &'static str) -> (u16, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut lines = vec![];
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                lines.push(line.trim().to_lowercase());
+            }
+            write!(stream, "HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body).unwrap();
+            lines
+        });
+        (port, handle)
+    }
+
+    const REPODATA: &str = r#"{"info": {"subdir": "noarch"}, "packages": {}, "packages.conda": {}, "removed": [], "repodata_version": 1}"#;
+
+    #[test]
+    fn test_fetch_with_token() {
+        let (port, server) = serve_once(200, REPODATA);
+        let channel = Channel::parse(&format!("http://127.0.0.1:{}/t/tk-1/private", port));
+        let repodata = fetch_repodata(&channel, "noarch").unwrap();
+        assert_eq!(repodata.info.subdir, "noarch");
+        assert_eq!(server.join().unwrap()[0], "get /t/tk-1/private/noarch/repodata.json http/1.1");
+    }
+
+    #[test]
+    fn test_fetch_with_bearer() {
+        let (port, server) = serve_once(200, "{}");
+        let channel = Channel::new(format!("http://127.0.0.1:{}/c", port)).with_credentials(Credentials::Bearer("abc".to_string()));
+        assert_eq!(fetch_bytes(&channel, "noarch/x").unwrap(), b"{}");
+        assert!(server.join().unwrap().contains(&"authorization: bearer abc".to_string()));
+    }
+
+    #[test]
+    fn test_fetch_cancelled() {
+        let token = CancellationToken::new();
+        token.cancel();
+        let err = fetch_bytes_cancellable(&Channel::new("http://127.0.0.1:9"), "noarch/repodata.json", &token).unwrap_err();
+        assert!(matches!(err, FetchError::Cancelled(_)));
+    }
+
+    // A URL on localhost that refuses connections
+    fn refused_url() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port())
+    }
+
+    #[test]
+    fn test_fetch_falls_back_to_mirror() {
+        let (port, server) = serve_once(200, "{}");
+        let channel = Channel::new(refused_url()).with_mirror(&format!("http://127.0.0.1:{}/mirror", port));
+        assert_eq!(fetch_bytes(&channel, "noarch/x").unwrap(), b"{}");
+        assert_eq!(server.join().unwrap()[0], "get /mirror/noarch/x http/1.1");
+    }
+
+    #[test]
+    fn test_fetch_falls_back_on_status() {
+        let (missing, missing_server) = serve_once(404, "");
+        let (port, server) = serve_once(200, "{}");
+        let channel = Channel::new(format!("http://127.0.0.1:{}", missing)).with_mirror(&format!("http://127.0.0.1:{}", port));
+        assert_eq!(fetch_bytes(&channel, "noarch/x").unwrap(), b"{}");
+        missing_server.join().unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_fetch_reports_first_error() {
+        let (port, server) = serve_once(503, "");
+        let channel = Channel::new(format!("http://127.0.0.1:{}", port)).with_mirror(&refused_url());
+        let err = fetch_bytes(&channel, "noarch/x").unwrap_err();
+        server.join().unwrap();
+        assert!(matches!(err, FetchError::Status(_, 503)), "{}", err);
+    }
+
+    #[test]
+    fn test_fastest_sources_first() {
+        let (port, server) = serve_once(200, "");
+        let working = format!("http://127.0.0.1:{}", port);
+        let channel = Channel::new(refused_url()).with_mirror(&working);
+        let probed = fastest_sources_first(&channel, "noarch/repodata.json", Duration::from_secs(5));
+        assert_eq!(server.join().unwrap()[0], "head /noarch/repodata.json http/1.1");
+        assert_eq!(probed.sources()[0], working);
+        assert_eq!(probed.base_url(), channel.base_url());
+    }
+
+    #[test]
+    fn test_client_throttles() {
+        let body: &'static str = Box::leak("x".repeat(2000).into_boxed_str());
+        let (port, server) = serve_once(200, body);
+        let client = FetchClient::new().max_bytes_per_second(4000);
+        let start = Instant::now();
+        assert_eq!(client.fetch_bytes(&Channel::new(format!("http://127.0.0.1:{}", port)), "x").unwrap().len(), 2000);
+        server.join().unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(400), "{:?}", start.elapsed());
+    }
+
+    #[test]
+    fn test_client_clones_share_limits() {
+        let client = FetchClient::new().max_concurrent(4).max_per_host(0);
+        assert_eq!((client.max_concurrent, client.max_per_host), (Some(4), Some(1)));
+        assert!(Arc::ptr_eq(&client.shared, &client.clone().shared));
+        assert!(!Arc::ptr_eq(&client.shared, &client.clone().max_bytes_per_second(1).shared));
+    }
+
+    #[test]
+    fn test_client_waits_for_a_slot() {
+        let client = FetchClient::new().max_per_host(1);
+        let (port, server) = serve_once(200, "{}");
+        let channel = Channel::new(format!("http://127.0.0.1:{}", port));
+        let held = client.shared.limiter.acquire(&format!("127.0.0.1:{}", port), &CancellationToken::new()).unwrap();
+        let token = CancellationToken::new();
+        token.cancel();
+        assert!(matches!(client.fetch_bytes_cancellable(&channel, "x", &token), Err(FetchError::Cancelled(_))));
+        drop(held);
+        assert_eq!(client.fetch_bytes(&channel, "x").unwrap(), b"{}");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_error_hides_token() {
+        let (port, server) = serve_once(403, "");
+        let channel = Channel::parse(&format!("http://127.0.0.1:{}/t/tk-1/private", port));
+        let err = fetch_bytes(&channel, "noarch/repodata.json").unwrap_err();
+        server.join().unwrap();
+        assert!(matches!(err, FetchError::Status(_, 403)));
+        assert!(!err.to_string().contains("tk-1"), "{}", err);
+    }
+}
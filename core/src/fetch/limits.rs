@@ -0,0 +1,171 @@
+//! Politeness limits shared by every download of a `FetchClient`: how many run at once, overall
+//! and per host, and how many bytes per second they may read between them.
+
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::cancel::cancel::{CancellationToken, Cancelled};
+
+// How often a download waiting for a slot checks whether it was cancelled
+const CANCEL_POLL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Default)]
+struct Running {
+    total: usize,
+    per_host: HashMap<String, usize>,
+}
+
+/// Counts running downloads, making new ones wait while a limit is reached.
+#[derive(Debug, Default)]
+pub(crate) struct Limiter {
+    max_total: Option<usize>,
+    max_per_host: Option<usize>,
+    running: Mutex<Running>,
+    freed: Condvar,
+}
+
+impl Limiter {
+    pub(crate) fn new(max_total: Option<usize>, max_per_host: Option<usize>) -> Limiter {
+        Limiter { max_total, max_per_host, ..Limiter::default() }
+    }
+
+    /// Wait for a slot to download from `host`, held until the returned permit is dropped.
+    pub(crate) fn acquire(&self, host: &str, token: &CancellationToken) -> Result<Permit<'_>, Cancelled> {
+        let mut running = self.running.lock().unwrap();
+        loop {
+            token.check()?;
+            let on_host = running.per_host.get(host).copied().unwrap_or(0);
+            if self.max_total.is_none_or(|max| running.total < max) && self.max_per_host.is_none_or(|max| on_host < max) {
+                running.total += 1;
+                *running.per_host.entry(host.to_string()).or_insert(0) += 1;
+                return Ok(Permit { limiter: self, host: host.to_string() });
+            }
+            running = self.freed.wait_timeout(running, CANCEL_POLL).unwrap().0;
+        }
+    }
+}
+
+/// A running download's slot in a `Limiter`.
+pub(crate) struct Permit<'a> {
+    limiter: &'a Limiter,
+    host: String,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let mut running = self.limiter.running.lock().unwrap();
+        running.total -= 1;
+        if let Some(count) = running.per_host.get_mut(&self.host) {
+            *count -= 1;
+            if *count == 0 {
+                running.per_host.remove(&self.host);
+            }
+        }
+        self.limiter.freed.notify_all();
+    }
+}
+
+/// Paces reads so that together they stay under a number of bytes per second.
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    bytes_per_second: u64,
+    // when the bytes read so far have been paid for
+    paid_until: Mutex<Instant>,
+}
+
+impl Throttle {
+    pub(crate) fn new(bytes_per_second: u64) -> Throttle {
+        Throttle { bytes_per_second: bytes_per_second.max(1), paid_until: Mutex::new(Instant::now()) }
+    }
+
+    /// The most to read at once, so that a read is paid for in about a second at most.
+    pub(crate) fn chunk_size(&self, chunk_size: usize) -> usize {
+        chunk_size.min(self.bytes_per_second as usize)
+    }
+
+    /// Sleep until `bytes` more have been paid for.
+    pub(crate) fn pay(&self, bytes: usize) {
+        let wait = {
+            let mut paid_until = self.paid_until.lock().unwrap();
+            let now = Instant::now();
+            *paid_until = (*paid_until).max(now) + Duration::from_secs_f64(bytes as f64 / self.bytes_per_second as f64);
+            *paid_until - now
+        };
+        thread::sleep(wait);
+    }
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::channel::host;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    // Run downloads from `hosts` at once, returning the most that ran together
+    fn most_at_once(limiter: Limiter, hosts: &[&'static str]) -> usize {
+        let limiter = Arc::new(limiter);
+        let (now, most) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let threads: Vec<_> = hosts.iter().map(|&host| {
+            let (limiter, now, most) = (limiter.clone(), now.clone(), most.clone());
+            thread::spawn(move || {
+                let _permit = limiter.acquire(host, &CancellationToken::new()).unwrap();
+                most.fetch_max(now.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(20));
+                now.fetch_sub(1, Ordering::SeqCst);
+            })
+        }).collect();
+        threads.into_iter().for_each(|t| t.join().unwrap());
+        most.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn test_total_limit() {
+        assert!(most_at_once(Limiter::new(Some(2), None), &["a", "b", "c", "d", "e", "f"]) <= 2);
+    }
+
+    #[test]
+    fn test_per_host_limit() {
+        assert_eq!(most_at_once(Limiter::new(None, Some(1)), &["a", "a", "a", "a"]), 1);
+        assert!(most_at_once(Limiter::new(None, Some(1)), &["a", "b", "a", "b"]) <= 2);
+    }
+
+    #[test]
+    fn test_permit_released() {
+        let limiter = Limiter::new(Some(1), Some(1));
+        drop(limiter.acquire("a", &CancellationToken::new()).unwrap());
+        assert!(limiter.acquire("a", &CancellationToken::new()).is_ok());
+        assert!(limiter.running.lock().unwrap().per_host.is_empty());
+    }
+
+    #[test]
+    fn test_waiting_is_cancellable() {
+        let limiter = Limiter::new(Some(1), None);
+        let _held = limiter.acquire("a", &CancellationToken::new()).unwrap();
+        let token = CancellationToken::new();
+        token.cancel();
+        assert!(limiter.acquire("b", &token).is_err());
+    }
+
+    #[test]
+    fn test_throttle() {
+        let throttle = Throttle::new(1000);
+        assert_eq!(throttle.chunk_size(64 * 1024), 1000);
+        let start = Instant::now();
+        throttle.pay(100);
+        throttle.pay(100);
+        assert!(start.elapsed() >= Duration::from_millis(190));
+    }
+
+    #[rstest(url, expected,
+        case("https://conda.anaconda.org/conda-forge", "conda.anaconda.org"),
+        case("http://127.0.0.1:8000", "127.0.0.1:8000"),
+        case("example.com/x", "example.com")
+    )]
+    fn test_host(url: &str, expected: &str) {
+        assert_eq!(host(url), expected);
+    }
+}
@@ -0,0 +1,9 @@
+#[cfg(feature = "fetch")]
+pub mod cache_state;
+pub mod channel;
+#[cfg(feature = "fetch")]
+pub mod fetch;
+#[cfg(feature = "fetch")]
+pub mod limits;
+#[cfg(feature = "fetch")]
+pub mod shards;
@@ -0,0 +1,160 @@
+//! Decompressing many small zstd compressed downloads, such as the per-package shards of sharded
+//! repodata, cheaply.
+//!
+//! A shard is often only a few kilobytes, so setting up a decompression context costs about as
+//! much as using it.  A `ShardDecoder` keeps a pool of contexts, with the channel's dictionary
+//! already loaded, and hands them out to whichever thread decodes next.  It also counts what it
+//! did, to tune the number of fetches in flight or to judge whether a dictionary pays off.
+
+use std::io::{self, Read};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use zstd::bulk::Decompressor;
+use zstd::zstd_safe;
+
+// Larger claimed sizes are decoded as a stream rather than trusted for an allocation up front
+const MAX_PREALLOCATED: u64 = 256 * 1024 * 1024;
+
+/// What a `ShardDecoder` has done so far.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DecompressionStats {
+    /// Shards decompressed successfully
+    pub shards: u64,
+    pub compressed_bytes: u64,
+    pub decompressed_bytes: u64,
+    /// Time spent decompressing, added up over all threads
+    pub time: Duration,
+    /// Decompression contexts created; every other shard reused one from the pool
+    pub contexts: u64,
+}
+
+impl DecompressionStats {
+    /// Decompressed bytes per compressed byte, or 0 before anything was decompressed.
+    pub fn ratio(&self) -> f64 {
+        if self.compressed_bytes == 0 { 0.0 } else { self.decompressed_bytes as f64 / self.compressed_bytes as f64 }
+    }
+}
+
+/// Decompresses zstd frames, optionally compressed with a shared dictionary, reusing contexts
+///    between calls.  It can be shared between threads.
+///
+/// ```
+/// use ronda::ShardDecoder;
+///
+/// let decoder = ShardDecoder::new();
+/// let shard = zstd::bulk::compress(b"{\"packages\": {}}", 3).unwrap();
+/// assert_eq!(decoder.decompress(&shard).unwrap(), b"{\"packages\": {}}");
+/// assert_eq!(decoder.stats().shards, 1);
+/// ```
+#[derive(Default)]
+pub struct ShardDecoder {
+    dictionary: Vec<u8>,
+    pool: Mutex<Vec<Decompressor<'static>>>,
+    stats: Mutex<DecompressionStats>,
+}
+
+impl ShardDecoder {
+    pub fn new() -> ShardDecoder {
+        ShardDecoder::default()
+    }
+
+    /// Decode shards compressed with `dictionary`, either a trained zstd dictionary or raw
+    ///    content.
+    pub fn with_dictionary(dictionary: Vec<u8>) -> ShardDecoder {
+        ShardDecoder { dictionary, ..ShardDecoder::default() }
+    }
+
+    /// Decompress one shard.
+    pub fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let start = Instant::now();
+        let pooled = self.pool.lock().unwrap().pop();
+        let created = pooled.is_none();
+        let mut decompressor = match pooled {
+            Some(decompressor) => decompressor,
+            None => Decompressor::with_dictionary(&self.dictionary)?,
+        };
+        let decompressed = match zstd_safe::get_frame_content_size(data) {
+            Ok(Some(size)) if size <= MAX_PREALLOCATED => decompressor.decompress(data, size as usize),
+            // a streaming compressor doesn't record the size
+            _ => {
+                let mut decompressed = vec![];
+                zstd::stream::read::Decoder::with_dictionary(data, &self.dictionary)?.read_to_end(&mut decompressed)?;
+                Ok(decompressed)
+            }
+        };
+        self.pool.lock().unwrap().push(decompressor);
+        let mut stats = self.stats.lock().unwrap();
+        stats.contexts += created as u64;
+        let decompressed = decompressed?;
+        stats.shards += 1;
+        stats.compressed_bytes += data.len() as u64;
+        stats.decompressed_bytes += decompressed.len() as u64;
+        stats.time += start.elapsed();
+        Ok(decompressed)
+    }
+
+    pub fn stats(&self) -> DecompressionStats {
+        *self.stats.lock().unwrap()
+    }
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::Arc;
+    use std::thread;
+
+    const SHARD: &[u8] = br#"{"packages": {"numpy-1.26.4-py312h8753938_0.tar.bz2": {"build": "py312h8753938_0", "depends": ["libblas >=3.9.0,<4.0a0", "python >=3.12,<3.13.0a0"]}}}"#;
+    // A raw content dictionary: text the shards have in common
+    const DICTIONARY: &[u8] = br#"{"packages": {"build": "depends": ["libblas >=3.9.0,<4.0a0", "python >=3.12,<3.13.0a0"]"#;
+
+    #[test]
+    fn test_reuses_contexts() {
+        let decoder = ShardDecoder::new();
+        let shard = zstd::bulk::compress(SHARD, 3).unwrap();
+        for _ in 0..3 {
+            assert_eq!(decoder.decompress(&shard).unwrap(), SHARD);
+        }
+        let stats = decoder.stats();
+        assert_eq!((stats.shards, stats.contexts), (3, 1));
+        assert_eq!(stats.compressed_bytes, 3 * shard.len() as u64);
+        assert_eq!(stats.decompressed_bytes, 3 * SHARD.len() as u64);
+        assert!(stats.ratio() > 1.0);
+    }
+
+    #[test]
+    fn test_dictionary() {
+        let shard = zstd::bulk::Compressor::with_dictionary(3, DICTIONARY).unwrap().compress(SHARD).unwrap();
+        assert!(shard.len() < zstd::bulk::compress(SHARD, 3).unwrap().len());
+        assert_eq!(ShardDecoder::with_dictionary(DICTIONARY.to_vec()).decompress(&shard).unwrap(), SHARD);
+        let without = ShardDecoder::new();
+        assert!(without.decompress(&shard).is_err());
+        assert_eq!(without.stats().shards, 0);
+    }
+
+    #[test]
+    fn test_stream_without_content_size() {
+        let mut encoder = zstd::stream::write::Encoder::with_dictionary(vec![], 3, DICTIONARY).unwrap();
+        encoder.write_all(SHARD).unwrap();
+        let shard = encoder.finish().unwrap();
+        assert_eq!(zstd_safe::get_frame_content_size(&shard).ok(), Some(None));
+        assert_eq!(ShardDecoder::with_dictionary(DICTIONARY.to_vec()).decompress(&shard).unwrap(), SHARD);
+    }
+
+    #[test]
+    fn test_shared_between_threads() {
+        let decoder = Arc::new(ShardDecoder::new());
+        let shard = Arc::new(zstd::bulk::compress(SHARD, 3).unwrap());
+        let threads: Vec<_> = (0..4).map(|_| {
+            let (decoder, shard) = (decoder.clone(), shard.clone());
+            thread::spawn(move || (0..10).for_each(|_| assert_eq!(decoder.decompress(&shard).unwrap(), SHARD)))
+        }).collect();
+        threads.into_iter().for_each(|t| t.join().unwrap());
+        let stats = decoder.stats();
+        assert_eq!(stats.shards, 40);
+        assert!(stats.contexts >= 1 && stats.contexts <= 4);
+    }
+}
@@ -0,0 +1,206 @@
+//! The graph condensed into what a SAT encoding works on: for every package name, its
+//! candidates in order of preference, and for every candidate's dependency the set of the
+//! depended-on name's candidates that satisfy it.
+//!
+//! Building the tables walks each dependency once per distinct `depends` string rather than
+//! per candidate, and afterwards nothing needs the graph: candidates are numbered within their
+//! name, and a dependency is a bitset over those numbers.  The graph stays what analysis passes
+//! work on.
+
+use std::collections::HashMap;
+
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::visit::IntoNodeReferences;
+
+use crate::graph::graph::GraphNode;
+use crate::repodata::depends::Depend;
+use crate::repodata::repodata::candidate_order;
+use crate::Spec;
+
+/// A set of candidates of one name, by their index in its `NameTable`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CandidateMask {
+    bits: Vec<u64>,
+}
+
+impl CandidateMask {
+    fn new(len: usize) -> CandidateMask {
+        CandidateMask { bits: vec![0; len.div_ceil(64)] }
+    }
+
+    fn insert(&mut self, index: usize) {
+        self.bits[index / 64] |= 1 << (index % 64);
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        self.bits.get(index / 64).is_some_and(|word| word & (1 << (index % 64)) != 0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|word| *word == 0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.bits.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    /// The candidates in the set, most preferred first.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.bits.len() * 64).filter(move |i| self.contains(*i))
+    }
+}
+
+/// One entry of a candidate's `depends`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Dependency<'a> {
+    pub name: &'a str,
+    /// The index of `name`'s table, or `None` if nothing in the graph has that name (e.g. a
+    ///    virtual package)
+    pub table: Option<usize>,
+    /// The candidates of `name` that satisfy the dependency
+    pub candidates: CandidateMask,
+}
+
+/// The candidates for one package name.
+#[derive(Clone, Debug)]
+pub struct NameTable<'a> {
+    pub name: &'a str,
+    /// Highest priority channel first, then as `Repodata::latest` picks: the first candidate
+    ///    is the one preferred
+    pub candidates: Vec<(NodeIndex, GraphNode<'a>)>,
+    /// The dependencies of each candidate, in the order of `candidates`
+    pub depends: Vec<Vec<Dependency<'a>>>,
+}
+
+/// The candidate tables of every name in a graph, sorted by name.
+#[derive(Clone, Debug)]
+pub struct CandidateTables<'a> {
+    tables: Vec<NameTable<'a>>,
+    ids: HashMap<&'a str, usize>,
+}
+
+impl<'a> CandidateTables<'a> {
+    pub fn new(graph: &DiGraph<GraphNode<'a>, i16>) -> CandidateTables<'a> {
+        let mut by_name: HashMap<&'a str, Vec<(NodeIndex, GraphNode<'a>)>> = HashMap::new();
+        for (index, node) in graph.node_references() {
+            by_name.entry(node.record.name.as_str()).or_default().push((index, *node));
+        }
+        let mut tables: Vec<NameTable<'a>> = by_name.into_iter().map(|(name, mut candidates)| {
+            candidates.sort_by(|(_, a), (_, b)| a.priority.cmp(&b.priority)
+                .then_with(|| candidate_order((b.filename, b.record), (a.filename, a.record))));
+            NameTable { name, candidates, depends: vec![] }
+        }).collect();
+        tables.sort_by(|a, b| a.name.cmp(b.name));
+        let ids: HashMap<&'a str, usize> = tables.iter().enumerate().map(|(i, t)| (t.name, i)).collect();
+
+        let mut masks: HashMap<&'a str, Dependency<'a>> = HashMap::new();
+        let mut depends = Vec::with_capacity(tables.len());
+        for table in &tables {
+            depends.push(table.candidates.iter().map(|(_, node)| {
+                node.record.depends.iter()
+                    .map(|entry| masks.entry(entry.as_str())
+                        .or_insert_with(|| dependency(entry, &tables, &ids))
+                        .clone())
+                    .collect()
+            }).collect());
+        }
+        for (table, depends) in tables.iter_mut().zip(depends) {
+            table.depends = depends;
+        }
+        CandidateTables { tables, ids }
+    }
+
+    pub fn tables(&self) -> &[NameTable<'a>] {
+        &self.tables
+    }
+
+    /// The index of `name`'s table.
+    pub fn id(&self, name: &str) -> Option<usize> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn table(&self, name: &str) -> Option<&NameTable<'a>> {
+        self.id(name).map(|i| &self.tables[i])
+    }
+}
+
+// The candidates satisfying the `depends` entry `entry`; none if its spec doesn't parse
+fn dependency<'a>(entry: &'a str, tables: &[NameTable<'a>], ids: &HashMap<&'a str, usize>) -> Dependency<'a> {
+    let depend = Depend::parse(entry);
+    let table = ids.get(depend.name).copied();
+    let candidates = table.map(|t| &tables[t].candidates[..]).unwrap_or_default();
+    let mut mask = CandidateMask::new(candidates.len());
+    let spec = depend.version_spec();
+    for (i, (_, node)) in candidates.iter().enumerate() {
+        let version_ok = match &spec {
+            None => true,
+            Some(Ok(spec)) => spec.test_match_version(&node.record.version),
+            Some(Err(_)) => false,
+        };
+        if version_ok && depend.build_matches(node.record) {
+            mask.insert(i);
+        }
+    }
+    Dependency { name: depend.name, table, candidates: mask }
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::combine::ComboMethod;
+    use crate::graph::graph::populate_graph;
+    use crate::repodata::repodata::{read_repodata_from_slice, Repodata};
+
+    fn repodata(records: &[(&str, &str, u16, &str)]) -> Repodata {
+        let packages: Vec<String> = records.iter().map(|(name, version, build_number, depends)| format!(
+            r#""{n}-{v}-{b}.conda": {{"build": "{b}", "build_number": {b}, "depends": [{d}], "md5": "", "name": "{n}",
+                "size": 0, "version": "{v}"}}"#, n = name, v = version, b = build_number, d = depends)).collect();
+        let data = format!(r#"{{"info": {{"subdir": "linux-64"}}, "repodata_version": 1, "removed": [],
+            "packages": {{}}, "packages.conda": {{{}}}}}"#, packages.join(","));
+        read_repodata_from_slice(data.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_tables() {
+        let first = repodata(&[
+            ("app", "1.0", 0, r#""lib >=2", "__glibc >=2.17""#),
+            ("app", "2.0", 0, r#""lib >=2,<3""#),
+            ("lib", "2.0", 0, ""),
+            ("lib", "3.0", 0, ""),
+            ("lib", "3.0", 1, ""),
+        ]);
+        let second = repodata(&[("lib", "4.0", 0, ""), ("zlib", "1.3", 0, "")]);
+        let graph = populate_graph(vec![&first, &second], ComboMethod::Strict);
+        let tables = CandidateTables::new(&graph);
+        assert_eq!(tables.tables().iter().map(|t| t.name).collect::<Vec<_>>(), ["app", "lib", "zlib"]);
+
+        let lib = tables.table("lib").unwrap();
+        let order: Vec<String> = lib.candidates.iter().map(|(_, n)| n.filename.to_string()).collect();
+        assert_eq!(order, ["lib-3.0-1.conda", "lib-3.0-0.conda", "lib-2.0-0.conda", "lib-4.0-0.conda"]);
+
+        let app = tables.table("app").unwrap();
+        assert_eq!(app.candidates[0].1.filename.as_str(), "app-2.0-0.conda");
+        // lib >=2,<3
+        let dependency = &app.depends[0][0];
+        assert_eq!((dependency.name, dependency.table), ("lib", tables.id("lib")));
+        assert_eq!(dependency.candidates.iter().collect::<Vec<_>>(), [2]);
+        // lib >=2, and a virtual package that isn't in the graph
+        let depends = &app.depends[1];
+        assert_eq!(depends[0].candidates.iter().collect::<Vec<_>>(), [0, 1, 2, 3]);
+        assert_eq!(depends[0].candidates.len(), 4);
+        assert_eq!((depends[1].name, depends[1].table), ("__glibc", None));
+        assert!(depends[1].candidates.is_empty());
+        assert!(tables.table("surely-not-a-package").is_none());
+    }
+
+    #[test]
+    fn test_mask() {
+        let mut mask = CandidateMask::new(130);
+        assert!(mask.is_empty());
+        mask.insert(0);
+        mask.insert(129);
+        assert!(mask.contains(129) && !mask.contains(128) && !mask.contains(1000));
+        assert_eq!(mask.iter().collect::<Vec<_>>(), [0, 129]);
+    }
+}
@@ -0,0 +1,269 @@
+//! How central each package name is to a channel's dependency graph, for finding the packages
+//! whose breakage would reach furthest.
+//!
+//! The graph of records is first collapsed to one node per name, with an edge wherever some
+//! record of one name depends on some record of the other, and then every strongly connected
+//! component (packages depending on each other in a cycle) to a single node.  Betweenness and
+//! dominators are computed on that DAG, so every name in a cycle reports its component's values.
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use petgraph::algo::{condensation, dominators};
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::visit::{EdgeRef, IntoNodeReferences};
+use petgraph::Direction;
+
+use crate::graph::graph::GraphNode;
+
+/// The measures of one package name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Centrality<'a> {
+    pub name: &'a str,
+    /// How many other names depend directly on it (its in-degree)
+    pub dependents: usize,
+    /// The share of shortest dependency paths between other names that go through it
+    pub betweenness: f64,
+    /// How many names are only reached through it: starting from the packages nothing depends
+    ///    on, every path to them passes through this one, so they'd be orphaned without it
+    pub dominated: usize,
+}
+
+/// What `CentralityReport::ranked` sorts by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Measure {
+    Dependents,
+    Betweenness,
+    Dominated,
+}
+
+impl Centrality<'_> {
+    fn measure(&self, measure: Measure) -> f64 {
+        match measure {
+            Measure::Dependents => self.dependents as f64,
+            Measure::Betweenness => self.betweenness,
+            Measure::Dominated => self.dominated as f64,
+        }
+    }
+}
+
+/// The centrality of every name in a graph, sorted by name.
+#[derive(Clone, Debug)]
+pub struct CentralityReport<'a> {
+    pub packages: Vec<Centrality<'a>>,
+}
+
+impl<'a> CentralityReport<'a> {
+    /// Measure every name in `g`, which needs its edges (see `resolve_edges`).  Betweenness
+    ///    takes a breadth-first search from every name, so expect seconds on a full channel.
+    pub fn new(g: &DiGraph<GraphNode<'a>, i16>) -> CentralityReport<'a> {
+        let mut names: Vec<&'a str> = g.node_references().map(|(_, n)| n.record.name.as_str()).collect();
+        names.sort_unstable();
+        names.dedup();
+        let ids: HashMap<&str, usize> = names.iter().enumerate().map(|(i, n)| (*n, i)).collect();
+
+        let mut by_name: DiGraph<usize, ()> = DiGraph::with_capacity(names.len(), g.edge_count());
+        for i in 0..names.len() {
+            by_name.add_node(i);
+        }
+        for edge in g.edge_references() {
+            let from = ids[g[edge.source()].record.name.as_str()];
+            let to = ids[g[edge.target()].record.name.as_str()];
+            if from != to {
+                by_name.update_edge(NodeIndex::new(from), NodeIndex::new(to), ());
+            }
+        }
+        let dependents: Vec<usize> = by_name.node_indices()
+            .map(|i| by_name.neighbors_directed(i, Direction::Incoming).count())
+            .collect();
+
+        let dag = condensation(by_name, true);
+        let mut component = vec![0; names.len()];
+        for (c, members) in dag.node_references() {
+            for &m in members {
+                component[m] = c.index();
+            }
+        }
+        let betweenness = betweenness(&dag);
+        let dominated = dominated(&dag);
+        let packages = names.iter().enumerate().map(|(i, name)| Centrality {
+            name,
+            dependents: dependents[i],
+            betweenness: betweenness[component[i]],
+            dominated: dominated[component[i]],
+        }).collect();
+        CentralityReport { packages }
+    }
+
+    /// The packages, highest `measure` first, ties by name.
+    pub fn ranked(&self, measure: Measure) -> Vec<&Centrality<'a>> {
+        let mut ranked: Vec<&Centrality<'a>> = self.packages.iter().collect();
+        ranked.sort_by(|a, b| b.measure(measure).partial_cmp(&a.measure(measure))
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| a.name.cmp(b.name)));
+        ranked
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Centrality<'a>> {
+        self.packages.binary_search_by(|c| c.name.cmp(name)).ok().map(|i| &self.packages[i])
+    }
+}
+
+// Brandes' algorithm: for every source, count the shortest paths to each node breadth first,
+//    then hand each node's share of them back along the paths
+fn betweenness<N>(g: &DiGraph<N, ()>) -> Vec<f64> {
+    let n = g.node_count();
+    let mut centrality = vec![0.0; n];
+    for s in g.node_indices() {
+        let mut order = Vec::with_capacity(n);
+        let mut predecessors: Vec<Vec<usize>> = vec![vec![]; n];
+        let mut paths = vec![0.0; n];
+        let mut distance = vec![usize::MAX; n];
+        paths[s.index()] = 1.0;
+        distance[s.index()] = 0;
+        let mut queue = VecDeque::from(vec![s.index()]);
+        while let Some(v) = queue.pop_front() {
+            order.push(v);
+            for w in g.neighbors(NodeIndex::new(v)).map(|w| w.index()) {
+                if distance[w] == usize::MAX {
+                    distance[w] = distance[v] + 1;
+                    queue.push_back(w);
+                }
+                if distance[w] == distance[v] + 1 {
+                    paths[w] += paths[v];
+                    predecessors[w].push(v);
+                }
+            }
+        }
+        let mut share = vec![0.0; n];
+        for &w in order.iter().rev() {
+            for &v in &predecessors[w] {
+                share[v] += paths[v] / paths[w] * (1.0 + share[w]);
+            }
+            if w != s.index() {
+                centrality[w] += share[w];
+            }
+        }
+    }
+    centrality
+}
+
+// How many names each component dominates, from a root above every component that nothing
+//    depends on
+fn dominated(dag: &DiGraph<Vec<usize>, ()>) -> Vec<usize> {
+    let mut rooted = dag.map(|_, members| members.len(), |_, _| ());
+    let root = rooted.add_node(0);
+    for c in dag.node_indices() {
+        if dag.neighbors_directed(c, Direction::Incoming).next().is_none() {
+            rooted.add_edge(root, c, ());
+        }
+    }
+    let dominators = dominators::simple_fast(&rooted, root);
+    let mut dominated = vec![0; dag.node_count()];
+    for c in dag.node_indices() {
+        let mut above = dominators.immediate_dominator(c);
+        while let Some(d) = above.filter(|d| *d != root) {
+            dominated[d.index()] += rooted[c];
+            above = dominators.immediate_dominator(d);
+        }
+    }
+    // the other members of a cycle are only reached through it, too
+    for (c, members) in dag.node_references() {
+        dominated[c.index()] += members.len() - 1;
+    }
+    dominated
+}
+
+/// A table of the packages, most dependents first:
+///
+/// ```text
+/// package      dependents  dominated  betweenness
+/// python                2          0         2.17
+/// ```
+impl fmt::Display for CentralityReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self.packages.iter().map(|c| c.name.len()).max().unwrap_or(0).max("package".len());
+        write!(f, "{:<width$}  dependents  dominated  betweenness", "package", width = width)?;
+        for c in self.ranked(Measure::Dependents) {
+            write!(f, "\n{:<width$}  {:>10}  {:>9}  {:>11.2}", c.name, c.dependents, c.dominated, c.betweenness,
+                   width = width)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::combine::ComboMethod;
+    use crate::graph::graph::{populate_graph, resolve_edges};
+    use crate::repodata::repodata::{read_repodata_from_slice, Repodata};
+
+    fn repodata(records: &[(&str, &str)]) -> Repodata {
+        let packages: Vec<String> = records.iter().map(|(name, depends)| format!(
+            r#""{n}-1.0-0.conda": {{"build": "0", "build_number": 0, "depends": [{d}], "md5": "", "name": "{n}",
+                "size": 0, "version": "1.0"}}"#, n = name, d = depends)).collect();
+        let data = format!(r#"{{"info": {{"subdir": "linux-64"}}, "repodata_version": 1, "removed": [],
+            "packages": {{}}, "packages.conda": {{{}}}}}"#, packages.join(","));
+        read_repodata_from_slice(data.as_bytes()).unwrap()
+    }
+
+    fn report(repodata: &Repodata) -> CentralityReport<'_> {
+        let mut graph = populate_graph(vec![repodata], ComboMethod::Strict);
+        resolve_edges(&mut graph);
+        CentralityReport::new(&graph)
+    }
+
+    #[test]
+    fn test_chain() {
+        let repodata = repodata(&[
+            ("app", r#""requests", "numpy""#),
+            ("requests", r#""urllib3", "python""#),
+            ("urllib3", r#""openssl >=1""#),
+            ("numpy", r#""python""#),
+            ("python", r#""openssl >=1", "__glibc""#),
+            ("openssl", ""),
+            ("zlib", ""),
+        ]);
+        let report = report(&repodata);
+        let measures = |name| {
+            let c = report.get(name).unwrap();
+            (c.dependents, c.dominated, (c.betweenness * 100.0).round() / 100.0)
+        };
+        assert_eq!(measures("app"), (0, 5, 0.0));
+        assert_eq!(measures("requests"), (1, 1, 2.17));
+        assert_eq!(measures("numpy"), (1, 0, 0.83));
+        assert_eq!(measures("urllib3"), (1, 0, 0.83));
+        assert_eq!(measures("python"), (2, 0, 2.17));
+        assert_eq!(measures("openssl"), (2, 0, 0.0));
+        assert_eq!(measures("zlib"), (0, 0, 0.0));
+        assert!(report.get("__glibc").is_none());
+
+        let ranked: Vec<&str> = report.ranked(Measure::Betweenness).iter().map(|c| c.name).collect();
+        assert_eq!(ranked, ["python", "requests", "numpy", "urllib3", "app", "openssl", "zlib"]);
+        let table = report.to_string();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "package   dependents  dominated  betweenness");
+        assert_eq!(lines[1], "openssl            2          0         0.00");
+        assert_eq!(lines.len(), 8);
+    }
+
+    #[test]
+    fn test_cycle() {
+        let repodata = repodata(&[
+            ("app", r#""a""#),
+            ("a", r#""b""#),
+            ("b", r#""a", "c""#),
+            ("c", ""),
+        ]);
+        let report = report(&repodata);
+        let (a, b) = (report.get("a").unwrap(), report.get("b").unwrap());
+        assert_eq!((a.dependents, b.dependents), (2, 1));
+        // a and b are one node of the DAG, between app and c
+        assert_eq!((a.dominated, b.dominated), (2, 2));
+        assert_eq!((a.betweenness, b.betweenness), (1.0, 1.0));
+        assert_eq!(report.get("app").unwrap().dominated, 3);
+    }
+}
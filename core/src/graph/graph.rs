@@ -1,31 +1,330 @@
-use crate::{Repodata, Record};
-use petgraph::graph::DiGraph;
+use std::fmt;
+
+use crate::{Cancelled, CancellationToken, Channel, ChannelStack, FormatPolicy, MatchSpec, PackageFilename, Repodata, Record};
+use petgraph::algo::tarjan_scc;
+use petgraph::dot::{Config, Dot};
+use petgraph::graph::{DiGraph, NodeIndex};
 use petgraph::visit::IntoNodeReferences;
+use petgraph::Direction;
 
+use crate::graph::candidates::CandidateTables;
 use crate::graph::combine::ComboMethod;
 
+/// A package in the graph, with where it came from: when several channels are merged into one
+///    graph, each node still knows its channel, that channel's priority and its subdir.
+#[derive(Clone, Copy, Debug)]
+pub struct GraphNode<'a> {
+    pub record: &'a Record,
+    pub filename: &'a PackageFilename,
+    /// The channel the repodata was from, if known
+    pub channel: Option<&'a Channel>,
+    /// The index of the repodata among those the graph was built from, lower being higher
+    ///    priority
+    pub priority: usize,
+    pub subdir: &'a str,
+}
 
-pub fn extend_graph_with_repodata(g: &mut DiGraph<&Record, i16>, repodata: &Repodata) {
-    for collection in (&repodata.packages, &repodata.packages_conda) {
-        for (pkg_name, pkg_dict) in collection.iter() {
-            g.add_node(pkg_dict);
+impl GraphNode<'_> {
+    /// `channel/subdir`, e.g. `https://conda.anaconda.org/conda-forge/linux-64`; a channel that
+    ///    isn't known is named by its priority.
+    pub fn provenance(&self) -> String {
+        match self.channel {
+            Some(channel) => format!("{}/{}", channel.base_url(), self.subdir),
+            None => format!("channel {}/{}", self.priority, self.subdir),
         }
     }
 }
 
-pub fn resolve_edges(g: &mut DiGraph<&Record, i16>) {
-    for (idx, node) in g.node_references() {
-        for matchspec in node.depends.iter() {
-            // match package name and version with other packages
+/// `filename (channel/subdir)`
+impl fmt::Display for GraphNode<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.filename, self.provenance())
+    }
+}
+
+/// Add a node for every package in `repodata`, from `channel` at `priority`.  A package that is
+///    in both formats is added once, as its .conda record.
+pub fn extend_graph_with_repodata<'a>(g: &mut DiGraph<GraphNode<'a>, i16>, repodata: &'a Repodata, priority: usize,
+                                      channel: Option<&'a Channel>) {
+    // HashMap iteration order changes from run to run.  Sort by filename so that node indices
+    //    (and anything derived from them) are the same for identical input.
+    let mut records: Vec<(&PackageFilename, &Record)> = repodata.iter_records_with(FormatPolicy::PreferConda).collect();
+    records.sort_by(|a, b| a.0.cmp(b.0));
+    g.reserve_nodes(records.len());
+    for (filename, record) in records {
+        g.add_node(GraphNode { record, filename, channel, priority, subdir: &repodata.info.subdir });
+    }
+}
+
+/// Add an edge from every node to every node that satisfies one of its `depends` entries,
+///    weighted by the entry's index in `depends`.
+pub fn resolve_edges(g: &mut DiGraph<GraphNode, i16>) {
+    let edges: Vec<(NodeIndex, NodeIndex, i16)> = {
+        let tables = CandidateTables::new(g);
+        let mut edges = vec![];
+        for table in tables.tables() {
+            for ((from, _), depends) in table.candidates.iter().zip(&table.depends) {
+                for (entry, dependency) in depends.iter().enumerate() {
+                    let to = match dependency.table {
+                        Some(to) => &tables.tables()[to].candidates,
+                        None => continue,
+                    };
+                    edges.extend(dependency.candidates.iter().map(|c| (*from, to[c].0, entry as i16)));
+                }
+            }
         }
+        edges
+    };
+    g.reserve_edges(edges.len());
+    for (from, to, entry) in edges {
+        g.add_edge(from, to, entry);
     }
 }
 
-pub fn populate_graph(repodatas: Vec<&Repodata>, combo_method: ComboMethod) -> DiGraph<&Record, i16> {
-    // TODO: make nodes/edges configurable, or auto-scale based on repodata input size
-    let mut graph = DiGraph::with_capacity(50_000, 1_000_000);
-    for repodata in repodatas {
-        extend_graph_with_repodata(&graph, repodata)
+/// Which edges `neighborhood` follows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hops {
+    /// From a package to what it depends on
+    Dependencies,
+    /// From a package to what depends on it
+    Dependents,
+    Both,
+}
+
+/// The subgraph of the packages matching `spec` and of those within `depth` hops of them, with
+///    the edges between those packages.  The graph needs its edges (see `resolve_edges`).  To
+///    see what an update to openssl could affect, follow `Hops::Dependents`.
+pub fn neighborhood<'a>(g: &DiGraph<GraphNode<'a>, i16>, spec: &MatchSpec, depth: usize, hops: Hops)
+                        -> DiGraph<GraphNode<'a>, i16> {
+    let directions: &[Direction] = match hops {
+        Hops::Dependencies => &[Direction::Outgoing],
+        Hops::Dependents => &[Direction::Incoming],
+        Hops::Both => &[Direction::Outgoing, Direction::Incoming],
+    };
+    let mut within = vec![false; g.node_count()];
+    let mut frontier: Vec<NodeIndex> = g.node_references()
+        .filter(|(_, n)| spec.matches_filename(n.filename, n.record)
+            && spec.subdir.as_ref().is_none_or(|s| s == n.subdir)
+            && n.channel.map_or(spec.channel.is_none(), |c| spec.matches_channel(c)))
+        .map(|(i, _)| i)
+        .collect();
+    for _ in 0..=depth {
+        frontier.retain(|i| !std::mem::replace(&mut within[i.index()], true));
+        frontier = frontier.iter()
+            .flat_map(|&i| directions.iter().flat_map(move |&d| g.neighbors_directed(i, d)))
+            .collect();
     }
+    g.filter_map(|i, n| Some(*n).filter(|_| within[i.index()]), |_, e| Some(*e))
+}
+
+/// The nodes of package `name`, highest priority channel first.
+pub fn nodes_named<'g, 'a>(g: &'g DiGraph<GraphNode<'a>, i16>, name: &str) -> Vec<(NodeIndex, &'g GraphNode<'a>)> {
+    let mut nodes: Vec<_> = g.node_references().filter(|(_, n)| n.record.name == name).collect();
+    nodes.sort_by_key(|(_, n)| n.priority);
+    nodes
+}
+
+/// The graph of just `records`, from a repodata of `subdir`, with its edges: e.g. of the records
+///    `Repodata::dependency_closure` finds, to put them in `install_order`.
+pub fn graph_of_records<'a>(records: &[(&'a PackageFilename, &'a Record)], subdir: &'a str)
+                            -> DiGraph<GraphNode<'a>, i16> {
+    let mut graph = DiGraph::with_capacity(records.len(), records.len());
+    for &(filename, record) in records {
+        graph.add_node(GraphNode { record, filename, channel: None, priority: 0, subdir });
+    }
+    resolve_edges(&mut graph);
     graph
-}
\ No newline at end of file
+}
+
+/// The nodes in an order they could be installed in: every package after the packages it depends
+///    on.  Packages depending on each other in a cycle have no such order, and go together, by
+///    filename.  The graph needs its edges (see `resolve_edges`).
+pub fn install_order(g: &DiGraph<GraphNode, i16>) -> Vec<NodeIndex> {
+    // edges point from a package to its dependencies, and tarjan_scc gives the components
+    //    depended on first
+    tarjan_scc(g).into_iter().flat_map(|mut component| {
+        component.sort_by_key(|i| g[*i].filename);
+        component
+    }).collect()
+}
+
+/// The graph in graphviz DOT format, each node labelled with its filename without the extension.
+pub fn to_dot(g: &DiGraph<GraphNode, i16>) -> String {
+    let labelled = g.map(|_, n| n.filename.stem(), |_, e| *e);
+    format!("{}", Dot::with_config(&labelled, &[Config::EdgeNoLabel]))
+}
+
+/// Merge `repodatas`, highest priority first, into one graph.  Their channels aren't known; see
+///    `populate_graph_from_stack`.
+pub fn populate_graph(repodatas: Vec<&Repodata>, combo_method: ComboMethod) -> DiGraph<GraphNode<'_>, i16> {
+    populate_graph_cancellable(repodatas, combo_method, &CancellationToken::new()).unwrap()
+}
+
+/// Like `populate_graph`, giving up after the current channel once `token` is cancelled.
+pub fn populate_graph_cancellable<'a>(repodatas: Vec<&'a Repodata>, _combo_method: ComboMethod,
+                                      token: &CancellationToken) -> Result<DiGraph<GraphNode<'a>, i16>, Cancelled> {
+    populate(repodatas.into_iter().map(|r| (r, None)), token)
+}
+
+/// Merge the channels of `stack` into one graph, each node knowing its channel.
+pub fn populate_graph_from_stack(stack: &ChannelStack, _combo_method: ComboMethod) -> DiGraph<GraphNode<'_>, i16> {
+    let channels = stack.channels().iter().enumerate().map(|(i, r)| (r, stack.channel(i)));
+    populate(channels, &CancellationToken::new()).unwrap()
+}
+
+fn populate<'a, I>(repodatas: I, token: &CancellationToken) -> Result<DiGraph<GraphNode<'a>, i16>, Cancelled>
+    where
+        I: Iterator<Item = (&'a Repodata, Option<&'a Channel>)>,
+{
+    let repodatas: Vec<_> = repodatas.collect();
+    let (nodes, edges) = estimate_capacity(repodatas.iter().map(|(r, _)| *r));
+    let mut graph = DiGraph::with_capacity(nodes, edges);
+    for (priority, (repodata, channel)) in repodatas.into_iter().enumerate() {
+        token.check()?;
+        extend_graph_with_repodata(&mut graph, repodata, priority, channel)
+    }
+    token.check()?;
+    Ok(graph)
+}
+
+/// Room for the graph of `repodatas`: a node per package, counting one in both formats once, and
+///    an edge per `depends` entry.  A dependency can match several packages, so the edges may
+///    still grow, but by doubling from a realistic start rather than from nothing.
+pub(crate) fn estimate_capacity<'a, I: Iterator<Item = &'a Repodata>>(repodatas: I) -> (usize, usize) {
+    repodatas.flat_map(|r| r.iter_records_with(FormatPolicy::PreferConda))
+        .fold((0, 0), |(nodes, edges), (_, record)| (nodes + 1, edges + record.depends.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use super::*;
+    use crate::read_repodata;
+
+    fn node_keys(g: &DiGraph<GraphNode, i16>) -> Vec<String> {
+        g.node_references().map(|(_, n)| format!("{}-{}-{}", n.record.name, n.record.version, n.record.build)).collect()
+    }
+
+    #[test]
+    fn test_populate_graph_cancelled() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("tests/data/current_repodata.json");
+        let repodata = read_repodata(&d).unwrap();
+        let token = CancellationToken::new();
+        assert!(populate_graph_cancellable(vec![&repodata], ComboMethod::Strict, &token).is_ok());
+        token.cancel();
+        assert_eq!(populate_graph_cancellable(vec![&repodata], ComboMethod::Strict, &token).err(), Some(Cancelled));
+    }
+
+    #[test]
+    fn test_populate_graph_is_deterministic() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("tests/data/current_repodata.json");
+        // separate loads, so that each map gets its own hasher state
+        let first = read_repodata(&d).unwrap();
+        let second = read_repodata(&d).unwrap();
+        let g1 = populate_graph(vec![&first], ComboMethod::Strict);
+        let g2 = populate_graph(vec![&second], ComboMethod::Strict);
+        assert_eq!(g1.node_count(), first.iter_records_with(FormatPolicy::PreferConda).count());
+        assert_eq!(node_keys(&g1), node_keys(&g2));
+    }
+
+    #[test]
+    fn test_capacity_fits_the_repodata() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("tests/data/current_repodata.json");
+        let repodata = read_repodata(&d).unwrap();
+        let (nodes, edges) = estimate_capacity(vec![&repodata, &repodata].into_iter());
+        assert_eq!(nodes, 2 * repodata.iter_records_with(FormatPolicy::PreferConda).count());
+        assert!(edges > 0);
+        let graph = populate_graph(vec![&repodata, &repodata], ComboMethod::Strict);
+        assert_eq!(graph.node_count(), nodes);
+        assert!(graph.capacity().0 < 2 * nodes);
+        assert_eq!(estimate_capacity(std::iter::empty()), (0, 0));
+    }
+
+    fn chain() -> Repodata {
+        let record = |name: &str, depends: &str| format!(
+            r#""{n}-1.0-0.conda": {{"build": "0", "build_number": 0, "depends": [{d}], "md5": "", "name": "{n}",
+                "size": 0, "version": "1.0"}}"#, n = name, d = depends);
+        let packages = [
+            record("app", r#""requests", "numpy""#),
+            record("requests", r#""urllib3", "python""#),
+            record("urllib3", r#""openssl >=1""#),
+            record("numpy", r#""python""#),
+            record("python", r#""openssl >=1", "__glibc""#),
+            record("openssl", ""),
+            record("zlib", ""),
+        ].join(",");
+        crate::read_repodata_from_slice(format!(r#"{{"info": {{"subdir": "linux-64"}}, "repodata_version": 1,
+            "removed": [], "packages": {{}}, "packages.conda": {{{}}}}}"#, packages).as_bytes()).unwrap()
+    }
+
+    fn names<'a>(g: &DiGraph<GraphNode<'a>, i16>) -> Vec<&'a str> {
+        let mut names: Vec<&str> = g.node_references().map(|(_, n)| n.record.name.as_str()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_neighborhood() {
+        let repodata = chain();
+        let mut graph = populate_graph(vec![&repodata], ComboMethod::Strict);
+        resolve_edges(&mut graph);
+        assert_eq!(graph.edge_count(), 7);
+        let openssl = MatchSpec::parse("openssl").unwrap();
+        assert_eq!(names(&neighborhood(&graph, &openssl, 0, Hops::Dependents)), ["openssl"]);
+        let one_hop = neighborhood(&graph, &openssl, 1, Hops::Dependents);
+        assert_eq!(names(&one_hop), ["openssl", "python", "urllib3"]);
+        assert_eq!(one_hop.edge_count(), 2);
+        assert_eq!(names(&neighborhood(&graph, &openssl, 9, Hops::Dependents)),
+                   ["app", "numpy", "openssl", "python", "requests", "urllib3"]);
+        let requests = MatchSpec::parse("requests").unwrap();
+        assert_eq!(names(&neighborhood(&graph, &requests, 1, Hops::Dependencies)), ["python", "requests", "urllib3"]);
+        assert_eq!(names(&neighborhood(&graph, &requests, 1, Hops::Both)), ["app", "python", "requests", "urllib3"]);
+        assert_eq!(neighborhood(&graph, &MatchSpec::parse("openssl >=3").unwrap(), 9, Hops::Both).node_count(), 0);
+    }
+
+    #[test]
+    fn test_install_order_and_dot() {
+        let repodata = chain();
+        let mut graph = populate_graph(vec![&repodata], ComboMethod::Strict);
+        resolve_edges(&mut graph);
+        let order: Vec<&str> = install_order(&graph).into_iter().map(|i| graph[i].record.name.as_str()).collect();
+        assert_eq!(order.len(), 7);
+        let position = |name| order.iter().position(|n| *n == name).unwrap();
+        for (dependent, dependency) in [("app", "requests"), ("app", "numpy"), ("requests", "urllib3"),
+                                        ("requests", "python"), ("numpy", "python"), ("python", "openssl"),
+                                        ("urllib3", "openssl")] {
+            assert!(position(dependency) < position(dependent), "{} before {}", dependency, dependent);
+        }
+
+        let closure = repodata.dependency_closure(&["numpy"], "linux-64");
+        let dot = to_dot(&graph_of_records(&closure, "linux-64"));
+        assert!(dot.starts_with("digraph {\n"));
+        assert!(dot.contains("[label=\"numpy-1.0-0\"]"));
+        assert_eq!(dot.matches(" -> ").count(), 2);
+    }
+
+    #[test]
+    fn test_provenance() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("tests/data/current_repodata.json");
+        let stack = ChannelStack::with_channels(vec![
+            (Channel::parse("conda-forge/label/rc"), read_repodata(&d).unwrap()),
+            (Channel::parse("conda-forge"), read_repodata(&d).unwrap()),
+        ]);
+        let graph = populate_graph_from_stack(&stack, ComboMethod::Strict);
+        let nodes = nodes_named(&graph, "_libarchive_static_for_cph");
+        assert_eq!(nodes.len() % 2, 0);
+        let (first, last) = (nodes[0].1, nodes[nodes.len() - 1].1);
+        assert_eq!((first.priority, last.priority), (0, 1));
+        let subdir = &stack.channels()[0].info.subdir;
+        assert_eq!(first.provenance(), format!("https://conda.anaconda.org/conda-forge/label/rc/{}", subdir));
+        assert_eq!(last.to_string(), format!("{} (https://conda.anaconda.org/conda-forge/{})", last.filename, subdir));
+
+        let unknown = populate_graph(vec![&stack.channels()[0]], ComboMethod::Strict);
+        let (_, node) = nodes_named(&unknown, "_libarchive_static_for_cph")[0];
+        assert_eq!(node.provenance(), format!("channel 0/{}", subdir));
+    }
+}
@@ -1,2 +1,4 @@
+pub mod candidates;
+pub mod centrality;
 pub mod graph;
 pub mod combine;
\ No newline at end of file
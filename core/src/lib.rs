@@ -2,6 +2,19 @@
 //!
 //! ## Features
 //!
+//! Version parsing, comparison and spec matching are always there.  Everything else that needs
+//! more dependencies is a cargo feature: `regex` (regex version specs) and `graph` (dependency
+//! graphs, via petgraph) are on by default; `fetch`, `package`, `msgpack`, `cbor`, `schema`,
+//! `simd` and `chrono` are opt-in, as is `test-support`, which exports the proptest strategies
+//! in `test_support`.
+//!
+//! ## Thread safety
+//!
+//! Everything libronda parses (versions, specs, repodata, installed records) and the graphs built
+//! from it are `Send + Sync`, so a channel parsed once can be shared by worker threads behind an
+//! `Arc`.  The only shared mutable state is the string intern pool, behind a mutex.  This is
+//! checked at compile time below.
+//!
 //! ## Examples
 //!
 
@@ -16,17 +29,125 @@ extern crate enum_dispatch;
 #[macro_use]
 extern crate rstest;
 
+mod cancel;
+mod conda_lock;
+pub mod conformance;
+mod error;
+mod fetch;
+#[cfg(feature = "graph")]
+mod graph;
+mod matchspec;
+mod package;
+mod prefix;
+mod pypi;
 mod repodata;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
 mod version;
-// mod graph;
 // mod resolve;
 
 // Reexports
-pub use crate::repodata::repodata::{read_repodata, Record, Repodata, RepodataInfo};
+pub use crate::cancel::cancel::{Cancelled, CancellationToken};
+pub use crate::conda_lock::conda_lock::{
+    read_conda_lock, CondaLock, CondaLockError, LockManager, LockedHash, LockedPackage,
+};
+pub use crate::error::error::RondaError;
+pub use crate::fetch::channel::{Channel, Credentials, DEFAULT_CHANNEL_ALIAS};
+#[cfg(feature = "fetch")]
+pub use crate::fetch::cache_state::{cache_file_stem, CacheState, CacheStateError, CheckedFlag};
+#[cfg(feature = "fetch")]
+pub use crate::fetch::shards::{DecompressionStats, ShardDecoder};
+#[cfg(feature = "fetch")]
+pub use crate::fetch::fetch::{
+    fastest_sources_first, fetch_bytes, fetch_bytes_cancellable, fetch_repodata, FetchClient, FetchError,
+};
+#[cfg(feature = "graph")]
+pub use crate::graph::candidates::{CandidateMask, CandidateTables, Dependency, NameTable};
+#[cfg(feature = "graph")]
+pub use crate::graph::centrality::{Centrality, CentralityReport, Measure};
+#[cfg(feature = "graph")]
+pub use crate::graph::combine::ComboMethod;
+#[cfg(feature = "graph")]
+pub use crate::graph::graph::{
+    install_order, neighborhood, nodes_named, populate_graph, populate_graph_cancellable, populate_graph_from_stack,
+    graph_of_records, resolve_edges, to_dot, GraphNode, Hops,
+};
+pub use crate::matchspec::matchspec::{split_namespace, MatchSpec, MatchSpecError};
+pub use crate::matchspec::spec_set::{SpecConflict, SpecSet};
+pub use crate::package::metadata::{
+    read_about_json, read_index_json, read_link_json, read_menu_json, AboutJson, EntryPoint, IndexJson, LicenseFile, LinkJson,
+    MenuJson, MenuShortcut, MetadataError, NoarchLinks,
+};
+#[cfg(feature = "package")]
+pub use crate::package::package::{extract_conda, extract_conda_all, extract_conda_from_reader, ExtractError};
+#[cfg(feature = "package")]
+pub use crate::package::verify::{verify_all, CacheReport, VerifyError};
+pub use crate::package::prefix_replace::{
+    replace_prefix_binary, replace_prefix_in_file, replace_prefix_text, FileMode, PrefixReplaceError,
+};
+pub use crate::package::run_exports::{read_run_exports, run_exports_to_inject, IgnoreRunExports, InjectedRunExports, RunExports};
+#[cfg(feature = "graph")]
+pub use crate::prefix::analysis::{installed_graph, leaves, orphans};
+#[cfg(feature = "graph")]
+pub use crate::prefix::export::{export_environment, export_explicit, EnvironmentExport, ExportError, ExportOptions};
+pub use crate::prefix::history::{read_history, History, Revision};
+pub use crate::prefix::prefix::{
+    read_prefix, read_prefix_record, write_prefix_record, Link, LinkType, PrefixReadError, PrefixRecord, PrefixWriteError,
+};
+pub use crate::prefix::transaction::{Change, Dist, Transaction, TransactionError};
+pub use crate::pypi::pypi::{read_pypi_json, PypiDigests, PypiFile, PypiProject, PypiRelease};
+pub use crate::repodata::channel_stack::ChannelStack;
+pub use crate::repodata::depends::Depend;
+pub use crate::repodata::explain::{Candidate, Reason, Selection};
+pub use crate::repodata::filename::{PackageFilename, PackageFilenameError, PackageFormat};
+pub use crate::repodata::intern::InternedStr;
+pub use crate::repodata::loader::RepodataLoader;
+pub use crate::repodata::platform::Platform;
+pub use crate::repodata::python::PythonWindow;
+pub use crate::repodata::repodata::{
+    read_repodata, read_repodata_from_reader, read_repodata_from_slice, read_repodata_from_slice_cancellable,
+    FormatPolicy, PackageHasher, PackageMap, PickCriterion, Record, Repodata, RepodataInfo, RepodataReadError,
+};
+#[cfg(feature = "schema")]
+pub use crate::repodata::repodata::repodata_json_schema;
+#[cfg(feature = "simd")]
+pub use crate::repodata::repodata::read_repodata_simd;
+pub use crate::repodata::select::{FilterError, RecordFilter};
 pub use crate::version::conda_parser;
+pub use crate::version::pep440_parser;
+pub use crate::version::spec_program::CompiledSpec;
 pub use crate::version::spec_trees::{
-    treeify, untreeify, Combinator, ConstraintTree, VersionSpecOrConstraintTree,
+    simplify, treeify, untreeify, Combinator, ConstraintTree, Spec, VersionSpec, VersionSpecOrConstraintTree,
 };
 pub use crate::version::CompOp;
 pub use crate::version::Version;
+pub use crate::version::VersionDelta;
+pub use crate::version::VersionMode;
 pub use crate::version::VersionPart;
+pub use crate::version::errors::VersionParsingError;
+
+// Compile time check of the thread safety promised above
+#[allow(dead_code)]
+fn assert_send_sync() {
+    fn check<T: Send + Sync>() {}
+    check::<Version>();
+    check::<VersionSpec>();
+    check::<VersionSpecOrConstraintTree>();
+    check::<ConstraintTree>();
+    check::<CompiledSpec>();
+    check::<crate::version::matching::MatchEnum>();
+    check::<MatchSpec>();
+    check::<Record>();
+    check::<Repodata>();
+    check::<PackageFilename>();
+    check::<InternedStr>();
+    check::<ChannelStack>();
+    check::<PrefixRecord>();
+    check::<Channel>();
+    check::<CancellationToken>();
+    #[cfg(feature = "graph")]
+    check::<petgraph::graph::DiGraph<GraphNode, i16>>();
+    #[cfg(feature = "graph")]
+    check::<petgraph::graph::DiGraph<&PrefixRecord, ()>>();
+    check::<RondaError>();
+}
@@ -0,0 +1,650 @@
+//! Match specs: conda's query language for package records.
+//!
+//! A spec names a package and optionally narrows down which of its records are acceptable:
+//!
+//! ```text
+//! [channel[/subdir]::][namespace:]name[ version[ build]][[key=value, ...]]
+//! ```
+//!
+//! e.g. `numpy >=1.20`, `conda-forge/linux-64::python 3.9.* *_cpython` or
+//! `pandas[version='>=1.2,<2', build_number=0]`.  Version and build may also be attached with `=`
+//! (`numpy=1.20=py39_0`); a lone `=1.20` means `1.20.*`, as in conda.
+//!
+//! Namespaces (`python:numpy`) are planned for conda but not yet used by any channel.  A package
+//! name without one is in the global namespace, and a namespaced name is a different package:
+//! `numpy` doesn't match a `python:numpy` record.
+
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::fetch::channel::Channel;
+use crate::repodata::filename::PackageFilename;
+use crate::repodata::platform::Platform;
+use crate::repodata::repodata::Record;
+use crate::version::errors::VersionParsingError;
+use crate::{CompOp, Spec, VersionSpecOrConstraintTree};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MatchSpecError {
+    /// A spec that doesn't fit the syntax at all
+    Invalid(String),
+    /// A `[key=value]` entry with a key that isn't understood
+    UnknownKey(String),
+    Version(VersionParsingError),
+}
+
+impl fmt::Display for MatchSpecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MatchSpecError::Invalid(s) => write!(f, "invalid match spec '{}'", s),
+            MatchSpecError::UnknownKey(k) => write!(f, "unknown match spec key '{}'", k),
+            MatchSpecError::Version(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for MatchSpecError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MatchSpecError::Version(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<VersionParsingError> for MatchSpecError {
+    fn from(e: VersionParsingError) -> Self {
+        MatchSpecError::Version(e)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MatchSpec {
+    pub channel: Option<String>,
+    pub subdir: Option<String>,
+    /// `python` in `python:numpy`; `None` for the global namespace
+    pub namespace: Option<String>,
+    /// The package name without its namespace, or `*` for any package
+    pub name: String,
+    pub version: Option<VersionSpecOrConstraintTree>,
+    /// A build string, which may contain `*` wildcards
+    pub build: Option<String>,
+    pub build_number: Option<(CompOp, u16)>,
+    /// The package filename (`fn=`)
+    pub filename: Option<String>,
+    pub md5: Option<String>,
+    pub sha256: Option<String>,
+}
+
+impl MatchSpec {
+    /// Parse a spec.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ronda::MatchSpec;
+    ///
+    /// let spec = MatchSpec::parse("conda-forge/linux-64::numpy >=1.20 py39*").unwrap();
+    /// assert_eq!(spec.name, "numpy");
+    /// assert_eq!(spec.channel.as_deref(), Some("conda-forge"));
+    /// assert_eq!(spec.subdir.as_deref(), Some("linux-64"));
+    /// assert_eq!(spec.build.as_deref(), Some("py39*"));
+    /// assert!(MatchSpec::parse("numpy[colour=blue]").is_err());
+    /// ```
+    pub fn parse(spec: &str) -> Result<MatchSpec, MatchSpecError> {
+        let invalid = || MatchSpecError::Invalid(spec.to_string());
+        let mut s = spec.split('#').next().unwrap_or("").trim();
+
+        let mut brackets = vec![];
+        if let Some(inner) = s.strip_suffix(']') {
+            let open = inner.find('[').ok_or_else(invalid)?;
+            brackets = parse_brackets(&inner[open + 1..]).ok_or_else(invalid)?;
+            s = inner[..open].trim_end();
+        }
+
+        let mut channel = None;
+        let mut subdir = None;
+        if let Some((channel_part, rest)) = s.rsplit_once("::") {
+            let (c, sd) = split_channel(channel_part);
+            channel = Some(c.to_string());
+            subdir = sd.map(str::to_string);
+            s = rest.trim();
+        }
+
+        let name_end = s.find(|c: char| c.is_whitespace() || "=<>!~".contains(c)).unwrap_or(s.len());
+        let (namespace, name) = split_namespace(&s[..name_end]);
+        let valid = |part: &str, wildcard: &str| {
+            !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c) || wildcard.contains(c))
+        };
+        if !valid(name, "*") || !namespace.is_none_or(|ns| valid(ns, "")) {
+            return Err(invalid());
+        }
+        let (version, build) = split_version_build(s[name_end..].trim()).ok_or_else(invalid)?;
+
+        let mut spec_out = MatchSpec {
+            channel,
+            subdir,
+            namespace: namespace.map(str::to_string),
+            name: name.to_string(),
+            version: version.map(|v| VersionSpecOrConstraintTree::try_from(v.as_str())).transpose()?,
+            build: build.map(str::to_string),
+            build_number: None,
+            filename: None,
+            md5: None,
+            sha256: None,
+        };
+        for (key, value) in brackets {
+            match key.as_str() {
+                "version" => spec_out.version = Some(VersionSpecOrConstraintTree::try_from(value.as_str())?),
+                "build" => spec_out.build = Some(value),
+                "build_number" => spec_out.build_number = Some(parse_build_number(&value).ok_or_else(invalid)?),
+                "channel" => spec_out.channel = Some(value),
+                "subdir" => spec_out.subdir = Some(value),
+                "fn" => spec_out.filename = Some(value),
+                "md5" => spec_out.md5 = Some(value),
+                "sha256" => spec_out.sha256 = Some(value),
+                _ => return Err(MatchSpecError::UnknownKey(key)),
+            }
+        }
+        Ok(spec_out)
+    }
+
+    /// The spec that pins exactly `record`: its name, `==` its version and its build, e.g.
+    ///    `numpy==1.20.1=py39h34a8a5c_2`.  Add the channel and hashes with `with_channel` and
+    ///    `with_hashes`, e.g. when freezing an environment.
+    pub fn from_record(record: &Record) -> MatchSpec {
+        let version = format!("=={}", record.version.as_str());
+        let (namespace, name) = split_namespace(&record.name);
+        MatchSpec {
+            channel: None,
+            subdir: None,
+            namespace: namespace.map(str::to_string),
+            name: name.to_string(),
+            // an exact version is always a valid spec
+            version: VersionSpecOrConstraintTree::try_from(version.as_str()).ok(),
+            build: Some(record.build.to_string()),
+            build_number: None,
+            filename: None,
+            md5: None,
+            sha256: None,
+        }
+    }
+
+    /// Restrict the spec to `channel`, and to `subdir` if given.
+    pub fn with_channel(mut self, channel: &str, subdir: Option<&str>) -> MatchSpec {
+        self.channel = Some(channel.to_string());
+        self.subdir = subdir.map(str::to_string);
+        self
+    }
+
+    /// Require the md5 and sha256 of `record`, those of them it has.
+    pub fn with_hashes(mut self, record: &Record) -> MatchSpec {
+        self.md5 = Some(record.md5.clone()).filter(|m| !m.is_empty());
+        self.sha256 = Some(record.sha256.clone()).filter(|h| !h.is_empty());
+        self
+    }
+
+    /// The package name as records have it, with the namespace if there is one: `python:numpy`.
+    pub fn qualified_name(&self) -> Cow<'_, str> {
+        match &self.namespace {
+            Some(namespace) => Cow::Owned(format!("{}:{}", namespace, self.name)),
+            None => Cow::Borrowed(&self.name),
+        }
+    }
+
+    /// Whether a package called `name` (`python:numpy` if namespaced) is one this spec names: the
+    ///    namespaces must be the same, and the names too unless the spec's is `*`.
+    pub fn matches_name(&self, name: &str) -> bool {
+        let (namespace, name) = split_namespace(name);
+        self.namespace.as_deref() == namespace && (self.name == "*" || name == self.name)
+    }
+
+    /// Whether `record` satisfies the name, version, build, build number and hashes of this spec.
+    ///    A record doesn't know its channel, subdir or filename, so those aren't checked; see
+    ///    `matches_filename`.
+    pub fn matches(&self, record: &Record) -> bool {
+        self.matches_name(&record.name)
+            && self.version.as_ref().is_none_or(|v| v.test_match_version(&record.version))
+            && self.build.as_deref().is_none_or(|b| glob_match(b, &record.build))
+            && self.build_number.is_none_or(|(op, n)| match op {
+                CompOp::Ne => record.build_number != n,
+                CompOp::Lt => record.build_number < n,
+                CompOp::Le => record.build_number <= n,
+                CompOp::Ge => record.build_number >= n,
+                CompOp::Gt => record.build_number > n,
+                _ => record.build_number == n,
+            })
+            && self.md5.as_deref().is_none_or(|m| record.md5 == m)
+            && self.sha256.as_deref().is_none_or(|h| record.sha256 == h)
+    }
+
+    /// Whether the spec allows packages from `channel`: any channel if it doesn't name one, and
+    ///    otherwise only that channel.  Labels are channels of their own, so `conda-forge::numpy`
+    ///    doesn't take packages from `conda-forge/label/rc`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ronda::{Channel, MatchSpec};
+    ///
+    /// let rc = Channel::parse("conda-forge/label/rc");
+    /// assert!(MatchSpec::parse("conda-forge/label/rc::numpy").unwrap().matches_channel(&rc));
+    /// assert!(!MatchSpec::parse("conda-forge::numpy").unwrap().matches_channel(&rc));
+    /// assert!(MatchSpec::parse("numpy").unwrap().matches_channel(&rc));
+    /// ```
+    pub fn matches_channel(&self, channel: &Channel) -> bool {
+        self.channel.as_deref().is_none_or(|c| Channel::parse(c).base_url() == channel.base_url())
+    }
+
+    /// Like `matches`, also checking `fn=` against the record's filename.
+    pub fn matches_filename(&self, filename: &PackageFilename, record: &Record) -> bool {
+        self.filename.as_deref().is_none_or(|f| filename == f) && self.matches(record)
+    }
+}
+
+/// The canonical form: `channel/subdir::name`, then the version and build inline where they
+///    read back unchanged (`numpy>=1.20`, `numpy=1.20`, `numpy 1.20.* py39_0`,
+///    `numpy==1.20.1=py39_0`), and everything
+///    else in brackets in conda's key order, quoted where needed.  Parsing the output gives an
+///    equal spec.
+impl fmt::Display for MatchSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut brackets: Vec<(&str, String)> = vec![];
+        // a subdir only goes before the name along with a channel, and a channel that looks like
+        //    it ends in one has to go in the brackets
+        let subdir_inline = self.channel.as_ref().is_some_and(|c| !c.contains("::"))
+            && self.subdir.as_ref().is_some_and(|s| s.parse::<Platform>().is_ok());
+        if let Some(channel) = &self.channel {
+            if channel.contains("::") || (!subdir_inline && split_channel(channel).1.is_some()) {
+                brackets.push(("channel", channel.clone()));
+            } else {
+                match self.subdir.as_ref().filter(|_| subdir_inline) {
+                    Some(subdir) => write!(f, "{}/{}::", channel, subdir)?,
+                    None => write!(f, "{}::", channel)?,
+                }
+            }
+        }
+        if let Some(namespace) = &self.namespace {
+            write!(f, "{}:", namespace)?;
+        }
+        f.write_str(&self.name)?;
+
+        let version = self.version.as_ref().map(|v| v.get_spec());
+        // how the build can follow the version, if it can
+        let mut build_separator = None;
+        match version.as_deref() {
+            None => {}
+            Some(v) if v.contains(|c: char| c.is_whitespace() || "=,|()[]'\"#".contains(c))
+                && !is_inline_operator_spec(v) => brackets.push(("version", v.to_string())),
+            Some(v) if is_inline_operator_spec(v) => {
+                write!(f, "{}", v)?;
+                // an exact pin takes its build as conda writes it, `numpy==1.20.1=py39_0`
+                if v.starts_with("==") {
+                    build_separator = Some("=");
+                }
+            }
+            // `=1.20` reads back as `1.20*`
+            Some(v) if v.len() > 1 && v.ends_with('*') && !v.ends_with(".*") && !v[..v.len() - 1].contains('*')
+                && !v.starts_with(|c: char| "<>!~".contains(c)) && self.build.is_none() => write!(f, "={}", &v[..v.len() - 1])?,
+            Some(v) => {
+                write!(f, " {}", v)?;
+                build_separator = Some(" ");
+            }
+        }
+        if let Some(build) = &self.build {
+            let plain = !build.contains(|c: char| c.is_whitespace() || "=<>!~,|[]'\"#".contains(c));
+            match build_separator.filter(|_| plain) {
+                Some(separator) => write!(f, "{}{}", separator, build)?,
+                None => brackets.push(("build", build.clone())),
+            }
+        }
+        if let Some((op, n)) = self.build_number {
+            brackets.push(("build_number", match op {
+                CompOp::Eq => n.to_string(),
+                op => format!("{}{}", op.sign(), n),
+            }));
+        }
+        if let Some(md5) = &self.md5 {
+            brackets.push(("md5", md5.clone()));
+        }
+        if let Some(sha256) = &self.sha256 {
+            brackets.push(("sha256", sha256.clone()));
+        }
+        if let Some(filename) = &self.filename {
+            brackets.push(("fn", filename.clone()));
+        }
+        if let Some(subdir) = self.subdir.as_ref().filter(|_| !subdir_inline) {
+            brackets.push(("subdir", subdir.clone()));
+        }
+        if brackets.is_empty() {
+            return Ok(());
+        }
+        let brackets: Vec<String> = brackets.into_iter().map(|(key, value)| {
+            if !value.contains(|c: char| c.is_whitespace() || "=,[]'\"".contains(c)) {
+                format!("{}={}", key, value)
+            } else if value.contains('\'') {
+                format!("{}=\"{}\"", key, value)
+            } else {
+                format!("{}='{}'", key, value)
+            }
+        }).collect();
+        write!(f, "[{}]", brackets.join(", "))
+    }
+}
+
+/// Whether version spec `v` is a single operator spec such as `>=1.20` or `==1.2`, which can
+///    follow the name directly.  A lone `=` is left out: after the name it means a prefix match.
+fn is_inline_operator_spec(v: &str) -> bool {
+    let op_end = v.find(|c: char| !"=<>!~".contains(c)).unwrap_or(v.len());
+    let op = &v[..op_end];
+    !op.is_empty() && op != "=" && op_end < v.len()
+        && !v[op_end..].contains(|c: char| c.is_whitespace() || "=<>!~,|()[]'\"#".contains(c))
+}
+
+impl FromStr for MatchSpec {
+    type Err = MatchSpecError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        MatchSpec::parse(s)
+    }
+}
+
+impl TryFrom<&str> for MatchSpec {
+    type Error = MatchSpecError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        MatchSpec::parse(s)
+    }
+}
+
+/// Split a package name into its namespace, if it has one, and the name within it:
+///    `python:numpy` is `(Some("python"), "numpy")`.
+pub fn split_namespace(name: &str) -> (Option<&str>, &str) {
+    match name.split_once(':') {
+        Some((namespace, name)) => (Some(namespace), name),
+        None => (None, name),
+    }
+}
+
+/// Split `conda-forge/linux-64` into channel and subdir; the subdir is only split off if it is one
+///    conda knows.
+fn split_channel(channel: &str) -> (&str, Option<&str>) {
+    match channel.rsplit_once('/') {
+        Some((c, subdir)) if subdir.parse::<Platform>().is_ok() => (c, Some(subdir)),
+        _ => (channel, None),
+    }
+}
+
+/// Split what follows the name into a version spec and a build, translating conda's `=` forms:
+///    `=1.2` is `1.2*`, and `=1.2=build` or `1.2=build` is version `1.2` with that build.  `None`
+///    if it can't be split.
+fn split_version_build(rest: &str) -> Option<(Option<String>, Option<&str>)> {
+    if rest.is_empty() {
+        return Some((None, None));
+    }
+    let mut fields = rest.split_whitespace();
+    let (version, build) = match (fields.next(), fields.next(), fields.next()) {
+        (Some(version), build, None) if build.is_some() => (version, build),
+        (Some(joined), None, None) => {
+            // the build is attached with the last '=' that isn't part of an operator
+            let bytes = joined.as_bytes();
+            let split = joined.rfind('=').filter(|&i| {
+                i > 0 && !b"=!<>~,|".contains(&bytes[i - 1])
+                    && i + 1 < joined.len() && !joined[i + 1..].contains(|c: char| "=,|<>~".contains(c))
+            });
+            match split {
+                Some(i) => (&joined[..i], Some(&joined[i + 1..])),
+                None => (joined, None),
+            }
+        }
+        _ => return None,
+    };
+    if version == "=" || version == "==" {
+        return None;
+    }
+    let version = match version.strip_prefix('=') {
+        Some(exact) if exact.starts_with('=') => version.to_string(),
+        Some(prefix) if !prefix.contains(|c: char| "=,|".contains(c)) => {
+            if build.is_none() && !prefix.ends_with('*') {
+                format!("{}*", prefix)
+            } else {
+                prefix.to_string()
+            }
+        }
+        _ => version.to_string(),
+    };
+    Some((Some(version), build))
+}
+
+/// The `key=value` pairs of a bracket, e.g. `version='>=1.2,<2', build=py_0`.  Values may be
+///    quoted with `'` or `"`, and must be if they contain a comma.
+fn parse_brackets(inner: &str) -> Option<Vec<(String, String)>> {
+    let mut pairs = vec![];
+    let mut rest = inner.trim();
+    while !rest.is_empty() {
+        let (key, after) = rest.split_once('=')?;
+        let after = after.trim_start();
+        let (value, after) = match after.chars().next() {
+            Some(q) if q == '\'' || q == '"' => {
+                let end = after[1..].find(q)? + 1;
+                (&after[1..end], &after[end + 1..])
+            }
+            _ => {
+                let end = after.find(',').unwrap_or(after.len());
+                (after[..end].trim_end(), &after[end..])
+            }
+        };
+        pairs.push((key.trim().to_string(), value.to_string()));
+        rest = after.trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+    }
+    Some(pairs)
+}
+
+/// `3`, `=3` or an operator and a number, e.g. `>=3`.
+fn parse_build_number(value: &str) -> Option<(CompOp, u16)> {
+    let value = value.trim();
+    let digits = value.find(|c: char| c.is_ascii_digit())?;
+    let op = match &value[..digits] {
+        "" | "=" | "==" => CompOp::Eq,
+        sign => match CompOp::from_sign(sign).ok()? {
+            op @ (CompOp::Ne | CompOp::Lt | CompOp::Le | CompOp::Ge | CompOp::Gt) => op,
+            _ => return None,
+        },
+    };
+    Some((op, value[digits..].parse().ok()?))
+}
+
+/// Match `text` against `pattern`, in which `*` stands for any run of characters.
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let mut rest = match text.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let last = match parts.pop() {
+        Some(last) => last,
+        // no '*' at all
+        None => return rest.is_empty(),
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use crate::test_support::strategies::match_spec;
+
+    fn record(name: &str, version: &str, build: &str, build_number: u16) -> Record {
+        serde_json::from_value(serde_json::json!({
+            "name": name, "version": version, "build": build, "build_number": build_number,
+            "depends": [], "md5": "abc", "size": 0,
+        })).unwrap()
+    }
+
+    #[rstest(
+        spec, version, build,
+        case("numpy", None, None),
+        case("numpy >=1.20", Some(">=1.20"), None),
+        case("numpy 1.20.* py39_0", Some("1.20.*"), Some("py39_0")),
+        case("numpy=1.20", Some("1.20*"), None),
+        case("numpy=1.20=py39_0", Some("1.20"), Some("py39_0")),
+        case("numpy==1.20", Some("==1.20"), None),
+        case("numpy>=1.20,<2", Some(">=1.20,<2"), None),
+        case("numpy[version='>=1.20,<2', build=py*]", Some(">=1.20,<2"), Some("py*"))
+    )]
+    fn test_parse(spec: &str, version: Option<&str>, build: Option<&str>) {
+        let parsed = MatchSpec::parse(spec).unwrap();
+        assert_eq!(parsed.name, "numpy");
+        let expected = version.map(|v| VersionSpecOrConstraintTree::try_from(v).unwrap());
+        assert_eq!(parsed.version, expected);
+        assert_eq!(parsed.build.as_deref(), build);
+    }
+
+    #[rstest(
+        spec,
+        case(""),
+        case(">=1.20"),
+        case("numpy=="),
+        case("numpy 1.20 py39_0 extra"),
+        case(":numpy"),
+        case("python:"),
+        case("py*:numpy"),
+        case("numpy[version='>=1.20'"),
+        case("numpy[build_number=x]"),
+        case("numpy[colour=blue]")
+    )]
+    fn test_parse_errors(spec: &str) {
+        assert!(MatchSpec::parse(spec).is_err());
+    }
+
+    #[test]
+    fn test_channel_and_keys() {
+        let spec = MatchSpec::parse("conda-forge/label/rc::numpy[build_number='>=2', fn=x.conda, md5=abc]").unwrap();
+        assert_eq!(spec.channel.as_deref(), Some("conda-forge/label/rc"));
+        assert_eq!(spec.subdir, None);
+        assert_eq!(spec.build_number, Some((CompOp::Ge, 2)));
+        assert_eq!(spec.filename.as_deref(), Some("x.conda"));
+        assert_eq!(spec.md5.as_deref(), Some("abc"));
+    }
+
+    #[rstest(
+        spec, channel, matches,
+        case("conda-forge/label/rc/linux-64::numpy", "conda-forge/label/rc", true),
+        case("numpy[channel=conda-forge/label/rc]", "https://conda.anaconda.org/conda-forge/label/rc/", true),
+        case("https://conda.anaconda.org/conda-forge/label/rc::numpy", "conda-forge/label/rc", true),
+        case("conda-forge/label/main::numpy", "conda-forge", true),
+        case("conda-forge/label/rc::numpy", "conda-forge", false),
+        case("conda-forge/label/rc::numpy", "conda-forge/label/beta", false),
+        case("conda-forge::numpy", "conda-forge/label/rc", false)
+    )]
+    fn test_channel_labels(spec: &str, channel: &str, matches: bool) {
+        let parsed = MatchSpec::parse(spec).unwrap();
+        assert_eq!(parsed.matches_channel(&Channel::parse(channel)), matches);
+        assert_eq!(MatchSpec::parse(&parsed.to_string()).unwrap(), parsed);
+    }
+
+    #[test]
+    fn test_matches() {
+        let r = record("numpy", "1.20.1", "py39h34a8a5c_2", 2);
+        let matches = |spec: &str| MatchSpec::parse(spec).unwrap().matches(&r);
+        assert!(matches("numpy"));
+        assert!(matches("*[version='1.20.*']"));
+        assert!(matches("numpy=1.20"));
+        assert!(matches("numpy >=1.20 py39*_2"));
+        assert!(matches("numpy[build_number='>1', md5=abc]"));
+        assert!(!matches("numpy=1.2"));
+        assert!(!matches("numpy 1.20.1 py38*"));
+        assert!(!matches("numpy[build_number=3]"));
+        assert!(!matches("scipy"));
+    }
+
+    #[rstest(
+        spec, expected,
+        case("numpy", "numpy"),
+        case("numpy >=1.20", "numpy>=1.20"),
+        case("numpy=1.20", "numpy=1.20"),
+        case("numpy 1.20.* py39_0", "numpy 1.20.* py39_0"),
+        case("numpy=1.20=py39_0", "numpy 1.20 py39_0"),
+        case("numpy>=1.20,<2", "numpy[version='>=1.20,<2']"),
+        case("numpy >=1.20 py*", "numpy>=1.20[build=py*]"),
+        case("conda-forge/linux-64::numpy[md5=abc, build_number='>=2']", "conda-forge/linux-64::numpy[build_number='>=2', md5=abc]"),
+        case("numpy[subdir=linux-64, fn=numpy-1.20-0.conda]", "numpy[fn=numpy-1.20-0.conda, subdir=linux-64]"),
+        case("*[version='1.20.*']", "* 1.20.*"),
+        case("numpy==1.20.1=py39_0", "numpy==1.20.1=py39_0"),
+        case("conda-forge::python:numpy >=1.20", "conda-forge::python:numpy>=1.20"),
+        case("numpy[version='==1.20.1', build='py39*']", "numpy==1.20.1=py39*")
+    )]
+    fn test_display(spec: &str, expected: &str) {
+        let parsed = MatchSpec::parse(spec).unwrap();
+        assert_eq!(parsed.to_string(), expected);
+        assert_eq!(MatchSpec::parse(expected).unwrap(), parsed);
+    }
+
+    proptest! {
+        #[test]
+        fn test_display_round_trip(spec in match_spec()) {
+            let displayed = spec.to_string();
+            let parsed = MatchSpec::parse(&displayed).unwrap();
+            prop_assert_eq!(&parsed, &spec, "{}", displayed);
+            prop_assert_eq!(parsed.to_string(), displayed);
+        }
+    }
+
+    #[test]
+    fn test_namespaces() {
+        let spec = MatchSpec::parse("conda-forge::python:numpy >=1.20").unwrap();
+        assert_eq!((spec.namespace.as_deref(), spec.name.as_str()), (Some("python"), "numpy"));
+        assert_eq!(MatchSpec::parse("numpy").unwrap().namespace, None);
+        let namespaced = record("python:numpy", "1.20.1", "py39_0", 0);
+        let global = record("numpy", "1.20.1", "py39_0", 0);
+        assert!(spec.matches(&namespaced) && !spec.matches(&global));
+        let spec = MatchSpec::parse("numpy").unwrap();
+        assert!(!spec.matches(&namespaced) && spec.matches(&global));
+        assert!(MatchSpec::parse("python:*").unwrap().matches(&namespaced));
+        assert_eq!(MatchSpec::from_record(&namespaced).to_string(), "python:numpy==1.20.1=py39_0");
+        assert_eq!(split_namespace("python:numpy"), (Some("python"), "numpy"));
+    }
+
+    #[test]
+    fn test_from_record() {
+        let r = record("numpy", "1.20.1", "py39h34a8a5c_2", 2);
+        let pin = MatchSpec::from_record(&r);
+        assert_eq!(pin.to_string(), "numpy==1.20.1=py39h34a8a5c_2");
+        assert!(pin.matches(&r));
+        assert!(!pin.matches(&record("numpy", "1.20.10", "py39h34a8a5c_2", 2)));
+        assert!(!pin.matches(&record("numpy", "1.20.1", "py39h34a8a5c_3", 3)));
+
+        let full = MatchSpec::from_record(&r).with_channel("conda-forge", Some("linux-64")).with_hashes(&r);
+        assert_eq!(full.to_string(), "conda-forge/linux-64::numpy==1.20.1=py39h34a8a5c_2[md5=abc]");
+        assert_eq!(MatchSpec::parse(&full.to_string()).unwrap(), full);
+    }
+
+    #[rstest(
+        pattern, text, expected,
+        case("py39_0", "py39_0", true),
+        case("py39_0", "py39_01", false),
+        case("py*", "py39_0", true),
+        case("*_0", "py39_0", true),
+        case("py*h*_0", "py39habc_0", true),
+        case("py*_0*", "py39_1", false),
+        case("*", "", true),
+        case("a*a", "a", false)
+    )]
+    fn test_glob_match(pattern: &str, text: &str, expected: bool) {
+        assert_eq!(glob_match(pattern, text), expected);
+    }
+}
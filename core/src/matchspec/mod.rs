@@ -0,0 +1,2 @@
+pub mod matchspec;
+pub mod spec_set;
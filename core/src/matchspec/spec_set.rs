@@ -0,0 +1,186 @@
+//! A set of match specs with at most one per package: what a solve starts from once the specs
+//! it was given have been normalized.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::matchspec::matchspec::{split_namespace, MatchSpec};
+use crate::{Spec, Version, VersionSpecOrConstraintTree};
+
+/// Two specs for the same package that no record can satisfy together.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpecConflict {
+    pub existing: Box<MatchSpec>,
+    pub added: Box<MatchSpec>,
+    /// The part of the specs that disagrees, e.g. `version` or `build`
+    pub field: &'static str,
+}
+
+impl fmt::Display for SpecConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "'{}' conflicts with '{}' on {}", self.added, self.existing, self.field)
+    }
+}
+
+impl Error for SpecConflict {}
+
+/// Match specs keyed by package (namespace and name), in canonical order: by namespace, global
+///    first, then by name.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SpecSet {
+    specs: BTreeMap<(Option<String>, String), MatchSpec>,
+}
+
+impl SpecSet {
+    pub fn new() -> SpecSet {
+        SpecSet::default()
+    }
+
+    /// Add `spec`, merging it into the spec already there for the same package: versions are
+    ///    intersected, and other fields are kept from whichever spec has them.  Fails, leaving the
+    ///    set as it was, if the two can't both hold: different builds, channels or hashes, or
+    ///    versions where one is an exact pin the other excludes.
+    pub fn insert(&mut self, spec: MatchSpec) -> Result<(), SpecConflict> {
+        let key = (spec.namespace.clone(), spec.name.clone());
+        let merged = match self.specs.get(&key) {
+            Some(existing) => merge(existing, &spec).map_err(|field| SpecConflict {
+                existing: Box::new(existing.clone()),
+                added: Box::new(spec),
+                field,
+            })?,
+            None => spec,
+        };
+        self.specs.insert(key, merged);
+        Ok(())
+    }
+
+    /// The spec for package `name`, which may include a namespace (`python:numpy`).
+    pub fn get(&self, name: &str) -> Option<&MatchSpec> {
+        let (namespace, name) = split_namespace(name);
+        self.specs.get(&(namespace.map(str::to_string), name.to_string()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.specs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.specs.is_empty()
+    }
+
+    /// The specs in canonical order.
+    pub fn iter(&self) -> impl Iterator<Item = &MatchSpec> {
+        self.specs.values()
+    }
+
+    /// The specs in canonical order, as strings.
+    pub fn to_strings(&self) -> Vec<String> {
+        self.iter().map(|s| s.to_string()).collect()
+    }
+
+    /// Build a set from specs, stopping at the first conflict.
+    pub fn try_from_specs<I: IntoIterator<Item = MatchSpec>>(specs: I) -> Result<SpecSet, SpecConflict> {
+        let mut set = SpecSet::new();
+        for spec in specs {
+            set.insert(spec)?;
+        }
+        Ok(set)
+    }
+}
+
+/// The version an exact spec such as `1.2.3` or `==1.2.3` pins.
+fn pinned_version(spec: &VersionSpecOrConstraintTree) -> Option<Version> {
+    if !spec.is_exact() {
+        return None;
+    }
+    spec.get_spec().trim_start_matches('=').parse().ok()
+}
+
+fn merge_versions(a: &VersionSpecOrConstraintTree, b: &VersionSpecOrConstraintTree)
+                  -> Result<VersionSpecOrConstraintTree, &'static str> {
+    if a == b {
+        return Ok(a.clone());
+    }
+    // a pin either satisfies the other spec, and is all that's left of the intersection, or the
+    //    intersection is empty
+    for (pinned, other) in [(a, b), (b, a)] {
+        if let Some(version) = pinned_version(pinned) {
+            return if other.test_match_version(&version) { Ok(pinned.clone()) } else { Err("version") };
+        }
+    }
+    match a.merge(b) {
+        // drop the parts both specs had
+        VersionSpecOrConstraintTree::ConstraintTree(mut tree) => {
+            tree.dedup();
+            Ok(VersionSpecOrConstraintTree::ConstraintTree(tree))
+        }
+        merged => Ok(merged),
+    }
+}
+
+fn merge_field<T: Clone + PartialEq>(a: &Option<T>, b: &Option<T>, field: &'static str) -> Result<Option<T>, &'static str> {
+    match (a, b) {
+        (Some(x), Some(y)) if x != y => Err(field),
+        _ => Ok(a.clone().or_else(|| b.clone())),
+    }
+}
+
+fn merge(a: &MatchSpec, b: &MatchSpec) -> Result<MatchSpec, &'static str> {
+    Ok(MatchSpec {
+        channel: merge_field(&a.channel, &b.channel, "channel")?,
+        subdir: merge_field(&a.subdir, &b.subdir, "subdir")?,
+        namespace: a.namespace.clone(),
+        name: a.name.clone(),
+        version: match (&a.version, &b.version) {
+            (Some(x), Some(y)) => Some(merge_versions(x, y)?),
+            (x, y) => x.clone().or_else(|| y.clone()),
+        },
+        build: merge_field(&a.build, &b.build, "build")?,
+        build_number: merge_field(&a.build_number, &b.build_number, "build_number")?,
+        filename: merge_field(&a.filename, &b.filename, "fn")?,
+        md5: merge_field(&a.md5, &b.md5, "md5")?,
+        sha256: merge_field(&a.sha256, &b.sha256, "sha256")?,
+    })
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(specs: &[&str]) -> Result<SpecSet, SpecConflict> {
+        SpecSet::try_from_specs(specs.iter().map(|s| MatchSpec::parse(s).unwrap()))
+    }
+
+    #[test]
+    fn test_canonical_order_and_merging() {
+        let specs = set(&["zlib", "numpy >=1.20", "python:numpy", "conda-forge::numpy <2", "numpy >=1.20"]).unwrap();
+        assert_eq!(specs.len(), 3);
+        assert_eq!(specs.to_strings(), vec!["conda-forge::numpy[version='>=1.20,<2']", "zlib", "python:numpy"]);
+        assert_eq!(specs.get("python:numpy").unwrap().namespace.as_deref(), Some("python"));
+        assert!(specs.get("scipy").is_none());
+    }
+
+    #[test]
+    fn test_pins_absorb_ranges() {
+        let specs = set(&["numpy >=1.20", "numpy==1.21.0=py39_0", "numpy <2"]).unwrap();
+        assert_eq!(specs.to_strings(), vec!["numpy==1.21.0=py39_0"]);
+    }
+
+    #[rstest(
+        specs, field,
+        case(&["numpy==1.20.0", "numpy==1.21.0"], "version"),
+        case(&["numpy 1.20.0", "numpy >=1.21"], "version"),
+        case(&["numpy 1.20 py39_0", "numpy 1.20 py38_0"], "build"),
+        case(&["conda-forge::numpy", "defaults::numpy"], "channel"),
+        case(&["numpy[md5=abc]", "numpy[md5=def]"], "md5")
+    )]
+    fn test_conflicts(specs: &[&str], field: &str) {
+        let mut set = set(&specs[..1]).unwrap();
+        let conflict = set.insert(MatchSpec::parse(specs[1]).unwrap()).unwrap_err();
+        assert_eq!(conflict.field, field);
+        assert_eq!(set.to_strings(), vec![MatchSpec::parse(specs[0]).unwrap().to_string()]);
+        assert!(conflict.to_string().contains(specs[1].split_whitespace().next().unwrap()));
+    }
+}
@@ -0,0 +1,365 @@
+//! The metadata conda-build puts in a package's `info` directory: `index.json`, what the
+//! package's repodata record is made from, `about.json`, its descriptive metadata, and for
+//! `noarch: python` packages `link.json`, the entry points to generate when linking.  Packages
+//! with menu shortcuts also carry their menuinst files in `Menu`, outside `info`.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::de::{self, DeserializeOwned};
+use serde::{Deserialize, Deserializer};
+
+use crate::matchspec::matchspec::MatchSpec;
+use crate::repodata::repodata::{deserialize_json_str_to_version, deserialize_timestamp};
+use crate::Version;
+
+/// `info/index.json`.  Dependencies are parsed into match specs; a package whose dependencies
+///    don't parse fails to load.
+#[derive(Deserialize, Debug)]
+pub struct IndexJson {
+    #[serde(default)]
+    pub arch: Option<String>,
+    pub build: String,
+    pub build_number: u16,
+    #[serde(default, deserialize_with = "deserialize_match_specs")]
+    pub constrains: Vec<MatchSpec>,
+    #[serde(default, deserialize_with = "deserialize_match_specs")]
+    pub depends: Vec<MatchSpec>,
+    #[serde(default)]
+    pub features: Option<String>,
+    #[serde(default)]
+    pub license: Option<String>,
+    #[serde(default)]
+    pub license_family: Option<String>,
+    pub name: String,
+    /// `generic` or `python`; absent for packages built per platform
+    #[serde(default)]
+    pub noarch: Option<String>,
+    #[serde(default)]
+    pub platform: Option<String>,
+    #[serde(default)]
+    pub subdir: Option<String>,
+    /// Milliseconds since the epoch, converted from seconds as for `Record::timestamp`
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    pub timestamp: Option<u64>,
+    #[serde(default)]
+    pub track_features: Option<String>,
+    #[serde(deserialize_with = "deserialize_json_str_to_version")]
+    pub version: Version,
+}
+
+/// `info/about.json`.  Everything is optional: what's there depends on the recipe and on the
+///    conda-build version.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct AboutJson {
+    pub channels: Vec<String>,
+    pub conda_build_version: Option<String>,
+    pub conda_version: Option<String>,
+    pub description: Option<String>,
+    pub dev_url: Option<String>,
+    pub doc_url: Option<String>,
+    pub env_vars: HashMap<String, String>,
+    /// The recipe's free-form `extra` section
+    pub extra: serde_json::Map<String, serde_json::Value>,
+    pub home: Option<String>,
+    pub identifiers: Vec<String>,
+    pub keywords: Vec<String>,
+    pub license: Option<String>,
+    pub license_family: Option<String>,
+    pub license_file: Option<LicenseFile>,
+    pub license_url: Option<String>,
+    pub root_pkgs: Vec<String>,
+    pub source_url: Option<String>,
+    pub summary: Option<String>,
+    pub tags: Vec<String>,
+}
+
+/// Recipes list one license file or several.
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum LicenseFile {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// `info/link.json`, written for `noarch` packages.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct LinkJson {
+    pub noarch: Option<NoarchLinks>,
+    pub package_metadata_version: Option<u32>,
+    pub preferred_env: Option<String>,
+}
+
+/// The `noarch` section of `link.json`.
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct NoarchLinks {
+    /// `python` or `generic`
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default, deserialize_with = "deserialize_entry_points")]
+    pub entry_points: Vec<EntryPoint>,
+}
+
+/// A console script to generate when linking a `noarch: python` package, written
+///    `command = module:function` (e.g. `jupyter = jupyter_core.command:main`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntryPoint {
+    pub command: String,
+    pub module: String,
+    pub function: String,
+}
+
+impl EntryPoint {
+    /// Parse an entry point as conda writes it; `None` if it isn't `command = module:function`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ronda::EntryPoint;
+    ///
+    /// let entry_point = EntryPoint::parse("jupyter = jupyter_core.command:main").unwrap();
+    /// assert_eq!(entry_point.command, "jupyter");
+    /// assert_eq!(entry_point.module, "jupyter_core.command");
+    /// assert_eq!(entry_point.function, "main");
+    /// assert!(EntryPoint::parse("jupyter_core.command:main").is_none());
+    /// ```
+    pub fn parse(entry_point: &str) -> Option<EntryPoint> {
+        let (command, target) = entry_point.split_once('=')?;
+        let (module, function) = target.split_once(':')?;
+        let (command, module, function) = (command.trim(), module.trim(), function.trim());
+        if command.is_empty() || module.is_empty() || function.is_empty() {
+            return None;
+        }
+        Some(EntryPoint { command: command.to_string(), module: module.to_string(), function: function.to_string() })
+    }
+}
+
+impl fmt::Display for EntryPoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} = {}:{}", self.command, self.module, self.function)
+    }
+}
+
+/// A `Menu/*.json` file, describing the shortcuts menuinst creates when the package is installed.
+///    Nothing is run or created here; the file is kept whole for an installer to hand to
+///    menuinst.
+#[derive(Debug, PartialEq)]
+pub struct MenuJson {
+    /// Relative to the package, e.g. `Menu/spyder.json`
+    pub path: PathBuf,
+    pub menu_name: Option<String>,
+    pub shortcuts: Vec<MenuShortcut>,
+    pub contents: serde_json::Value,
+}
+
+/// One shortcut of a `MenuJson`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MenuShortcut {
+    pub name: Option<String>,
+    /// The platforms it is made on (`win`, `osx`, `linux`); empty when the file doesn't say, as
+    ///    in the older format
+    pub platforms: Vec<String>,
+}
+
+impl MenuJson {
+    // Both formats: the current one lists `menus`, each with its `platforms`, and the older
+    //    one `menu_items`
+    fn new(path: PathBuf, contents: serde_json::Value) -> MenuJson {
+        let str_field = |v: &serde_json::Value, key: &str| v.get(key).and_then(|n| n.as_str()).map(str::to_string);
+        let items = contents.get("menus").or_else(|| contents.get("menu_items")).and_then(|m| m.as_array());
+        let shortcuts = items.into_iter().flatten().map(|item| MenuShortcut {
+            name: str_field(item, "name"),
+            platforms: item.get("platforms").and_then(|p| p.as_object())
+                .map_or_else(Vec::new, |p| p.keys().cloned().collect()),
+        }).collect();
+        MenuJson { path, menu_name: str_field(&contents, "menu_name"), shortcuts, contents }
+    }
+}
+
+fn deserialize_entry_points<'de, D>(deserializer: D) -> Result<Vec<EntryPoint>, D::Error>
+    where
+        D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|s| EntryPoint::parse(s).ok_or_else(|| de::Error::custom(format!("invalid entry point '{}'", s))))
+        .collect()
+}
+
+fn deserialize_match_specs<'de, D>(deserializer: D) -> Result<Vec<MatchSpec>, D::Error>
+    where
+        D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|s| MatchSpec::parse(s).map_err(de::Error::custom))
+        .collect()
+}
+
+#[derive(Debug)]
+pub enum MetadataError {
+    Io(PathBuf, io::Error),
+    Json(PathBuf, serde_json::error::Error),
+}
+
+impl fmt::Display for MetadataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MetadataError::Io(path, e) => write!(f, "unable to read {}: {}", path.display(), e),
+            MetadataError::Json(path, e) => write!(f, "unable to parse {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl Error for MetadataError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MetadataError::Io(_, e) => Some(e),
+            MetadataError::Json(_, e) => Some(e),
+        }
+    }
+}
+
+fn read_info_file<T: DeserializeOwned>(package_dir: &Path, name: &str) -> Result<T, MetadataError> {
+    let path = package_dir.join("info").join(name);
+    let contents = std::fs::read(&path).map_err(|e| MetadataError::Io(path.clone(), e))?;
+    serde_json::from_slice(&contents).map_err(|e| MetadataError::Json(path, e))
+}
+
+/// Read `info/index.json` from the extracted package at `package_dir`.
+pub fn read_index_json<P: AsRef<Path>>(package_dir: P) -> Result<IndexJson, MetadataError> {
+    read_info_file(package_dir.as_ref(), "index.json")
+}
+
+/// Read `info/about.json` from the extracted package at `package_dir`.
+pub fn read_about_json<P: AsRef<Path>>(package_dir: P) -> Result<AboutJson, MetadataError> {
+    read_info_file(package_dir.as_ref(), "about.json")
+}
+
+/// Read `info/link.json` from the extracted package at `package_dir`.  Only `noarch` packages
+///    have one, so a missing file gives `None` rather than an error.
+pub fn read_link_json<P: AsRef<Path>>(package_dir: P) -> Result<Option<LinkJson>, MetadataError> {
+    match read_info_file(package_dir.as_ref(), "link.json") {
+        Err(MetadataError::Io(_, e)) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        result => result.map(Some),
+    }
+}
+
+/// Read the menuinst files in `Menu` of the extracted package at `package_dir`, sorted by name.
+///    A package without shortcuts has none, giving an empty list.
+pub fn read_menu_json<P: AsRef<Path>>(package_dir: P) -> Result<Vec<MenuJson>, MetadataError> {
+    let menu_dir = package_dir.as_ref().join("Menu");
+    let entries = match std::fs::read_dir(&menu_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(MetadataError::Io(menu_dir, e)),
+    };
+    let mut paths = vec![];
+    for entry in entries {
+        let path = entry.map_err(|e| MetadataError::Io(menu_dir.clone(), e))?.path();
+        if path.extension().is_some_and(|e| e == "json") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    paths.into_iter().map(|path| {
+        let contents = std::fs::read(&path).map_err(|e| MetadataError::Io(path.clone(), e))?;
+        let contents = serde_json::from_slice(&contents).map_err(|e| MetadataError::Json(path.clone(), e))?;
+        let relative = Path::new("Menu").join(path.file_name().unwrap_or_default());
+        Ok(MenuJson::new(relative, contents))
+    }).collect()
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package_dir(index: &str, about: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ronda-metadata-{}-{}", std::process::id(), index.len()));
+        std::fs::create_dir_all(dir.join("info")).unwrap();
+        std::fs::write(dir.join("info/index.json"), index).unwrap();
+        std::fs::write(dir.join("info/about.json"), about).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_read_metadata() {
+        let dir = package_dir(
+            r#"{"arch": "x86_64", "build": "h7f98852_1", "build_number": 1, "constrains": ["zlib-ng <0"],
+                "depends": ["libgcc-ng >=9.4.0"], "license": "Zlib", "name": "libzlib", "platform": "linux",
+                "subdir": "linux-64", "timestamp": 1636045219345, "version": "1.2.11"}"#,
+            r#"{"channels": ["https://conda.anaconda.org/conda-forge"], "conda_build_version": "3.21.6",
+                "extra": {"recipe-maintainers": ["someone"]}, "home": "https://zlib.net/",
+                "license_file": ["LICENSE", "NOTICE"], "summary": "Massively spiffy yet delicately unobtrusive"}"#);
+        let index = read_index_json(&dir).unwrap();
+        assert_eq!((index.name.as_str(), index.version.as_str(), index.build_number), ("libzlib", "1.2.11", 1));
+        assert_eq!(index.depends, vec![MatchSpec::parse("libgcc-ng >=9.4.0").unwrap()]);
+        assert_eq!(index.constrains[0].name, "zlib-ng");
+        assert!(index.noarch.is_none());
+        assert_eq!(index.timestamp, Some(1636045219345));
+
+        let about = read_about_json(&dir).unwrap();
+        assert_eq!(about.home.as_deref(), Some("https://zlib.net/"));
+        assert_eq!(about.license_file, Some(LicenseFile::Many(vec!["LICENSE".into(), "NOTICE".into()])));
+        assert!(about.extra.contains_key("recipe-maintainers"));
+        assert!(about.tags.is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_metadata_errors() {
+        let dir = package_dir(r#"{"build": "0", "build_number": 0, "depends": ["not a [valid spec"],
+                                   "name": "a", "version": "1.0"}"#, "{}");
+        assert!(matches!(read_index_json(&dir), Err(MetadataError::Json(_, _))));
+        assert!(read_about_json(&dir).is_ok());
+        assert!(matches!(read_index_json(dir.join("missing")), Err(MetadataError::Io(_, _))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_link_json() {
+        let dir = package_dir(r#"{"build": "pyhd8ed1ab_0", "build_number": 0, "name": "jupyter_core",
+                                   "noarch": "python", "version": "5.3.0"}"#, "{}");
+        assert!(read_link_json(&dir).unwrap().is_none());
+        std::fs::write(dir.join("info/link.json"), r#"{"noarch": {"type": "python", "entry_points":
+            ["jupyter = jupyter_core.command:main", "jupyter-migrate=jupyter_core.migrate:main"]},
+            "package_metadata_version": 1}"#).unwrap();
+        let link = read_link_json(&dir).unwrap().unwrap();
+        assert_eq!(link.package_metadata_version, Some(1));
+        let noarch = link.noarch.unwrap();
+        assert_eq!(noarch.kind, "python");
+        let commands: Vec<String> = noarch.entry_points.iter().map(|e| e.to_string()).collect();
+        assert_eq!(commands, ["jupyter = jupyter_core.command:main", "jupyter-migrate = jupyter_core.migrate:main"]);
+
+        std::fs::write(dir.join("info/link.json"), r#"{"noarch": {"type": "python", "entry_points": ["jupyter"]}}"#).unwrap();
+        assert!(matches!(read_link_json(&dir), Err(MetadataError::Json(_, _))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_menu_json() {
+        let dir = package_dir(r#"{"build": "0", "build_number": 0, "name": "spyder", "version": "5.4.3"}"#, "{}");
+        assert!(read_menu_json(&dir).unwrap().is_empty());
+        std::fs::create_dir_all(dir.join("Menu")).unwrap();
+        std::fs::write(dir.join("Menu/spyder.json"), r#"{"$schema": "https://json-schema.org/draft-07/schema",
+            "menu_name": "Spyder", "menus": [{"name": "Spyder", "command": ["{{ PREFIX }}/bin/spyder"],
+            "platforms": {"linux": {}, "osx": {}, "win": {}}}]}"#).unwrap();
+        std::fs::write(dir.join("Menu/legacy.json"), r#"{"menu_name": "Anaconda", "menu_items":
+            [{"name": "Prompt", "system": "cmd.exe"}]}"#).unwrap();
+        std::fs::write(dir.join("Menu/spyder.icns"), "").unwrap();
+        let menus = read_menu_json(&dir).unwrap();
+        assert_eq!(menus.iter().map(|m| m.path.clone()).collect::<Vec<_>>(),
+                   [PathBuf::from("Menu/legacy.json"), PathBuf::from("Menu/spyder.json")]);
+        assert_eq!(menus[0].shortcuts, [MenuShortcut { name: Some("Prompt".into()), platforms: vec![] }]);
+        assert_eq!(menus[1].menu_name.as_deref(), Some("Spyder"));
+        assert_eq!(menus[1].shortcuts[0].platforms, ["linux", "osx", "win"]);
+        assert_eq!(menus[1].contents["menus"][0]["command"][0], "{{ PREFIX }}/bin/spyder");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,7 @@
+pub mod metadata;
+#[cfg(feature = "package")]
+pub mod package;
+pub mod prefix_replace;
+pub mod run_exports;
+#[cfg(feature = "package")]
+pub mod verify;
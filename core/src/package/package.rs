@@ -0,0 +1,193 @@
+//! Extracting `.conda` package archives.
+//!
+//! A `.conda` file is an uncompressed zip holding `metadata.json` and two zstd compressed
+//! tarballs: `info-<stem>.tar.zst` with the package metadata and `pkg-<stem>.tar.zst` with the
+//! files to install.  Both are streamed straight from the zip through the decompressor into the
+//! destination directory; neither tarball is ever held in memory or written out whole.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek};
+use std::path::{Component, Path, PathBuf};
+
+use rayon::prelude::*;
+
+#[derive(Debug)]
+pub enum ExtractError {
+    Io(PathBuf, io::Error),
+    Zip(zip::result::ZipError),
+    /// The archive lacks its `info` or `pkg` tarball
+    MissingComponent(&'static str),
+    /// An entry that would land outside the destination directory, e.g. `../../etc/passwd`
+    UnsafePath(PathBuf),
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExtractError::Io(path, e) => write!(f, "unable to extract {}: {}", path.display(), e),
+            ExtractError::Zip(e) => write!(f, "invalid .conda archive: {}", e),
+            ExtractError::MissingComponent(c) => write!(f, "invalid .conda archive: no {} tarball", c),
+            ExtractError::UnsafePath(path) =>
+                write!(f, "refusing to extract {} outside the destination", path.display()),
+        }
+    }
+}
+
+impl Error for ExtractError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ExtractError::Io(_, e) => Some(e),
+            ExtractError::Zip(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<zip::result::ZipError> for ExtractError {
+    fn from(e: zip::result::ZipError) -> Self {
+        ExtractError::Zip(e)
+    }
+}
+
+/// Extract the `.conda` archive at `archive` into `dest`, which is created if needed.
+pub fn extract_conda<P: AsRef<Path>, Q: AsRef<Path>>(archive: P, dest: Q) -> Result<(), ExtractError> {
+    let archive = archive.as_ref();
+    let file = File::open(archive).map_err(|e| ExtractError::Io(archive.to_path_buf(), e))?;
+    extract_conda_from_reader(BufReader::new(file), dest)
+}
+
+/// Like `extract_conda`, reading the archive from `reader`.
+pub fn extract_conda_from_reader<R: Read + Seek, Q: AsRef<Path>>(reader: R, dest: Q) -> Result<(), ExtractError> {
+    let dest = dest.as_ref();
+    let mut zip = zip::ZipArchive::new(reader)?;
+    for component in ["info", "pkg"] {
+        let prefix = format!("{}-", component);
+        let name = zip.file_names()
+            .find(|n| n.starts_with(&prefix) && n.ends_with(".tar.zst"))
+            .ok_or(ExtractError::MissingComponent(component))?
+            .to_string();
+        let entry = zip.by_name(&name)?;
+        let decoder = zstd::stream::read::Decoder::new(entry).map_err(|e| ExtractError::Io(dest.to_path_buf(), e))?;
+        unpack_tar(decoder, dest)?;
+    }
+    Ok(())
+}
+
+/// Extract many archives at once, as (archive, destination) pairs, on rayon's thread pool.
+///    Returns one result per pair, in order; one failure doesn't stop the others.
+pub fn extract_conda_all<P, Q>(jobs: &[(P, Q)]) -> Vec<Result<(), ExtractError>>
+where
+    P: AsRef<Path> + Sync,
+    Q: AsRef<Path> + Sync,
+{
+    jobs.par_iter().map(|(archive, dest)| extract_conda(archive, dest)).collect()
+}
+
+/// Unpack a tarball into `dest`, refusing any entry whose path is absolute or climbs out with
+///    `..`.  The tar crate would skip such entries silently; a package containing one is broken
+///    or malicious, so it is an error here.  Links are left to the tar crate, which won't write
+///    through a symlink to outside `dest`.
+fn unpack_tar<R: Read>(reader: R, dest: &Path) -> Result<(), ExtractError> {
+    let io_error = |e| ExtractError::Io(dest.to_path_buf(), e);
+    std::fs::create_dir_all(dest).map_err(io_error)?;
+    let mut archive = tar::Archive::new(reader);
+    archive.set_preserve_permissions(true);
+    archive.set_preserve_mtime(true);
+    for entry in archive.entries().map_err(io_error)? {
+        let mut entry = entry.map_err(io_error)?;
+        let path = entry.path().map_err(io_error)?.into_owned();
+        if !path.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+            return Err(ExtractError::UnsafePath(path));
+        }
+        if !entry.unpack_in(dest).map_err(io_error)? {
+            return Err(ExtractError::UnsafePath(path));
+        }
+    }
+    Ok(())
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+    use super::*;
+
+    /// A zstd compressed tarball of (path, contents).  Paths are written into the header raw,
+    ///    so that tests can make entries the tar crate would refuse to create.
+    fn tar_zst(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(vec![]);
+        for (path, contents) in files {
+            let mut header = tar::Header::new_old();
+            header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, contents.as_bytes()).unwrap();
+        }
+        zstd::stream::encode_all(&builder.into_inner().unwrap()[..], 3).unwrap()
+    }
+
+    fn conda(info: &[(&str, &str)], pkg: Option<&[(&str, &str)]>) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+        let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        zip.start_file("metadata.json", options).unwrap();
+        zip.write_all(br#"{"conda_pkg_format_version": 2}"#).unwrap();
+        zip.start_file("info-a-1.0-0.tar.zst", options).unwrap();
+        zip.write_all(&tar_zst(info)).unwrap();
+        if let Some(pkg) = pkg {
+            zip.start_file("pkg-a-1.0-0.tar.zst", options).unwrap();
+            zip.write_all(&tar_zst(pkg)).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ronda-extract-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_extract_conda() {
+        let dest = scratch_dir("ok");
+        let archive = conda(&[("info/index.json", "{}")], Some(&[("lib/a.txt", "hello")]));
+        extract_conda_from_reader(Cursor::new(archive), &dest).unwrap();
+        assert_eq!(std::fs::read_to_string(dest.join("info/index.json")).unwrap(), "{}");
+        assert_eq!(std::fs::read_to_string(dest.join("lib/a.txt")).unwrap(), "hello");
+        std::fs::remove_dir_all(&dest).unwrap();
+    }
+
+    #[test]
+    fn test_extract_conda_all() {
+        let dir = scratch_dir("all");
+        std::fs::create_dir_all(&dir).unwrap();
+        let archive = dir.join("a-1.0-0.conda");
+        std::fs::write(&archive, conda(&[("info/index.json", "{}")], Some(&[("lib/a.txt", "hello")]))).unwrap();
+        let jobs: Vec<(PathBuf, PathBuf)> = (0..4).map(|i| (archive.clone(), dir.join(i.to_string()))).collect();
+        let missing = vec![(dir.join("missing.conda"), dir.join("missing"))];
+        assert!(extract_conda_all(&jobs).iter().all(|r| r.is_ok()));
+        assert!(jobs.iter().all(|(_, dest)| dest.join("lib/a.txt").exists()));
+        assert!(matches!(extract_conda_all(&missing)[0], Err(ExtractError::Io(_, _))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_missing_component() {
+        let dest = scratch_dir("missing");
+        let err = extract_conda_from_reader(Cursor::new(conda(&[("info/index.json", "{}")], None)), &dest).unwrap_err();
+        assert!(matches!(err, ExtractError::MissingComponent("pkg")));
+        let _ = std::fs::remove_dir_all(&dest);
+    }
+
+    #[rstest(path, case("../evil.txt"), case("lib/../../evil.txt"), case("/tmp/evil.txt"))]
+    fn test_unsafe_paths(path: &str) {
+        let dest = scratch_dir("unsafe");
+        let archive = conda(&[("info/index.json", "{}")], Some(&[(path, "gotcha")]));
+        let err = extract_conda_from_reader(Cursor::new(archive), dest.join("inner")).unwrap_err();
+        assert!(matches!(err, ExtractError::UnsafePath(_)), "{}", err);
+        assert!(!dest.join("evil.txt").exists());
+        let _ = std::fs::remove_dir_all(&dest);
+    }
+}
@@ -0,0 +1,211 @@
+//! Rewriting the placeholder prefix that packages are built with to the prefix they're installed
+//! into.
+//!
+//! conda-build records, for each file containing the build prefix, a placeholder and whether the
+//! file is text or binary.  Text files just get the placeholder replaced.  Binary files can't
+//! change length, so each C string containing the placeholder gets the replacement and is then
+//! padded with NULs back to its old length; the new prefix must not be longer than the
+//! placeholder.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use memchr::memmem;
+use serde::Deserialize;
+
+/// How a file's placeholder is replaced, as in `paths.json`'s `file_mode`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileMode {
+    Text,
+    Binary,
+}
+
+#[derive(Debug)]
+pub enum PrefixReplaceError {
+    Io(PathBuf, io::Error),
+    /// The placeholder is empty, so it would match everywhere
+    EmptyPlaceholder,
+    /// The new prefix doesn't fit in the space of the placeholder in a binary file
+    TooLong { placeholder: usize, prefix: usize },
+}
+
+impl fmt::Display for PrefixReplaceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PrefixReplaceError::Io(path, e) => write!(f, "unable to rewrite {}: {}", path.display(), e),
+            PrefixReplaceError::EmptyPlaceholder => f.write_str("the prefix placeholder is empty"),
+            PrefixReplaceError::TooLong { placeholder, prefix } => write!(
+                f, "prefix of {} bytes is too long for a binary placeholder of {} bytes", prefix, placeholder),
+        }
+    }
+}
+
+impl Error for PrefixReplaceError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PrefixReplaceError::Io(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Replace every `placeholder` in `data` with `prefix`.
+pub fn replace_prefix_text(data: &[u8], placeholder: &[u8], prefix: &[u8]) -> Result<Vec<u8>, PrefixReplaceError> {
+    check_placeholder(placeholder)?;
+    let mut out = Vec::with_capacity(data.len());
+    let mut pos = 0;
+    for i in memmem::find_iter(data, placeholder) {
+        out.extend_from_slice(&data[pos..i]);
+        out.extend_from_slice(prefix);
+        pos = i + placeholder.len();
+    }
+    out.extend_from_slice(&data[pos..]);
+    Ok(out)
+}
+
+/// Replace `placeholder` with `prefix` in each NUL terminated string of `data` that contains it,
+///    padding the string with NULs so that nothing after it moves.  A placeholder with no NUL
+///    after it isn't in a C string and is left alone.
+pub fn replace_prefix_binary(data: &[u8], placeholder: &[u8], prefix: &[u8]) -> Result<Vec<u8>, PrefixReplaceError> {
+    check_placeholder(placeholder)?;
+    let too_long = || PrefixReplaceError::TooLong { placeholder: placeholder.len(), prefix: prefix.len() };
+    let shrink = placeholder.len().checked_sub(prefix.len()).ok_or_else(too_long)?;
+    let finder = memmem::Finder::new(placeholder);
+    let mut out = Vec::with_capacity(data.len());
+    let mut pos = 0;
+    while let Some(start) = finder.find(&data[pos..]).map(|i| pos + i) {
+        let end = match memchr::memchr(0, &data[start..]) {
+            Some(len) => start + len,
+            None => break,
+        };
+        out.extend_from_slice(&data[pos..start]);
+        let string = &data[start..end];
+        let mut copied = 0;
+        let mut count = 0;
+        for i in finder.find_iter(string) {
+            out.extend_from_slice(&string[copied..i]);
+            out.extend_from_slice(prefix);
+            copied = i + placeholder.len();
+            count += 1;
+        }
+        out.extend_from_slice(&string[copied..]);
+        out.resize(out.len() + shrink * count, 0);
+        // the terminating NUL is copied with what follows
+        pos = end;
+    }
+    out.extend_from_slice(&data[pos..]);
+    Ok(out)
+}
+
+/// Rewrite the file at `path`, replacing `placeholder` with `prefix` as `mode` says.  The file
+///    is only written if it contains the placeholder.  The new contents go to a new file, with
+///    the same permissions, that is renamed over `path`, so files hard linked to it (e.g. the
+///    package cache's copy) keep the placeholder.
+pub fn replace_prefix_in_file<P: AsRef<Path>>(path: P, placeholder: &str, prefix: &str, mode: FileMode)
+                                              -> Result<(), PrefixReplaceError> {
+    check_placeholder(placeholder.as_bytes())?;
+    let path = path.as_ref();
+    let data = std::fs::read(path).map_err(|e| PrefixReplaceError::Io(path.to_path_buf(), e))?;
+    if memmem::find(&data, placeholder.as_bytes()).is_none() {
+        return Ok(());
+    }
+    let replaced = match mode {
+        FileMode::Text => replace_prefix_text(&data, placeholder.as_bytes(), prefix.as_bytes())?,
+        FileMode::Binary => replace_prefix_binary(&data, placeholder.as_bytes(), prefix.as_bytes())?,
+    };
+    let io_error = |e| PrefixReplaceError::Io(path.to_path_buf(), e);
+    let permissions = std::fs::metadata(path).map_err(io_error)?.permissions();
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    std::fs::write(&partial, replaced).map_err(|e| PrefixReplaceError::Io(partial.clone(), e))?;
+    std::fs::set_permissions(&partial, permissions).map_err(|e| PrefixReplaceError::Io(partial.clone(), e))?;
+    std::fs::rename(&partial, path).map_err(io_error)
+}
+
+// An empty placeholder is found at every position: text would get the prefix between every
+//    byte, and the binary scan would never move past a NUL
+fn check_placeholder(placeholder: &[u8]) -> Result<(), PrefixReplaceError> {
+    if placeholder.is_empty() {
+        return Err(PrefixReplaceError::EmptyPlaceholder);
+    }
+    Ok(())
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLACEHOLDER: &[u8] = b"/opt/placeholder_long";
+
+    #[test]
+    fn test_text() {
+        let data = b"#!/opt/placeholder_long/bin/python\nPATH=/opt/placeholder_long/bin:/opt/placeholder_long/lib";
+        assert_eq!(replace_prefix_text(data, PLACEHOLDER, b"/home/me/env").unwrap(),
+                   b"#!/home/me/env/bin/python\nPATH=/home/me/env/bin:/home/me/env/lib".to_vec());
+        assert_eq!(replace_prefix_text(b"nothing here", PLACEHOLDER, b"/x").unwrap(), b"nothing here".to_vec());
+    }
+
+    #[rstest(
+        data, expected,
+        case(b"\x7fELF\0/opt/placeholder_long/lib\0rest", b"\x7fELF\0/env/lib\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0rest"),
+        // two placeholders in one string pad twice, after the whole string
+        case(b"/opt/placeholder_long:/opt/placeholder_long\0", b"/env:/env\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0"),
+        // not NUL terminated, so not a C string
+        case(b"x/opt/placeholder_long", b"x/opt/placeholder_long")
+    )]
+    fn test_binary(data: &[u8], expected: &[u8]) {
+        let replaced = replace_prefix_binary(data, PLACEHOLDER, b"/env").unwrap();
+        assert_eq!(replaced, expected.to_vec());
+        assert_eq!(replaced.len(), data.len());
+    }
+
+    #[test]
+    fn test_binary_too_long() {
+        let err = replace_prefix_binary(b"/opt/placeholder_long\0", PLACEHOLDER, b"/a/much/longer/prefix/than/that").unwrap_err();
+        assert!(matches!(err, PrefixReplaceError::TooLong { placeholder: 21, prefix: 31 }));
+    }
+
+    #[test]
+    fn test_empty_placeholder() {
+        assert!(matches!(replace_prefix_text(b"abc", b"", b"/env"), Err(PrefixReplaceError::EmptyPlaceholder)));
+        assert!(matches!(replace_prefix_binary(b"a\0b\0", b"", b""), Err(PrefixReplaceError::EmptyPlaceholder)));
+        // checked before the file is read, so a missing file doesn't matter
+        let err = replace_prefix_in_file("surely/not/a/file", "", "/env", FileMode::Text).unwrap_err();
+        assert!(matches!(err, PrefixReplaceError::EmptyPlaceholder));
+    }
+
+    #[test]
+    fn test_replace_in_file() {
+        let path = std::env::temp_dir().join(format!("ronda-prefix-replace-{}", std::process::id()));
+        std::fs::write(&path, b"prefix=/opt/placeholder_long\0tail").unwrap();
+        replace_prefix_in_file(&path, "/opt/placeholder_long", "/env", FileMode::Binary).unwrap();
+        let replaced = std::fs::read(&path).unwrap();
+        assert_eq!(replaced.len(), 33);
+        assert!(replaced.starts_with(b"prefix=/env\0"));
+        replace_prefix_in_file(&path, "/opt/placeholder_long", "/env", FileMode::Text).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), replaced);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_replace_in_file_keeps_hard_links() {
+        let dir = std::env::temp_dir().join(format!("ronda-prefix-replace-link-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (cached, linked) = (dir.join("cached"), dir.join("linked"));
+        std::fs::write(&cached, b"#!/opt/placeholder_long/bin/python").unwrap();
+        std::fs::hard_link(&cached, &linked).unwrap();
+        let mut permissions = std::fs::metadata(&linked).unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&linked, permissions).unwrap();
+        replace_prefix_in_file(&linked, "/opt/placeholder_long", "/env", FileMode::Text).unwrap();
+        assert_eq!(std::fs::read(&linked).unwrap(), b"#!/env/bin/python");
+        assert_eq!(std::fs::read(&cached).unwrap(), b"#!/opt/placeholder_long/bin/python");
+        assert!(std::fs::metadata(&linked).unwrap().permissions().readonly());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,161 @@
+//! Run exports: dependencies a package adds to the run requirements of whatever is built
+//! against it.  A C library exports a pin on itself, for example, so that packages linking it
+//! at build time also require a compatible version at run time.
+//!
+//! A package lists them in `info/run_exports.json`, in kinds that differ in where they apply:
+//!
+//! * `weak` exports apply when the package is in the host environment.
+//! * `strong` exports apply when it is in the build or the host environment, and from the build
+//!   environment they are added to the host requirements too.
+//! * `noarch` exports are the only ones that apply when building a noarch package.
+//!
+//! `weak_constrains` and `strong_constrains` work the same way, but become run constraints.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::package::metadata::MetadataError;
+use crate::repodata::depends::Depend;
+
+/// The contents of `info/run_exports.json`.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct RunExports {
+    pub weak: Vec<String>,
+    pub strong: Vec<String>,
+    pub weak_constrains: Vec<String>,
+    pub strong_constrains: Vec<String>,
+    pub noarch: Vec<String>,
+}
+
+/// A recipe's `ignore_run_exports` and `ignore_run_exports_from`.
+#[derive(Debug, Default, Clone)]
+pub struct IgnoreRunExports {
+    /// Names of exported packages to leave out, whichever package exports them
+    pub names: Vec<String>,
+    /// Names of packages whose run exports are all left out
+    pub from_packages: Vec<String>,
+}
+
+/// The requirements run exports add to a recipe.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct InjectedRunExports {
+    pub host: Vec<String>,
+    pub run: Vec<String>,
+    pub run_constrains: Vec<String>,
+}
+
+impl InjectedRunExports {
+    fn add(list: &mut Vec<String>, specs: &[String], ignore: &IgnoreRunExports) {
+        for spec in specs {
+            let name = Depend::parse(spec).name;
+            if !ignore.names.iter().any(|n| n == name) && !list.contains(spec) {
+                list.push(spec.clone());
+            }
+        }
+    }
+}
+
+/// Work out the requirements to inject from the run exports of the packages in the build and
+///    host environments, each given as (package name, its run exports).  `host` is `None` for a
+///    recipe without a host section, in which case the build environment's weak exports apply
+///    as well, as they would have from the host.  For a noarch recipe, only `noarch` exports are
+///    used.  Specs come out in the order first exported, without duplicates.
+pub fn run_exports_to_inject<'a>(build: &[(&str, &'a RunExports)], host: Option<&[(&str, &'a RunExports)]>,
+                             ignore: &IgnoreRunExports, noarch: bool) -> InjectedRunExports {
+    let mut injected = InjectedRunExports::default();
+    let ignored_from: HashSet<&str> = ignore.from_packages.iter().map(|p| p.as_str()).collect();
+    let used = |env: &[(&str, &'a RunExports)]| -> Vec<&'a RunExports> {
+        env.iter().filter(|(name, _)| !ignored_from.contains(name)).map(|(_, e)| *e).collect()
+    };
+    let (build, host) = (used(build), host.map(used));
+
+    if noarch {
+        for exports in build.iter().chain(host.iter().flatten()) {
+            InjectedRunExports::add(&mut injected.run, &exports.noarch, ignore);
+        }
+        return injected;
+    }
+    for exports in build {
+        InjectedRunExports::add(&mut injected.host, &exports.strong, ignore);
+        InjectedRunExports::add(&mut injected.run, &exports.strong, ignore);
+        InjectedRunExports::add(&mut injected.run_constrains, &exports.strong_constrains, ignore);
+        if host.is_none() {
+            InjectedRunExports::add(&mut injected.run, &exports.weak, ignore);
+            InjectedRunExports::add(&mut injected.run_constrains, &exports.weak_constrains, ignore);
+        }
+    }
+    for exports in host.iter().flatten() {
+        InjectedRunExports::add(&mut injected.run, &exports.weak, ignore);
+        InjectedRunExports::add(&mut injected.run, &exports.strong, ignore);
+        InjectedRunExports::add(&mut injected.run_constrains, &exports.weak_constrains, ignore);
+        InjectedRunExports::add(&mut injected.run_constrains, &exports.strong_constrains, ignore);
+    }
+    injected
+}
+
+/// Read `info/run_exports.json` from the extracted package at `package_dir`.  Most packages
+///    don't have one, and export nothing.
+pub fn read_run_exports<P: AsRef<Path>>(package_dir: P) -> Result<RunExports, MetadataError> {
+    let path = package_dir.as_ref().join("info/run_exports.json");
+    match std::fs::read(&path) {
+        Ok(contents) => serde_json::from_slice(&contents).map_err(|e| MetadataError::Json(path, e)),
+        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => Ok(RunExports::default()),
+        Err(e) => Err(MetadataError::Io(path, e)),
+    }
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exports(json: &str) -> RunExports {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_run_exports_to_inject() {
+        let zlib = exports(r#"{"weak": ["zlib >=1.2.11,<1.3.0a0"], "weak_constrains": ["zlib-ng <0"]}"#);
+        let compiler = exports(r#"{"strong": ["libgcc-ng >=9.4.0"], "strong_constrains": ["sysroot_linux-64 >=2.17"]}"#);
+        let python = exports(r#"{"weak": ["python_abi 3.9.* *_cp39"], "noarch": ["python"]}"#);
+        let build = [("gcc_impl_linux-64", &compiler)];
+        let host = [("zlib", &zlib), ("python", &python)];
+
+        let injected = run_exports_to_inject(&build, Some(&host), &IgnoreRunExports::default(), false);
+        assert_eq!(injected.host, vec!["libgcc-ng >=9.4.0"]);
+        assert_eq!(injected.run, vec!["libgcc-ng >=9.4.0", "zlib >=1.2.11,<1.3.0a0", "python_abi 3.9.* *_cp39"]);
+        assert_eq!(injected.run_constrains, vec!["sysroot_linux-64 >=2.17", "zlib-ng <0"]);
+
+        // weak exports from the build environment only count without a host environment
+        let build_only = run_exports_to_inject(&[("zlib", &zlib)], None, &IgnoreRunExports::default(), false);
+        assert_eq!(build_only.run, vec!["zlib >=1.2.11,<1.3.0a0"]);
+        assert!(run_exports_to_inject(&[("zlib", &zlib)], Some(&[]), &IgnoreRunExports::default(), false).run.is_empty());
+
+        let noarch = run_exports_to_inject(&build, Some(&host), &IgnoreRunExports::default(), true);
+        assert_eq!(noarch, InjectedRunExports { run: vec!["python".into()], ..Default::default() });
+    }
+
+    #[test]
+    fn test_ignore_run_exports() {
+        let zlib = exports(r#"{"weak": ["zlib >=1.2.11,<1.3.0a0"]}"#);
+        let compiler = exports(r#"{"strong": ["libgcc-ng >=9.4.0", "libstdcxx-ng >=9.4.0"]}"#);
+        let build = [("gcc_impl_linux-64", &compiler)];
+        let host = [("zlib", &zlib)];
+        let ignore = IgnoreRunExports { names: vec!["libstdcxx-ng".into()], from_packages: vec!["zlib".into()] };
+        let injected = run_exports_to_inject(&build, Some(&host), &ignore, false);
+        assert_eq!(injected.run, vec!["libgcc-ng >=9.4.0"]);
+    }
+
+    #[test]
+    fn test_read_run_exports() {
+        let dir = std::env::temp_dir().join(format!("ronda-run-exports-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("info")).unwrap();
+        assert_eq!(read_run_exports(&dir).unwrap(), RunExports::default());
+        std::fs::write(dir.join("info/run_exports.json"), r#"{"weak": ["zlib >=1.2.11"]}"#).unwrap();
+        assert_eq!(read_run_exports(&dir).unwrap().weak, vec!["zlib >=1.2.11"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,181 @@
+//! Checking downloaded packages against their repodata records.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use md5::{Digest, Md5};
+use rayon::prelude::*;
+use sha2::Sha256;
+
+use crate::repodata::filename::PackageFilename;
+use crate::repodata::repodata::Record;
+
+#[derive(Debug)]
+pub enum VerifyError {
+    Io(PathBuf, io::Error),
+    Size { expected: u64, found: u64 },
+    Md5 { expected: String, found: String },
+    Sha256 { expected: String, found: String },
+    /// A cached package with no extracted `info/repodata_record.json` to check it against
+    MissingRecord(PathBuf),
+    Json(PathBuf, serde_json::Error),
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VerifyError::Io(path, e) => write!(f, "unable to read {}: {}", path.display(), e),
+            VerifyError::Size { expected, found } => write!(f, "size is {}, expected {}", found, expected),
+            VerifyError::Md5 { expected, found } => write!(f, "md5 is {}, expected {}", found, expected),
+            VerifyError::Sha256 { expected, found } => write!(f, "sha256 is {}, expected {}", found, expected),
+            VerifyError::MissingRecord(path) => write!(f, "no record to verify against at {}", path.display()),
+            VerifyError::Json(path, e) => write!(f, "unable to parse {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl Error for VerifyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            VerifyError::Io(_, e) => Some(e),
+            VerifyError::Json(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Record {
+    /// Check that the file at `path` is the artifact this record describes: its size, md5 and
+    ///    sha256.  The file is hashed as it is read, in one pass.  Hashes the record doesn't have
+    ///    (older packages have no sha256) aren't checked.
+    pub fn verify_artifact<P: AsRef<Path>>(&self, path: P) -> Result<(), VerifyError> {
+        let path = path.as_ref();
+        let io_error = |e| VerifyError::Io(path.to_path_buf(), e);
+        let mut file = File::open(path).map_err(io_error)?;
+        let (mut md5, mut sha256) = (Md5::new(), Sha256::new());
+        let mut buffer = vec![0; 256 * 1024];
+        let mut size = 0;
+        loop {
+            let read = file.read(&mut buffer).map_err(io_error)?;
+            if read == 0 {
+                break;
+            }
+            size += read as u64;
+            md5.update(&buffer[..read]);
+            sha256.update(&buffer[..read]);
+        }
+        if size != self.size {
+            return Err(VerifyError::Size { expected: self.size, found: size });
+        }
+        let md5 = format!("{:x}", md5.finalize());
+        if !self.md5.is_empty() && !self.md5.eq_ignore_ascii_case(&md5) {
+            return Err(VerifyError::Md5 { expected: self.md5.clone(), found: md5 });
+        }
+        let sha256 = format!("{:x}", sha256.finalize());
+        if !self.sha256.is_empty() && !self.sha256.eq_ignore_ascii_case(&sha256) {
+            return Err(VerifyError::Sha256 { expected: self.sha256.clone(), found: sha256 });
+        }
+        Ok(())
+    }
+}
+
+/// The outcome of verifying each archive in a package cache.
+pub type CacheReport = Vec<(PathBuf, Result<(), VerifyError>)>;
+
+/// Verify every package archive in the package cache `cache_dir` against the
+///    `<stem>/info/repodata_record.json` conda extracted next to it, in parallel.  Returns one
+///    result per archive, sorted by path.
+pub fn verify_all<P: AsRef<Path>>(cache_dir: P) -> Result<CacheReport, VerifyError> {
+    let cache_dir = cache_dir.as_ref();
+    let io_error = |e| VerifyError::Io(cache_dir.to_path_buf(), e);
+    let mut archives = vec![];
+    for entry in std::fs::read_dir(cache_dir).map_err(io_error)? {
+        let path = entry.map_err(io_error)?.path();
+        if let Some(filename) = path.file_name().and_then(|f| f.to_str()).and_then(|f| PackageFilename::parse(f).ok()) {
+            archives.push((path.clone(), cache_dir.join(filename.stem()).join("info/repodata_record.json")));
+        }
+    }
+    archives.sort();
+    Ok(archives.into_par_iter().map(|(archive, record_path)| {
+        let result = read_record(&record_path).and_then(|record| record.verify_artifact(&archive));
+        (archive, result)
+    }).collect())
+}
+
+fn read_record(path: &Path) -> Result<Record, VerifyError> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Err(VerifyError::MissingRecord(path.to_path_buf())),
+        Err(e) => return Err(VerifyError::Io(path.to_path_buf(), e)),
+    };
+    serde_json::from_slice(&data).map_err(|e| VerifyError::Json(path.to_path_buf(), e))
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENTS: &[u8] = b"not really a package";
+    const MD5: &str = "b6ff4b9fdc2d8ddc4d06abd2e4eaaae8";
+    const SHA256: &str = "e25bcd49c8e6a7a8ba5d1e3d9d80d40be54bc0e1ed9a8e5fb7e6e7d9c7f0f9ad";
+
+    fn record(md5: &str, sha256: &str, size: u64) -> Record {
+        serde_json::from_value(serde_json::json!({
+            "name": "a", "version": "1.0", "build": "0", "build_number": 0, "depends": [],
+            "md5": md5, "sha256": sha256, "size": size,
+        })).unwrap()
+    }
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ronda-verify-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn digests() -> (String, String) {
+        (format!("{:x}", Md5::digest(CONTENTS)), format!("{:x}", Sha256::digest(CONTENTS)))
+    }
+
+    #[test]
+    fn test_verify_artifact() {
+        let dir = cache_dir("artifact");
+        let path = dir.join("a-1.0-0.conda");
+        std::fs::write(&path, CONTENTS).unwrap();
+        let (md5, sha256) = digests();
+        let size = CONTENTS.len() as u64;
+        assert!(record(&md5, &sha256, size).verify_artifact(&path).is_ok());
+        assert!(record(&md5.to_uppercase(), "", size).verify_artifact(&path).is_ok());
+        assert!(matches!(record(&md5, &sha256, size + 1).verify_artifact(&path), Err(VerifyError::Size { .. })));
+        assert!(matches!(record(MD5, &sha256, size).verify_artifact(&path), Err(VerifyError::Md5 { .. })));
+        assert!(matches!(record(&md5, SHA256, size).verify_artifact(&path), Err(VerifyError::Sha256 { .. })));
+        assert!(matches!(record(&md5, &sha256, size).verify_artifact(dir.join("missing")), Err(VerifyError::Io(_, _))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_verify_all() {
+        let dir = cache_dir("all");
+        let (md5, sha256) = digests();
+        for (stem, md5) in [("good-1.0-0", md5.as_str()), ("bad-1.0-0", MD5)] {
+            std::fs::write(dir.join(format!("{}.conda", stem)), CONTENTS).unwrap();
+            std::fs::create_dir_all(dir.join(stem).join("info")).unwrap();
+            let record = serde_json::to_vec(&record(md5, &sha256, CONTENTS.len() as u64)).unwrap();
+            std::fs::write(dir.join(stem).join("info/repodata_record.json"), record).unwrap();
+        }
+        std::fs::write(dir.join("unextracted-1.0-0.tar.bz2"), CONTENTS).unwrap();
+        std::fs::write(dir.join("urls.txt"), "").unwrap();
+
+        let results = verify_all(&dir).unwrap();
+        let names: Vec<&str> = results.iter().map(|(p, _)| p.file_name().unwrap().to_str().unwrap()).collect();
+        assert_eq!(names, vec!["bad-1.0-0.conda", "good-1.0-0.conda", "unextracted-1.0-0.tar.bz2"]);
+        assert!(matches!(results[0].1, Err(VerifyError::Md5 { .. })));
+        assert!(results[1].1.is_ok());
+        assert!(matches!(results[2].1, Err(VerifyError::MissingRecord(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,124 @@
+//! What an environment's packages are there for: the dependency graph between installed records,
+//! and the `conda autoremove` style questions asked of it.
+
+use std::collections::HashMap;
+
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::visit::{Dfs, IntoNodeReferences};
+use petgraph::Direction;
+
+use crate::prefix::history::History;
+use crate::prefix::prefix::PrefixRecord;
+use crate::Depend;
+
+/// The dependency graph of an environment, with an edge from each record to each record it
+///    depends on.  An environment has one record per name, so edges go by name alone; depends on
+///    packages that aren't installed (virtual packages, say) have no edge.  Names are compared
+///    whole, so `python:numpy` and `numpy` are different packages.
+pub fn installed_graph(records: &[PrefixRecord]) -> DiGraph<&PrefixRecord, ()> {
+    let mut graph = DiGraph::with_capacity(records.len(), records.len() * 4);
+    let nodes: HashMap<&str, NodeIndex> = records.iter()
+        .map(|r| (r.record.name.as_str(), graph.add_node(r)))
+        .collect();
+    for record in records {
+        let from = nodes[record.record.name.as_str()];
+        for entry in &record.record.depends {
+            if let Some(&to) = nodes.get(Depend::parse(entry).name) {
+                graph.update_edge(from, to, ());
+            }
+        }
+    }
+    graph
+}
+
+/// The records nothing else installed depends on, in node order.
+pub fn leaves<'a>(graph: &DiGraph<&'a PrefixRecord, ()>) -> Vec<&'a PrefixRecord> {
+    graph.node_references()
+        .filter(|(idx, _)| graph.neighbors_directed(*idx, Direction::Incoming).next().is_none())
+        .map(|(_, r)| *r)
+        .collect()
+}
+
+/// The records that are only there as dependencies of packages since removed, in node order:
+///    those not reachable from anything the user asked for.
+///
+/// A package counts as asked for if `history` says so, or if its record has a `requested_spec`
+///    (older histories don't list specs, but conda has always written that field).
+pub fn orphans<'a>(graph: &DiGraph<&'a PrefixRecord, ()>, history: &History) -> Vec<&'a PrefixRecord> {
+    let requested = history.requested_specs();
+    let mut dfs = Dfs::empty(graph);
+    for (idx, record) in graph.node_references() {
+        if requested.contains_key(record.record.name.as_str()) || record.requested_spec.is_some() {
+            dfs.move_to(idx);
+            while dfs.next(graph).is_some() {}
+        }
+    }
+    graph.node_references()
+        .filter(|(idx, _)| !dfs.discovered.contains(idx.index()))
+        .map(|(_, r)| *r)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use super::*;
+    use crate::read_prefix;
+
+    fn record(name: &str, depends: &[&str], requested: bool) -> PrefixRecord {
+        let json = serde_json::json!({
+            "name": name, "version": "1.0", "build": "0", "build_number": 0, "depends": depends,
+            "md5": "", "size": 0, "requested_spec": if requested { Some(name) } else { None },
+        });
+        serde_json::from_value(json).unwrap()
+    }
+
+    fn names(records: Vec<&PrefixRecord>) -> Vec<&str> {
+        records.iter().map(|r| r.record.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_fixture_prefix() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("tests/data/prefix");
+        let records = read_prefix(&d).unwrap();
+        let graph = installed_graph(&records);
+        assert_eq!(graph.edge_count(), 1);
+        assert_eq!(names(leaves(&graph)), vec!["zlib"]);
+        assert!(orphans(&graph, &History::default()).is_empty());
+    }
+
+    #[test]
+    fn test_orphans_after_removal() {
+        // pandas was asked for, then removed; numpy and python are left behind, but python is
+        //     still needed by requests
+        let records = vec![
+            record("certifi", &[], false),
+            record("numpy", &["python >=3.9"], false),
+            record("python", &["__win", "certifi"], false),
+            record("requests", &["python"], false),
+            record("tzdata", &[], true),
+        ];
+        let history = History::parse("\
+==> 2021-03-01 09:00:00 <==
+# update specs: ['pandas', 'requests']
+==> 2021-03-02 09:00:00 <==
+# remove specs: ['pandas']
+");
+        let graph = installed_graph(&records);
+        assert_eq!(names(leaves(&graph)), vec!["numpy", "requests", "tzdata"]);
+        assert_eq!(names(orphans(&graph, &history)), vec!["numpy"]);
+    }
+
+    #[test]
+    fn test_namespaced_names_are_distinct() {
+        let records = vec![
+            record("app", &["python:numpy"], true),
+            record("numpy", &[], false),
+            record("python:numpy", &[], false),
+        ];
+        let graph = installed_graph(&records);
+        assert_eq!(names(leaves(&graph)), vec!["app", "numpy"]);
+        assert_eq!(names(orphans(&graph, &History::default())), vec!["numpy"]);
+    }
+}
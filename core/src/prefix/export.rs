@@ -0,0 +1,202 @@
+//! Writing an environment back out as something that recreates it: an `environment.yml`, as
+//! `conda env export` writes, or an explicit list of package URLs, as `conda list --explicit`.
+
+use std::error::Error;
+use std::fmt;
+
+use petgraph::algo::toposort;
+use serde::Serialize;
+
+use crate::prefix::analysis::installed_graph;
+use crate::prefix::prefix::PrefixRecord;
+use crate::repodata::platform::Platform;
+
+/// What goes into an `environment.yml`.
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    pub name: Option<String>,
+    pub prefix: Option<String>,
+    /// Pin builds as well as versions (`numpy=1.20.1=py39h_0`); `conda env export --no-builds`
+    ///    leaves them out
+    pub builds: bool,
+    /// List the channels the packages came from
+    pub channels: bool,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct EnvironmentExport {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<String>,
+    pub dependencies: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+}
+
+impl EnvironmentExport {
+    pub fn to_yaml(&self) -> Result<String, serde_yaml::Error> {
+        serde_yaml::to_string(self)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportError {
+    /// A record with neither a URL nor a channel, so there is nowhere to say it came from
+    MissingUrl(String),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExportError::MissingUrl(name) => write!(f, "no URL recorded for installed package {}", name),
+        }
+    }
+}
+
+impl Error for ExportError {}
+
+/// Describe the environment made of `records` as an `environment.yml`, one `name=version[=build]`
+///    per package, sorted by name.  Channels are listed in the order their packages first appear.
+pub fn export_environment(records: &[PrefixRecord], options: &ExportOptions) -> EnvironmentExport {
+    let mut sorted: Vec<&PrefixRecord> = records.iter().collect();
+    sorted.sort_by(|a, b| a.record.name.cmp(&b.record.name));
+    let dependencies = sorted.iter().map(|r| {
+        if options.builds {
+            format!("{}={}={}", r.record.name, r.record.version, r.record.build)
+        } else {
+            format!("{}={}", r.record.name, r.record.version)
+        }
+    }).collect();
+    let mut channels: Vec<String> = vec![];
+    if options.channels {
+        for channel in sorted.iter().filter_map(|r| r.channel.as_deref()) {
+            let name = channel_name(channel);
+            if !channels.iter().any(|c| c == name) {
+                channels.push(name.to_string());
+            }
+        }
+    }
+    EnvironmentExport { name: options.name.clone(), channels, dependencies, prefix: options.prefix.clone() }
+}
+
+/// The `conda list --explicit` file for `records`: a URL per package, dependencies before the
+///    packages that need them, each followed by `#<md5>` if `md5` is set.
+pub fn export_explicit(records: &[PrefixRecord], md5: bool) -> Result<String, ExportError> {
+    let graph = installed_graph(records);
+    // edges point from a package to its dependencies, so dependencies come last; a cycle has no
+    //    such order, and then the packages just go by name
+    let order: Vec<&PrefixRecord> = match toposort(&graph, None) {
+        Ok(nodes) => nodes.into_iter().rev().map(|n| graph[n]).collect(),
+        Err(_) => records.iter().collect(),
+    };
+    let mut urls = Vec::with_capacity(order.len());
+    for record in &order {
+        urls.push(package_url(record)?);
+    }
+    let platform = urls.iter()
+        .filter_map(|u| u.rsplit('/').nth(1))
+        .find(|s| s.parse::<Platform>().is_ok_and(|p| p != Platform::NoArch))
+        .unwrap_or("noarch");
+
+    let mut out = format!(
+        "# This file may be used to create an environment using:\n\
+         # $ conda create --name <env> --file <this file>\n\
+         # platform: {}\n@EXPLICIT\n", platform);
+    for (record, url) in order.iter().zip(urls) {
+        out.push_str(&url);
+        if md5 && !record.record.md5.is_empty() {
+            out.push('#');
+            out.push_str(&record.record.md5);
+        }
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// Where a record was downloaded from: its `url`, or failing that its channel and filename.
+fn package_url(record: &PrefixRecord) -> Result<String, ExportError> {
+    match (&record.url, &record.channel) {
+        (Some(url), _) => Ok(url.clone()),
+        (None, Some(channel)) if !record.filename.is_empty() =>
+            Ok(format!("{}/{}", channel.trim_end_matches('/'), record.filename)),
+        _ => Err(ExportError::MissingUrl(record.record.name.to_string())),
+    }
+}
+
+/// The name an environment file gives a channel: `defaults` for Anaconda's main channels, the
+///    bare name for anaconda.org channels, and otherwise the URL, all without a trailing subdir.
+fn channel_name(channel: &str) -> &str {
+    let channel = match channel.rsplit_once('/') {
+        Some((base, subdir)) if subdir.parse::<Platform>().is_ok() => base,
+        _ => channel,
+    };
+    let channel = channel.trim_end_matches('/');
+    if channel.starts_with("https://repo.anaconda.com/pkgs/") || channel.starts_with("pkgs/") {
+        "defaults"
+    } else if let Some(name) = channel.strip_prefix("https://conda.anaconda.org/") {
+        name
+    } else {
+        channel
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use super::*;
+    use crate::read_prefix;
+
+    fn records() -> Vec<PrefixRecord> {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("tests/data/prefix");
+        read_prefix(d).unwrap()
+    }
+
+    #[test]
+    fn test_export_environment() {
+        let options = ExportOptions { name: Some("test".to_string()), builds: true, channels: true, ..Default::default() };
+        let export = export_environment(&records(), &options);
+        assert_eq!(export.channels, vec!["defaults"]);
+        assert_eq!(export.dependencies, vec!["vc=14.1=h0510ff6_4", "zlib=1.2.11=h62dcd97_4"]);
+        assert_eq!(export.to_yaml().unwrap(),
+                   "name: test\nchannels:\n- defaults\ndependencies:\n- vc=14.1=h0510ff6_4\n- zlib=1.2.11=h62dcd97_4\n");
+
+        let bare = export_environment(&records(), &ExportOptions::default());
+        assert_eq!(bare.to_yaml().unwrap(), "dependencies:\n- vc=14.1\n- zlib=1.2.11\n");
+    }
+
+    #[test]
+    fn test_export_explicit() {
+        let explicit = export_explicit(&records(), true).unwrap();
+        let lines: Vec<&str> = explicit.lines().collect();
+        assert_eq!(lines[2], "# platform: win-64");
+        assert_eq!(lines[3], "@EXPLICIT");
+        // vc before zlib, which depends on it
+        assert_eq!(&lines[4..], &[
+            "https://repo.anaconda.com/pkgs/main/win-64/vc-14.1-h0510ff6_4.tar.bz2#6ea7a1ba5a6d7e5d4a6a5ffa3d2c4f3c",
+            "https://repo.anaconda.com/pkgs/main/win-64/zlib-1.2.11-h62dcd97_4.tar.bz2#35a3a5ac4a5b3ac7b1e8e4ad4a45a8ad",
+        ]);
+    }
+
+    #[test]
+    fn test_export_explicit_without_url() {
+        let mut records = records();
+        records[0].url = None;
+        assert!(export_explicit(&records, false).unwrap().contains("/win-64/vc-14.1-h0510ff6_4.tar.bz2\n"));
+        records[0].channel = None;
+        assert_eq!(export_explicit(&records, false), Err(ExportError::MissingUrl("vc".to_string())));
+    }
+
+    #[rstest(
+        channel, name,
+        case("https://repo.anaconda.com/pkgs/main/win-64", "defaults"),
+        case("pkgs/main", "defaults"),
+        case("https://conda.anaconda.org/conda-forge/linux-64", "conda-forge"),
+        case("conda-forge", "conda-forge"),
+        case("https://example.com/channel/", "https://example.com/channel")
+    )]
+    fn test_channel_name(channel: &str, name: &str) {
+        assert_eq!(channel_name(channel), name);
+    }
+}
@@ -0,0 +1,281 @@
+//! The `conda-meta/history` file: a log of every transaction applied to an environment.
+//!
+//! Each revision starts with a `==> <date> <==` header, followed by comment lines (`# cmd: ...`,
+//! `# update specs: [...]`, ...) and one `+channel::dist` or `-channel::dist` line per package
+//! linked or unlinked.  The spec lists say which packages the user asked for, as opposed to the
+//! ones pulled in as dependencies.
+//!
+//! Replaying the revisions in order gives the packages installed after each one, which is what
+//! `conda list --revisions` shows and `conda install --revision` restores.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+use crate::prefix::prefix::PrefixReadError;
+use crate::prefix::transaction::{Change, Dist, Transaction};
+
+/// One transaction in the history.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Revision {
+    pub date: String,
+    /// The command that made this revision, if conda noted it
+    pub cmd: Option<String>,
+    /// Dists linked, as `channel::name-version-build`
+    pub added: Vec<String>,
+    /// Dists unlinked, as `channel::name-version-build`
+    pub removed: Vec<String>,
+    /// Specs the user asked for: the `update`, `install` and `neutered` spec lists
+    pub update_specs: Vec<String>,
+    pub remove_specs: Vec<String>,
+}
+
+impl Revision {
+    /// What this revision unlinked and linked.  Dists that don't parse are left out.
+    pub fn transaction(&self) -> Transaction {
+        let parse = |dists: &[String]| dists.iter().filter_map(|d| Dist::parse(d)).collect();
+        Transaction { unlink: parse(&self.removed), link: parse(&self.added) }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct History {
+    pub revisions: Vec<Revision>,
+}
+
+impl History {
+    /// Parse the contents of a history file.  Like conda, this is lenient: lines it doesn't
+    ///    understand are skipped.
+    pub fn parse(contents: &str) -> History {
+        let mut revisions: Vec<Revision> = vec![];
+        for line in contents.lines().map(str::trim) {
+            if let Some(date) = line.strip_prefix("==>").and_then(|l| l.strip_suffix("<==")) {
+                revisions.push(Revision { date: date.trim().to_string(), ..Default::default() });
+                continue;
+            }
+            let revision = match revisions.last_mut() {
+                Some(revision) => revision,
+                None => continue,
+            };
+            if let Some(comment) = line.strip_prefix('#') {
+                let (key, value) = match comment.split_once(':') {
+                    Some((key, value)) => (key.trim(), value.trim()),
+                    None => continue,
+                };
+                match key {
+                    "cmd" => revision.cmd = Some(value.to_string()),
+                    "update specs" | "install specs" | "neutered specs" =>
+                        revision.update_specs.extend(parse_spec_list(value)),
+                    "remove specs" => revision.remove_specs.extend(parse_spec_list(value)),
+                    _ => {}
+                }
+            } else if let Some(dist) = line.strip_prefix('+') {
+                revision.added.push(dist.to_string());
+            } else if let Some(dist) = line.strip_prefix('-') {
+                revision.removed.push(dist.to_string());
+            }
+        }
+        History { revisions }
+    }
+
+    /// The names of the packages the user has asked for and not since removed, with the spec
+    ///    they last asked for each by.
+    pub fn requested_specs(&self) -> HashMap<&str, &str> {
+        let mut requested = HashMap::new();
+        for revision in &self.revisions {
+            for spec in &revision.update_specs {
+                requested.insert(spec_name(spec), spec.as_str());
+            }
+            for spec in &revision.remove_specs {
+                requested.remove(spec_name(spec));
+            }
+        }
+        requested
+    }
+
+    /// The packages installed after revision `revision` (counting from 0, as conda does), sorted
+    ///    by name; `None` if there is no such revision.  Like conda, the revisions are replayed
+    ///    leniently: unlinking a package that isn't there is ignored, and linking another version
+    ///    of an installed package replaces it.
+    pub fn state_at(&self, revision: usize) -> Option<Vec<Dist>> {
+        if revision >= self.revisions.len() {
+            return None;
+        }
+        let mut state: Vec<Dist> = vec![];
+        for transaction in self.revisions[..=revision].iter().map(Revision::transaction) {
+            state.retain(|d| !transaction.unlink.contains(d) && !transaction.link.iter().any(|l| l.name == d.name));
+            state.extend(transaction.link);
+        }
+        state.sort_by(|a, b| a.name.cmp(&b.name));
+        Some(state)
+    }
+
+    /// The transaction from the packages installed after revision `from` to those installed
+    ///    after revision `to`.
+    pub fn diff(&self, from: usize, to: usize) -> Option<Transaction> {
+        Some(Transaction::between(&self.state_at(from)?, &self.state_at(to)?))
+    }
+
+    /// The transaction that takes an environment with the `installed` packages back to
+    ///    revision `revision`, as `conda install --revision` does.
+    pub fn transaction_to(&self, revision: usize, installed: &[Dist]) -> Option<Transaction> {
+        Some(Transaction::between(installed, &self.state_at(revision)?))
+    }
+
+    /// The revisions as `conda list --revisions` lists them: the date and number of each, then
+    ///    a line for each package it changed.
+    ///
+    /// ```text
+    /// 2021-03-02 09:00:00  (rev 1)
+    ///     +pandas-1.2.3 (defaults)
+    ///     numpy  {1.20.1 (defaults) -> 1.26.4 (conda-forge)}
+    /// ```
+    pub fn list_revisions(&self) -> String {
+        let with_channel = |dist: &Dist| match &dist.channel {
+            Some(channel) => format!("{} ({})", dist.version, channel),
+            None => dist.version.clone(),
+        };
+        let mut out = vec![];
+        for (i, revision) in self.revisions.iter().enumerate() {
+            let mut lines = vec![format!("{}  (rev {})", revision.date, i)];
+            for change in revision.transaction().changes() {
+                lines.push(match change {
+                    Change::Install(dist) => format!("    +{}-{}", dist.name, with_channel(dist)),
+                    Change::Remove(dist) => format!("    -{}-{}", dist.name, with_channel(dist)),
+                    Change::Upgrade { from, to } | Change::Downgrade { from, to } | Change::Replace { from, to } =>
+                        format!("    {}  {{{} -> {}}}", to.name, with_channel(from), with_channel(to)),
+                });
+            }
+            out.push(lines.join("\n"));
+        }
+        out.join("\n\n")
+    }
+}
+
+/// The package name a history spec (e.g. `conda-forge::numpy[version='>=1.20']`) is about.
+fn spec_name(spec: &str) -> &str {
+    let spec = spec.rsplit("::").next().unwrap_or(spec).trim();
+    let end = spec.find(|c: char| c.is_whitespace() || "=<>!~[".contains(c)).unwrap_or(spec.len());
+    &spec[..end]
+}
+
+/// The items of a Python list literal of strings, e.g. `['numpy', "python[version='>=3.8']"]`.
+///    Commas inside the quotes don't split items.
+fn parse_spec_list(list: &str) -> Vec<String> {
+    let mut specs = vec![];
+    let mut chars = list.chars();
+    while let Some(c) = chars.next() {
+        if c == '\'' || c == '"' {
+            specs.push(chars.by_ref().take_while(|&q| q != c).collect());
+        }
+    }
+    specs
+}
+
+/// Read `conda-meta/history` in the environment at `prefix`.  An environment without one has
+///    an empty history.
+pub fn read_history<P: AsRef<Path>>(prefix: P) -> Result<History, PrefixReadError> {
+    let path = prefix.as_ref().join("conda-meta").join("history");
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => Ok(History::parse(&contents)),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(History::default()),
+        Err(e) => Err(PrefixReadError::Io(path, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use super::*;
+
+    const HISTORY: &str = "\
+==> 2021-03-01 09:00:00 <==
+# cmd: conda create -n test numpy
+# conda version: 4.9.2
++defaults::numpy-1.20.1-py39h34a8a5c_0
++defaults::python-3.9.2-h6244533_0
+# update specs: ['numpy']
+==> 2021-03-02 09:00:00 <==
+# cmd: conda install \"pandas>=1.2,<2\" conda-forge::requests
++defaults::pandas-1.2.3-py39hf11a4ad_0
++conda-forge::requests-2.25.1-pyhd3deb0d_0
+# update specs: [\"pandas[version='>=1.2,<2']\", 'conda-forge::requests']
+==> 2021-03-03 09:00:00 <==
+# cmd: conda remove numpy
+-defaults::numpy-1.20.1-py39h34a8a5c_0
+# remove specs: ['numpy']
+";
+
+    #[test]
+    fn test_parse() {
+        let history = History::parse(HISTORY);
+        assert_eq!(history.revisions.len(), 3);
+        let second = &history.revisions[1];
+        assert_eq!(second.date, "2021-03-02 09:00:00");
+        assert_eq!(second.cmd.as_deref(), Some("conda install \"pandas>=1.2,<2\" conda-forge::requests"));
+        assert_eq!(second.added, vec!["defaults::pandas-1.2.3-py39hf11a4ad_0", "conda-forge::requests-2.25.1-pyhd3deb0d_0"]);
+        assert_eq!(second.update_specs, vec!["pandas[version='>=1.2,<2']", "conda-forge::requests"]);
+        assert_eq!(history.revisions[2].removed, vec!["defaults::numpy-1.20.1-py39h34a8a5c_0"]);
+    }
+
+    #[test]
+    fn test_requested_specs() {
+        let history = History::parse(HISTORY);
+        let mut requested: Vec<(&str, &str)> = history.requested_specs().into_iter().collect();
+        requested.sort();
+        assert_eq!(requested, vec![("pandas", "pandas[version='>=1.2,<2']"), ("requests", "conda-forge::requests")]);
+    }
+
+    #[test]
+    fn test_revisions() {
+        let mut history = History::parse(HISTORY);
+        history.revisions.extend(History::parse("\
+==> 2021-03-04 09:00:00 <==
+-defaults::pandas-1.2.3-py39hf11a4ad_0
++conda-forge::pandas-1.3.0-py39h_0
+").revisions);
+        let names = |state: Vec<Dist>| state.iter().map(Dist::to_string).collect::<Vec<_>>();
+        assert_eq!(names(history.state_at(0).unwrap()),
+                   ["defaults::numpy-1.20.1-py39h34a8a5c_0", "defaults::python-3.9.2-h6244533_0"]);
+        assert_eq!(names(history.state_at(3).unwrap()), [
+            "conda-forge::pandas-1.3.0-py39h_0", "defaults::python-3.9.2-h6244533_0",
+            "conda-forge::requests-2.25.1-pyhd3deb0d_0",
+        ]);
+        assert!(history.state_at(4).is_none());
+
+        let diff = history.diff(0, 3).unwrap();
+        assert!(matches!(diff.changes()[..], [Change::Remove(_), Change::Install(_), Change::Install(_)]));
+        let installed = history.state_at(3).unwrap();
+        let back = history.transaction_to(1, &installed).unwrap();
+        assert_eq!(back.apply(&installed).unwrap(), history.state_at(1).unwrap());
+        assert!(history.transaction_to(3, &installed).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_list_revisions() {
+        let mut history = History::parse(HISTORY);
+        history.revisions[2].added.push("conda-forge::numpy-1.26.4-py39_0".to_string());
+        assert_eq!(history.list_revisions(), "\
+2021-03-01 09:00:00  (rev 0)
+    +numpy-1.20.1 (defaults)
+    +python-3.9.2 (defaults)
+
+2021-03-02 09:00:00  (rev 1)
+    +pandas-1.2.3 (defaults)
+    +requests-2.25.1 (conda-forge)
+
+2021-03-03 09:00:00  (rev 2)
+    numpy  {1.20.1 (defaults) -> 1.26.4 (conda-forge)}");
+    }
+
+    #[test]
+    fn test_read_history() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("tests/data/prefix");
+        let history = read_history(&d).unwrap();
+        assert_eq!(history.revisions.len(), 1);
+        assert_eq!(history.revisions[0].added.len(), 2);
+        d.push("does-not-exist");
+        assert_eq!(read_history(&d).unwrap(), History::default());
+    }
+}
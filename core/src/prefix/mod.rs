@@ -0,0 +1,7 @@
+#[cfg(feature = "graph")]
+pub mod analysis;
+#[cfg(feature = "graph")]
+pub mod export;
+pub mod history;
+pub mod prefix;
+pub mod transaction;
@@ -0,0 +1,267 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::Record;
+
+/// How a package's files were put into the prefix.  Numbered as conda writes them.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(from = "u8", into = "u8")]
+pub enum LinkType {
+    HardLink,
+    SoftLink,
+    Copy,
+    Directory,
+    Unknown(u8),
+}
+
+impl From<u8> for LinkType {
+    fn from(t: u8) -> Self {
+        match t {
+            1 => LinkType::HardLink,
+            2 => LinkType::SoftLink,
+            3 => LinkType::Copy,
+            4 => LinkType::Directory,
+            other => LinkType::Unknown(other),
+        }
+    }
+}
+
+impl From<LinkType> for u8 {
+    fn from(t: LinkType) -> u8 {
+        match t {
+            LinkType::HardLink => 1,
+            LinkType::SoftLink => 2,
+            LinkType::Copy => 3,
+            LinkType::Directory => 4,
+            LinkType::Unknown(other) => other,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Link {
+    /// The extracted package directory the files were linked from.
+    pub source: String,
+    #[serde(rename = "type")]
+    pub link_type: LinkType,
+}
+
+/// A package installed in an environment, as recorded in `conda-meta/<dist>.json`: the repodata
+///    record plus what conda noted when linking it.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct PrefixRecord {
+    #[serde(flatten)]
+    pub record: Record,
+    #[serde(rename = "fn", default)]
+    pub filename: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub files: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<Link>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requested_spec: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extracted_package_dir: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package_tarball_full_path: Option<String>,
+}
+
+impl PrefixRecord {
+    /// The record of a package downloaded from `url` in `channel`, with the `files` it
+    ///    installed.  The filename is taken from the end of the url; `link` and the other
+    ///    optional members are left for the installer to fill in.
+    pub fn new(record: Record, url: &str, channel: &str, files: Vec<String>) -> PrefixRecord {
+        PrefixRecord {
+            record,
+            filename: url.rsplit('/').next().unwrap_or(url).to_string(),
+            channel: Some(channel.to_string()),
+            url: Some(url.to_string()),
+            files,
+            link: None,
+            requested_spec: None,
+            extracted_package_dir: None,
+            package_tarball_full_path: None,
+        }
+    }
+
+    /// `<name>-<version>-<build>`, the name of the record's file in `conda-meta`.
+    pub fn dist_name(&self) -> String {
+        format!("{}-{}-{}", self.record.name, self.record.version.as_str(), self.record.build)
+    }
+}
+
+#[derive(Debug)]
+pub enum PrefixReadError {
+    Io(PathBuf, io::Error),
+    Json(PathBuf, serde_json::error::Error),
+}
+
+impl fmt::Display for PrefixReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PrefixReadError::Io(path, e) => write!(f, "unable to read {}: {}", path.display(), e),
+            PrefixReadError::Json(path, e) => write!(f, "unable to parse {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl Error for PrefixReadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PrefixReadError::Io(_, e) => Some(e),
+            PrefixReadError::Json(_, e) => Some(e),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PrefixWriteError {
+    Io(PathBuf, io::Error),
+    Json(serde_json::error::Error),
+}
+
+impl fmt::Display for PrefixWriteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PrefixWriteError::Io(path, e) => write!(f, "unable to write {}: {}", path.display(), e),
+            PrefixWriteError::Json(e) => write!(f, "unable to serialize prefix record: {}", e),
+        }
+    }
+}
+
+impl Error for PrefixWriteError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PrefixWriteError::Io(_, e) => Some(e),
+            PrefixWriteError::Json(e) => Some(e),
+        }
+    }
+}
+
+/// Read one `conda-meta/<dist>.json` file.
+pub fn read_prefix_record<P: AsRef<Path>>(path: P) -> Result<PrefixRecord, PrefixReadError> {
+    let path = path.as_ref();
+    let contents = std::fs::read_to_string(path).map_err(|e| PrefixReadError::Io(path.to_path_buf(), e))?;
+    serde_json::from_str(&contents).map_err(|e| PrefixReadError::Json(path.to_path_buf(), e))
+}
+
+/// Read the packages installed in the environment at `prefix`, sorted by package name.
+///
+/// An environment without a `conda-meta` directory has nothing installed and yields no records.
+/// Anything in `conda-meta` other than `*.json` (e.g. the `history` file) is ignored.
+pub fn read_prefix<P: AsRef<Path>>(prefix: P) -> Result<Vec<PrefixRecord>, PrefixReadError> {
+    let meta_dir = prefix.as_ref().join("conda-meta");
+    let entries = match std::fs::read_dir(&meta_dir) {
+        Ok(entries) => entries,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(PrefixReadError::Io(meta_dir, e)),
+    };
+    let mut records = vec![];
+    for entry in entries {
+        let path = entry.map_err(|e| PrefixReadError::Io(meta_dir.clone(), e))?.path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            records.push(read_prefix_record(&path)?);
+        }
+    }
+    records.sort_by(|a, b| a.record.name.cmp(&b.record.name));
+    Ok(records)
+}
+
+/// Write `record` to `conda-meta/<dist>.json` in the environment at `prefix`, as conda does
+///    after linking a package: keys sorted, indented by two spaces.  The file is written beside
+///    its final name and renamed into place, so readers never see half of it.  Returns the path
+///    written.
+pub fn write_prefix_record<P: AsRef<Path>>(prefix: P, record: &PrefixRecord) -> Result<PathBuf, PrefixWriteError> {
+    let meta_dir = prefix.as_ref().join("conda-meta");
+    std::fs::create_dir_all(&meta_dir).map_err(|e| PrefixWriteError::Io(meta_dir.clone(), e))?;
+    // going through Value sorts the keys
+    let value = serde_json::to_value(record).map_err(PrefixWriteError::Json)?;
+    let mut contents = serde_json::to_string_pretty(&value).map_err(PrefixWriteError::Json)?;
+    contents.push('\n');
+    let path = meta_dir.join(format!("{}.json", record.dist_name()));
+    let partial = meta_dir.join(format!("{}.json.partial", record.dist_name()));
+    std::fs::write(&partial, contents).map_err(|e| PrefixWriteError::Io(partial.clone(), e))?;
+    std::fs::rename(&partial, &path).map_err(|e| PrefixWriteError::Io(path.clone(), e))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use super::*;
+
+    fn test_prefix() -> PathBuf {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("tests/data/prefix");
+        d
+    }
+
+    #[test]
+    fn test_read_prefix() {
+        let records = read_prefix(test_prefix()).unwrap();
+        let names: Vec<&str> = records.iter().map(|r| r.record.name.as_str()).collect();
+        assert_eq!(names, vec!["vc", "zlib"]);
+
+        let zlib = &records[1];
+        assert_eq!(zlib.record.version, "1.2.11".into());
+        assert_eq!(zlib.record.build_number, 4);
+        assert_eq!(zlib.filename, "zlib-1.2.11-h62dcd97_4.tar.bz2");
+        assert_eq!(zlib.files.len(), 4);
+        assert_eq!(zlib.requested_spec.as_deref(), Some("zlib"));
+        assert_eq!(zlib.link.as_ref().unwrap().link_type, LinkType::HardLink);
+
+        // older records don't carry sha256 or timestamp
+        let vc = &records[0];
+        assert_eq!(vc.record.sha256, "");
+        assert!(vc.record.timestamp.is_none());
+        assert!(vc.requested_spec.is_none());
+    }
+
+    #[test]
+    fn test_write_prefix_record() {
+        let record: Record = serde_json::from_str(r#"{"build": "0", "build_number": 0, "depends": ["vc"],
+            "md5": "abc", "name": "zlib", "size": 10, "version": "1.2.11"}"#).unwrap();
+        let mut written = PrefixRecord::new(record, "https://conda.anaconda.org/conda-forge/win-64/zlib-1.2.11-0.conda",
+                                            "https://conda.anaconda.org/conda-forge/win-64", vec!["Library/bin/zlib.dll".into()]);
+        written.link = Some(Link { source: "C:/pkgs/zlib-1.2.11-0".into(), link_type: LinkType::Copy });
+        written.requested_spec = Some("zlib".into());
+
+        let prefix = std::env::temp_dir().join(format!("ronda-write-prefix-{}", std::process::id()));
+        let path = write_prefix_record(&prefix, &written).unwrap();
+        assert_eq!(path, prefix.join("conda-meta/zlib-1.2.11-0.json"));
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.starts_with("{\n  \"build\": \"0\",\n  \"build_number\": 0,"));
+
+        let read = read_prefix(&prefix).unwrap();
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].filename, "zlib-1.2.11-0.conda");
+        assert_eq!(read[0].files, written.files);
+        assert_eq!(read[0].record.depends, vec!["vc"]);
+        assert_eq!(read[0].link.as_ref().unwrap().link_type, LinkType::Copy);
+        assert_eq!(read[0].requested_spec.as_deref(), Some("zlib"));
+        std::fs::remove_dir_all(&prefix).unwrap();
+    }
+
+    #[test]
+    fn test_read_prefix_without_conda_meta() {
+        let mut d = test_prefix();
+        d.push("does-not-exist");
+        assert!(read_prefix(d).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_read_prefix_record_error_names_file() {
+        let mut d = test_prefix();
+        d.push("conda-meta/history");
+        let err = read_prefix_record(&d).unwrap_err();
+        assert!(err.to_string().contains("history"));
+    }
+}
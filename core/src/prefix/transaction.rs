@@ -0,0 +1,418 @@
+//! The packages a change to an environment unlinks and links, and how to show them.
+//!
+//! A transaction is the difference between two sets of installed packages, however the new set
+//! was arrived at.  It is kept as conda keeps it, two lists of dists, and only paired up by
+//! package name (`numpy 1.20.1 → 1.26.4`) when shown.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::prefix::prefix::PrefixRecord;
+use crate::Version;
+
+/// A package as a transaction sees it.  Packages read from the history only have a name,
+///    version, build and channel; the size is known for those from repodata or `conda-meta`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct Dist {
+    pub name: String,
+    pub version: String,
+    #[serde(rename = "build_string")]
+    pub build: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+    /// Download size in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+}
+
+impl Dist {
+    /// Parse a dist as the history writes it, `channel::name-version-build`, the channel being
+    ///    optional.  `None` if it doesn't have a name, version and build.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ronda::Dist;
+    ///
+    /// let dist = Dist::parse("conda-forge::python-dateutil-2.8.2-pyhd8ed1ab_0").unwrap();
+    /// assert_eq!((dist.name.as_str(), dist.version.as_str()), ("python-dateutil", "2.8.2"));
+    /// assert_eq!(dist.channel.as_deref(), Some("conda-forge"));
+    /// assert!(Dist::parse("numpy").is_none());
+    /// ```
+    pub fn parse(dist: &str) -> Option<Dist> {
+        let (channel, dist) = match dist.rsplit_once("::") {
+            Some((channel, dist)) => (Some(channel.to_string()), dist),
+            None => (None, dist),
+        };
+        let mut fields = dist.rsplitn(3, '-');
+        let (build, version, name) = (fields.next()?, fields.next()?, fields.next()?);
+        if name.is_empty() || version.is_empty() || build.is_empty() {
+            return None;
+        }
+        Some(Dist { name: name.to_string(), version: version.to_string(), build: build.to_string(), channel, size: None })
+    }
+
+    /// `name-version-build`
+    pub fn dist_name(&self) -> String {
+        format!("{}-{}-{}", self.name, self.version, self.build)
+    }
+
+    // Sizes aren't always known, so they don't make a package different
+    fn same_package(&self, other: &Dist) -> bool {
+        self.name == other.name && self.version == other.version && self.build == other.build && self.channel == other.channel
+    }
+}
+
+impl From<&PrefixRecord> for Dist {
+    fn from(record: &PrefixRecord) -> Dist {
+        Dist {
+            name: record.record.name.to_string(),
+            version: record.record.version.as_str().to_string(),
+            build: record.record.build.to_string(),
+            channel: record.channel.clone(),
+            size: Some(record.record.size),
+        }
+    }
+}
+
+/// `channel::name-version-build`, as in the history.
+impl fmt::Display for Dist {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.channel {
+            Some(channel) => write!(f, "{}::{}", channel, self.dist_name()),
+            None => f.write_str(&self.dist_name()),
+        }
+    }
+}
+
+/// What a transaction does to one package.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change<'a> {
+    Install(&'a Dist),
+    Remove(&'a Dist),
+    Upgrade { from: &'a Dist, to: &'a Dist },
+    Downgrade { from: &'a Dist, to: &'a Dist },
+    /// The same version, from another build or channel, or a change to or from a version that
+    ///    doesn't parse
+    Replace { from: &'a Dist, to: &'a Dist },
+}
+
+impl<'a> Change<'a> {
+    pub fn name(&self) -> &'a str {
+        match *self {
+            Change::Install(dist) | Change::Remove(dist) => &dist.name,
+            Change::Upgrade { to, .. } | Change::Downgrade { to, .. } | Change::Replace { to, .. } => &to.name,
+        }
+    }
+
+    fn action(&self) -> &'static str {
+        match self {
+            Change::Install(_) => "install",
+            Change::Remove(_) => "remove",
+            Change::Upgrade { .. } => "upgrade",
+            Change::Downgrade { .. } => "downgrade",
+            Change::Replace { .. } => "replace",
+        }
+    }
+}
+
+/// The packages to unlink from an environment and the packages to link into it.  Serializes
+///    as conda's `--json` output names its actions, `{"UNLINK": [...], "LINK": [...]}`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct Transaction {
+    #[serde(default)]
+    pub unlink: Vec<Dist>,
+    #[serde(default)]
+    pub link: Vec<Dist>,
+}
+
+impl Transaction {
+    /// The transaction that turns an environment with the `installed` packages into one with the
+    ///    `target` packages.  Packages in both are left alone; the lists are sorted by name.
+    pub fn between(installed: &[Dist], target: &[Dist]) -> Transaction {
+        let missing_from = |from: &[Dist], to: &[Dist]| -> Vec<Dist> {
+            let mut missing: Vec<Dist> = from.iter().filter(|d| !to.iter().any(|t| t.same_package(d))).cloned().collect();
+            missing.sort_by(|a, b| a.name.cmp(&b.name));
+            missing
+        };
+        Transaction { unlink: missing_from(installed, target), link: missing_from(target, installed) }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.unlink.is_empty() && self.link.is_empty()
+    }
+
+    /// The packages installed after applying this transaction to an environment with the
+    ///    `installed` packages.  Fails if it unlinks a package that isn't installed, or links one
+    ///    next to another version of the same package.
+    pub fn apply(&self, installed: &[Dist]) -> Result<Vec<Dist>, TransactionError> {
+        if let Some(missing) = self.unlink.iter().find(|u| !installed.iter().any(|i| i.same_package(u))) {
+            return Err(TransactionError::NotInstalled(missing.clone()));
+        }
+        let mut after: Vec<Dist> = installed.iter().filter(|i| !self.unlink.iter().any(|u| u.same_package(i))).cloned().collect();
+        for dist in &self.link {
+            if after.iter().any(|a| a.name == dist.name) {
+                return Err(TransactionError::Conflict(dist.clone()));
+            }
+            after.push(dist.clone());
+        }
+        after.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(after)
+    }
+
+    /// The transaction that undoes this one, given the packages installed before it was
+    ///    applied: it relinks what this unlinked and unlinks what it linked, restoring `prior`
+    ///    as it was, sizes and channels included.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ronda::{Dist, Transaction};
+    ///
+    /// let prior = [Dist::parse("defaults::numpy-1.20.1-py39_0").unwrap()];
+    /// let update = Transaction::between(&prior, &[Dist::parse("conda-forge::numpy-1.26.4-py39_0").unwrap()]);
+    /// let rollback = update.rollback(&prior).unwrap();
+    /// assert_eq!((rollback.unlink[0].version.as_str(), rollback.link[0].version.as_str()), ("1.26.4", "1.20.1"));
+    /// ```
+    pub fn rollback(&self, prior: &[Dist]) -> Result<Transaction, TransactionError> {
+        Ok(Transaction::between(&self.apply(prior)?, prior))
+    }
+
+    /// The change to each package, by name: a package both unlinked and linked is upgraded,
+    ///    downgraded or replaced.
+    pub fn changes(&self) -> Vec<Change<'_>> {
+        let mut by_name: BTreeMap<&str, (Option<&Dist>, Option<&Dist>)> = BTreeMap::new();
+        for dist in &self.unlink {
+            by_name.entry(&dist.name).or_default().0 = Some(dist);
+        }
+        for dist in &self.link {
+            by_name.entry(&dist.name).or_default().1 = Some(dist);
+        }
+        by_name.into_values().filter_map(|pair| match pair {
+            (Some(from), Some(to)) => {
+                // versions that don't parse can't be ordered, so a change between them is a replacement
+                let ord = match (from.version.parse::<Version>(), to.version.parse::<Version>()) {
+                    (Ok(old), Ok(new)) => new.partial_cmp(&old),
+                    _ => None,
+                };
+                Some(match ord {
+                    Some(Ordering::Greater) => Change::Upgrade { from, to },
+                    Some(Ordering::Less) => Change::Downgrade { from, to },
+                    _ => Change::Replace { from, to },
+                })
+            }
+            (Some(from), None) => Some(Change::Remove(from)),
+            (None, Some(to)) => Some(Change::Install(to)),
+            (None, None) => None,
+        }).collect()
+    }
+
+    /// The total size of the packages to link, as far as it is known.
+    pub fn download_size(&self) -> u64 {
+        self.link.iter().filter_map(|d| d.size).sum()
+    }
+
+    /// A table of the changes for a `--dry-run`: one row per package with what happens to it,
+    ///    the versions before and after, and the channel and size of the package linked.
+    ///
+    /// ```text
+    /// Package  Action   Version                                Channel      Size
+    /// numpy    upgrade  1.20.1-py39h34a8a5c_0 → 1.26.4-py39_0  conda-forge  7.1 MB
+    /// six      remove   1.16.0-pyh6c4a22f_0                    conda-forge
+    ///
+    /// Total download: 7.1 MB
+    /// ```
+    pub fn dry_run_table(&self) -> String {
+        let mut rows = vec![["Package".to_string(), "Action".to_string(), "Version".to_string(), "Channel".to_string(),
+                             "Size".to_string()]];
+        for change in self.changes() {
+            let (version, shown) = match change {
+                Change::Install(dist) | Change::Remove(dist) => (format!("{}-{}", dist.version, dist.build), dist),
+                Change::Upgrade { from, to } | Change::Downgrade { from, to } | Change::Replace { from, to } =>
+                    (format!("{}-{} → {}-{}", from.version, from.build, to.version, to.build), to),
+            };
+            let size = match change {
+                Change::Remove(_) => String::new(),
+                _ => shown.size.map(format_size).unwrap_or_default(),
+            };
+            rows.push([change.name().to_string(), change.action().to_string(), version,
+                       shown.channel.clone().unwrap_or_default(), size]);
+        }
+        let mut widths = [0; 5];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        let mut table = String::new();
+        for row in &rows {
+            let line: Vec<String> = row.iter().zip(widths).map(|(cell, width)| format!("{:<1$}", cell, width)).collect();
+            table.push_str(line.join("  ").trim_end());
+            table.push('\n');
+        }
+        table.push_str(&format!("\nTotal download: {}\n", format_size(self.download_size())));
+        table
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransactionError {
+    /// A package to unlink isn't installed
+    NotInstalled(Dist),
+    /// A package to link would be installed next to another version of itself
+    Conflict(Dist),
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransactionError::NotInstalled(dist) => write!(f, "{} is not installed", dist),
+            TransactionError::Conflict(dist) => write!(f, "{} would be installed next to another {}", dist, dist.name),
+        }
+    }
+}
+
+impl Error for TransactionError {}
+
+// Sizes as conda shows them, in decimal units
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1000 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1000.0;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dist(dist: &str, size: u64) -> Dist {
+        Dist { size: Some(size), ..Dist::parse(dist).unwrap() }
+    }
+
+    fn transaction() -> Transaction {
+        let installed = [
+            dist("defaults::numpy-1.20.1-py39h34a8a5c_0", 6_000_000),
+            dist("defaults::python-3.9.2-h6244533_0", 20_000_000),
+            dist("conda-forge::six-1.16.0-pyh6c4a22f_0", 14_000),
+            dist("conda-forge::zlib-1.2.13-h166bdaf_4", 94_000),
+        ];
+        let target = [
+            dist("conda-forge::numpy-1.26.4-py39_0", 7_100_000),
+            dist("defaults::python-3.9.2-h6244533_0", 20_000_000),
+            dist("conda-forge::zlib-1.2.11-h166bdaf_4", 90_000),
+            dist("conda-forge::requests-2.31.0-pyhd8ed1ab_0", 56_700),
+        ];
+        Transaction::between(&installed, &target)
+    }
+
+    #[rstest(dist, expected,
+        case("defaults::numpy-1.20.1-py39h34a8a5c_0", Some(("defaults", "numpy", "1.20.1", "py39h34a8a5c_0"))),
+        case("https://conda.anaconda.org/conda-forge/noarch::python-dateutil-2.8.2-pyhd8ed1ab_0",
+             Some(("https://conda.anaconda.org/conda-forge/noarch", "python-dateutil", "2.8.2", "pyhd8ed1ab_0"))),
+        case("numpy-1.20.1", None),
+        case("defaults::-1.0-0", None)
+    )]
+    fn test_parse(dist: &str, expected: Option<(&str, &str, &str, &str)>) {
+        let parsed = Dist::parse(dist);
+        assert_eq!(parsed.as_ref().map(|d| (d.channel.as_deref().unwrap(), d.name.as_str(), d.version.as_str(), d.build.as_str())),
+                   expected);
+        if let Some(parsed) = parsed {
+            assert_eq!(parsed.to_string(), dist);
+        }
+    }
+
+    #[test]
+    fn test_changes() {
+        let transaction = transaction();
+        assert_eq!(transaction.unlink.len(), 3);
+        assert_eq!(transaction.link.len(), 3);
+        let changes: Vec<(&str, &str)> = transaction.changes().iter().map(|c| (c.name(), c.action())).collect();
+        assert_eq!(changes, [("numpy", "upgrade"), ("requests", "install"), ("six", "remove"), ("zlib", "downgrade")]);
+        assert_eq!(transaction.download_size(), 7_246_700);
+        assert!(Transaction::between(&transaction.link, &transaction.link).is_empty());
+    }
+
+    #[test]
+    fn test_changes_with_unparseable_version() {
+        let transaction = Transaction::between(&[dist("numpy-1!2!3-py39_0", 1)], &[dist("numpy-1.26.4-py39_0", 1)]);
+        let changes: Vec<(&str, &str)> = transaction.changes().iter().map(|c| (c.name(), c.action())).collect();
+        assert_eq!(changes, [("numpy", "replace")]);
+        assert!(transaction.dry_run_table().contains("1!2!3-py39_0 → 1.26.4-py39_0"));
+    }
+
+    #[test]
+    fn test_json() {
+        let transaction = transaction();
+        let json = serde_json::to_value(&transaction).unwrap();
+        assert_eq!(json["LINK"][0], serde_json::json!({"name": "numpy", "version": "1.26.4", "build_string": "py39_0",
+                                                       "channel": "conda-forge", "size": 7_100_000}));
+        assert_eq!(json["UNLINK"].as_array().unwrap().len(), 3);
+        assert_eq!(serde_json::from_value::<Transaction>(json).unwrap(), transaction);
+    }
+
+    #[test]
+    fn test_dry_run_table() {
+        let table = transaction().dry_run_table();
+        assert_eq!(table, "\
+Package   Action     Version                                Channel      Size
+numpy     upgrade    1.20.1-py39h34a8a5c_0 → 1.26.4-py39_0  conda-forge  7.1 MB
+requests  install    2.31.0-pyhd8ed1ab_0                    conda-forge  56.7 KB
+six       remove     1.16.0-pyh6c4a22f_0                    conda-forge
+zlib      downgrade  1.2.13-h166bdaf_4 → 1.2.11-h166bdaf_4  conda-forge  90.0 KB
+
+Total download: 7.2 MB
+");
+    }
+
+    #[test]
+    fn test_rollback() {
+        let prior = [
+            dist("defaults::numpy-1.20.1-py39h34a8a5c_0", 6_000_000),
+            dist("defaults::python-3.9.2-h6244533_0", 20_000_000),
+            dist("conda-forge::six-1.16.0-pyh6c4a22f_0", 14_000),
+            dist("conda-forge::zlib-1.2.13-h166bdaf_4", 94_000),
+        ];
+        let transaction = transaction();
+        let after = transaction.apply(&prior).unwrap();
+        assert_eq!(after.iter().map(|d| d.name.as_str()).collect::<Vec<_>>(), ["numpy", "python", "requests", "zlib"]);
+        let rollback = transaction.rollback(&prior).unwrap();
+        assert_eq!((rollback.unlink.clone(), rollback.link.clone()), (transaction.link.clone(), transaction.unlink.clone()));
+        let mut restored = rollback.apply(&after).unwrap();
+        restored.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(restored, prior);
+        assert_eq!(rollback.download_size(), 6_108_000);
+    }
+
+    #[test]
+    fn test_rollback_needs_the_prior_state() {
+        let transaction = transaction();
+        let numpy = dist("defaults::numpy-1.20.1-py39h34a8a5c_0", 6_000_000);
+        assert_eq!(transaction.rollback(&[]), Err(TransactionError::NotInstalled(numpy.clone())));
+        let conflicting = Transaction { unlink: vec![], link: vec![numpy.clone()] };
+        assert!(matches!(conflicting.apply(&[numpy]), Err(TransactionError::Conflict(_))));
+    }
+
+    #[rstest(bytes, expected,
+        case(999, "999 B"),
+        case(1_500, "1.5 KB"),
+        case(7_100_000, "7.1 MB"),
+        case(2_000_000_000_000_000, "2000.0 TB")
+    )]
+    fn test_format_size(bytes: u64, expected: &str) {
+        assert_eq!(format_size(bytes), expected);
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::{pep440_parser, Spec, Version, VersionSpecOrConstraintTree};
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PypiDigests {
+    #[serde(default)]
+    pub md5: Option<String>,
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
+
+/// One distribution file (sdist or wheel) of a release.
+#[derive(Deserialize, Debug, Clone)]
+pub struct PypiFile {
+    pub filename: String,
+    pub url: String,
+    pub digests: PypiDigests,
+    pub size: u64,
+    pub packagetype: String,
+    #[serde(default)]
+    pub requires_python: Option<String>,
+    #[serde(default)]
+    pub yanked: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct PypiRelease {
+    pub version: Version,
+    pub files: Vec<PypiFile>,
+}
+
+impl PypiRelease {
+    /// A release counts as yanked when every one of its files is.  Releases without files aren't
+    ///    yanked - they just have nothing to install.
+    pub fn is_yanked(&self) -> bool {
+        !self.files.is_empty() && self.files.iter().all(|f| f.yanked)
+    }
+}
+
+/// The releases of one project, as listed by PyPI's JSON API (`/pypi/<project>/json`).
+#[derive(Debug, Clone)]
+pub struct PypiProject {
+    pub name: String,
+    /// Sorted from oldest to newest version.
+    pub releases: Vec<PypiRelease>,
+}
+
+impl PypiProject {
+    /// Releases whose version matches `spec`, oldest first.  Yanked releases are left out, as pip
+    ///    leaves them out unless asked for by exact version.
+    pub fn query(&self, spec: &VersionSpecOrConstraintTree) -> Vec<&PypiRelease> {
+        self.releases.iter()
+            .filter(|r| !r.is_yanked() && spec.test_match_version(&r.version))
+            .collect()
+    }
+
+    /// The newest release that isn't yanked.
+    pub fn latest(&self) -> Option<&PypiRelease> {
+        self.releases.iter().rev().find(|r| !r.is_yanked())
+    }
+}
+
+#[derive(Deserialize)]
+struct PypiInfo {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct PypiJson {
+    info: PypiInfo,
+    releases: HashMap<String, Vec<PypiFile>>,
+}
+
+/// Parse a PyPI JSON API project listing.  Release versions are parsed as PEP 440; the few old
+///    releases whose versions aren't valid PEP 440 are skipped, as pip skips them.
+pub fn read_pypi_json(data: &[u8]) -> Result<PypiProject, serde_json::error::Error> {
+    let listing: PypiJson = serde_json::from_slice(data)?;
+    let mut releases: Vec<PypiRelease> = listing.releases.into_iter()
+        .filter_map(|(version, files)| match Version::parse(&version, &pep440_parser) {
+            Ok(version) => Some(PypiRelease { version, files }),
+            Err(_) => None,
+        })
+        .collect();
+    releases.sort_by_cached_key(|r| r.version.sort_key());
+    Ok(PypiProject { name: listing.info.name, releases })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::path::PathBuf;
+    use super::*;
+
+    fn example() -> PypiProject {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("tests/data/pypi_example.json");
+        read_pypi_json(&std::fs::read(d).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_read_pypi_json() {
+        let project = example();
+        assert_eq!(project.name, "example");
+        let versions: Vec<&str> = project.releases.iter().map(|r| r.version.as_str()).collect();
+        // 0.9-legacy isn't PEP 440 and is dropped
+        assert_eq!(versions, vec!["1.0.0", "1.0.0.post1", "1.1.0rc1", "1.1.0", "2.0.0"]);
+        assert_eq!(project.releases[4].files.len(), 2);
+        assert_eq!(project.releases[4].files[0].digests.sha256.as_deref(),
+                   Some("4444444444444444444444444444444444444444444444444444444444444444"));
+    }
+
+    #[test]
+    fn test_query_skips_yanked() {
+        let project = example();
+        let spec = VersionSpecOrConstraintTree::try_from(">1.0.0,<2").unwrap();
+        let found: Vec<&str> = project.query(&spec).iter().map(|r| r.version.as_str()).collect();
+        // 1.1.0 is yanked
+        assert_eq!(found, vec!["1.0.0.post1", "1.1.0rc1"]);
+        assert_eq!(project.latest().unwrap().version.as_str(), "2.0.0");
+    }
+}
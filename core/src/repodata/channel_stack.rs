@@ -0,0 +1,240 @@
+//! Several channels searched as one, in priority order.
+//!
+//! Most names a solver or query asks about are in at most one or two of the channels, and many
+//! (optional dependencies, virtual packages) are in none.  Rather than hashing every name into
+//! every channel's maps, the stack builds one Bloom filter over all names up front.  A name the
+//! filter rejects is certainly missing; the rare false positive is settled by the set of names.
+//!
+//! A label (`conda-forge/label/rc`) is stacked like any other channel, in its own place in the
+//! priority order, so pre-releases can be preferred by putting the label first.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use rustc_hash::FxHashSet;
+
+use crate::fetch::channel::Channel;
+use crate::matchspec::matchspec::MatchSpec;
+use crate::repodata::explain::Selection;
+use crate::repodata::filename::PackageFilename;
+use crate::repodata::intern::InternedStr;
+use crate::repodata::repodata::{Record, Repodata};
+use crate::VersionSpecOrConstraintTree;
+
+// About 1% false positives at this size
+const BITS_PER_NAME: usize = 10;
+const HASHES: u64 = 7;
+
+/// Bloom filter over package names.
+struct NameFilter {
+    bits: Vec<u64>,
+}
+
+impl NameFilter {
+    fn new<'a, I: ExactSizeIterator<Item = &'a str>>(names: I) -> NameFilter {
+        let words = (names.len() * BITS_PER_NAME / 64).max(1);
+        let mut filter = NameFilter { bits: vec![0; words] };
+        for name in names {
+            for bit in filter.positions(name) {
+                filter.bits[bit / 64] |= 1 << (bit % 64);
+            }
+        }
+        filter
+    }
+
+    // Derive all positions from one 64 bit hash, split into two halves (Kirsch & Mitzenmacher)
+    fn positions(&self, name: &str) -> impl Iterator<Item = usize> {
+        let mut hasher = DefaultHasher::new();
+        name.hash(&mut hasher);
+        let hash = hasher.finish();
+        let (h1, h2) = (hash & 0xffff_ffff, hash >> 32);
+        let len = self.bits.len() as u64 * 64;
+        (0..HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+    }
+
+    fn may_contain(&self, name: &str) -> bool {
+        self.positions(name).all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+}
+
+pub struct ChannelStack {
+    channels: Vec<Repodata>,
+    // where each repodata came from, if known
+    sources: Vec<Option<Channel>>,
+    names: FxHashSet<InternedStr>,
+    filter: NameFilter,
+}
+
+impl ChannelStack {
+    /// Stack `channels`, highest priority first.
+    pub fn new(channels: Vec<Repodata>) -> ChannelStack {
+        let sources = vec![None; channels.len()];
+        ChannelStack::build(channels, sources)
+    }
+
+    /// Stack the repodata of `channels`, highest priority first, knowing which channel each is
+    ///    from, so that specs naming a channel or label only look there.
+    pub fn with_channels(channels: Vec<(Channel, Repodata)>) -> ChannelStack {
+        let (sources, channels) = channels.into_iter().map(|(c, r)| (Some(c), r)).unzip();
+        ChannelStack::build(channels, sources)
+    }
+
+    fn build(channels: Vec<Repodata>, sources: Vec<Option<Channel>>) -> ChannelStack {
+        let names: FxHashSet<InternedStr> = channels.iter()
+            .flat_map(|c| c.iter_records())
+            .map(|(_, r)| r.name.clone())
+            .collect();
+        let filter = NameFilter::new(names.iter().map(|n| n.as_str()));
+        ChannelStack { channels, sources, names, filter }
+    }
+
+    pub fn channels(&self) -> &[Repodata] {
+        &self.channels
+    }
+
+    /// The channel the repodata at `index` is from, if the stack was built `with_channels`.
+    pub fn channel(&self, index: usize) -> Option<&Channel> {
+        self.sources.get(index)?.as_ref()
+    }
+
+    /// The index of `channel` in the stack, lower being higher priority.  A label is only found
+    ///    as itself, not as the channel it is on.
+    pub fn priority(&self, channel: &Channel) -> Option<usize> {
+        self.sources.iter().position(|c| c.as_ref().is_some_and(|c| c.base_url() == channel.base_url()))
+    }
+
+    /// Whether any channel has a package called `name`.
+    pub fn contains_name(&self, name: &str) -> bool {
+        self.filter.may_contain(name) && self.names.contains(name)
+    }
+
+    /// Find the records for package `name` matching `spec` in all channels, as (channel index,
+    ///    filename, record), highest priority channel first.
+    pub fn query(&self, name: &str, spec: &VersionSpecOrConstraintTree) -> Vec<(usize, &str, &Record)> {
+        if !self.contains_name(name) {
+            return vec![];
+        }
+        self.channels.iter()
+            .enumerate()
+            .flat_map(|(i, c)| c.query(name, spec).into_iter().map(move |(f, r)| (i, f, r)))
+            .collect()
+    }
+
+    /// The newest record matching `spec` (see `Repodata::latest_matching`) in the highest
+    ///    priority channel that has one, as (channel index, filename, record).  If the spec names a
+    ///    channel, only that channel is searched; repodata from an unknown channel never matches
+    ///    such a spec.
+    pub fn latest_matching(&self, spec: &MatchSpec) -> Option<(usize, &PackageFilename, &Record)> {
+        if spec.name != "*" && !self.contains_name(&spec.qualified_name()) {
+            return None;
+        }
+        self.channels.iter()
+            .zip(&self.sources)
+            .enumerate()
+            .filter(|(_, (_, source))| spec.channel.is_none() || source.as_ref().is_some_and(|c| spec.matches_channel(c)))
+            .find_map(|(i, (c, _))| c.latest_matching(spec).map(|(f, r)| (i, f, r)))
+    }
+
+    /// What `latest_matching` picks for `spec`, with the reason each other record of the package
+    ///    wasn't picked.
+    pub fn explain(&self, spec: &MatchSpec) -> Selection<'_> {
+        Selection::new(self, spec)
+    }
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repodata::repodata::{read_repodata, read_repodata_from_slice};
+    use std::convert::TryFrom;
+    use std::path::PathBuf;
+
+    fn stack() -> ChannelStack {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("tests/data/current_repodata.json");
+        ChannelStack::new(vec![read_repodata(&d).unwrap(), read_repodata(&d).unwrap()])
+    }
+
+    fn labeled_stack() -> ChannelStack {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("tests/data/current_repodata.json");
+        ChannelStack::with_channels(vec![
+            (Channel::parse("conda-forge/label/rc"), read_repodata(&d).unwrap()),
+            (Channel::parse("conda-forge"), read_repodata(&d).unwrap()),
+        ])
+    }
+
+    #[test]
+    fn test_filter_has_no_false_negatives() {
+        let names: Vec<String> = (0..1000).map(|i| format!("package-{}", i)).collect();
+        let filter = NameFilter::new(names.iter().map(|n| n.as_str()));
+        assert!(names.iter().all(|n| filter.may_contain(n)));
+        let false_positives = (0..1000).filter(|i| filter.may_contain(&format!("missing-{}", i))).count();
+        assert!(false_positives < 50, "{} false positives", false_positives);
+    }
+
+    #[test]
+    fn test_contains_name() {
+        let stack = stack();
+        assert!(stack.contains_name("_libarchive_static_for_cph"));
+        assert!(!stack.contains_name("surely-not-a-package"));
+    }
+
+    #[test]
+    fn test_query_spans_channels() {
+        let stack = stack();
+        let spec = VersionSpecOrConstraintTree::try_from("3.3.3").unwrap();
+        let found = stack.query("_libarchive_static_for_cph", &spec);
+        let per_channel = stack.channels()[0].query("_libarchive_static_for_cph", &spec).len();
+        assert!(per_channel > 0);
+        assert_eq!(found.len(), 2 * per_channel);
+        assert!(found[..per_channel].iter().all(|(i, _, _)| *i == 0));
+        assert!(stack.query("surely-not-a-package", &spec).is_empty());
+    }
+
+    #[test]
+    fn test_label_priority() {
+        let stack = labeled_stack();
+        assert_eq!(stack.priority(&Channel::parse("conda-forge/label/rc")), Some(0));
+        assert_eq!(stack.priority(&Channel::parse("conda-forge")), Some(1));
+        assert_eq!(stack.priority(&Channel::parse("conda-forge/label/beta")), None);
+        assert_eq!(stack.channel(1), Some(&Channel::parse("conda-forge/label/main")));
+        assert_eq!(stack.channel(2), None);
+    }
+
+    #[rstest(
+        spec, index,
+        case("_libarchive_static_for_cph", Some(0)),
+        case("conda-forge/label/rc::_libarchive_static_for_cph", Some(0)),
+        case("conda-forge::_libarchive_static_for_cph", Some(1)),
+        case("conda-forge/label/beta::_libarchive_static_for_cph", None),
+        case("surely-not-a-package", None)
+    )]
+    fn test_latest_matching_respects_labels(spec: &str, index: Option<usize>) {
+        let spec = MatchSpec::parse(spec).unwrap();
+        assert_eq!(labeled_stack().latest_matching(&spec).map(|(i, _, _)| i), index);
+    }
+
+    #[test]
+    fn test_latest_matching_namespaced() {
+        let record = |name: &str| format!(
+            r#""{n}-1.0-0.conda": {{"build": "0", "build_number": 0, "depends": [], "md5": "", "name": "{n}",
+                "size": 0, "version": "1.0"}}"#, n = name);
+        let data = format!(r#"{{"info": {{"subdir": "noarch"}}, "packages": {{}}, "packages.conda": {{{}, {}}}}}"#,
+                           record("python:numpy"), record("zlib"));
+        let stack = ChannelStack::new(vec![read_repodata_from_slice(data.as_bytes()).unwrap()]);
+        let found = stack.latest_matching(&MatchSpec::parse("python:numpy").unwrap());
+        assert_eq!(found.map(|(_, _, r)| r.name.as_str()), Some("python:numpy"));
+        assert!(stack.latest_matching(&MatchSpec::parse("numpy").unwrap()).is_none());
+        assert!(stack.latest_matching(&MatchSpec::parse("python:zlib").unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_unknown_channel_only_matches_specs_without_one() {
+        let stack = stack();
+        assert_eq!(stack.channel(0), None);
+        assert!(stack.latest_matching(&MatchSpec::parse("_libarchive_static_for_cph").unwrap()).is_some());
+        assert!(stack.latest_matching(&MatchSpec::parse("conda-forge::_libarchive_static_for_cph").unwrap()).is_none());
+    }
+}
@@ -0,0 +1,108 @@
+//! The entries of a record's `depends` list.
+//!
+//! Repodata writes dependencies as up to three space separated fields: the package name, a
+//! version spec and a build string, e.g. `python >=3.7,<3.8.0a0` or `zlib 1.2.11 h62dcd97_3`.
+
+use std::convert::TryFrom;
+
+use crate::version::errors::VersionParsingError;
+use crate::{Record, Spec, VersionSpecOrConstraintTree};
+
+/// One dependency, borrowing from the `depends` entry it was read from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Depend<'a> {
+    pub name: &'a str,
+    pub version: Option<&'a str>,
+    pub build: Option<&'a str>,
+}
+
+impl<'a> Depend<'a> {
+    /// Split a `depends` entry into its fields.  Never fails: anything after a third field is
+    ///    ignored, and an empty entry has an empty name.
+    pub fn parse(entry: &'a str) -> Depend<'a> {
+        let mut fields = entry.split_whitespace();
+        Depend {
+            name: fields.next().unwrap_or(""),
+            version: fields.next(),
+            build: fields.next(),
+        }
+    }
+
+    /// Parse the version field into a spec; `None` if there is no version field.
+    pub fn version_spec(&self) -> Option<Result<VersionSpecOrConstraintTree, VersionParsingError>> {
+        self.version.map(VersionSpecOrConstraintTree::try_from)
+    }
+
+    /// Whether `record` satisfies this dependency.  Build strings may end in `*` to match any
+    ///    build with that prefix, and in old repodata may instead name a legacy feature the
+    ///    record provides.  A version field that isn't a valid spec matches nothing.
+    pub fn matches(&self, record: &Record) -> bool {
+        if record.name != self.name {
+            return false;
+        }
+        let version_ok = match self.version_spec() {
+            None => true,
+            Some(Ok(spec)) => spec.test_match_version(&record.version),
+            Some(Err(_)) => false,
+        };
+        version_ok && self.build_matches(record)
+    }
+
+    /// Whether `record`'s build satisfies this dependency's build field, if it has one.
+    pub(crate) fn build_matches(&self, record: &Record) -> bool {
+        match self.build {
+            None | Some("*") => true,
+            Some(build) => match build.strip_suffix('*') {
+                Some(prefix) => record.build.starts_with(prefix),
+                None => record.build == build || record.has_feature(build),
+            },
+        }
+    }
+}
+
+/// Whether virtual package `name` (e.g. `__win`) is available on `platform`, a subdir such as
+///    `linux-64`.  Only the OS packages depend on the platform; others (`__glibc`, `__cuda`, ...)
+///    are assumed to be there.
+pub(crate) fn virtual_package_available(name: &str, platform: &str) -> bool {
+    let os = platform.split('-').next().unwrap_or("");
+    match name {
+        "__win" => os == "win",
+        "__unix" => os == "linux" || os == "osx",
+        "__linux" => os == "linux",
+        "__osx" => os == "osx",
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{virtual_package_available, Depend};
+    use crate::Record;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Depend::parse("python >=3.7,<3.8.0a0"),
+                   Depend { name: "python", version: Some(">=3.7,<3.8.0a0"), build: None });
+        assert_eq!(Depend::parse("zlib 1.2.11 h62dcd97_3"),
+                   Depend { name: "zlib", version: Some("1.2.11"), build: Some("h62dcd97_3") });
+        assert_eq!(Depend::parse("vc"), Depend { name: "vc", version: None, build: None });
+        assert_eq!(Depend::parse("").name, "");
+    }
+
+    #[test]
+    fn test_legacy_feature_suffix() {
+        let record: Record = serde_json::from_str(r#"{"arch": "x86_64", "platform": "linux", "build": "np111py27_0",
+            "build_number": 0, "features": "mkl", "md5": "", "name": "numpy", "size": 0, "version": "1.11.3"}"#).unwrap();
+        assert!(Depend::parse("numpy 1.11*|1.12* mkl").matches(&record));
+        assert!(Depend::parse("numpy 1.11* np111py27_0").matches(&record));
+        assert!(!Depend::parse("numpy 1.11* nomkl").matches(&record));
+    }
+
+    #[test]
+    fn test_virtual_package_available() {
+        assert!(virtual_package_available("__win", "win-64"));
+        assert!(!virtual_package_available("__win", "linux-64"));
+        assert!(virtual_package_available("__unix", "osx-arm64"));
+        assert!(virtual_package_available("__glibc", "linux-64"));
+    }
+}
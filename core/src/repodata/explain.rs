@@ -0,0 +1,211 @@
+//! Why `ChannelStack::latest_matching` picked the record it did, and what every other record of
+//! the package lost on: the spec, the channel priority, or the first of the `PickCriterion`s
+//! where it compared worse.
+
+use std::fmt;
+
+use crate::matchspec::matchspec::MatchSpec;
+use crate::repodata::channel_stack::ChannelStack;
+use crate::repodata::filename::PackageFilename;
+use crate::repodata::repodata::{deciding_criterion, PickCriterion, Record};
+
+/// Why a record wasn't picked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reason {
+    /// The spec names a channel, and the record is in another one
+    OtherChannel,
+    /// The spec names a subdir, and the record is in another one
+    OtherSubdir,
+    /// The record doesn't satisfy the spec's version, build or other constraints
+    NotMatching,
+    /// A record in a higher priority channel was picked
+    ChannelPriority,
+    /// It lost to the picked record, from the same channel, on this criterion
+    Lost(PickCriterion),
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reason::OtherChannel => f.write_str("not in the requested channel"),
+            Reason::OtherSubdir => f.write_str("not in the requested subdir"),
+            Reason::NotMatching => f.write_str("doesn't match the spec"),
+            Reason::ChannelPriority => f.write_str("lower priority channel"),
+            Reason::Lost(criterion) => criterion.fmt(f),
+        }
+    }
+}
+
+/// A record that wasn't picked.
+#[derive(Clone, Debug)]
+pub struct Candidate<'a> {
+    /// The index of its channel in the stack
+    pub channel: usize,
+    pub subdir: &'a str,
+    pub filename: &'a PackageFilename,
+    pub record: &'a Record,
+    pub reason: Reason,
+}
+
+/// The record picked for a spec, if any, and why each other record of the package wasn't.
+#[derive(Clone)]
+pub struct Selection<'a> {
+    pub spec: MatchSpec,
+    /// As (channel index, filename, record), the same as `ChannelStack::latest_matching`
+    pub chosen: Option<(usize, &'a PackageFilename, &'a Record)>,
+    /// Highest priority channel first, then by filename
+    pub alternatives: Vec<Candidate<'a>>,
+    stack: &'a ChannelStack,
+}
+
+impl<'a> Selection<'a> {
+    pub(crate) fn new(stack: &'a ChannelStack, spec: &MatchSpec) -> Selection<'a> {
+        let chosen = stack.latest_matching(spec);
+        let mut alternatives = vec![];
+        for (i, repodata) in stack.channels().iter().enumerate() {
+            let mut records: Vec<_> = repodata.iter_records()
+                .filter(|(_, r)| spec.matches_name(&r.name))
+                .filter(|(f, _)| chosen.is_none_or(|(c, chosen, _)| c != i || chosen != *f))
+                .collect();
+            records.sort_by(|a, b| a.0.cmp(b.0));
+            for (filename, record) in records {
+                let reason = if !stack.channel(i).map_or(spec.channel.is_none(), |c| spec.matches_channel(c)) {
+                    Reason::OtherChannel
+                } else if spec.subdir.as_ref().is_some_and(|s| *s != repodata.info.subdir) {
+                    Reason::OtherSubdir
+                } else if !spec.matches_filename(filename, record) {
+                    Reason::NotMatching
+                } else {
+                    match chosen {
+                        Some((c, f, r)) if c == i => {
+                            // the picked record is the greatest, so they differ somewhere
+                            Reason::Lost(deciding_criterion((filename, record), (f, r)).unwrap_or(PickCriterion::Filename))
+                        }
+                        _ => Reason::ChannelPriority,
+                    }
+                };
+                alternatives.push(Candidate { channel: i, subdir: &repodata.info.subdir, filename, record, reason });
+            }
+        }
+        Selection { spec: spec.clone(), chosen, alternatives, stack }
+    }
+
+    /// The alternatives that were ruled out for `reason`.
+    pub fn ruled_out(&self, reason: Reason) -> impl Iterator<Item = &Candidate<'a>> {
+        self.alternatives.iter().filter(move |c| c.reason == reason)
+    }
+
+    // `channel/subdir`, as `GraphNode::provenance` names it
+    fn source(&self, index: usize) -> String {
+        let subdir = &self.stack.channels()[index].info.subdir;
+        match self.stack.channel(index) {
+            Some(channel) => format!("{}/{}", channel, subdir),
+            None => format!("channel {}/{}", index, subdir),
+        }
+    }
+}
+
+/// One line for the pick, then one per alternative with the reason it lost:
+///
+/// ```text
+/// numpy: picked numpy-1.26.4-py312h8753938_0.conda from https://conda.anaconda.org/conda-forge/linux-64
+///   numpy-1.26.3-py312h8753938_0.conda (https://conda.anaconda.org/conda-forge/linux-64): older version
+/// ```
+impl fmt::Display for Selection<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.chosen {
+            Some((i, filename, _)) => write!(f, "{}: picked {} from {}", self.spec, filename, self.source(i))?,
+            None => write!(f, "{}: nothing matches", self.spec)?,
+        }
+        for candidate in &self.alternatives {
+            write!(f, "\n  {} ({}): {}", candidate.filename, self.source(candidate.channel), candidate.reason)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::channel::Channel;
+    use crate::repodata::repodata::read_repodata_from_slice;
+
+    fn repodata(subdir: &str, records: &[(&str, &str, u64, &str)]) -> crate::repodata::repodata::Repodata {
+        let packages: Vec<String> = records.iter().map(|(version, build, build_number, track_features)| format!(
+            r#""numpy-{v}-{b}.conda": {{"build": "{b}", "build_number": {n}, "depends": [], "md5": "", "name": "numpy",
+                "size": 0, "track_features": "{t}", "version": "{v}"}}"#,
+            v = version, b = build, n = build_number, t = track_features)).collect();
+        let data = format!(r#"{{"info": {{"subdir": "{}"}}, "repodata_version": 1, "removed": [],
+            "packages": {{}}, "packages.conda": {{{}}}}}"#, subdir, packages.join(","));
+        read_repodata_from_slice(data.as_bytes()).unwrap()
+    }
+
+    fn stack() -> ChannelStack {
+        ChannelStack::with_channels(vec![
+            (Channel::parse("conda-forge"), repodata("linux-64", &[
+                ("1.26.4", "mkl_1", 1, ""),
+                ("1.26.4", "mkl_0", 0, ""),
+                ("1.26.4", "nomkl_2", 2, "nomkl"),
+                ("1.26.3", "mkl_0", 0, ""),
+                ("1.25.0", "mkl_0", 0, ""),
+            ])),
+            (Channel::parse("bioconda"), repodata("linux-64", &[("2.0.0", "0", 0, "")])),
+        ])
+    }
+
+    fn reason(selection: &Selection, filename: &str) -> Reason {
+        selection.alternatives.iter().find(|c| c.filename == filename).unwrap().reason
+    }
+
+    #[test]
+    fn test_reasons() {
+        let stack = stack();
+        let selection = stack.explain(&MatchSpec::parse("numpy >=1.26").unwrap());
+        assert_eq!(selection.chosen.map(|(i, f, _)| (i, f.as_str())), Some((0, "numpy-1.26.4-mkl_1.conda")));
+        assert_eq!(selection.alternatives.len(), 5);
+        assert_eq!(reason(&selection, "numpy-1.26.4-nomkl_2.conda"), Reason::Lost(PickCriterion::TrackFeatures));
+        assert_eq!(reason(&selection, "numpy-1.26.4-mkl_0.conda"), Reason::Lost(PickCriterion::BuildNumber));
+        assert_eq!(reason(&selection, "numpy-1.26.3-mkl_0.conda"), Reason::Lost(PickCriterion::Version));
+        assert_eq!(reason(&selection, "numpy-1.25.0-mkl_0.conda"), Reason::NotMatching);
+        assert_eq!(reason(&selection, "numpy-2.0.0-0.conda"), Reason::ChannelPriority);
+        assert_eq!(selection.ruled_out(Reason::NotMatching).count(), 1);
+    }
+
+    #[test]
+    fn test_channel_and_subdir() {
+        let stack = stack();
+        let selection = stack.explain(&MatchSpec::parse("bioconda::numpy").unwrap());
+        assert_eq!(selection.chosen.map(|(i, f, _)| (i, f.as_str())), Some((1, "numpy-2.0.0-0.conda")));
+        assert_eq!(selection.ruled_out(Reason::OtherChannel).count(), 5);
+        let selection = stack.explain(&MatchSpec::parse("conda-forge/osx-64::numpy").unwrap());
+        assert!(selection.chosen.is_none());
+        assert_eq!(selection.ruled_out(Reason::OtherSubdir).count(), 5);
+        assert!(selection.to_string().starts_with("conda-forge/osx-64::numpy: nothing matches\n"));
+    }
+
+    #[test]
+    fn test_namespaces_are_different_packages() {
+        let data = r#"{"info": {"subdir": "noarch"}, "packages": {}, "packages.conda": {
+            "numpy-1.0-0.conda": {"build": "0", "build_number": 0, "depends": [], "md5": "", "name": "numpy",
+                "size": 0, "version": "1.0"},
+            "numpy-2.0-0.conda": {"build": "0", "build_number": 0, "depends": [], "md5": "", "name": "python:numpy",
+                "size": 0, "version": "2.0"}}}"#;
+        let stack = ChannelStack::new(vec![read_repodata_from_slice(data.as_bytes()).unwrap()]);
+        for (spec, version) in [("numpy", "1.0"), ("python:numpy", "2.0")] {
+            let selection = stack.explain(&MatchSpec::parse(spec).unwrap());
+            assert_eq!(selection.chosen.map(|(_, _, r)| r.version.as_str()), Some(version));
+            assert!(selection.alternatives.is_empty(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let stack = stack();
+        let report = stack.explain(&MatchSpec::parse("numpy 1.26.*").unwrap()).to_string();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[0], "numpy 1.26.*: picked numpy-1.26.4-mkl_1.conda from https://conda.anaconda.org/conda-forge/linux-64");
+        assert!(lines.contains(&"  numpy-1.26.4-nomkl_2.conda (https://conda.anaconda.org/conda-forge/linux-64): more track_features"));
+        assert!(lines.contains(&"  numpy-2.0.0-0.conda (https://conda.anaconda.org/bioconda/linux-64): doesn't match the spec"));
+    }
+}
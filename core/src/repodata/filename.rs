@@ -0,0 +1,271 @@
+//! Package filenames, the keys of a channel's `packages` and `packages.conda` maps.
+//!
+//! A filename is `<name>-<version>-<build><extension>`.  Names may contain dashes
+//! (`python-dateutil`), but versions and builds never do, so the filename is split at its last two
+//! dashes.  The original string is kept, and the components are slices of it.
+
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PackageFormat {
+    /// `.conda`, a zip of zstd compressed tarballs
+    Conda,
+    /// `.tar.bz2`, the original package format
+    TarBz2,
+}
+
+impl PackageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PackageFormat::Conda => ".conda",
+            PackageFormat::TarBz2 => ".tar.bz2",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PackageFilenameError {
+    UnknownExtension(String),
+    MissingComponents(String),
+}
+
+impl fmt::Display for PackageFilenameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PackageFilenameError::UnknownExtension(s) =>
+                write!(f, "'{}' doesn't end with .conda or .tar.bz2", s),
+            PackageFilenameError::MissingComponents(s) =>
+                write!(f, "'{}' isn't of the form <name>-<version>-<build>", s),
+        }
+    }
+}
+
+impl Error for PackageFilenameError {}
+
+#[derive(Clone)]
+pub struct PackageFilename {
+    filename: Box<str>,
+    // positions of the dashes after the name and after the version
+    name_end: u16,
+    version_end: u16,
+    format: PackageFormat,
+}
+
+impl PackageFilename {
+    /// Split a filename such as `python-dateutil-2.8.2-pyhd3eb1b0_0.conda` into its components.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ronda::{PackageFilename, PackageFormat};
+    ///
+    /// let f = PackageFilename::parse("python-dateutil-2.8.2-pyhd3eb1b0_0.conda").unwrap();
+    /// assert_eq!(f.name(), "python-dateutil");
+    /// assert_eq!(f.version(), "2.8.2");
+    /// assert_eq!(f.build(), "pyhd3eb1b0_0");
+    /// assert_eq!(f.format(), PackageFormat::Conda);
+    /// assert!(PackageFilename::parse("zlib-1.2.11.tar.bz2").is_err());
+    /// ```
+    pub fn parse(filename: &str) -> Result<PackageFilename, PackageFilenameError> {
+        let (stem, format) = if let Some(stem) = filename.strip_suffix(".conda") {
+            (stem, PackageFormat::Conda)
+        } else if let Some(stem) = filename.strip_suffix(".tar.bz2") {
+            (stem, PackageFormat::TarBz2)
+        } else {
+            return Err(PackageFilenameError::UnknownExtension(filename.to_string()));
+        };
+        let missing = || PackageFilenameError::MissingComponents(filename.to_string());
+        let version_end = stem.rfind('-').ok_or_else(missing)?;
+        let name_end = stem[..version_end].rfind('-').ok_or_else(missing)?;
+        // every component must be there; the name can't start with the dash it is split at
+        if name_end == 0 || version_end == name_end + 1 || version_end + 1 == stem.len() {
+            return Err(missing());
+        }
+        Ok(PackageFilename {
+            filename: filename.into(),
+            name_end: u16::try_from(name_end).map_err(|_| missing())?,
+            version_end: u16::try_from(version_end).map_err(|_| missing())?,
+            format,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.filename
+    }
+
+    pub fn name(&self) -> &str {
+        &self.filename[..self.name_end as usize]
+    }
+
+    pub fn version(&self) -> &str {
+        &self.filename[self.name_end as usize + 1..self.version_end as usize]
+    }
+
+    pub fn build(&self) -> &str {
+        &self.filename[self.version_end as usize + 1..self.filename.len() - self.format.extension().len()]
+    }
+
+    pub fn format(&self) -> PackageFormat {
+        self.format
+    }
+
+    /// The filename without its extension, e.g. the name of the directory a package is
+    ///    extracted to.
+    pub fn stem(&self) -> &str {
+        &self.filename[..self.filename.len() - self.format.extension().len()]
+    }
+}
+
+impl FromStr for PackageFilename {
+    type Err = PackageFilenameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PackageFilename::parse(s)
+    }
+}
+
+impl TryFrom<&str> for PackageFilename {
+    type Error = PackageFilenameError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        PackageFilename::parse(s)
+    }
+}
+
+impl fmt::Display for PackageFilename {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.filename)
+    }
+}
+
+impl fmt::Debug for PackageFilename {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&*self.filename, f)
+    }
+}
+
+// Equality, ordering and hashing all go by the filename string, so that maps keyed by
+//    PackageFilename can be looked up with a &str through Borrow
+impl PartialEq for PackageFilename {
+    fn eq(&self, other: &Self) -> bool {
+        self.filename == other.filename
+    }
+}
+
+impl Eq for PackageFilename {}
+
+impl PartialEq<str> for PackageFilename {
+    fn eq(&self, other: &str) -> bool {
+        &*self.filename == other
+    }
+}
+
+impl PartialEq<&str> for PackageFilename {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.filename == *other
+    }
+}
+
+impl PartialOrd for PackageFilename {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PackageFilename {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.filename.cmp(&other.filename)
+    }
+}
+
+impl Hash for PackageFilename {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl Borrow<str> for PackageFilename {
+    fn borrow(&self) -> &str {
+        &self.filename
+    }
+}
+
+impl AsRef<str> for PackageFilename {
+    fn as_ref(&self) -> &str {
+        &self.filename
+    }
+}
+
+impl Serialize for PackageFilename {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.filename)
+    }
+}
+
+struct PackageFilenameVisitor;
+
+impl<'de> de::Visitor<'de> for PackageFilenameVisitor {
+    type Value = PackageFilename;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a package filename")
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<PackageFilename, E> {
+        PackageFilename::parse(s).map_err(de::Error::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for PackageFilename {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<PackageFilename, D::Error> {
+        deserializer.deserialize_str(PackageFilenameVisitor)
+    }
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[rstest(
+        filename, name, version, build, format,
+        case("zlib-1.2.11-h62dcd97_4.tar.bz2", "zlib", "1.2.11", "h62dcd97_4", PackageFormat::TarBz2),
+        case("python-dateutil-2.8.2-pyhd3eb1b0_0.conda", "python-dateutil", "2.8.2", "pyhd3eb1b0_0", PackageFormat::Conda),
+        case("_libgcc_mutex-0.1-main.conda", "_libgcc_mutex", "0.1", "main", PackageFormat::Conda),
+        case("r-a-b-c-1!1.0_2-r36_0.tar.bz2", "r-a-b-c", "1!1.0_2", "r36_0", PackageFormat::TarBz2)
+    )]
+    fn test_parse(filename: &str, name: &str, version: &str, build: &str, format: PackageFormat) {
+        let f = PackageFilename::parse(filename).unwrap();
+        assert_eq!((f.name(), f.version(), f.build(), f.format()), (name, version, build, format));
+        assert_eq!(f.as_str(), filename);
+        assert_eq!(f.stem(), &filename[..filename.len() - format.extension().len()]);
+    }
+
+    #[rstest(
+        filename,
+        case("zlib-1.2.11-h62dcd97_4.zip"),
+        case("zlib-1.2.11.conda"),
+        case("-1.2.11-0.conda"),
+        case("zlib--0.conda"),
+        case("zlib-1.2.11-.conda"),
+        case(".conda")
+    )]
+    fn test_parse_errors(filename: &str) {
+        assert!(PackageFilename::parse(filename).is_err());
+    }
+
+    #[test]
+    fn test_lookup_by_str() {
+        let mut map = std::collections::HashMap::new();
+        map.insert(PackageFilename::parse("zlib-1.2.11-0.conda").unwrap(), 1);
+        assert_eq!(map.get("zlib-1.2.11-0.conda"), Some(&1));
+    }
+}
@@ -0,0 +1,187 @@
+//! Interned strings for the values that repeat across a channel: package names and build strings.
+//!
+//! A channel has hundreds of thousands of records but only tens of thousands of distinct names
+//! and builds.  Interning stores each distinct string once, and because equal strings share one
+//! allocation, comparing two interned strings is a pointer comparison.
+
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use rustc_hash::FxHashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+lazy_static! {
+    // Strings are never removed: the set of names and builds in use only grows as channels load
+    static ref POOL: Mutex<FxHashSet<Arc<str>>> = Mutex::new(FxHashSet::default());
+}
+
+/// An immutable string shared through a process-wide pool.
+#[derive(Clone)]
+pub struct InternedStr(Arc<str>);
+
+impl InternedStr {
+    pub fn new(s: &str) -> InternedStr {
+        let mut pool = POOL.lock().unwrap();
+        if let Some(existing) = pool.get(s) {
+            return InternedStr(existing.clone());
+        }
+        let interned: Arc<str> = Arc::from(s);
+        pool.insert(interned.clone());
+        InternedStr(interned)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for InternedStr {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for InternedStr {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for InternedStr {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for InternedStr {
+    fn from(s: &str) -> InternedStr {
+        InternedStr::new(s)
+    }
+}
+
+impl PartialEq for InternedStr {
+    fn eq(&self, other: &InternedStr) -> bool {
+        // every InternedStr comes from the pool, so equal strings are the same allocation
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for InternedStr {}
+
+impl PartialEq<str> for InternedStr {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for InternedStr {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+impl PartialEq<String> for InternedStr {
+    fn eq(&self, other: &String) -> bool {
+        &*self.0 == other.as_str()
+    }
+}
+
+// Hash the contents, not the pointer, so lookups by &str through Borrow work
+impl Hash for InternedStr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl PartialOrd for InternedStr {
+    fn partial_cmp(&self, other: &InternedStr) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for InternedStr {
+    fn cmp(&self, other: &InternedStr) -> Ordering {
+        if self == other {
+            return Ordering::Equal;
+        }
+        self.0.cmp(&other.0)
+    }
+}
+
+impl fmt::Display for InternedStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Debug for InternedStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl Serialize for InternedStr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+struct InternedStrVisitor;
+
+impl<'de> de::Visitor<'de> for InternedStrVisitor {
+    type Value = InternedStr;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a string")
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<InternedStr, E> {
+        Ok(InternedStr::new(s))
+    }
+}
+
+impl<'de> Deserialize<'de> for InternedStr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<InternedStr, D::Error> {
+        deserializer.deserialize_str(InternedStrVisitor)
+    }
+}
+
+#[cfg(feature = "schema")]
+impl schemars::JsonSchema for InternedStr {
+    fn schema_name() -> String {
+        String::schema_name()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        String::json_schema(gen)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InternedStr;
+
+    #[test]
+    fn test_equal_strings_share_storage() {
+        let a = InternedStr::new("numpy");
+        let b: InternedStr = serde_json::from_str("\"numpy\"").unwrap();
+        assert_eq!(a, b);
+        assert!(std::ptr::eq(a.as_str(), b.as_str()));
+        assert_ne!(a, InternedStr::new("scipy"));
+        assert_eq!(a, "numpy");
+    }
+
+    #[test]
+    fn test_ordering_follows_contents() {
+        let mut names: Vec<InternedStr> = vec!["zlib".into(), "numpy".into(), "openssl".into(), "numpy".into()];
+        names.sort();
+        let sorted: Vec<&str> = names.iter().map(|n| n.as_str()).collect();
+        assert_eq!(sorted, vec!["numpy", "numpy", "openssl", "zlib"]);
+    }
+}
@@ -0,0 +1,228 @@
+//! One entry point for loading repodata with options.
+//!
+//! ```no_run
+//! use ronda::{Platform, RepodataLoader};
+//!
+//! let repodata = RepodataLoader::new()
+//!     .platform(Platform::Linux64)
+//!     .tolerate_errors(true)
+//!     .load("linux-64/repodata.json")
+//!     .unwrap();
+//! ```
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use serde_json::{Map, Value};
+
+use crate::cancel::cancel::CancellationToken;
+use crate::repodata::filename::PackageFilename;
+use crate::repodata::platform::Platform;
+use crate::repodata::repodata::{
+    read_repodata_from_slice_cancellable, PackageMap, Record, Repodata, RepodataInfo, RepodataReadError,
+};
+
+/// Options for loading repodata.  Every option is off by default, which loads exactly like
+///    `read_repodata`.
+#[derive(Clone, Debug, Default)]
+pub struct RepodataLoader {
+    platform: Option<Platform>,
+    tolerate_errors: bool,
+    keep_unknown_fields: bool,
+    cancel: Option<CancellationToken>,
+}
+
+// The JSON fields of a record that Record has members for; everything else is kept in `extra`
+const RECORD_FIELDS: &[&str] = &["arch", "build", "build_number", "depends", "features", "md5", "name", "platform",
+                                  "preferred_env", "sha256", "size", "timestamp", "track_features", "version"];
+
+// The package maps as unparsed JSON, so each record can succeed or fail alone
+#[derive(Deserialize)]
+struct RawRepodata<'a> {
+    #[serde(default)]
+    info: RepodataInfo,
+    #[serde(borrow)]
+    packages: HashMap<String, &'a RawValue>,
+    #[serde(rename = "packages.conda", default, borrow)]
+    packages_conda: HashMap<String, &'a RawValue>,
+    #[serde(default)]
+    repodata_version: u8,
+    #[serde(default)]
+    removed: Vec<String>,
+}
+
+impl RepodataLoader {
+    pub fn new() -> RepodataLoader {
+        RepodataLoader::default()
+    }
+
+    /// Fail with `RepodataReadError::WrongPlatform` unless the repodata is for `platform`.
+    pub fn platform(mut self, platform: Platform) -> RepodataLoader {
+        self.platform = Some(platform);
+        self
+    }
+
+    /// Drop records that can't be parsed (a bad version, a filename that isn't
+    ///    `name-version-build.ext`) instead of failing the whole load.  Third party channels
+    ///    occasionally have a few.  This parses each record separately, which is slower.
+    pub fn tolerate_errors(mut self, tolerate: bool) -> RepodataLoader {
+        self.tolerate_errors = tolerate;
+        self
+    }
+
+    /// Keep the fields of each record that `Record` doesn't model in `Record::extra`, so that
+    ///    serializing the repodata again loses nothing, e.g. to patch a channel's repodata.
+    ///    Like `tolerate_errors`, this parses each record separately, which is slower.
+    pub fn keep_unknown_fields(mut self, keep: bool) -> RepodataLoader {
+        self.keep_unknown_fields = keep;
+        self
+    }
+
+    /// Give up with `RepodataReadError::Cancelled` soon after `token` is cancelled.
+    pub fn cancellation(mut self, token: CancellationToken) -> RepodataLoader {
+        self.cancel = Some(token);
+        self
+    }
+
+    pub fn load<P: AsRef<Path>>(&self, path: P) -> Result<Repodata, RepodataReadError> {
+        let data = std::fs::read(path).map_err(RepodataReadError::Io)?;
+        self.load_from_slice(&data)
+    }
+
+    pub fn load_from_slice(&self, data: &[u8]) -> Result<Repodata, RepodataReadError> {
+        let token = self.cancel.clone().unwrap_or_default();
+        let repodata = if self.tolerate_errors || self.keep_unknown_fields {
+            self.read_per_record(data, &token)?
+        } else {
+            read_repodata_from_slice_cancellable(data, &token)?
+        };
+        match self.platform {
+            Some(platform) if repodata.info.subdir != platform.as_str() =>
+                Err(RepodataReadError::WrongPlatform { expected: platform, found: repodata.info.subdir }),
+            _ => Ok(repodata),
+        }
+    }
+
+    /// Parse the package maps as raw JSON, then each record on its own.
+    fn read_per_record(&self, data: &[u8], token: &CancellationToken) -> Result<Repodata, RepodataReadError> {
+        let raw: RawRepodata = serde_json::from_slice(data).map_err(RepodataReadError::Json)?;
+        let parse_map = |entries: HashMap<String, &RawValue>| -> Result<PackageMap, RepodataReadError> {
+            let mut map = PackageMap::with_capacity_and_hasher(entries.len(), Default::default());
+            for (filename, raw_record) in entries {
+                if token.is_cancelled() {
+                    return Err(RepodataReadError::Cancelled);
+                }
+                match self.parse_record(&filename, raw_record) {
+                    Ok((filename, record)) => { map.insert(filename, record); }
+                    Err(_) if self.tolerate_errors => {}
+                    Err(e) => return Err(RepodataReadError::Json(e)),
+                }
+            }
+            Ok(map)
+        };
+        Ok(Repodata {
+            info: raw.info,
+            packages: parse_map(raw.packages)?,
+            packages_conda: parse_map(raw.packages_conda)?,
+            repodata_version: raw.repodata_version,
+            removed: raw.removed,
+        })
+    }
+
+    fn parse_record(&self, filename: &str, raw: &RawValue) -> Result<(PackageFilename, Record), serde_json::Error> {
+        let filename = PackageFilename::parse(filename).map_err(serde::de::Error::custom)?;
+        let mut record: Record = serde_json::from_str(raw.get())?;
+        if self.keep_unknown_fields {
+            let mut fields: Map<String, Value> = serde_json::from_str(raw.get())?;
+            for known in RECORD_FIELDS {
+                fields.remove(*known);
+            }
+            if !fields.is_empty() {
+                record.extra = Some(Box::new(fields));
+            }
+        }
+        Ok((filename, record))
+    }
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use super::*;
+    use crate::read_repodata;
+
+    const DATA: &[u8] = br#"{"info": {"subdir": "linux-64"}, "repodata_version": 1, "removed": [],
+        "packages": {
+            "a-1.0-0.tar.bz2": {"build": "0", "build_number": 0, "depends": [], "md5": "", "name": "a", "size": 0, "version": "1.0"},
+            "a-2.0-0.tar.bz2": {"build": "0", "build_number": 0, "depends": [], "md5": "", "name": "a", "size": 0, "version": 2},
+            "not-a-filename.zip": {"build": "0", "build_number": 0, "depends": [], "md5": "", "name": "a", "size": 0, "version": "1.0"}
+        },
+        "packages.conda": {
+            "b-1.0-0.conda": {"build": "0", "build_number": "zero", "depends": [], "md5": "", "name": "b", "size": 0, "version": "1.0"}
+        }}"#;
+
+    #[test]
+    fn test_tolerate_errors() {
+        assert!(RepodataLoader::new().load_from_slice(DATA).is_err());
+        let repodata = RepodataLoader::new().tolerate_errors(true).load_from_slice(DATA).unwrap();
+        let filenames: Vec<&str> = repodata.iter_records().map(|(f, _)| f.as_str()).collect();
+        assert_eq!(filenames, vec!["a-1.0-0.tar.bz2"]);
+    }
+
+    #[test]
+    fn test_platform() {
+        let loader = RepodataLoader::new().tolerate_errors(true);
+        assert!(loader.clone().platform(Platform::Linux64).load_from_slice(DATA).is_ok());
+        let err = loader.platform(Platform::Osx64).load_from_slice(DATA).unwrap_err();
+        assert!(matches!(err, RepodataReadError::WrongPlatform { expected: Platform::Osx64, .. }));
+        assert_eq!(err.to_string(), "repodata is for linux-64, not osx-64");
+    }
+
+    #[test]
+    fn test_same_as_read_repodata() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("tests/data/current_repodata.json");
+        let expected = read_repodata(&d).unwrap();
+        for tolerate in [false, true] {
+            let loaded = RepodataLoader::new().tolerate_errors(tolerate).platform(Platform::Win64).load(&d).unwrap();
+            assert_eq!(loaded.packages.len(), expected.packages.len());
+            assert_eq!(loaded.packages_conda.len(), expected.packages_conda.len());
+        }
+    }
+
+    #[test]
+    fn test_keep_unknown_fields() {
+        let data = br#"{"info": {"subdir": "noarch"}, "repodata_version": 1, "removed": [], "packages": {},
+            "packages.conda": {
+                "a-1.0-0.conda": {"build": "0", "build_number": 0, "depends": [], "md5": "", "name": "a", "sha256": "",
+                                  "size": 0, "timestamp": 0, "version": "1.0", "license": "MIT",
+                                  "constrains": ["b >=2"], "noarch": "python"},
+                "c-1.0-0.conda": {"build": "0", "build_number": 0, "depends": [], "md5": "", "name": "c", "sha256": "",
+                                  "size": 0, "timestamp": 0, "version": "1.0"}
+            }}"#;
+        let plain = RepodataLoader::new().load_from_slice(data).unwrap();
+        assert!(plain.iter_records().all(|(_, r)| r.extra.is_none()));
+
+        let kept = RepodataLoader::new().keep_unknown_fields(true).load_from_slice(data).unwrap();
+        let extra = kept.record("a-1.0-0.conda").unwrap().extra.as_ref().unwrap();
+        assert_eq!(extra.keys().collect::<Vec<_>>(), vec!["constrains", "license", "noarch"]);
+        assert!(kept.record("c-1.0-0.conda").unwrap().extra.is_none());
+        // written back out, every field is there again
+        let original: Value = serde_json::from_slice(data).unwrap();
+        let written = serde_json::to_value(&kept).unwrap();
+        assert_eq!(written["packages.conda"], original["packages.conda"]);
+    }
+
+    #[test]
+    fn test_cancelled() {
+        let token = CancellationToken::new();
+        token.cancel();
+        for tolerate in [false, true] {
+            let loader = RepodataLoader::new().tolerate_errors(tolerate).cancellation(token.clone());
+            assert!(matches!(loader.load_from_slice(DATA), Err(RepodataReadError::Cancelled)));
+        }
+    }
+}
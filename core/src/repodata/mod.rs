@@ -1 +1,10 @@
-pub mod repodata;
\ No newline at end of file
+pub mod channel_stack;
+pub mod depends;
+pub mod explain;
+pub mod filename;
+pub mod intern;
+pub mod loader;
+pub mod platform;
+pub mod python;
+pub mod repodata;
+pub mod select;
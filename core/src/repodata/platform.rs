@@ -0,0 +1,84 @@
+//! The platforms (subdirs) a channel is split into.
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Platform {
+    NoArch,
+    Linux32,
+    Linux64,
+    LinuxAarch64,
+    LinuxArmV6l,
+    LinuxArmV7l,
+    LinuxPpc64,
+    LinuxPpc64le,
+    LinuxS390x,
+    Osx64,
+    OsxArm64,
+    Win32,
+    Win64,
+    WinArm64,
+    ZosZ,
+    EmscriptenWasm32,
+    WasiWasm32,
+}
+
+impl Platform {
+    pub const ALL: [Platform; 17] = [
+        Platform::NoArch, Platform::Linux32, Platform::Linux64, Platform::LinuxAarch64, Platform::LinuxArmV6l,
+        Platform::LinuxArmV7l, Platform::LinuxPpc64, Platform::LinuxPpc64le, Platform::LinuxS390x, Platform::Osx64,
+        Platform::OsxArm64, Platform::Win32, Platform::Win64, Platform::WinArm64, Platform::ZosZ,
+        Platform::EmscriptenWasm32, Platform::WasiWasm32,
+    ];
+
+    /// The subdir name, e.g. `linux-64`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Platform::NoArch => "noarch",
+            Platform::Linux32 => "linux-32",
+            Platform::Linux64 => "linux-64",
+            Platform::LinuxAarch64 => "linux-aarch64",
+            Platform::LinuxArmV6l => "linux-armv6l",
+            Platform::LinuxArmV7l => "linux-armv7l",
+            Platform::LinuxPpc64 => "linux-ppc64",
+            Platform::LinuxPpc64le => "linux-ppc64le",
+            Platform::LinuxS390x => "linux-s390x",
+            Platform::Osx64 => "osx-64",
+            Platform::OsxArm64 => "osx-arm64",
+            Platform::Win32 => "win-32",
+            Platform::Win64 => "win-64",
+            Platform::WinArm64 => "win-arm64",
+            Platform::ZosZ => "zos-z",
+            Platform::EmscriptenWasm32 => "emscripten-wasm32",
+            Platform::WasiWasm32 => "wasi-wasm32",
+        }
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Platform::ALL.iter().find(|p| p.as_str() == s).copied().ok_or_else(|| format!("unknown platform '{}'", s))
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        for platform in Platform::ALL.iter() {
+            assert_eq!(platform.as_str().parse::<Platform>(), Ok(*platform));
+        }
+        assert!("linux-65".parse::<Platform>().is_err());
+    }
+}
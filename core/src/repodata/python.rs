@@ -0,0 +1,253 @@
+//! The python versions a package can be installed with, as far as its `python` dependencies
+//! say.
+//!
+//! A `noarch: python` package is built once for every python, so the only thing tying it to
+//! some of them is a dependency such as `python >=3.8` (or `python >=3.8,<3.12`).  A linker
+//! generating entry points, or a report on which pythons an environment could move to, needs
+//! that range rather than the spec text.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Bound::{self, Excluded, Included, Unbounded};
+
+use crate::repodata::depends::Depend;
+use crate::repodata::repodata::Record;
+use crate::version::matching::MatchEnum;
+use crate::version::spec_trees::Combinator;
+use crate::version::CompOp;
+use crate::{Version, VersionSpecOrConstraintTree};
+
+/// A range of python versions.  Specs with holes in them (`!=3.9`, `3.8.*|3.10.*`) give the
+///    smallest range around what they allow, so a version outside the window is certainly not
+///    allowed, while one inside it may still be.
+///
+/// # Examples
+///
+/// ```
+/// use ronda::{PythonWindow, Version};
+/// use std::convert::TryFrom;
+///
+/// let window = PythonWindow::from_spec(&TryFrom::try_from(">=3.8,<3.12").unwrap());
+/// assert_eq!(window.to_string(), ">=3.8,<3.12");
+/// assert!(window.contains(&Version::from("3.11.4")));
+/// assert!(!window.contains(&Version::from("3.12")));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct PythonWindow {
+    pub lower: Bound<Version>,
+    pub upper: Bound<Version>,
+}
+
+impl Default for PythonWindow {
+    fn default() -> PythonWindow {
+        PythonWindow { lower: Unbounded, upper: Unbounded }
+    }
+}
+
+impl PythonWindow {
+    /// Every python version.
+    pub fn any() -> PythonWindow {
+        PythonWindow::default()
+    }
+
+    /// The window around the versions `spec` allows.  Regex specs and exclusions don't narrow it.
+    pub fn from_spec(spec: &VersionSpecOrConstraintTree) -> PythonWindow {
+        match spec {
+            VersionSpecOrConstraintTree::VersionSpec(spec) => match &spec.matcher {
+                MatchEnum::MatchOperator(m) => {
+                    let version = || m.version.clone();
+                    match m.operator {
+                        CompOp::Eq => PythonWindow { lower: Included(version()), upper: Included(version()) },
+                        CompOp::Ge => PythonWindow { lower: Included(version()), upper: Unbounded },
+                        CompOp::Gt => PythonWindow { lower: Excluded(version()), upper: Unbounded },
+                        CompOp::Le => PythonWindow { lower: Unbounded, upper: Included(version()) },
+                        CompOp::Lt => PythonWindow { lower: Unbounded, upper: Excluded(version()) },
+                        // 3.8.* is below 3.9.0a0
+                        CompOp::StartsWith => PythonWindow {
+                            lower: Included(version()),
+                            upper: next_release(m.version.as_str()).map_or(Unbounded, Excluded),
+                        },
+                        // ~=3.8.1 is 3.8.1 up to 3.9.0a0
+                        CompOp::Compatible => PythonWindow {
+                            lower: Included(version()),
+                            upper: m.version.as_str().rsplit_once('.')
+                                .and_then(|(prefix, _)| next_release(prefix))
+                                .map_or(Unbounded, Excluded),
+                        },
+                        _ => PythonWindow::any(),
+                    }
+                }
+                _ => PythonWindow::any(),
+            },
+            VersionSpecOrConstraintTree::ConstraintTree(tree) => {
+                let mut windows = tree.parts().iter().map(PythonWindow::from_spec);
+                let first = windows.next().unwrap_or_default();
+                match tree.combinator() {
+                    Combinator::And => windows.fold(first, |a, b| a.intersect(&b)),
+                    Combinator::Or => windows.fold(first, |a, b| a.hull(&b)),
+                }
+            }
+        }
+    }
+
+    /// The window `record`'s `python` dependencies allow, or `None` if it doesn't depend on
+    ///    python.  A dependency whose spec doesn't parse doesn't narrow the window.
+    pub fn of_record(record: &Record) -> Option<PythonWindow> {
+        record.depends.iter()
+            .map(|d| Depend::parse(d))
+            .filter(|d| d.name == "python")
+            .map(|d| match d.version_spec() {
+                Some(Ok(spec)) => PythonWindow::from_spec(&spec),
+                _ => PythonWindow::any(),
+            })
+            .reduce(|a, b| a.intersect(&b))
+    }
+
+    /// The window every one of `records` allows, e.g. the python versions an environment could
+    ///    use without replacing any of its packages.  `None` if none of them depend on python.
+    pub fn of_records<'a, I: IntoIterator<Item = &'a Record>>(records: I) -> Option<PythonWindow> {
+        records.into_iter().filter_map(PythonWindow::of_record).reduce(|a, b| a.intersect(&b))
+    }
+
+    /// The versions in both windows.
+    pub fn intersect(&self, other: &PythonWindow) -> PythonWindow {
+        PythonWindow {
+            lower: pick(&self.lower, &other.lower, Ordering::Greater, true),
+            upper: pick(&self.upper, &other.upper, Ordering::Less, true),
+        }
+    }
+
+    // The smallest window containing both
+    fn hull(&self, other: &PythonWindow) -> PythonWindow {
+        PythonWindow {
+            lower: pick(&self.lower, &other.lower, Ordering::Greater, false),
+            upper: pick(&self.upper, &other.upper, Ordering::Less, false),
+        }
+    }
+
+    pub fn contains(&self, version: &Version) -> bool {
+        let above = match &self.lower {
+            Included(v) => version >= v,
+            Excluded(v) => version > v,
+            Unbounded => true,
+        };
+        above && match &self.upper {
+            Included(v) => version <= v,
+            Excluded(v) => version < v,
+            Unbounded => true,
+        }
+    }
+
+    /// Whether no version is in the window, as when two packages need different pythons.
+    pub fn is_empty(&self) -> bool {
+        match (&self.lower, &self.upper) {
+            (Included(lower), Included(upper)) => lower > upper,
+            (Included(lower) | Excluded(lower), Included(upper) | Excluded(upper)) => lower >= upper,
+            _ => false,
+        }
+    }
+}
+
+// The tighter (or looser) of two bounds, where `inward` is how a version compares to another
+//    when it is further into the window: greater for lower bounds, less for upper ones
+fn pick(a: &Bound<Version>, b: &Bound<Version>, inward: Ordering, tighter: bool) -> Bound<Version> {
+    let (va, vb) = match (a, b) {
+        (Unbounded, _) => return if tighter { b.clone() } else { Unbounded },
+        (_, Unbounded) => return if tighter { a.clone() } else { Unbounded },
+        (Included(va) | Excluded(va), Included(vb) | Excluded(vb)) => (va, vb),
+    };
+    let a_tighter = match va.partial_cmp(vb).unwrap_or(Ordering::Equal) {
+        Ordering::Equal => matches!(a, Excluded(_)),
+        order => order == inward,
+    };
+    if a_tighter == tighter { a.clone() } else { b.clone() }
+}
+
+// The first pre-release after every version starting with `prefix`: 3.8 gives 3.9.0a0
+fn next_release(prefix: &str) -> Option<Version> {
+    let (head, last) = prefix.rsplit_once('.').map_or(("", prefix), |(h, l)| (h, l));
+    let next = last.parse::<u64>().ok()? + 1;
+    let dot = if head.is_empty() { "" } else { "." };
+    format!("{}{}{}.0a0", head, dot, next).parse().ok()
+}
+
+/// As a version spec: `>=3.8,<3.12`, or `*` for any version.
+impl fmt::Display for PythonWindow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let (Included(lower), Included(upper)) = (&self.lower, &self.upper) {
+            if lower == upper {
+                return write!(f, "=={}", lower);
+            }
+        }
+        let lower = match &self.lower {
+            Included(v) => Some(format!(">={}", v)),
+            Excluded(v) => Some(format!(">{}", v)),
+            Unbounded => None,
+        };
+        let upper = match &self.upper {
+            Included(v) => Some(format!("<={}", v)),
+            Excluded(v) => Some(format!("<{}", v)),
+            Unbounded => None,
+        };
+        match (lower, upper) {
+            (Some(lower), Some(upper)) => write!(f, "{},{}", lower, upper),
+            (Some(bound), None) | (None, Some(bound)) => f.write_str(&bound),
+            (None, None) => f.write_str("*"),
+        }
+    }
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    fn window(spec: &str) -> PythonWindow {
+        PythonWindow::from_spec(&VersionSpecOrConstraintTree::try_from(spec).unwrap())
+    }
+
+    fn record(depends: &[&str]) -> Record {
+        let depends: Vec<String> = depends.iter().map(|d| format!("{:?}", d)).collect();
+        serde_json::from_str(&format!(r#"{{"build": "pyhd8ed1ab_0", "build_number": 0, "depends": [{}], "md5": "",
+            "name": "a", "size": 0, "version": "1.0"}}"#, depends.join(","))).unwrap()
+    }
+
+    #[rstest(spec, expected,
+        case(">=3.8", ">=3.8"),
+        case(">=3.8,<3.12", ">=3.8,<3.12"),
+        case("<3.12,>3.7,>=3.8", ">=3.8,<3.12"),
+        case("3.8.*", ">=3.8,<3.9.0a0"),
+        case("~=3.8.1", ">=3.8.1,<3.9.0a0"),
+        case("3.10", "==3.10"),
+        case("3.8.*|3.10.*", ">=3.8,<3.11.0a0"),
+        case(">=3.8,!=3.9", ">=3.8"),
+        case("*", "*")
+    )]
+    fn test_from_spec(spec: &str, expected: &str) {
+        assert_eq!(window(spec).to_string(), expected);
+    }
+
+    #[test]
+    fn test_contains_and_empty() {
+        let w = window(">3.7,<=3.12");
+        assert!(!w.contains(&Version::from("3.7")));
+        assert!(w.contains(&Version::from("3.12")));
+        assert!(!w.is_empty());
+        assert!(window(">=3.12").intersect(&window("<3.12")).is_empty());
+        assert!(!window(">=3.12").intersect(&window("<=3.12")).is_empty());
+        assert!(PythonWindow::any().contains(&Version::from("2.7")));
+    }
+
+    #[test]
+    fn test_records() {
+        assert_eq!(PythonWindow::of_record(&record(&["numpy"])), None);
+        assert_eq!(PythonWindow::of_record(&record(&["python"])), Some(PythonWindow::any()));
+        let a = record(&["python >=3.8", "numpy", "python <3.13"]);
+        assert_eq!(PythonWindow::of_record(&a).unwrap().to_string(), ">=3.8,<3.13");
+        let b = record(&["python >=3.10"]);
+        let c = record(&["requests"]);
+        assert_eq!(PythonWindow::of_records([&a, &b, &c]).unwrap().to_string(), ">=3.10,<3.13");
+        assert_eq!(PythonWindow::of_records([&c]), None);
+    }
+}
@@ -1,62 +1,503 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::hash::BuildHasherDefault;
+use std::io::{self, Read};
 use std::path::Path;
 
+use std::fmt;
+
+use rustc_hash::FxHasher;
 use serde::de;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize, Serializer};
 
-use crate::{Version, conda_parser};
+use crate::{Version, conda_parser, Spec, VersionSpecOrConstraintTree};
+use crate::cancel::cancel::CancellationToken;
+use crate::matchspec::matchspec::MatchSpec;
+use crate::repodata::depends::{virtual_package_available, Depend};
+use crate::repodata::filename::{PackageFilename, PackageFormat};
+use crate::repodata::intern::InternedStr;
+use crate::repodata::platform::Platform;
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Record {
-    pub build: String,
+    // arch and platform predate subdirs, which replaced them; old channels still have them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
+    pub arch: Option<InternedStr>,
+    pub build: InternedStr,
     pub build_number: u16,
+    #[serde(default)]
     pub depends: Vec<String>,
+    /// Legacy features this package provides, space separated (e.g. `mkl`).  Old dependencies
+    ///    name them where a build string would go: `numpy 1.11*|1.12* mkl`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
+    pub features: Option<InternedStr>,
     pub md5: String,
-    pub name: String,
+    pub name: InternedStr,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
+    pub platform: Option<InternedStr>,
+    /// The environment conda 4.4 briefly installed some packages into, e.g. `python2`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
+    pub preferred_env: Option<InternedStr>,
+    // sha256 and timestamp were added to package metadata later; older packages don't have them
+    #[serde(default)]
     pub sha256: String,
     pub size: u64,
-    pub timestamp: u64,
-    #[serde(deserialize_with="deserialize_json_str_to_version")]
+    /// Build time in milliseconds since the epoch.  Older packages wrote seconds; those are
+    ///    converted when read, so this is always milliseconds.
+    #[serde(default, deserialize_with = "deserialize_timestamp", skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    /// Legacy features that lower this package's priority when solving, space separated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
+    pub track_features: Option<InternedStr>,
+    #[serde(deserialize_with="deserialize_json_str_to_version", serialize_with="serialize_version_to_str")]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
     pub version: Version,
+    /// The JSON fields this struct doesn't have (`license`, `constrains`, `noarch`, ...), kept
+    ///    only when loading with `RepodataLoader::keep_unknown_fields`, and written back out
+    ///    when serializing.  Normal loading skips them without allocating.
+    #[serde(flatten, skip_deserializing)]
+    #[cfg_attr(feature = "schema", schemars(skip))]
+    pub extra: Option<Box<serde_json::Map<String, serde_json::Value>>>,
+}
+
+impl Record {
+    /// Whether the record provides legacy feature `feature`.
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.as_ref().is_some_and(|f| f.split_whitespace().any(|f| f == feature))
+    }
+
+    /// Build time in milliseconds since the epoch, if the record has one.
+    pub fn timestamp_ms(&self) -> Option<u64> {
+        self.timestamp
+    }
+
+    /// Build time as a UTC date and time, if the record has one.
+    #[cfg(feature = "chrono")]
+    pub fn built_at(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        use std::convert::TryFrom;
+        self.timestamp.and_then(|ms| chrono::DateTime::from_timestamp_millis(i64::try_from(ms).ok()?))
+    }
+}
+
+// The largest timestamp taken to be in seconds, 9999-12-31T23:59:59; conda uses the same cutoff
+const MAX_TIMESTAMP_SECONDS: u64 = 253_402_300_799;
+
+pub(crate) fn deserialize_timestamp<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+    where
+        D: de::Deserializer<'de>,
+{
+    let timestamp = Option::<u64>::deserialize(deserializer)?;
+    Ok(timestamp.map(|t| if t > MAX_TIMESTAMP_SECONDS { t } else { t * 1000 }))
 }
 
-fn deserialize_json_str_to_version<'de, D>(deserializer: D) -> Result<Version, D::Error>
+struct VersionStrVisitor;
+
+impl<'de> de::Visitor<'de> for VersionStrVisitor {
+    type Value = Version;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a version string")
+    }
+
+    // Borrowed and owned strings both end up here, so this works for formats and readers that
+    //    can't hand out borrowed data
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Version, E> {
+        match Version::parse(s, &conda_parser) {
+            Ok(v) => Ok(v),
+            Err(_e) => Err(de::Error::custom("Version parsing error"))
+        }
+    }
+}
+
+pub(crate) fn deserialize_json_str_to_version<'de, D>(deserializer: D) -> Result<Version, D::Error>
     where
         D: de::Deserializer<'de>,
 {
-    let s: &str = Deserialize::deserialize(deserializer)?;
-    match Version::parse(s, &conda_parser) {
-        Ok(v) => Ok(v),
-        Err(_e) => Err(de::Error::custom("Version parsing error"))
+    deserializer.deserialize_str(VersionStrVisitor)
+}
+
+fn serialize_version_to_str<S: Serializer>(version: &Version, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(version.as_str())
+}
+
+/// Hasher of the package maps.  Keys are filenames from a channel index rather than untrusted
+///    input, so a fast non-cryptographic hash is fine.
+pub type PackageHasher = BuildHasherDefault<FxHasher>;
+
+/// Map from package filename to its record, as in `Repodata::packages`.  Look records up with
+///    either a `PackageFilename` or a `&str`.
+pub type PackageMap = HashMap<PackageFilename, Record, PackageHasher>;
+
+// Set while read_repodata_from_slice_cancellable parses on this thread.  The package maps are
+//    deserialized deep inside serde_json, so this is how the token reaches their visitor.
+thread_local! {
+    static PARSE_CANCEL: RefCell<Option<CancellationToken>> = const { RefCell::new(None) };
+}
+
+// How many records to parse between looks at the cancellation token
+const CANCEL_CHECK_INTERVAL: usize = 256;
+
+fn parse_cancelled() -> bool {
+    PARSE_CANCEL.with(|t| t.borrow().as_ref().is_some_and(|t| t.is_cancelled()))
+}
+
+struct PackageMapVisitor;
+
+impl<'de> de::Visitor<'de> for PackageMapVisitor {
+    type Value = PackageMap;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of package filenames to records")
+    }
+
+    // Formats that know the length up front (msgpack, cbor) let the map be allocated once
+    fn visit_map<A: de::MapAccess<'de>>(self, mut access: A) -> Result<PackageMap, A::Error> {
+        let mut map = PackageMap::with_capacity_and_hasher(access.size_hint().unwrap_or(0), Default::default());
+        while let Some((filename, record)) = access.next_entry()? {
+            map.insert(filename, record);
+            if map.len().is_multiple_of(CANCEL_CHECK_INTERVAL) && parse_cancelled() {
+                return Err(de::Error::custom("cancelled"));
+            }
+        }
+        Ok(map)
     }
 }
 
-#[derive(Deserialize, Debug)]
+fn deserialize_package_map<'de, D>(deserializer: D) -> Result<PackageMap, D::Error>
+    where
+        D: de::Deserializer<'de>,
+{
+    deserializer.deserialize_map(PackageMapVisitor)
+}
+
+/// Which records to visit when a package is in a channel in both formats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FormatPolicy {
+    /// Every record, from `packages` and `packages.conda`
+    All,
+    /// Skip a `.tar.bz2` record when the same name, version and build is also in `.conda`
+    ///    format; `.conda` packages are smaller and faster to extract
+    PreferConda,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct RepodataInfo {
+    // the oldest channels have an empty info
+    #[serde(default)]
     pub subdir: String
 }
 
-#[derive(Deserialize, Debug)]
+// info, packages.conda, repodata_version and removed were all added over time; historical
+//    repodata may have none of them
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Repodata {
+    #[serde(default)]
     pub info: RepodataInfo,
-    pub packages: HashMap<String, Record>,
-    #[serde(rename = "packages.conda")]
-    pub packages_conda: HashMap<String, Record>,
+    #[serde(deserialize_with = "deserialize_package_map")]
+    pub packages: PackageMap,
+    #[serde(rename = "packages.conda", default, deserialize_with = "deserialize_package_map")]
+    pub packages_conda: PackageMap,
+    #[serde(default)]
     pub repodata_version: u8,
+    #[serde(default)]
     pub removed: Vec<String>,
 }
 
-pub fn read_repodata<'a, P: AsRef<Path>>(path: P) -> Result<Repodata, serde_json::error::Error> {
-    let file = std::fs::read_to_string(path).unwrap();
-    // Read the JSON contents of the file as an instance of `Repodata`.
-    let r = serde_json::from_str(&file)?;
+impl Repodata {
+    /// Look up a record by its package filename, in either `packages` or `packages.conda`.
+    pub fn record(&self, filename: &str) -> Option<&Record> {
+        self.packages.get(filename).or_else(|| self.packages_conda.get(filename))
+    }
+
+    /// Iterate over the records in both `packages` and `packages.conda`, in no particular order.
+    pub fn iter_records(&self) -> impl Iterator<Item = (&PackageFilename, &Record)> {
+        self.packages.iter().chain(self.packages_conda.iter())
+    }
+
+    /// Like `iter_records`, but with a choice of what to do with packages that are in both
+    ///    formats.
+    pub fn iter_records_with(&self, policy: FormatPolicy) -> impl Iterator<Item = (&PackageFilename, &Record)> {
+        let mut conda_name = String::new();
+        let packages = self.packages.iter().filter(move |(f, _)| {
+            if policy == FormatPolicy::All {
+                return true;
+            }
+            // reuse one buffer rather than allocating a name for every record
+            conda_name.clear();
+            conda_name.push_str(f.stem());
+            conda_name.push_str(PackageFormat::Conda.extension());
+            !self.packages_conda.contains_key(conda_name.as_str())
+        });
+        packages.chain(self.packages_conda.iter())
+    }
+
+    /// Map each package name to the filenames of the records that depend on it, sorted and
+    ///    without duplicates.  Answers "who needs X" without building a graph; every `depends`
+    ///    entry is split once, up front.
+    pub fn reverse_depends_index(&self) -> HashMap<&str, Vec<&PackageFilename>, PackageHasher> {
+        let mut index: HashMap<&str, Vec<&PackageFilename>, PackageHasher> = HashMap::default();
+        for (filename, record) in self.iter_records() {
+            for entry in record.depends.iter() {
+                index.entry(Depend::parse(entry).name).or_default().push(filename);
+            }
+        }
+        for dependents in index.values_mut() {
+            dependents.sort();
+            dependents.dedup();
+        }
+        index
+    }
+
+    /// All records that `specs` (in `depends` format, e.g. `numpy >=1.20`) need, directly or
+    ///    through their dependencies, sorted by filename - the set `conda repoquery depends
+    ///    --recursive` shows.  This doesn't solve: every record matching a spec is included, along
+    ///    with everything those records depend on.
+    ///
+    /// Virtual packages (`__win`, `__glibc`, ...) aren't in any channel.  `platform` (a subdir such
+    ///    as `linux-64`) decides which of the OS ones are there; records that need one that isn't
+    ///    are left out, since they can't be installed.
+    pub fn dependency_closure(&self, specs: &[&str], platform: &str) -> Vec<(&PackageFilename, &Record)> {
+        let mut by_name: HashMap<&str, Vec<(&PackageFilename, &Record)>, PackageHasher> = HashMap::default();
+        for (filename, record) in self.iter_records() {
+            by_name.entry(record.name.as_str()).or_default().push((filename, record));
+        }
+        let installable = |record: &Record| record.depends.iter().all(|entry| {
+            let dep = Depend::parse(entry);
+            !dep.name.starts_with("__") || virtual_package_available(dep.name, platform)
+        });
+
+        let mut found: HashMap<&str, (&PackageFilename, &Record), PackageHasher> = HashMap::default();
+        // each distinct depends entry only needs matching once, however many records share it
+        let mut seen: HashSet<&str, PackageHasher> = HashSet::default();
+        let mut pending: Vec<&str> = specs.to_vec();
+        while let Some(entry) = pending.pop() {
+            if !seen.insert(entry) {
+                continue;
+            }
+            let dep = Depend::parse(entry);
+            for (filename, record) in by_name.get(dep.name).into_iter().flatten() {
+                if found.contains_key(filename.as_str()) || !dep.matches(record) || !installable(record) {
+                    continue;
+                }
+                found.insert(filename.as_str(), (filename, record));
+                pending.extend(record.depends.iter().map(|d| d.as_str()));
+            }
+        }
+        let mut closure: Vec<(&PackageFilename, &Record)> = found.into_values().collect();
+        closure.sort_by(|a, b| a.0.cmp(b.0));
+        closure
+    }
+
+    /// Find all records for package `name` whose version matches `spec`, as (filename, record)
+    ///    pairs sorted by filename.
+    pub fn query(&self, name: &str, spec: &VersionSpecOrConstraintTree) -> Vec<(&str, &Record)> {
+        let mut found: Vec<(&str, &Record)> = self.iter_records()
+            .filter(|(_, r)| r.name == name && spec.test_match_version(&r.version))
+            .map(|(f, r)| (f.as_str(), r))
+            .collect();
+        found.sort_by(|a, b| a.0.cmp(b.0));
+        found
+    }
+
+    /// The newest record for package `name`; see `latest_matching`.
+    pub fn latest(&self, name: &str) -> Option<(&PackageFilename, &Record)> {
+        self.iter_records().filter(|(_, r)| r.name == name).max_by(|a, b| candidate_order(*a, *b))
+    }
+
+    /// The newest record matching `spec`: the fewest `track_features`, then the highest
+    ///    version, build number and timestamp, with a `.conda` record preferred over the same
+    ///    package as `.tar.bz2` (see `PickCriterion`).  The spec's subdir is checked against
+    ///    this repodata's; its channel isn't checked, as repodata doesn't know which channel it
+    ///    came from.
+    pub fn latest_matching(&self, spec: &MatchSpec) -> Option<(&PackageFilename, &Record)> {
+        if spec.subdir.as_ref().is_some_and(|s| *s != self.info.subdir) {
+            return None;
+        }
+        self.iter_records().filter(|(f, r)| spec.matches_filename(f, r)).max_by(|a, b| candidate_order(*a, *b))
+    }
+
+    /// Encode as MessagePack, a much cheaper format than JSON to hand parsed repodata to another
+    ///    process.  Fields are written by name, so data written by an older libronda still loads.
+    #[cfg(feature = "msgpack")]
+    pub fn to_msgpack(&self) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+        rmp_serde::to_vec_named(self)
+    }
+
+    #[cfg(feature = "msgpack")]
+    pub fn from_msgpack(data: &[u8]) -> Result<Repodata, rmp_serde::decode::Error> {
+        rmp_serde::from_slice(data)
+    }
+
+    /// Encode as CBOR, for consumers that already speak it.
+    #[cfg(feature = "cbor")]
+    pub fn to_cbor(&self) -> Result<Vec<u8>, serde_cbor::Error> {
+        serde_cbor::to_vec(self)
+    }
+
+    #[cfg(feature = "cbor")]
+    pub fn from_cbor(data: &[u8]) -> Result<Repodata, serde_cbor::Error> {
+        serde_cbor::from_slice(data)
+    }
+}
+
+/// What picking one record of a package compares, in order: the first that differs decides.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PickCriterion {
+    /// Fewer `track_features`, which packages set to be picked only when asked for
+    TrackFeatures,
+    Version,
+    BuildNumber,
+    /// The newest build
+    Timestamp,
+    /// `.conda` over the same package as `.tar.bz2`
+    Format,
+    /// Anything still tied is the same package; settled by filename so the pick is stable
+    Filename,
+}
+
+const PICK_ORDER: [PickCriterion; 6] = [PickCriterion::TrackFeatures, PickCriterion::Version, PickCriterion::BuildNumber,
+                                        PickCriterion::Timestamp, PickCriterion::Format, PickCriterion::Filename];
+
+impl PickCriterion {
+    /// Compare two records by this criterion alone, the preferred one being greater.
+    pub(crate) fn compare(self, a: (&PackageFilename, &Record), b: (&PackageFilename, &Record)) -> Ordering {
+        let ((fa, ra), (fb, rb)) = (a, b);
+        match self {
+            PickCriterion::TrackFeatures => track_feature_count(rb).cmp(&track_feature_count(ra)),
+            PickCriterion::Version => ra.version.partial_cmp(&rb.version).unwrap_or(Ordering::Equal),
+            PickCriterion::BuildNumber => ra.build_number.cmp(&rb.build_number),
+            PickCriterion::Timestamp => ra.timestamp_ms().cmp(&rb.timestamp_ms()),
+            PickCriterion::Format => (fa.format() == PackageFormat::Conda).cmp(&(fb.format() == PackageFormat::Conda)),
+            PickCriterion::Filename => fa.cmp(fb),
+        }
+    }
+}
+
+impl fmt::Display for PickCriterion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            PickCriterion::TrackFeatures => "more track_features",
+            PickCriterion::Version => "older version",
+            PickCriterion::BuildNumber => "lower build number",
+            PickCriterion::Timestamp => "older build",
+            PickCriterion::Format => ".tar.bz2 rather than .conda",
+            PickCriterion::Filename => "tied, settled by filename",
+        })
+    }
+}
+
+// track_features are separated by spaces or commas
+fn track_feature_count(record: &Record) -> usize {
+    record.track_features.as_deref().map_or(0, |t| t.split([' ', ',']).filter(|f| !f.is_empty()).count())
+}
+
+/// The order `latest` picks by, lowest first.
+pub(crate) fn candidate_order(a: (&PackageFilename, &Record), b: (&PackageFilename, &Record)) -> Ordering {
+    PICK_ORDER.iter().map(|c| c.compare(a, b)).find(|o| o.is_ne()).unwrap_or(Ordering::Equal)
+}
+
+/// The criterion that puts `b` before `a` in the order `latest` picks by, if they differ at all.
+pub(crate) fn deciding_criterion(a: (&PackageFilename, &Record), b: (&PackageFilename, &Record)) -> Option<PickCriterion> {
+    PICK_ORDER.iter().copied().find(|c| c.compare(a, b).is_ne())
+}
+
+#[derive(Debug)]
+pub enum RepodataReadError {
+    Io(io::Error),
+    Json(serde_json::error::Error),
+    /// Stopped through a `CancellationToken`
+    Cancelled,
+    /// Loaded with `RepodataLoader::platform`, and for another platform
+    WrongPlatform { expected: Platform, found: String },
+}
+
+impl fmt::Display for RepodataReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RepodataReadError::Io(e) => write!(f, "unable to read repodata: {}", e),
+            RepodataReadError::Json(e) => write!(f, "unable to parse repodata: {}", e),
+            RepodataReadError::Cancelled => write!(f, "reading repodata was cancelled"),
+            RepodataReadError::WrongPlatform { expected, found } =>
+                write!(f, "repodata is for {}, not {}", found, expected),
+        }
+    }
+}
+
+impl Error for RepodataReadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RepodataReadError::Io(e) => Some(e),
+            RepodataReadError::Json(e) => Some(e),
+            RepodataReadError::Cancelled | RepodataReadError::WrongPlatform { .. } => None,
+        }
+    }
+}
+
+/// Read and parse the repodata.json file at `path`.
+pub fn read_repodata<P: AsRef<Path>>(path: P) -> Result<Repodata, RepodataReadError> {
+    let data = std::fs::read(path).map_err(RepodataReadError::Io)?;
+    read_repodata_from_slice(&data).map_err(RepodataReadError::Json)
+}
+
+/// Read and parse repodata from `reader`, e.g. a decompressing stream or a socket.  The whole
+///    document is read into memory first: parsing a slice is several times faster than
+///    serde_json's byte-at-a-time reader, and repodata is parsed into memory anyway.
+pub fn read_repodata_from_reader<R: Read>(mut reader: R) -> Result<Repodata, RepodataReadError> {
+    let mut data = vec![];
+    reader.read_to_end(&mut data).map_err(RepodataReadError::Io)?;
+    read_repodata_from_slice(&data).map_err(RepodataReadError::Json)
+}
+
+/// JSON schema describing the repodata.json documents `read_repodata` accepts.
+#[cfg(feature = "schema")]
+pub fn repodata_json_schema() -> serde_json::Value {
+    serde_json::to_value(schemars::schema_for!(Repodata)).unwrap()
+}
+
+/// Parse repodata that is already in memory, e.g. downloaded by another tool.  The bytes are
+///    read in place; no intermediate `String` is made.
+pub fn read_repodata_from_slice(data: &[u8]) -> Result<Repodata, serde_json::error::Error> {
+    serde_json::from_slice(data)
+}
+
+/// Like `read_repodata_from_slice`, giving up with `RepodataReadError::Cancelled` soon after
+///    `token` is cancelled.
+pub fn read_repodata_from_slice_cancellable(data: &[u8], token: &CancellationToken) -> Result<Repodata, RepodataReadError> {
+    token.check().map_err(|_| RepodataReadError::Cancelled)?;
+    // put back whatever was there, so that nested calls (and panics) leave the thread clean
+    struct Restore(Option<CancellationToken>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            PARSE_CANCEL.with(|t| *t.borrow_mut() = self.0.take());
+        }
+    }
+    let _restore = Restore(PARSE_CANCEL.with(|t| t.borrow_mut().replace(token.clone())));
+    match read_repodata_from_slice(data) {
+        Err(_) if token.is_cancelled() => Err(RepodataReadError::Cancelled),
+        result => result.map_err(RepodataReadError::Json),
+    }
+}
 
-    // Return the `Repodata`.
-    Ok(r)
+/// Parse repodata with simd-json instead of serde_json.  The result is the same `Repodata`.
+///    simd-json parses in place, so `data` is used as scratch space and holds garbage afterwards.
+#[cfg(feature = "simd")]
+pub fn read_repodata_simd(data: &mut [u8]) -> Result<Repodata, simd_json::Error> {
+    simd_json::serde::from_slice(data)
 }
 
 #[cfg(test)]
 mod tests {
+    use std::convert::TryFrom;
     use std::path::PathBuf;
     use super::*;
 
@@ -69,4 +510,279 @@ mod tests {
         assert_eq!(_u.info.subdir, "win-64");
 
     }
+
+    #[test]
+    fn test_load_repodata_from_slice() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("tests/data/current_repodata.json");
+        let data = std::fs::read(&d).unwrap();
+        let from_slice = read_repodata_from_slice(&data).unwrap();
+        let from_file = read_repodata(&d).unwrap();
+        assert_eq!(from_slice.info.subdir, "win-64");
+        assert_eq!(from_slice.packages.len(), from_file.packages.len());
+        assert_eq!(from_slice.packages_conda.len(), from_file.packages_conda.len());
+        assert!(read_repodata_from_slice(b"{").is_err());
+    }
+
+    #[test]
+    fn test_load_legacy_repodata() {
+        let data = br#"{"info": {"arch": "x86_64", "platform": "linux"}, "packages": {
+            "numpy-1.11.3-py27_nomkl_0.tar.bz2": {"arch": "x86_64", "platform": "linux", "build": "py27_nomkl_0",
+                "build_number": 0, "depends": ["nomkl", "python 2.7*"], "features": "nomkl", "md5": "",
+                "name": "numpy", "size": 0, "version": "1.11.3", "license_family": "BSD"},
+            "conda-env-2.6.0-0.tar.bz2": {"arch": null, "platform": null, "build": "0", "build_number": 0,
+                "md5": "", "name": "conda-env", "preferred_env": "root", "size": 0, "version": "2.6.0"},
+            "nomkl-1.0-0.tar.bz2": {"build": "0", "build_number": 0, "depends": [], "md5": "", "name": "nomkl",
+                "size": 0, "track_features": "nomkl", "version": "1.0"}
+        }}"#;
+        let repodata = read_repodata_from_slice(data).unwrap();
+        assert_eq!((repodata.info.subdir.as_str(), repodata.repodata_version), ("", 0));
+        assert!(repodata.packages_conda.is_empty());
+        let numpy = repodata.record("numpy-1.11.3-py27_nomkl_0.tar.bz2").unwrap();
+        assert_eq!(numpy.arch.as_deref(), Some("x86_64"));
+        assert!(numpy.has_feature("nomkl"));
+        let env = repodata.record("conda-env-2.6.0-0.tar.bz2").unwrap();
+        assert!(env.depends.is_empty() && env.arch.is_none());
+        assert_eq!(env.preferred_env.as_deref(), Some("root"));
+        assert_eq!(repodata.record("nomkl-1.0-0.tar.bz2").unwrap().track_features.as_deref(), Some("nomkl"));
+    }
+
+    #[test]
+    fn test_read_repodata_errors() {
+        match read_repodata("tests/data/does_not_exist.json") {
+            Err(RepodataReadError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::NotFound),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(read_repodata_from_reader(&b"{}"[..]), Err(RepodataReadError::Json(_))));
+    }
+
+    #[test]
+    fn test_load_repodata_from_reader() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("tests/data/current_repodata.json");
+        let from_reader = read_repodata_from_reader(std::fs::File::open(&d).unwrap()).unwrap();
+        assert_eq!(from_reader.info.subdir, "win-64");
+        assert_eq!(from_reader.packages.len(), read_repodata(&d).unwrap().packages.len());
+    }
+
+    #[cfg(any(feature = "msgpack", feature = "cbor", feature = "simd"))]
+    fn assert_same_repodata(a: &Repodata, b: &Repodata) {
+        assert_eq!(a.info.subdir, b.info.subdir);
+        assert_eq!(a.packages.len(), b.packages.len());
+        assert_eq!(a.packages_conda.len(), b.packages_conda.len());
+        for (filename, record) in a.iter_records() {
+            let other = b.record(filename.as_str()).unwrap();
+            assert_eq!(record.version.as_str(), other.version.as_str());
+            assert_eq!(record.depends, other.depends);
+            assert_eq!(record.sha256, other.sha256);
+        }
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn test_msgpack_round_trip() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("tests/data/current_repodata.json");
+        let repodata = read_repodata(d).unwrap();
+        let encoded = repodata.to_msgpack().unwrap();
+        assert_same_repodata(&repodata, &Repodata::from_msgpack(&encoded).unwrap());
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_cbor_round_trip() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("tests/data/current_repodata.json");
+        let repodata = read_repodata(d).unwrap();
+        let encoded = repodata.to_cbor().unwrap();
+        assert_same_repodata(&repodata, &Repodata::from_cbor(&encoded).unwrap());
+    }
+
+    #[cfg(feature = "simd")]
+    #[test]
+    fn test_load_repodata_simd() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("tests/data/current_repodata.json");
+        let mut data = std::fs::read(&d).unwrap();
+        let repodata = read_repodata(&d).unwrap();
+        assert_same_repodata(&repodata, &read_repodata_simd(&mut data).unwrap());
+        assert!(read_repodata_simd(&mut b"{".to_vec()).is_err());
+    }
+
+    #[cfg(feature = "schema")]
+    #[test]
+    fn test_repodata_json_schema() {
+        let schema = repodata_json_schema();
+        let required = schema["required"].as_array().unwrap();
+        assert!(required.contains(&"packages".into()));
+        // historical repodata predates packages.conda
+        assert!(!required.contains(&"packages.conda".into()));
+        let record = &schema["definitions"]["Record"];
+        assert_eq!(record["properties"]["version"]["type"], "string");
+        let record_required = record["required"].as_array().unwrap();
+        assert!(record_required.contains(&"md5".into()));
+        // older packages don't have these, so they're optional
+        assert!(!record_required.contains(&"sha256".into()));
+        assert!(!record_required.contains(&"timestamp".into()));
+        assert!(!record_required.contains(&"arch".into()));
+    }
+
+    #[test]
+    fn test_query() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("tests/data/current_repodata.json");
+        let repodata: Repodata = read_repodata(d).unwrap();
+        let spec = VersionSpecOrConstraintTree::try_from("3.3.3").unwrap();
+        let found = repodata.query("_libarchive_static_for_cph", &spec);
+        assert!(!found.is_empty());
+        for (filename, record) in found.iter() {
+            assert_eq!(record.name, "_libarchive_static_for_cph");
+            assert!(repodata.record(filename).is_some());
+        }
+        let spec = VersionSpecOrConstraintTree::try_from(">100").unwrap();
+        assert!(repodata.query("_libarchive_static_for_cph", &spec).is_empty());
+    }
+
+    #[test]
+    fn test_reverse_depends_index() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("tests/data/current_repodata.json");
+        let repodata: Repodata = read_repodata(d).unwrap();
+        let index = repodata.reverse_depends_index();
+        let mut expected: Vec<&str> = repodata.iter_records()
+            .filter(|(_, r)| r.depends.iter().any(|dep| Depend::parse(dep).name == "zlib"))
+            .map(|(f, _)| f.as_str())
+            .collect();
+        expected.sort();
+        let found: Vec<&str> = index["zlib"].iter().map(|f| f.as_str()).collect();
+        assert!(!found.is_empty());
+        assert_eq!(found, expected);
+        assert!(!index.contains_key("surely-not-a-package"));
+    }
+
+    #[test]
+    fn test_share_across_threads() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("tests/data/current_repodata.json");
+        let repodata = std::sync::Arc::new(read_repodata(d).unwrap());
+        let spec = MatchSpec::parse("_libarchive_static_for_cph").unwrap();
+        let expected = repodata.latest_matching(&spec).map(|(f, _)| f.to_string());
+        let workers: Vec<_> = (0..4).map(|_| {
+            let (repodata, spec) = (repodata.clone(), spec.clone());
+            std::thread::spawn(move || repodata.latest_matching(&spec).map(|(f, _)| f.to_string()))
+        }).collect();
+        for worker in workers {
+            assert_eq!(worker.join().unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_read_cancellable() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("tests/data/current_repodata.json");
+        let data = std::fs::read(d).unwrap();
+        let token = CancellationToken::new();
+        let repodata = read_repodata_from_slice_cancellable(&data, &token).unwrap();
+        assert!(repodata.iter_records().count() > CANCEL_CHECK_INTERVAL);
+        token.cancel();
+        assert!(matches!(read_repodata_from_slice_cancellable(&data, &token), Err(RepodataReadError::Cancelled)));
+        // the token doesn't outlive the call
+        assert!(read_repodata_from_slice(&data).is_ok());
+        PARSE_CANCEL.with(|t| assert!(t.borrow().is_none()));
+
+        // cancelling while the packages are being parsed stops it there
+        PARSE_CANCEL.with(|t| *t.borrow_mut() = Some(token.clone()));
+        let err = read_repodata_from_slice(&data).unwrap_err();
+        PARSE_CANCEL.with(|t| *t.borrow_mut() = None);
+        assert!(err.to_string().contains("cancelled"));
+    }
+
+    #[test]
+    fn test_timestamp_units() {
+        let record = |timestamp: &str| -> Record {
+            serde_json::from_str(&format!(r#"{{"build": "0", "build_number": 0, "depends": [], "md5": "",
+                "name": "a", "size": 0, "version": "1.0"{}}}"#, timestamp)).unwrap()
+        };
+        assert_eq!(record(r#", "timestamp": 1633000000"#).timestamp_ms(), Some(1_633_000_000_000));
+        assert_eq!(record(r#", "timestamp": 1633000000123"#).timestamp_ms(), Some(1_633_000_000_123));
+        assert_eq!(record(r#", "timestamp": null"#).timestamp_ms(), None);
+        let missing = record("");
+        assert_eq!(missing.timestamp_ms(), None);
+        assert!(!serde_json::to_string(&missing).unwrap().contains("timestamp"));
+        #[cfg(feature = "chrono")]
+        assert_eq!(record(r#", "timestamp": 1633000000"#).built_at().unwrap().to_rfc3339(), "2021-09-30T11:06:40+00:00");
+    }
+
+    #[test]
+    fn test_latest() {
+        let data = br#"{"info": {"subdir": "linux-64"}, "repodata_version": 1, "removed": [],
+            "packages": {
+                "a-1.10-0.tar.bz2": {"build": "0", "build_number": 0, "depends": [], "md5": "", "name": "a", "size": 0, "version": "1.10"},
+                "a-1.10-1.tar.bz2": {"build": "1", "build_number": 1, "depends": [], "md5": "", "name": "a", "size": 0, "version": "1.10"},
+                "a-1.9-5.tar.bz2": {"build": "5", "build_number": 5, "depends": [], "md5": "", "name": "a", "size": 0, "version": "1.9"}
+            },
+            "packages.conda": {
+                "a-1.10-1.conda": {"build": "1", "build_number": 1, "depends": [], "md5": "", "name": "a", "size": 0, "version": "1.10"}
+            }}"#;
+        let repodata = read_repodata_from_slice(data).unwrap();
+        let latest = |spec: &str| repodata.latest_matching(&spec.parse().unwrap()).map(|(f, _)| f.as_str());
+        assert_eq!(repodata.latest("a").map(|(f, _)| f.as_str()), Some("a-1.10-1.conda"));
+        assert_eq!(latest("a <1.10"), Some("a-1.9-5.tar.bz2"));
+        assert_eq!(latest("a[build_number=0]"), Some("a-1.10-0.tar.bz2"));
+        assert_eq!(latest("a[fn=a-1.10-1.tar.bz2]"), Some("a-1.10-1.tar.bz2"));
+        assert_eq!(latest("defaults/linux-64::a 1.9"), Some("a-1.9-5.tar.bz2"));
+        assert_eq!(latest("defaults/osx-64::a"), None);
+        assert!(repodata.latest("b").is_none());
+    }
+
+    fn closure_repodata() -> Repodata {
+        let record = |name: &str, version: &str, depends: &str| format!(
+            r#""{n}-{v}-0.conda": {{"build": "0", "build_number": 0, "depends": [{d}], "md5": "", "name": "{n}",
+                                   "size": 0, "version": "{v}"}}"#, n = name, v = version, d = depends);
+        let packages = [
+            record("app", "1.0", r#""lib >=2", "winonly""#),
+            record("lib", "1.0", ""),
+            record("lib", "2.0", r#""zlib 1.2.*""#),
+            record("lib", "3.0", r#""zlib 1.2.*""#),
+            record("zlib", "1.2.11", ""),
+            record("zlib", "1.3", ""),
+            record("winonly", "1.0", r#""__win""#),
+            record("unrelated", "1.0", ""),
+        ].join(",");
+        let data = format!(r#"{{"info": {{"subdir": "noarch"}}, "repodata_version": 1, "removed": [],
+            "packages": {{}}, "packages.conda": {{{}}}}}"#, packages);
+        read_repodata_from_slice(data.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_dependency_closure() {
+        let repodata = closure_repodata();
+        let closure = |specs: &[&str], platform| -> Vec<String> {
+            repodata.dependency_closure(specs, platform).iter().map(|(f, _)| f.stem().to_string()).collect()
+        };
+        assert_eq!(closure(&["app"], "win-64"),
+                   vec!["app-1.0-0", "lib-2.0-0", "lib-3.0-0", "winonly-1.0-0", "zlib-1.2.11-0"]);
+        // winonly needs __win, so it can't be installed on linux
+        assert_eq!(closure(&["winonly", "lib 3.0"], "linux-64"), vec!["lib-3.0-0", "zlib-1.2.11-0"]);
+        assert!(closure(&["surely-not-a-package"], "win-64").is_empty());
+    }
+
+    #[test]
+    fn test_iter_records_prefer_conda() {
+        let record = r#"{"build": "0", "build_number": 0, "depends": [], "md5": "", "name": "zlib",
+                         "size": 0, "version": "1.2.11"}"#;
+        let data = format!(r#"{{"info": {{"subdir": "noarch"}}, "repodata_version": 1, "removed": [],
+            "packages": {{"zlib-1.2.11-0.tar.bz2": {r}, "zlib-1.2.12-0.tar.bz2": {r}}},
+            "packages.conda": {{"zlib-1.2.11-0.conda": {r}}}}}"#, r = record);
+        let repodata = read_repodata_from_slice(data.as_bytes()).unwrap();
+        let filenames = |policy| {
+            let mut found: Vec<&str> = repodata.iter_records_with(policy).map(|(f, _)| f.as_str()).collect();
+            found.sort();
+            found
+        };
+        assert_eq!(filenames(FormatPolicy::All),
+                   vec!["zlib-1.2.11-0.conda", "zlib-1.2.11-0.tar.bz2", "zlib-1.2.12-0.tar.bz2"]);
+        assert_eq!(filenames(FormatPolicy::PreferConda), vec!["zlib-1.2.11-0.conda", "zlib-1.2.12-0.tar.bz2"]);
+        assert_eq!(repodata.iter_records().count(), 3);
+    }
 }
@@ -0,0 +1,239 @@
+//! A small filter language for searching repodata, as a command line or a notebook would:
+//!
+//! ```text
+//! numpy>=1.20 build=py39* newest=3
+//! ```
+//!
+//! Terms are separated by spaces.  The first term without a key is a package name, which may
+//! contain `*` wildcards and may be followed by a version spec, either directly (`numpy>=1.20`)
+//! or as the next term (`numpy >=1.20`).  The keyed terms are `name=`, `version=`, `build=` (a
+//! build string, with wildcards), `channel=`, `subdir=` and `newest=N`, which keeps only the N
+//! newest records of each package.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::matchspec::matchspec::glob_match;
+use crate::repodata::filename::PackageFilename;
+use crate::repodata::repodata::{candidate_order, Record, Repodata};
+use crate::version::errors::VersionParsingError;
+use crate::{Spec, VersionSpecOrConstraintTree};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterError {
+    /// A term that isn't a name, a version or `key=value`
+    Invalid(String),
+    UnknownKey(String),
+    /// `newest=` with something other than a positive number
+    Newest(String),
+    Version(VersionParsingError),
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FilterError::Invalid(s) => write!(f, "invalid filter term '{}'", s),
+            FilterError::UnknownKey(k) => write!(f, "unknown filter key '{}'", k),
+            FilterError::Newest(n) => write!(f, "newest needs a positive number, not '{}'", n),
+            FilterError::Version(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for FilterError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FilterError::Version(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<VersionParsingError> for FilterError {
+    fn from(e: VersionParsingError) -> Self {
+        FilterError::Version(e)
+    }
+}
+
+/// A parsed filter.  `matches` tests one record; `newest` is applied by `Repodata::select`, as it
+///    needs all of a package's records.
+#[derive(Debug, Clone, Default)]
+pub struct RecordFilter {
+    /// Package name pattern, `*` for any
+    pub name: Option<String>,
+    pub version: Option<VersionSpecOrConstraintTree>,
+    /// Build string pattern
+    pub build: Option<String>,
+    pub channel: Option<String>,
+    pub subdir: Option<String>,
+    pub newest: Option<usize>,
+}
+
+const OPERATOR_CHARS: &str = "=<>!~";
+
+impl RecordFilter {
+    pub fn parse(filter: &str) -> Result<RecordFilter, FilterError> {
+        let mut parsed = RecordFilter::default();
+        let mut terms = filter.split_whitespace().peekable();
+        while let Some(term) = terms.next() {
+            let keyed = term.split_once('=').filter(|(k, _)| !k.is_empty() && !k.contains(|c: char| OPERATOR_CHARS.contains(c)));
+            if let Some((key, value)) = keyed {
+                match key {
+                    "name" => parsed.name = Some(value.to_string()),
+                    "version" => parsed.version = Some(VersionSpecOrConstraintTree::try_from(value)?),
+                    "build" => parsed.build = Some(value.to_string()),
+                    "channel" => parsed.channel = Some(value.to_string()),
+                    "subdir" => parsed.subdir = Some(value.to_string()),
+                    "newest" => parsed.newest = Some(value.parse().ok().filter(|&n| n > 0)
+                        .ok_or_else(|| FilterError::Newest(value.to_string()))?),
+                    // `numpy=1.20` is a name with a version, not a key
+                    _ if parsed.name.is_none() =>
+                        parsed.set_name_and_version(term, &mut terms)?,
+                    _ => return Err(FilterError::UnknownKey(key.to_string())),
+                }
+            } else if parsed.name.is_none() && !term.starts_with(|c: char| OPERATOR_CHARS.contains(c)) {
+                parsed.set_name_and_version(term, &mut terms)?;
+            } else {
+                return Err(FilterError::Invalid(term.to_string()));
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn set_name_and_version<'a, I: Iterator<Item = &'a str>>(&mut self, term: &str, terms: &mut std::iter::Peekable<I>)
+                                                            -> Result<(), FilterError> {
+        let name_end = term.find(|c: char| OPERATOR_CHARS.contains(c)).unwrap_or(term.len());
+        self.name = Some(term[..name_end].to_string());
+        let mut version = &term[name_end..];
+        // a version as the next term; anything keyed is left for the caller
+        if version.is_empty() {
+            if let Some(next) = terms.peek().filter(|t| !t.contains('=') || t.starts_with(|c: char| OPERATOR_CHARS.contains(c))) {
+                version = next;
+                terms.next();
+            }
+        }
+        if !version.is_empty() {
+            // a lone `=1.20` means 1.20.*, as in match specs
+            let version = match version.strip_prefix('=') {
+                Some(prefix) if !prefix.starts_with('=') && !prefix.ends_with('*') => format!("{}*", prefix),
+                Some(prefix) if !prefix.starts_with('=') => prefix.to_string(),
+                _ => version.to_string(),
+            };
+            self.version = Some(VersionSpecOrConstraintTree::try_from(version.as_str())?);
+        }
+        Ok(())
+    }
+
+    /// Whether `record` passes the name, version and build terms.  Records don't know their
+    ///    channel or subdir; see `matches_channel`.
+    pub fn matches(&self, record: &Record) -> bool {
+        self.name.as_deref().is_none_or(|n| glob_match(n, &record.name))
+            && self.version.as_ref().is_none_or(|v| v.test_match_version(&record.version))
+            && self.build.as_deref().is_none_or(|b| glob_match(b, &record.build))
+    }
+
+    /// Whether the `channel=` and `subdir=` terms allow records from `channel` and `subdir`.
+    pub fn matches_channel(&self, channel: &str, subdir: &str) -> bool {
+        self.channel.as_deref().is_none_or(|c| glob_match(c, channel))
+            && self.subdir.as_deref().is_none_or(|s| glob_match(s, subdir))
+    }
+}
+
+impl FromStr for RecordFilter {
+    type Err = FilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RecordFilter::parse(s)
+    }
+}
+
+impl Repodata {
+    /// The records passing `filter`, e.g. `numpy>=1.20 build=py39* newest=3`, sorted by name and
+    ///    newest first within a name.  A `subdir=` term is checked against this repodata's subdir;
+    ///    a `channel=` term isn't checked, as repodata doesn't know which channel it came from.
+    pub fn select(&self, filter: &str) -> Result<Vec<(&PackageFilename, &Record)>, FilterError> {
+        Ok(self.select_with(&RecordFilter::parse(filter)?))
+    }
+
+    /// `select` with an already parsed filter.
+    pub fn select_with(&self, filter: &RecordFilter) -> Vec<(&PackageFilename, &Record)> {
+        if filter.subdir.as_deref().is_some_and(|s| !glob_match(s, &self.info.subdir)) {
+            return vec![];
+        }
+        let mut by_name: BTreeMap<&str, Vec<(&PackageFilename, &Record)>> = BTreeMap::new();
+        for (filename, record) in self.iter_records().filter(|(_, r)| filter.matches(r)) {
+            by_name.entry(record.name.as_str()).or_default().push((filename, record));
+        }
+        let mut selected = vec![];
+        for (_, mut records) in by_name {
+            records.sort_by(|a, b| candidate_order(*b, *a));
+            records.truncate(filter.newest.unwrap_or(usize::MAX));
+            selected.extend(records);
+        }
+        selected
+    }
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_repodata_from_slice;
+
+    fn repodata() -> Repodata {
+        let record = |name: &str, version: &str, build: &str| format!(
+            r#""{n}-{v}-{b}.conda": {{"build": "{b}", "build_number": 0, "depends": [], "md5": "", "name": "{n}",
+                                      "size": 0, "version": "{v}"}}"#, n = name, v = version, b = build);
+        let packages = [
+            record("numpy", "1.19.5", "py39_0"),
+            record("numpy", "1.20.1", "py38_0"),
+            record("numpy", "1.20.1", "py39_0"),
+            record("numpy", "1.21.0", "py39_0"),
+            record("numpy", "1.22.0", "py39_0"),
+            record("numpy-base", "1.22.0", "py39_0"),
+            record("scipy", "1.7.0", "py39_0"),
+        ].join(",");
+        let data = format!(r#"{{"info": {{"subdir": "linux-64"}}, "repodata_version": 1, "removed": [],
+            "packages": {{}}, "packages.conda": {{{}}}}}"#, packages);
+        read_repodata_from_slice(data.as_bytes()).unwrap()
+    }
+
+    #[rstest(
+        filter, expected,
+        case("numpy>=1.20 build=py39* newest=2", &["numpy-1.22.0-py39_0", "numpy-1.21.0-py39_0"]),
+        case("numpy >=1.20,<1.21", &["numpy-1.20.1-py39_0", "numpy-1.20.1-py38_0"]),
+        case("numpy=1.20", &["numpy-1.20.1-py39_0", "numpy-1.20.1-py38_0"]),
+        case("numpy* newest=1", &["numpy-1.22.0-py39_0", "numpy-base-1.22.0-py39_0"]),
+        case("build=py38* subdir=linux-*", &["numpy-1.20.1-py38_0"]),
+        case("name=scipy version=1.7.*", &["scipy-1.7.0-py39_0"]),
+        case("scipy subdir=osx-64", &[]),
+        case("scipy channel=conda-forge", &["scipy-1.7.0-py39_0"])
+    )]
+    fn test_select(filter: &str, expected: &[&str]) {
+        let repodata = repodata();
+        let found: Vec<&str> = repodata.select(filter).unwrap().iter().map(|(f, _)| f.stem()).collect();
+        assert_eq!(found, expected);
+    }
+
+    #[rstest(
+        filter,
+        case("numpy newest=0"),
+        case("numpy newest=all"),
+        case("numpy colour=blue"),
+        case("numpy >=1.20 <2"),
+        case("numpy version=(1.2")
+    )]
+    fn test_select_errors(filter: &str) {
+        assert!(repodata().select(filter).is_err());
+    }
+
+    #[test]
+    fn test_matches_channel() {
+        let filter = RecordFilter::parse("numpy channel=conda-forge subdir=linux-64").unwrap();
+        assert!(filter.matches_channel("conda-forge", "linux-64"));
+        assert!(!filter.matches_channel("defaults", "linux-64"));
+    }
+}
@@ -0,0 +1,158 @@
+//! proptest strategies for realistic conda inputs: version strings, version specs, match specs and
+//! small channels whose packages depend on each other.
+//!
+//! libronda's own property tests use these, and they are exported with the `test-support`
+//! feature so that crates built on libronda can fuzz against the same inputs:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! libronda = { version = "0.1", features = ["test-support"] }
+//! ```
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use crate::matchspec::matchspec::MatchSpec;
+use crate::repodata::repodata::{read_repodata_from_slice, Record, Repodata};
+use crate::version::spec_trees::VersionSpecOrConstraintTree;
+use crate::{CompOp, Version};
+
+/// The names `record` and `repodata` draw from, so that dependencies mostly name packages that
+///    are in the channel.  `missing` never is.
+pub const PACKAGE_NAMES: &[&str] = &["python", "numpy", "libzlib", "ca-certificates", "_openmp_mutex", "missing"];
+
+/// Version strings the conda parser accepts: an optional epoch, up to four release numbers, an
+///    optional pre/post/dev tag and an optional local version, e.g. `1!2.10.3rc1+cuda`.
+pub fn version_string() -> impl Strategy<Value = String> {
+    "([1-9]!)?[0-9]{1,3}(\\.[0-9]{1,3}){0,3}(\\.?(a|b|rc|dev|post)[0-9]{0,2})?(\\+[a-z0-9]{1,4})?"
+        .prop_filter("conda can't parse it", |v| v.parse::<Version>().is_ok())
+}
+
+pub fn version() -> impl Strategy<Value = Version> {
+    version_string().prop_map(|v| v.parse().unwrap())
+}
+
+/// Version specs, from single comparisons (`>=1.2`, `1.2.*`) to nested groups such as
+///    `>=1.2,(<2|3.0.*)`.  Regex specs are left out.
+pub fn version_spec_string() -> impl Strategy<Value = String> {
+    let leaf = prop_oneof![
+        version_string(),
+        ("(==|>=|<=|<|>|!=)", version_string()).prop_map(|(op, v)| format!("{}{}", op, v)),
+        version_string().prop_map(|v| format!("{}.*", v)),
+    ];
+    leaf.prop_recursive(3, 16, 4, |inner| prop_oneof![
+        vec(inner.clone(), 2..4).prop_map(|parts| parts.join(",")),
+        vec(inner, 2..4).prop_map(|parts| format!("({})", parts.join("|"))),
+    ])
+}
+
+pub fn version_spec() -> impl Strategy<Value = VersionSpecOrConstraintTree> {
+    version_spec_string().prop_filter_map("not a valid spec", |s| VersionSpecOrConstraintTree::try_from(s.as_str()).ok())
+}
+
+/// Match specs using every field that has a spec string form: channel, subdir, namespace, name,
+///    version, build, build number, md5 and filename.
+pub fn match_spec() -> impl Strategy<Value = MatchSpec> {
+    let version = "[0-9]{1,2}(\\.[0-9a-z]{1,2}){0,2}";
+    let version_spec = prop_oneof![
+        version,
+        ("(==|>=|<=|<|>|!=|~=|=)", version).prop_map(|(op, v)| format!("{}{}", op, v)),
+        (version, "(\\*|\\.\\*)").prop_map(|(v, star)| format!("{}{}", v, star)),
+        (version, version).prop_map(|(a, b)| format!(">={},<{}", a, b)),
+        (version, version).prop_map(|(a, b)| format!("{}|{}.*", a, b)),
+    ];
+    let channel = prop_oneof![
+        Just("conda-forge"), Just("conda-forge/label/rc"), Just("https://conda.anaconda.org/pkgs/main"),
+        Just("odd/linux-64"),
+    ];
+    (
+        (proptest::option::of(channel), proptest::option::of(prop_oneof![Just("linux-64"), Just("noarch"), Just("custom")])),
+        (proptest::option::of("[a-z]{1,6}"), "[a-z][a-z0-9_.-]{0,8}"),
+        proptest::option::of(version_spec),
+        proptest::option::of("[a-z0-9_]{1,8}\\*?"),
+        proptest::option::of((prop_oneof![Just(CompOp::Eq), Just(CompOp::Ge), Just(CompOp::Ne)], 0..100u16)),
+        (proptest::option::of("[0-9a-f]{32}"), proptest::option::of("[a-z]{1,5}-1\\.0-0\\.conda")),
+    ).prop_filter_map("unparseable version", |((channel, subdir), (namespace, name), version, build, build_number, (md5, filename))| {
+        Some(MatchSpec {
+            channel: channel.map(str::to_string),
+            subdir: subdir.map(str::to_string),
+            namespace,
+            name,
+            version: match version {
+                Some(v) => Some(VersionSpecOrConstraintTree::try_from(v.as_str()).ok()?),
+                None => None,
+            },
+            build,
+            build_number,
+            filename,
+            md5,
+            sha256: None,
+        })
+    })
+}
+
+// A record as repodata.json has it, so it goes through the same deserialization as a real one
+fn record_json() -> impl Strategy<Value = serde_json::Value> {
+    let depend = (proptest::sample::select(PACKAGE_NAMES), proptest::option::of(version_spec_string()))
+        .prop_map(|(name, spec)| match spec {
+            Some(spec) => format!("{} {}", name, spec),
+            None => name.to_string(),
+        });
+    (
+        proptest::sample::select(&PACKAGE_NAMES[..PACKAGE_NAMES.len() - 1]),
+        version_string(),
+        ("[a-z]{1,4}[0-9]{0,3}_[0-9]", 0..20u16),
+        vec(depend, 0..4),
+        ("[0-9a-f]{32}", 0..1_000_000u64),
+    ).prop_map(|(name, version, (build, build_number), depends, (md5, size))| serde_json::json!({
+        "name": name, "version": version, "build": build, "build_number": build_number,
+        "depends": depends, "md5": md5, "size": size,
+    }))
+}
+
+pub fn record() -> impl Strategy<Value = Record> {
+    record_json().prop_map(|r| serde_json::from_value(r).unwrap())
+}
+
+/// A channel of up to `max_records` packages, all in `packages.conda`, with dependencies between
+///    them (and on the occasional package that isn't there).
+pub fn repodata(max_records: usize) -> impl Strategy<Value = Repodata> {
+    let subdir = prop_oneof![Just("linux-64"), Just("osx-arm64"), Just("win-64"), Just("noarch")];
+    (subdir, vec(record_json(), 0..=max_records)).prop_map(|(subdir, records)| {
+        // keyed by filename, so records that happen to share one collapse into the last
+        let packages: BTreeMap<String, serde_json::Value> = records.into_iter()
+            .map(|r| (format!("{}-{}-{}.conda", r["name"].as_str().unwrap(), r["version"].as_str().unwrap(),
+                              r["build"].as_str().unwrap()), r))
+            .collect();
+        let data = serde_json::json!({
+            "info": {"subdir": subdir}, "repodata_version": 1, "packages": {}, "packages.conda": packages,
+        });
+        read_repodata_from_slice(data.to_string().as_bytes()).unwrap()
+    })
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Spec;
+
+    proptest! {
+        #[test]
+        fn test_version_spec_matches_without_panicking(spec in version_spec(), v in version()) {
+            spec.test_match_version(&v);
+        }
+
+        #[test]
+        fn test_repodata_records_are_queryable(repodata in repodata(8)) {
+            for (filename, record) in repodata.iter_records() {
+                prop_assert_eq!(filename.name(), record.name.as_str());
+                let spec = VersionSpecOrConstraintTree::try_from(format!("=={}", record.version).as_str()).unwrap();
+                prop_assert!(repodata.query(&record.name, &spec).iter().any(|(f, _)| f == &filename.as_str()));
+            }
+        }
+    }
+}
@@ -8,6 +8,8 @@
 
 use std::cmp::Ordering;
 
+use crate::version::errors::VersionParsingError;
+
 /// Enum of supported comparison operators.
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum CompOp {
@@ -86,7 +88,7 @@ impl CompOp {
     /// assert_eq!(CompOp::from_sign("  >=   "), Ok(CompOp::Ge));
     /// assert!(CompOp::from_sign("*").is_err());
     /// ```
-    pub fn from_sign(sign: &str) -> Result<CompOp, ()> {
+    pub fn from_sign(sign: &str) -> Result<CompOp, VersionParsingError> {
         match sign.trim().as_ref() {
             "==" => Ok(CompOp::Eq),
             "!=" => Ok(CompOp::Ne),
@@ -98,7 +100,7 @@ impl CompOp {
             "!=startswith" => Ok(CompOp::NotStartsWith),
             "~=" => Ok(CompOp::Compatible),
             "!~=" => Ok(CompOp::Incompatible),
-            _ => Err(()),
+            _ => Err(VersionParsingError::UnknownOperator(sign.trim().to_string())),
         }
     }
 
@@ -116,7 +118,7 @@ impl CompOp {
     /// assert_eq!(CompOp::from_name("  Ge   "), Ok(CompOp::Ge));
     /// assert!(CompOp::from_name("abc").is_err());
     /// ```
-    pub fn from_name(sign: &str) -> Result<CompOp, ()> {
+    pub fn from_name(sign: &str) -> Result<CompOp, VersionParsingError> {
         match sign.trim().to_lowercase().as_ref() {
             "eq" => Ok(CompOp::Eq),
             "ne" => Ok(CompOp::Ne),
@@ -128,7 +130,7 @@ impl CompOp {
             "notstartswith" => Ok(CompOp::NotStartsWith),
             "compatible" => Ok(CompOp::Compatible),
             "incompatible" => Ok(CompOp::Incompatible),
-            _ => Err(()),
+            _ => Err(VersionParsingError::UnknownOperator(sign.trim().to_string())),
         }
     }
 
@@ -434,7 +436,7 @@ impl CompOp {
 #[cfg(test)]
 mod tests {
     use std::cmp::Ordering;
-    use super::CompOp;
+    use super::{CompOp, VersionParsingError};
 
     #[test]
     fn from_sign() {
@@ -452,7 +454,7 @@ mod tests {
 
         // Exceptional cases
         assert_eq!(CompOp::from_sign("  <=  ").unwrap(), CompOp::Le);
-        assert!(CompOp::from_sign("*").is_err());
+        assert_eq!(CompOp::from_sign(" * "), Err(VersionParsingError::UnknownOperator("*".to_string())));
     }
 
     #[test]
@@ -0,0 +1,245 @@
+//! Compact encoding of version parts.
+//!
+//! A `VersionPart` takes 32 bytes, and string parts own a heap allocation on top of that, while
+//! most parts are small integers.  `Version` therefore keeps each part as one tagged `u64`:
+//!
+//! ```text
+//!  63     56 55    48 47          32 31                0
+//! +---------+--------+--------------+------------------+
+//! |   tag   | class  |    length    |  value / offset  |
+//! +---------+--------+--------------+------------------+
+//! ```
+//!
+//! Integers and epochs are stored in the low bits with their sign bit flipped, so two words of
+//! the same type compare like the numbers do.  String parts store the offset and length of their
+//! text, which usually is a piece of the version string itself; text a parser produced that
+//! doesn't occur in the version string (e.g. a normalized spelling) is appended after it.  PEP 440
+//! strings also store their class (dev, post, ...), and point at their case-folded text, so
+//! comparing them is a byte comparison at most.
+
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+use super::custom_parts::pep440::{compare_pep440_classified, write_pep440_sort_key, Pep440Class, PEP440String};
+use super::version_part::{write_sort_key_str, VersionPart};
+
+// Tags rank the part types the same way `VersionPart::partial_cmp` does: epoch highest
+const TAG_EMPTY: u8 = 0;
+const TAG_PEP440: u8 = 1;
+const TAG_LEX: u8 = 2;
+const TAG_INTEGER: u8 = 3;
+const TAG_EPOCH: u8 = 4;
+
+const TAG_SHIFT: u32 = 56;
+const CLASS_SHIFT: u32 = 48;
+const LENGTH_SHIFT: u32 = 32;
+
+/// Encode `parts`, parsed from `version`.  Returns the text string parts point into and the words:
+/// the length of `version` first, then one per part.  `None` if a string part or the text is too
+/// long for its field.
+pub(crate) fn encode(version: &str, parts: &[VersionPart]) -> Option<(Arc<str>, Arc<[u64]>)> {
+    let mut text = version.to_string();
+    let mut words = Vec::with_capacity(parts.len() + 1);
+    words.push(version.len() as u64);
+
+    let mut string_word = |tag: u8, class: u8, s: &str| -> Option<u64> {
+        let offset = match text.find(s) {
+            Some(offset) => offset,
+            None => {
+                text.push_str(s);
+                text.len() - s.len()
+            },
+        };
+        let offset = u32::try_from(offset).ok()?;
+        let length = u16::try_from(s.len()).ok()?;
+        Some(((tag as u64) << TAG_SHIFT) | ((class as u64) << CLASS_SHIFT) | ((length as u64) << LENGTH_SHIFT)
+             | offset as u64)
+    };
+
+    for part in parts {
+        words.push(match part {
+            VersionPart::Epoch(i) => ((TAG_EPOCH as u64) << TAG_SHIFT) | ((*i as u16) ^ 0x8000) as u64,
+            VersionPart::Integer(i) => ((TAG_INTEGER as u64) << TAG_SHIFT) | ((*i as u32) ^ 0x8000_0000) as u64,
+            VersionPart::LexicographicString(s) => string_word(TAG_LEX, 0, s)?,
+            VersionPart::PEP440String(s) => string_word(TAG_PEP440, s.class() as u8, s.stored_str())?,
+            VersionPart::Empty => (TAG_EMPTY as u64) << TAG_SHIFT,
+        });
+    }
+    // Offsets must stay within u32 even for text appended after the last check
+    u32::try_from(text.len()).ok()?;
+    Some((text.into(), words.into()))
+}
+
+/// One encoded part, with the text its string (if any) points into.
+#[derive(Clone, Copy)]
+pub(crate) struct PartRef<'a> {
+    word: u64,
+    text: &'a str,
+}
+
+impl<'a> PartRef<'a> {
+    pub(crate) fn new(word: u64, text: &'a str) -> PartRef<'a> {
+        PartRef { word, text }
+    }
+
+    fn tag(&self) -> u8 {
+        (self.word >> TAG_SHIFT) as u8
+    }
+
+    fn pep440_class(&self) -> Pep440Class {
+        Pep440Class::from_u8((self.word >> CLASS_SHIFT) as u8)
+    }
+
+    fn str(&self) -> &'a str {
+        let offset = self.word as u32 as usize;
+        let length = (self.word >> LENGTH_SHIFT) as u16 as usize;
+        &self.text[offset..offset + length]
+    }
+
+    fn epoch(&self) -> i16 {
+        ((self.word as u16) ^ 0x8000) as i16
+    }
+
+    /// The epoch, if this part is one.
+    pub(crate) fn as_epoch(&self) -> Option<i16> {
+        if self.tag() == TAG_EPOCH { Some(self.epoch()) } else { None }
+    }
+
+    pub(crate) fn is_integer(&self) -> bool {
+        self.tag() == TAG_INTEGER
+    }
+
+    /// Whether this is the local version label, the only part parsers make lexicographic.
+    pub(crate) fn is_local(&self) -> bool {
+        self.tag() == TAG_LEX
+    }
+
+    /// The text of a string part: the local version label, or a PEP 440 string (lower case
+    ///    unless it is a dev or post tag).
+    pub(crate) fn text(&self) -> Option<&'a str> {
+        if self.tag() == TAG_LEX || self.tag() == TAG_PEP440 { Some(self.str()) } else { None }
+    }
+
+    fn integer(&self) -> i32 {
+        ((self.word as u32) ^ 0x8000_0000) as i32
+    }
+
+    /// Same result as `partial_cmp` on the decoded parts.
+    pub(crate) fn cmp(&self, other: &PartRef) -> Ordering {
+        match self.tag().cmp(&other.tag()) {
+            Ordering::Equal => {},
+            ord => return ord,
+        }
+        match self.tag() {
+            TAG_LEX => self.str().cmp(other.str()),
+            TAG_PEP440 => compare_pep440_classified((self.pep440_class(), self.str()), (other.pep440_class(), other.str())),
+            // epochs and integers sort by their words; Empty is always equal
+            _ => self.word.cmp(&other.word),
+        }
+    }
+
+    /// Compare this part with the empty value of its own type (0, empty string).
+    pub(crate) fn cmp_empty(&self) -> Ordering {
+        match self.tag() {
+            TAG_EPOCH => self.epoch().cmp(&0),
+            TAG_INTEGER => self.integer().cmp(&0),
+            TAG_LEX => self.str().len().cmp(&0),
+            TAG_PEP440 => self.pep440_class().cmp(&Pep440Class::Empty),
+            _ => Ordering::Equal,
+        }
+    }
+
+    /// Compare with a part in the same place of another version, which may be of another type.
+    ///    A part equal to its type's empty value stands in for a missing one, so it is equal to
+    ///    every other empty part and ranks against the rest the way a missing part does: `1.0`
+    ///    is below `1.post1` just like `1` is.
+    pub(crate) fn cmp_padded(&self, other: &PartRef) -> Ordering {
+        match (self.cmp_empty(), other.cmp_empty()) {
+            (Ordering::Equal, Ordering::Equal) => Ordering::Equal,
+            (ord, other_ord) if ord != other_ord => ord.cmp(&other_ord),
+            _ => self.cmp(other),
+        }
+    }
+
+    /// Append a byte encoding of this part to a version sort key.  The leading byte orders the
+    ///    types the same way `cmp` does; the rest orders values within a type.
+    pub(crate) fn write_sort_key(&self, key: &mut Vec<u8>) {
+        key.push(self.tag());
+        match self.tag() {
+            TAG_EPOCH => key.extend_from_slice(&(self.word as u16).to_be_bytes()),
+            TAG_INTEGER => key.extend_from_slice(&(self.word as u32).to_be_bytes()),
+            TAG_LEX => write_sort_key_str(key, self.str()),
+            TAG_PEP440 => write_pep440_sort_key(key, self.pep440_class(), self.str()),
+            _ => {},
+        }
+    }
+
+    pub(crate) fn to_part(self) -> VersionPart {
+        match self.tag() {
+            TAG_EPOCH => VersionPart::Epoch(self.epoch()),
+            TAG_INTEGER => VersionPart::Integer(self.integer()),
+            TAG_LEX => VersionPart::LexicographicString(self.str().to_string()),
+            TAG_PEP440 => VersionPart::PEP440String(PEP440String::from(self.str())),
+            _ => VersionPart::Empty,
+        }
+    }
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty_of(part: &VersionPart) -> VersionPart {
+        match part {
+            VersionPart::Epoch(_) => VersionPart::Epoch(0),
+            VersionPart::Integer(_) => VersionPart::Integer(0),
+            VersionPart::LexicographicString(_) => VersionPart::LexicographicString(String::new()),
+            VersionPart::PEP440String(_) => VersionPart::PEP440String(PEP440String::empty()),
+            VersionPart::Empty => VersionPart::Empty,
+        }
+    }
+
+    fn parts() -> Vec<VersionPart> {
+        vec![VersionPart::Epoch(-2), VersionPart::Integer(i32::MIN), VersionPart::Integer(i32::MAX),
+             VersionPart::LexicographicString("local".to_string()), VersionPart::PEP440String(PEP440String::from("dev")),
+             VersionPart::PEP440String(PEP440String::from("RC")), VersionPart::PEP440String(PEP440String::empty()),
+             VersionPart::PEP440String(PEP440String::from("post")), VersionPart::Empty]
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let (text, words) = encode("1.0rc1+local", &parts()).unwrap();
+        let decoded: Vec<VersionPart> = words[1..].iter().map(|w| PartRef::new(*w, &text).to_part()).collect();
+        assert_eq!(decoded, parts());
+        assert_eq!(&text[..words[0] as usize], "1.0rc1+local");
+    }
+
+    #[test]
+    fn test_text_not_in_version_is_appended() {
+        let (text, _) = encode("1.0rc1+local", &parts()).unwrap();
+        // "local" and "RC", stored case-folded, are found in the version string; "dev" and "post"
+        //    aren't
+        assert_eq!(&*text, "1.0rc1+localdevpost");
+    }
+
+    #[test]
+    fn test_cmp_matches_version_part() {
+        let all = parts();
+        let (text, words) = encode("", &all).unwrap();
+        for (a, wa) in all.iter().zip(words[1..].iter()) {
+            let ra = PartRef::new(*wa, &text);
+            assert_eq!(Some(ra.cmp_empty()), a.partial_cmp(&empty_of(a)), "{:?}", a);
+            for (b, wb) in all.iter().zip(words[1..].iter()) {
+                assert_eq!(Some(ra.cmp(&PartRef::new(*wb, &text))), a.partial_cmp(b), "{:?} {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_overlong_part_is_rejected() {
+        let long = "a".repeat(70_000);
+        assert!(encode(&long, &[VersionPart::LexicographicString(long.clone())]).is_none());
+    }
+}
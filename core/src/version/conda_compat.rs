@@ -0,0 +1,368 @@
+//! conda's own version ordering, quirks included, for `VersionMode::CondaCompat`.
+//!
+//! conda's `VersionOrder` splits a version into components at `.` and `_` (or at `-`, if there is
+//! no `_`), and each component into runs of digits, runs of `*` and runs of other characters.  A
+//! component that starts with a string gets a `0` in front.  Components are compared in turn,
+//! each padded with zeros to the length of the other, so `1.1.dev1` equals `1.1.0dev1` but is
+//! greater than `1.1dev1`.  Strings sort below numbers, `dev` below other strings (but above `*`)
+//! and `post` above every number.  A trailing `_` stays part of the last component, and the local
+//! version after `+` is compared the same way once the rest is equal, which puts `1.0+abc` below
+//! `1.0`.
+//!
+//! The parser writes the components one after the other, separated by `VersionPart::Empty`.
+//! Numbers are `Integer`s, or `LexicographicString`s of their digits if they don't fit; other
+//! strings are lower-cased `LexicographicString`s, except `post`, which is a `PEP440String`.  A
+//! `LexicographicString` holding `+` starts the local version.
+
+use std::cmp::Ordering;
+
+use super::custom_parts::pep440::PEP440String;
+use super::errors::VersionParsingError;
+use super::version::{write_sort_key_marker, Version, SORT_KEY_END};
+use super::version_part::{write_sort_key_str, VersionPart};
+
+const LOCAL_MARKER: &str = "+";
+
+/// Split a version the way conda's `VersionOrder` does.  The parts are only meaningful to
+///    `Version`s in `VersionMode::CondaCompat`, which is what `Version::with_mode` makes.
+pub(crate) fn conda_compat_parser(version: &str) -> Result<Vec<VersionPart>, VersionParsingError> {
+    let invalid = |reason: &str| VersionParsingError::Message(format!("invalid version '{}': {}", version, reason));
+    let valid = |s: &str| s.bytes().all(|b| b.is_ascii_digit() || b.is_ascii_lowercase() || b"*.+!_".contains(&b));
+
+    let mut lowered = version.trim().to_lowercase();
+    if lowered.is_empty() {
+        return Err(invalid("empty version string"));
+    }
+    // dashes are allowed as long as there are no underscores as well
+    if !valid(&lowered) && lowered.contains('-') && !lowered.contains('_') {
+        lowered = lowered.replace('-', "_");
+    }
+    if !valid(&lowered) {
+        return Err(invalid("invalid character(s)"));
+    }
+
+    let mut parts = vec![];
+    let rest = match lowered.matches('!').count() {
+        0 => lowered.as_str(),
+        1 => {
+            let (epoch, rest) = lowered.split_once('!').unwrap_or_default();
+            if epoch.is_empty() || !epoch.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(invalid("epoch must be an integer"));
+            }
+            parts.push(VersionPart::Epoch(epoch.parse().map_err(|_| invalid("epoch is too big"))?));
+            rest
+        },
+        _ => return Err(invalid("duplicated epoch separator '!'")),
+    };
+    let (public, local) = match rest.matches('+').count() {
+        0 => (rest, None),
+        1 => rest.split_once('+').map_or((rest, None), |(public, local)| (public, Some(local))),
+        _ => return Err(invalid("duplicated local version separator '+'")),
+    };
+
+    // a trailing underscore isn't a separator, but part of the last component
+    let components: Vec<String> = match public.strip_suffix('_') {
+        Some(stripped) => {
+            let mut components: Vec<String> = stripped.replace('_', ".").split('.').map(str::to_string).collect();
+            if let Some(last) = components.last_mut() {
+                last.push('_');
+            }
+            components
+        },
+        None => public.replace('_', ".").split('.').map(str::to_string).collect(),
+    };
+    push_components(&mut parts, components.iter().map(String::as_str)).map_err(invalid)?;
+    if let Some(local) = local {
+        parts.push(VersionPart::LexicographicString(LOCAL_MARKER.to_string()));
+        push_components(&mut parts, local.replace('_', ".").split('.')).map_err(invalid)?;
+    }
+    Ok(parts)
+}
+
+fn push_components<'a>(parts: &mut Vec<VersionPart>, components: impl Iterator<Item = &'a str>)
+                       -> Result<(), &'static str> {
+    for (index, component) in components.enumerate() {
+        if component.is_empty() {
+            return Err("empty version component");
+        }
+        if index > 0 {
+            parts.push(VersionPart::Empty);
+        }
+        let mut start = 0;
+        while start < component.len() {
+            let kind = |b: u8| if b.is_ascii_digit() { 0 } else if b == b'*' { 1 } else { 2 };
+            let bytes = component.as_bytes();
+            let end = bytes[start..].iter()
+                .position(|b| kind(*b) != kind(bytes[start]))
+                .map_or(bytes.len(), |n| start + n);
+            let run = &component[start..end];
+            // components start with a number, to keep numbers and strings in phase
+            if start == 0 && kind(bytes[0]) != 0 {
+                parts.push(VersionPart::Integer(0));
+            }
+            parts.push(if kind(bytes[start]) == 0 {
+                match run.parse::<i32>() {
+                    Ok(number) => VersionPart::Integer(number),
+                    Err(_) => VersionPart::LexicographicString(run.trim_start_matches('0').to_string()),
+                }
+            } else {
+                match run {
+                    "post" => VersionPart::PEP440String(PEP440String::from(run)),
+                    // upper case puts dev after '*' and before every other (lower case) string
+                    "dev" => VersionPart::LexicographicString("DEV".to_string()),
+                    _ => VersionPart::LexicographicString(run.to_string()),
+                }
+            });
+            start = end;
+        }
+    }
+    Ok(())
+}
+
+/// One element of a component.  Strings sort below numbers, and `post` above them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Element<'a> {
+    Str(&'a str),
+    Int(i32),
+    // digits of a number too big for an i32, without leading zeros
+    Big(&'a str),
+    Post,
+}
+
+const ZERO: Element<'static> = Element::Int(0);
+
+impl<'a> Element<'a> {
+    fn rank(&self) -> u8 {
+        match self {
+            Element::Str(_) => 0,
+            Element::Int(_) => 1,
+            Element::Big(_) => 2,
+            Element::Post => 3,
+        }
+    }
+
+    fn cmp(&self, other: &Element) -> Ordering {
+        match (self, other) {
+            (Element::Str(a), Element::Str(b)) => a.cmp(b),
+            (Element::Int(a), Element::Int(b)) => a.cmp(b),
+            (Element::Big(a), Element::Big(b)) => a.len().cmp(&b.len()).then_with(|| a.cmp(b)),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+
+    fn write_sort_key(&self, key: &mut Vec<u8>) {
+        key.push(self.rank());
+        match self {
+            Element::Str(s) => write_sort_key_str(key, s),
+            Element::Int(i) => key.extend_from_slice(&((*i as u32) ^ 0x8000_0000).to_be_bytes()),
+            Element::Big(s) => {
+                key.extend_from_slice(&(s.len() as u16).to_be_bytes());
+                key.extend_from_slice(s.as_bytes());
+            },
+            Element::Post => {},
+        }
+    }
+}
+
+type Components<'a> = Vec<Vec<Element<'a>>>;
+
+// The components of the version and of the local version
+fn components(version: &Version) -> (Components<'_>, Components<'_>) {
+    let (mut public, mut local) = (vec![], vec![]);
+    let mut current = vec![];
+    let mut in_local = false;
+    for part in version.parts() {
+        let element = match part {
+            VersionPart::Epoch(_) => continue,
+            VersionPart::Empty => {
+                let list = if in_local { &mut local } else { &mut public };
+                list.push(std::mem::take(&mut current));
+                continue;
+            },
+            VersionPart::LexicographicString(s) if s == LOCAL_MARKER => {
+                public.push(std::mem::take(&mut current));
+                in_local = true;
+                continue;
+            },
+            VersionPart::Integer(i) => Element::Int(*i),
+            VersionPart::LexicographicString(s) if s.bytes().all(|b| b.is_ascii_digit()) => Element::Big(s),
+            VersionPart::LexicographicString(s) => Element::Str(s),
+            VersionPart::PEP440String(_) => Element::Post,
+        };
+        current.push(element);
+    }
+    if in_local { &mut local } else { &mut public }.push(current);
+    (public, local)
+}
+
+fn compare_elements(a: &[Element], b: &[Element]) -> Ordering {
+    (0..a.len().max(b.len()))
+        .map(|i| a.get(i).unwrap_or(&ZERO).cmp(b.get(i).unwrap_or(&ZERO)))
+        .find(|ord| *ord != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+fn compare_components(a: &[Vec<Element>], b: &[Vec<Element>]) -> Ordering {
+    (0..a.len().max(b.len()))
+        .map(|i| compare_elements(a.get(i).map_or(&[], |c| c), b.get(i).map_or(&[], |c| c)))
+        .find(|ord| *ord != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Compare two versions in `VersionMode::CondaCompat`.
+pub(crate) fn compare(a: &Version, b: &Version) -> Ordering {
+    let ((a_public, a_local), (b_public, b_local)) = (components(a), components(b));
+    a.epoch().cmp(&b.epoch())
+        .then_with(|| compare_components(&a_public, &b_public))
+        .then_with(|| compare_components(&a_local, &b_local))
+}
+
+/// Whether `version` matches `prefix` up to the last element of `prefix`, as conda's
+///    `VersionOrder.startswith`: a last element that is a string only has to be a prefix of the
+///    element it is compared with, so `1.1a` starts with `1.1` and `1.1.rc2` with `1.1.r`.
+pub(crate) fn startswith(version: &Version, prefix: &Version) -> bool {
+    if version.epoch() != prefix.epoch() {
+        return false;
+    }
+    let ((public, local), (prefix_public, prefix_local)) = (components(version), components(prefix));
+    let (t1, t2) = if prefix_local.is_empty() {
+        (public, prefix_public)
+    } else if compare_components(&public, &prefix_public) == Ordering::Equal {
+        (local, prefix_local)
+    } else {
+        return false;
+    };
+    let (v2, nt) = match t2.split_last() {
+        Some((last, rest)) => (last, rest.len()),
+        None => return true,
+    };
+    if compare_components(&t1[..nt.min(t1.len())], &t2[..nt]) != Ordering::Equal {
+        return false;
+    }
+    let v1 = t1.get(nt).map_or(&[][..], |c| c);
+    let (c2, nc) = match v2.split_last() {
+        Some((last, rest)) => (last, rest.len()),
+        None => return true,
+    };
+    if compare_elements(&v1[..nc.min(v1.len())], &v2[..nc]) != Ordering::Equal {
+        return false;
+    }
+    match (v1.get(nc).unwrap_or(&ZERO), c2) {
+        (Element::Str(s1), Element::Str(s2)) => s1.starts_with(s2),
+        (_, Element::Str(_)) => false,
+        (c1, c2) => c1.cmp(c2) == Ordering::Equal,
+    }
+}
+
+/// A key that orders like `compare`.  Zero padding is taken care of the same way as in
+///    `Version::sort_key`, for components and for the elements within them.
+pub(crate) fn sort_key(version: &Version) -> Vec<u8> {
+    let mut key = Vec::with_capacity(version.part_count() * 8 + 4);
+    key.extend_from_slice(&(version.epoch() as u16).to_be_bytes());
+    let (public, local) = components(version);
+    for list in [public, local] {
+        let mut empties: u16 = 0;
+        for component in list {
+            match component.iter().map(|e| e.cmp(&ZERO)).find(|ord| *ord != Ordering::Equal) {
+                Some(ord) => write_sort_key_marker(&mut key, ord, empties),
+                None => {
+                    empties = empties.saturating_add(1);
+                    continue;
+                },
+            }
+            empties = 0;
+            let mut element_empties: u16 = 0;
+            for element in component {
+                match element.cmp(&ZERO) {
+                    Ordering::Equal => {
+                        element_empties = element_empties.saturating_add(1);
+                        continue;
+                    },
+                    ord => write_sort_key_marker(&mut key, ord, element_empties),
+                }
+                element.write_sort_key(&mut key);
+                element_empties = 0;
+            }
+            key.push(SORT_KEY_END);
+        }
+        key.push(SORT_KEY_END);
+    }
+    key
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+mod tests {
+    use super::conda_compat_parser;
+    use crate::conformance::vectors::VERSION_ORDER;
+    use crate::{Version, VersionMode};
+
+    fn compat(s: &str) -> Version {
+        Version::with_mode(s, VersionMode::CondaCompat).unwrap()
+    }
+
+    #[test]
+    fn test_sort_key_orders_like_conda() {
+        let versions: Vec<Vec<Version>> = VERSION_ORDER.iter().map(|group| group.iter().map(|v| compat(v)).collect()).collect();
+        for (index, group) in versions.iter().enumerate() {
+            assert!(group.iter().all(|v| v.sort_key() == group[0].sort_key()), "{:?}", group);
+            if let Some(next) = versions.get(index + 1) {
+                assert!(group[0].sort_key() < next[0].sort_key(), "{} {}", group[0], next[0]);
+            }
+        }
+    }
+
+    #[rstest(
+        a, b,
+        case("0.4.1.rc", "  0.4.1.RC  "),
+        case("0.4", "0.4.0"),
+        case("0.4.a1", "0.4.0a1"),
+        case("1.1.dev1", "1.1.0dev1"),
+        case("1.0+abc", "1.0.0+abc")
+    )]
+    fn test_equal(a: &str, b: &str) {
+        assert!(compat(a) == compat(b));
+        assert_eq!(compat(a).sort_key(), compat(b).sort_key());
+    }
+
+    #[test]
+    fn test_quirks() {
+        // local versions sort below the plain version, unless they are numbers
+        assert!(compat("1.0+abc") < compat("1.0"));
+        assert!(compat("1.0+1") > compat("1.0"));
+        assert!(compat("0.4") != compat("0.4.1"));
+        assert!(compat("0.4.a1") != compat("0.4.1a1"));
+        // numbers too big for an i32
+        assert!(compat("20210101123456") > compat("2147483647"));
+        assert!(compat("20210101123456") < compat("20210101123457"));
+        assert!(compat("1.0apost") > compat("1.0a99999999999999"));
+    }
+
+    #[test]
+    fn test_dashes_and_underscores() {
+        assert!(compat("1.0-a") == compat("1.0_a"));
+        assert!(conda_compat_parser("1.0-a_b").is_err());
+        assert!(conda_compat_parser("99999!1.0").is_err());
+    }
+
+    #[rstest(
+        version, prefix, expected,
+        case("1.2.3", "1.2", true),
+        case("1.2a", "1.2", true),
+        case("1.2post1", "1.2", true),
+        case("1.2.0", "1.2", true),
+        case("1.20", "1.2", false),
+        case("1.5", "1.0", false),
+        case("1.1.rc2", "1.1.r", true),
+        case("1.1.post", "1.1.p", false),
+        case("1.1.0a", "1.1.a", true),
+        case("1.1.b", "1.1.a", false),
+        case("1.2", "1.2.0", true),
+        case("1!1.2", "1.2", false),
+        case("1.2+abc.1", "1.2+abc", true),
+        case("1.3+abc", "1.2+abc", false)
+    )]
+    fn test_startswith(version: &str, prefix: &str, expected: bool) {
+        assert_eq!(compat(version).startswith(&compat(prefix)), expected);
+    }
+}
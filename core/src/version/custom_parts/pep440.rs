@@ -1,60 +1,124 @@
 use std::cmp::Ordering;
 use std::fmt;
-use regex::Regex;
-use unicase::UniCase;
 use serde::Deserialize;
 
+use crate::version::version_part::write_sort_key_str;
+
+fn contains_ignore_case(haystack: &str, needle: &str) -> bool {
+    haystack.as_bytes().windows(needle.len()).any(|w| w.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+/// How a PEP 440 string ranks before falling back to comparing the text: dev always loses, post
+///    always wins, and a missing string (a release) beats any other text.  Worked out once when
+///    the string is made, so comparing two strings doesn't search them again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub(crate) enum Pep440Class {
+    Dev = 0,
+    Other = 1,
+    Empty = 2,
+    Post = 3,
+}
+
+impl Pep440Class {
+    pub(crate) fn of(alpha: &str) -> Pep440Class {
+        // post is checked first, so "devpost" counts as post
+        if contains_ignore_case(alpha, "post") {
+            Pep440Class::Post
+        } else if alpha.is_empty() {
+            Pep440Class::Empty
+        } else if contains_ignore_case(alpha, "dev") {
+            Pep440Class::Dev
+        } else {
+            Pep440Class::Other
+        }
+    }
+
+    /// The class stored as `class as u8`; unknown values count as `Other`.
+    pub(crate) fn from_u8(class: u8) -> Pep440Class {
+        match class {
+            0 => Pep440Class::Dev,
+            2 => Pep440Class::Empty,
+            3 => Pep440Class::Post,
+            _ => Pep440Class::Other,
+        }
+    }
+}
+
+/// Case-fold a PEP 440 string the way conda does (it lower-cases the whole version).  This is
+///    Unicode lower-casing, which doesn't depend on the locale.
+pub(crate) fn fold(alpha: &str) -> String {
+    alpha.to_lowercase()
+}
+
+#[derive(Deserialize)]
+struct RawPEP440String {
+    alpha: String,
+}
+
 #[derive(Deserialize, Debug, Clone)]
+#[serde(from = "RawPEP440String")]
 pub struct PEP440String {
     alpha: String,
+    class: Pep440Class,
+    // lower case text; only compared when the class is Other, and empty otherwise
+    folded: String,
 }
 
 impl PEP440String {
     pub fn from(alpha: &str) -> PEP440String {
-        PEP440String{ alpha: alpha.to_string() }
+        let class = Pep440Class::of(alpha);
+        let folded = if class == Pep440Class::Other { fold(alpha) } else { String::new() };
+        PEP440String { alpha: alpha.to_string(), class, folded }
     }
 
     pub fn empty() -> PEP440String {
-        PEP440String {alpha: "".to_string()}
+        PEP440String::from("")
+    }
+
+    pub(crate) fn class(&self) -> Pep440Class {
+        self.class
+    }
+
+    /// The text to keep in a compact version: folded for `Other` strings, which compare by it,
+    ///    and the original for the rest, which only need to give the same class back.
+    pub(crate) fn stored_str(&self) -> &str {
+        if self.class == Pep440Class::Other { &self.folded } else { &self.alpha }
     }
 }
 
-fn compare_pep440_str<'a>(left: &'a str, right: &'a str) -> Option<Ordering> {
-    lazy_static! { static ref DEV_RE: Regex = Regex::new("(?i)dev").unwrap(); }
-    lazy_static! { static ref POST_RE: Regex = Regex::new("(?i)post").unwrap(); }
-
-    // top on the list is post.  It always wins.  Process it first.
-    match (POST_RE.is_match(left), POST_RE.is_match(right)) {
-        (true, true) => Some(Ordering::Equal),
-        (false, true) => Some(Ordering::Less),
-        (true, false) => Some(Ordering::Greater),
-        // Empty strings are when no string value is present for one or the other (release versions)
-        _ => match (left.is_empty(), right.is_empty()) {
-            (true, true) => Some(Ordering::Equal),
-            (false, true) => Some(Ordering::Less),
-            (true, false) => Some(Ordering::Greater),
-            // dev is inverse of post - it always loses
-            _ => match (DEV_RE.is_match(left), DEV_RE.is_match(right)) {
-                (true, true) => Some(Ordering::Equal),
-                (false, true) => Some(Ordering::Greater),
-                (true, false) => Some(Ordering::Less),
-                // this is the final fallback to lexicographic sorting, if neither
-                //   dev nor post are in effect.  Case insensitive comparison here.
-                (false, false) => UniCase::new(left).partial_cmp(&UniCase::new(right)),
-            }
-        }
+impl From<RawPEP440String> for PEP440String {
+    fn from(raw: RawPEP440String) -> PEP440String {
+        PEP440String::from(&raw.alpha)
+    }
+}
+
+/// Order two PEP 440 strings by their classes and, for `Other`, their already folded text.
+pub(crate) fn compare_pep440_classified(left: (Pep440Class, &str), right: (Pep440Class, &str)) -> Ordering {
+    match left.0.cmp(&right.0) {
+        Ordering::Equal if left.0 == Pep440Class::Other => left.1.cmp(right.1),
+        ord => ord,
+    }
+}
+
+/// Append bytes that sort the same way `compare_pep440_classified` does: dev, then everything
+///    else by its folded text, then the empty string, then post.
+pub(crate) fn write_pep440_sort_key(key: &mut Vec<u8>, class: Pep440Class, folded: &str) {
+    key.push(class as u8);
+    if class == Pep440Class::Other {
+        write_sort_key_str(key, folded);
     }
 }
 
 impl PartialOrd for PEP440String {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        compare_pep440_str(&self.alpha, &other.alpha)
+        Some(compare_pep440_classified((self.class, &self.folded), (other.class, &other.folded)))
     }
 }
 
 impl PartialEq for PEP440String {
     fn eq(&self, other: &Self) -> bool {
-        self.partial_cmp(&other).unwrap() == Ordering::Equal
+        self.class == other.class && self.folded == other.folded
     }
 }
 
@@ -93,4 +157,17 @@ mod tests {
     fn compare_empty_greater_alpha() {
         assert_eq!(PEP440String::from("a") < PEP440String::from(""), true);
     }
+
+    #[test]
+    fn compare_ignores_case() {
+        assert_eq!(PEP440String::from("RC"), PEP440String::from("rc"));
+        assert!(PEP440String::from("Beta") < PEP440String::from("rc"));
+        assert!(PEP440String::from("DEV") < PEP440String::from("a"));
+    }
+
+    #[test]
+    fn deserialize_classifies() {
+        let s: PEP440String = serde_json::from_str(r#"{"alpha": "Post"}"#).unwrap();
+        assert!(s > PEP440String::from(""));
+    }
 }
\ No newline at end of file
@@ -19,6 +19,11 @@ pub enum VersionParsingError {
     DuplicatedEpochCharacter,
     DuplicatedLocalSeparatorCharacter,
     UnknownParseError,
+
+    // A spec string or tree that can't be turned into a matcher, e.g. unbalanced parentheses
+    InvalidSpec(String),
+    // An operator sign or name CompOp doesn't know
+    UnknownOperator(String),
 }
 
 impl de::Error for VersionParsingError {
@@ -29,21 +34,19 @@ impl de::Error for VersionParsingError {
 
 impl Display for VersionParsingError {
     fn fmt(&self, formatter: &mut Formatter) -> Result {
-        formatter.write_str(Error::description(self))
-    }
-}
-
-// This is important for other errors to wrap this one.
-impl Error for VersionParsingError {
-    fn description(&self) -> &str {
-        match *self {
-            VersionParsingError::Message(ref msg) => msg,
-            VersionParsingError::DisallowedCharacter => "Disallowed character in string",
-            VersionParsingError::DuplicatedEpochCharacter => "Duplicate epoch character (!)",
+        match self {
+            VersionParsingError::Message(msg) => formatter.write_str(msg),
+            VersionParsingError::DisallowedCharacter => formatter.write_str("Disallowed character in string"),
+            VersionParsingError::DuplicatedEpochCharacter => formatter.write_str("Duplicate epoch character (!)"),
             VersionParsingError::DuplicatedLocalSeparatorCharacter => {
-                "duplicated local version separator (+)"
+                formatter.write_str("duplicated local version separator (+)")
             }
-            VersionParsingError::UnknownParseError => "Unknown parse error",
+            VersionParsingError::UnknownParseError => formatter.write_str("Unknown parse error"),
+            VersionParsingError::InvalidSpec(msg) => write!(formatter, "invalid version spec: {}", msg),
+            VersionParsingError::UnknownOperator(op) => write!(formatter, "unknown comparison operator '{}'", op),
         }
     }
 }
+
+// This is important for other errors to wrap this one.
+impl Error for VersionParsingError {}
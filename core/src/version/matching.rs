@@ -1,27 +1,28 @@
+use std::convert::TryFrom;
+use std::sync::Arc;
+
 use crate::version::errors::VersionParsingError;
-use crate::{CompOp, Version};
+use crate::version::spec_trees::{tokenize, Spec};
+use crate::{CompOp, Version, VersionSpecOrConstraintTree};
+#[cfg(feature = "regex")]
 use regex::Regex;
-use std::collections::HashSet;
 
 pub(crate) fn create_match_enum_from_operator_str(
     input: &str,
 ) -> Result<(MatchEnum, bool), VersionParsingError> {
-    lazy_static! {
-        static ref VERSION_RELATION_RE: Regex = Regex::new(r#"^([<>=!~]=?)(\S+)$"#).unwrap();
-    }
-
-    let (mut operator_str, mut v_str) = match VERSION_RELATION_RE.captures(input) {
-        None => {
-            return Err(VersionParsingError::Message(format!(
-                "invalid operator in string {}",
-                input
-            )))
-        }
-        Some(caps) => (
-            caps.get(1).map_or("", |m| m.as_str()),
-            caps.get(2).map_or("", |m| m.as_str()),
-        ),
+    // One of <>=!~, optionally followed by "=", then a version with no whitespace in it
+    let operator_len = match input.as_bytes() {
+        [b'<' | b'>' | b'=' | b'!' | b'~', b'=', _, ..] => 2,
+        [b'<' | b'>' | b'=' | b'!' | b'~', _, ..] => 1,
+        _ => 0,
     };
+    let (mut operator_str, mut v_str) = input.split_at(operator_len);
+    if operator_len == 0 || v_str.contains(char::is_whitespace) {
+        return Err(VersionParsingError::Message(format!(
+            "invalid operator in string {}",
+            input
+        )));
+    }
 
     if v_str.ends_with(".*") {
         if operator_str == "!=" {
@@ -34,9 +35,18 @@ pub(crate) fn create_match_enum_from_operator_str(
         }
         v_str = &v_str[..v_str.len() - 2];
     }
+    let operator = match CompOp::from_sign(operator_str) {
+        Ok(op) => op,
+        Err(_) => {
+            return Err(VersionParsingError::Message(format!(
+                "invalid operator in string {}",
+                input
+            )))
+        }
+    };
     let matcher = MatchOperator {
-        operator: CompOp::from_sign(operator_str).unwrap(),
-        version: v_str.into(),
+        operator,
+        version: v_str.parse()?,
     };
     let _is_exact = operator_str == "==";
     Ok((matcher.into(), _is_exact))
@@ -50,10 +60,12 @@ pub trait MatchFn {
 #[enum_dispatch(MatchFn)]
 #[derive(Clone)]
 pub enum MatchEnum {
+    #[cfg(feature = "regex")]
     MatchRegex(MatchRegex),
     MatchOperator(MatchOperator),
     MatchAlways,
     MatchExact(MatchExact),
+    MatchTree(MatchTree),
     MatchNever,
 }
 
@@ -64,27 +76,28 @@ impl Default for MatchEnum {
 }
 
 pub fn get_matcher(input: &str) -> Result<(MatchEnum, bool), VersionParsingError> {
-    lazy_static! {
-        static ref REGEX_SPLIT_RE: Regex = Regex::new(r#".*[()|,^$]"#).unwrap();
-    }
-    lazy_static! {
-        static ref OPERATOR_START: HashSet<&'static str> =
-            ["=", "<", ">", "!", "~"].iter().cloned().collect();
+    let (first, last) = match (input.as_bytes().first(), input.as_bytes().last()) {
+        (Some(first), Some(last)) => (*first, *last),
+        _ => return Err(VersionParsingError::Message("empty version spec".to_string())),
+    };
+    // a compound spec is split into its members the way VersionSpecOrConstraintTree does it, so
+    // that a regex among them stays whole: 1.6.*|^0.*$|1.7.1
+    if input.bytes().any(|b| b"()|,".contains(&b)) && tokenize(input)?.len() > 1 {
+        let tree = VersionSpecOrConstraintTree::try_from(input)?;
+        return Ok((MatchTree { tree: Arc::new(tree) }.into(), false));
     }
-    let _is_exact = false;
     let matcher: MatchEnum;
     let mut _is_exact = false;
-    if input.starts_with("^") || input.ends_with("$") {
-        if !input.starts_with("^") || !input.ends_with("$") {
+    if first == b'^' || last == b'$' {
+        if first != b'^' || last != b'$' {
             return Err(VersionParsingError::Message(format!(
                 "regex specs must start with '^' and end with '$' - spec '{}' is incorrect",
                 input
             )));
         }
-        let re = Regex::new(input).unwrap();
-        matcher = MatchRegex { expression: re }.into();
+        matcher = regex_matcher(input, input)?;
         _is_exact = false;
-    } else if OPERATOR_START.contains(&input[..1]) {
+    } else if matches!(first, b'=' | b'<' | b'>' | b'!' | b'~') {
         let res = create_match_enum_from_operator_str(input);
         match res {
             Ok((_m, _e)) => {
@@ -101,20 +114,19 @@ pub fn get_matcher(input: &str) -> Result<(MatchEnum, bool), VersionParsingError
             .replace(".", r"\.")
             .replace("+", r"\+")
             .replace("*", r".*");
-        let rx: Regex = Regex::new(&format!(r"^(?:{})$", rx)).unwrap();
-        matcher = MatchRegex { expression: rx }.into();
+        matcher = regex_matcher(&format!(r"^(?:{})$", rx), input)?;
         _is_exact = false;
     } else if input.ends_with("*") {
         matcher = MatchOperator {
             operator: CompOp::StartsWith,
-            version: input.trim_end_matches(|c| c == '*' || c == '.').into(),
+            version: input.trim_end_matches(['*', '.']).parse()?,
         }
         .into();
         _is_exact = false;
     } else if !input.contains("@") {
         matcher = MatchOperator {
             operator: CompOp::Eq,
-            version: input.into(),
+            version: input.parse()?,
         }
         .into();
         _is_exact = true;
@@ -128,13 +140,36 @@ pub fn get_matcher(input: &str) -> Result<(MatchEnum, bool), VersionParsingError
     return Ok((matcher, _is_exact));
 }
 
+// Regex specs and globs with a star in the middle are the only kinds that need the regex engine
+#[cfg(feature = "regex")]
+fn regex_matcher(pattern: &str, spec: &str) -> Result<MatchEnum, VersionParsingError> {
+    match Regex::new(pattern) {
+        Ok(re) => Ok(MatchRegex { expression: re }.into()),
+        Err(e) => Err(VersionParsingError::Message(format!("invalid regex in spec '{}': {}", spec, e))),
+    }
+}
+
+#[cfg(not(feature = "regex"))]
+fn regex_matcher(_pattern: &str, spec: &str) -> Result<MatchEnum, VersionParsingError> {
+    Err(VersionParsingError::Message(format!(
+        "spec '{}' needs the regex engine, which this build of libronda leaves out (feature \"regex\")", spec)))
+}
+
+#[cfg(feature = "regex")]
 #[derive(Clone)]
 pub struct MatchRegex {
     pub expression: Regex,
 }
+#[cfg(feature = "regex")]
 impl MatchFn for MatchRegex {
-    fn test(&self, _other: &Version) -> bool {
-        panic!("Not implemented")
+    // conda matches the regex against the version string, which it keeps in lower case
+    fn test(&self, other: &Version) -> bool {
+        let version = other.as_str();
+        if version.bytes().any(|b| b.is_ascii_uppercase()) {
+            self.expression.is_match(&version.to_ascii_lowercase())
+        } else {
+            self.expression.is_match(version)
+        }
     }
 }
 
@@ -172,13 +207,25 @@ pub struct MatchExact {
 }
 impl MatchFn for MatchExact {
     fn test(&self, other: &Version) -> bool {
-        other.version == self.spec
+        other.as_str() == self.spec
+    }
+}
+
+/// A compound spec where a single one was expected, as in `VersionSpec::try_from("1.5.*|>1.7")`
+#[derive(Clone)]
+pub struct MatchTree {
+    pub tree: Arc<VersionSpecOrConstraintTree>,
+}
+impl MatchFn for MatchTree {
+    fn test(&self, other: &Version) -> bool {
+        self.tree.test_match_version(other)
     }
 }
 
 #[cfg_attr(tarpaulin, skip)]
 #[cfg(test)]
 mod tests {
+    use super::{get_matcher, MatchEnum};
     use crate::version::spec_trees::{Spec, VersionSpec};
     use crate::VersionSpecOrConstraintTree;
     use rstest::rstest;
@@ -255,6 +302,8 @@ mod tests {
         // assert_eq!(&v1 as *const _, &v2 as *const _);
     }
 
+    // a glob with a star before the last place is matched as a regex
+    #[cfg(feature = "regex")]
     #[test]
     fn test_version_spec_4() {
         let v1 = VersionSpec::try_from("1.7.1*,1.8.1*").unwrap();
@@ -296,9 +345,25 @@ mod tests {
             case::double_eq_no_last_place("==1.7", false),
             case::double_eq_diff_last_place("==1.7.2", false),
             case::double_eq_implicit_extended_places("==1.7.1.0", true),
-            case::star_compound("1.7.*|1.8.*", true),
             case::range(">1.7,<1.8", true),
             case::range_out_of_bounds(">1.7.1,<1.8", false),
+            case::match_all("*", true)
+        )]
+        fn test_match(vspec: &str, res: bool) {
+            let m = VersionSpec::try_from(vspec).unwrap();
+            //assert VersionSpec(m) is m
+            //assert str(m) == vspec
+            //assert repr(m) == "VersionSpec('%s')" % vspec
+            assert_eq!(m.test_match("1.7.1"), res);
+        }
+
+        // a VersionSpec with a star before its end is matched as one glob, so these all need the
+        //    regex engine
+        #[cfg(feature = "regex")]
+        #[rstest(
+            vspec,
+            res,
+            case::star_compound("1.7.*|1.8.*", true),
             case::regex("^1.7.1$", true),
             case::regex_escape_periods(r"^1\.7\.1$", true),
             case::regex_digit_range_end(r"^1\.7\.[0-9]+$", true),
@@ -312,17 +377,12 @@ mod tests {
             case::combine_star_or_regex_or_exact_match_exact("1.6.*|^0.*$|1.7.1", true),
             case::combine_regex_or_exact("^0.*$|1.7.1", true),
             case::combine_star_or_regex_or_exact_match_regex(r"1.6.*|^.*\.7\.1$|0.7.1", true),
-            case::match_all("*", true),
             case::star_middle("1.*.1", true),
             case::star_or_range("1.5.*|>1.7,<1.8", true),
             case::star_or_range_mismatch("1.5.*|>1.7,<1.7.1", false)
         )]
-        fn test_match(vspec: &str, res: bool) {
-            let m = VersionSpec::try_from(vspec).unwrap();
-            //assert VersionSpec(m) is m
-            //assert str(m) == vspec
-            //assert repr(m) == "VersionSpec('%s')" % vspec
-            assert_eq!(m.test_match("1.7.1"), res);
+        fn test_match_regex(vspec: &str, res: bool) {
+            assert_eq!(VersionSpec::try_from(vspec).unwrap().test_match("1.7.1"), res);
         }
     }
 
@@ -343,9 +403,9 @@ mod tests {
     )]
     fn test_local_identifier(vspec: &str) {
         //"""The separator for the local identifier should be either `.` or `+`"""
-        // a valid versionstr should match itself
         let m: VersionSpecOrConstraintTree = VersionSpec::try_from(vspec).unwrap().into();
-        assert!(m.test_match(vspec.into()))
+        // a valid versionstr should match itself; the spaces around `+` only belong in the spec
+        assert!(m.test_match(&vspec.replace(' ', "")))
     }
 
     #[test]
@@ -381,6 +441,63 @@ mod tests {
         };
     }
 
+    #[rstest(
+        spec,
+        kind,
+        exact,
+        case::exact("1.7.1", "operator", true),
+        case::double_eq("==1.7.1", "operator", true),
+        case::geq(">=1.5", "operator", false),
+        case::lt("<2", "operator", false),
+        case::single_eq_star("=1.7.*", "operator", false),
+        case::neq_star("!=1.7.*", "operator", false),
+        case::trailing_star("1.7.*", "operator", false),
+        case::match_all("*", "always", false),
+        case::with_build("1.7.1@abc", "exact", true),
+        case::compound("1.5.*|>1.7,<1.8", "tree", false)
+    )]
+    fn test_get_matcher_dispatch(spec: &str, kind: &str, exact: bool) {
+        let (matcher, is_exact) = get_matcher(spec).unwrap();
+        let found = match matcher {
+            MatchEnum::MatchOperator(_) => "operator",
+            #[cfg(feature = "regex")]
+            MatchEnum::MatchRegex(_) => "regex",
+            MatchEnum::MatchAlways(_) => "always",
+            MatchEnum::MatchExact(_) => "exact",
+            MatchEnum::MatchTree(_) => "tree",
+            MatchEnum::MatchNever(_) => "never",
+        };
+        assert_eq!((found, is_exact), (kind, exact));
+    }
+
+    #[cfg(feature = "regex")]
+    #[rstest(spec, case::middle_star("1.*.1"), case::regex(r"^1\.7\.[0-9]+$"))]
+    fn test_get_matcher_dispatch_regex(spec: &str) {
+        let (matcher, is_exact) = get_matcher(spec).unwrap();
+        assert!(matches!(matcher, MatchEnum::MatchRegex(_)) && !is_exact);
+    }
+
+    #[rstest(
+        spec,
+        case::empty(""),
+        case::bare_bang("!1.2"),
+        case::bare_tilde("~1.2"),
+        case::operator_only(">"),
+        case::whitespace(">= 1.2"),
+        case::unterminated_regex("^1.2"),
+        case::unstarted_regex("1.2$"),
+        case::invalid_regex("^1.(2$")
+    )]
+    fn test_get_matcher_errors(spec: &str) {
+        assert!(get_matcher(spec).is_err());
+    }
+
+    #[cfg(not(feature = "regex"))]
+    #[rstest(spec, case::regex("^1.7.1$"), case::middle_star("1.*.1"))]
+    fn test_regex_specs_need_feature(spec: &str) {
+        assert!(get_matcher(spec).is_err());
+    }
+
     #[test]
     fn test_pep_440_arbitrary_equality_operator() {
         // We're going to leave the not implemented for now.
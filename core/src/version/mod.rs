@@ -5,15 +5,20 @@
 pub mod test_tools;
 
 pub mod comp_op;
+mod compact;
+mod conda_compat;
 pub mod custom_parts;
 pub mod errors;
 pub mod matching;
 pub mod parsers;
+mod spec_arena;
+pub mod spec_program;
 pub mod spec_trees;
 pub mod version;
 pub mod version_part;
 
 pub use self::comp_op::CompOp;
 pub use self::parsers::conda::conda_parser;
-pub use self::version::Version;
+pub use self::parsers::pep440::pep440_parser;
+pub use self::version::{Version, VersionDelta, VersionMode};
 pub use self::version_part::VersionPart;
@@ -1,8 +1,7 @@
-use regex::Regex;
-
 use crate::version::VersionPart;
 use crate::version::custom_parts::pep440::PEP440String;
 use crate::version::errors::VersionParsingError;
+use crate::version::parsers::tokenizer::{find_single, tokenize, Token};
 
 /// Split the given version string, in it's version parts.
 pub fn conda_parser(
@@ -10,57 +9,38 @@ pub fn conda_parser(
 ) -> Result<Vec<VersionPart>, VersionParsingError> {
     // version len may be a bit wasteful of memory.  Let's start there and tune as necessary.
     let mut parts = Vec::with_capacity(version.len()/2);
-    lazy_static! { static ref LETTER_NUMBER_RE: Regex = Regex::new(r"(\d+)|(\D+)").unwrap(); }
 
     // Split at epoch
-    let epoch_split: Vec<&str> = version.split("!").collect();
-    let post_epoch_split: &str = match epoch_split.len() {
-        2 => {
-            parts.push(VersionPart::Epoch(epoch_split[0].parse().unwrap()));
-            epoch_split[1]
-        },
-        1 => {
-            epoch_split[0]
+    let post_epoch_split: &str = match find_single(version, b'!') {
+        Ok(Some(i)) => {
+            match version[..i].parse::<i16>() {
+                Ok(epoch) if epoch >= 0 => parts.push(VersionPart::Epoch(epoch)),
+                _ => return Err(VersionParsingError::Message(
+                    format!("epoch must be a non-negative integer, got '{}'", &version[..i])))
+            }
+            &version[i + 1..]
         },
-        _ => return Err(VersionParsingError::DuplicatedEpochCharacter)
+        Ok(None) => version,
+        Err(()) => return Err(VersionParsingError::DuplicatedEpochCharacter)
     };
 
     // Get any local version string
-    let local_version_split: Vec<&str> = post_epoch_split.split("+").collect();
-    let local: &str = match local_version_split.len() {
-        1 => "",
-        2 => local_version_split[1],
-        _ => return Err(VersionParsingError::DuplicatedLocalSeparatorCharacter)
+    let (public, local): (&str, &str) = match find_single(post_epoch_split, b'+') {
+        Ok(Some(i)) => (&post_epoch_split[..i], &post_epoch_split[i + 1..]),
+        Ok(None) => (post_epoch_split, ""),
+        Err(()) => return Err(VersionParsingError::DuplicatedLocalSeparatorCharacter)
     };
 
-    // Split at periods
-    let version_split: Vec<&str> = local_version_split[0].split(|c| c == '_' ||
-                                                                         c == '.' ||
-                                                                         c == '-')
-        .collect();
-
-    // Loop over the parts, and parse them
-    for part in version_split {
-        // Skip empty parts
-        if part.is_empty() {
-            continue;
-        }
-
-        // sub-split to separate numbers and letters that are joined together
-        for m in LETTER_NUMBER_RE.find_iter(part) {
-            let substr: &str = &part[m.start()..m.end()];
-            match substr.parse::<i32>() {
-                Ok(number) => {
-                    // Push the number part to the vector, and set the has number flag
-                    parts.push(VersionPart::Integer(number));
-                }
-                Err(_) => {
-                    // Push the text part to the vector
-                    parts.push(VersionPart::PEP440String(PEP440String::from(substr)));
-                }
-            }
-        }
-    }
+    // Split at periods, dashes and underscores, and separate numbers and letters that are
+    //     joined together
+    tokenize(public, |token| match token {
+        Token::Number(substr) => match substr.parse::<i32>() {
+            Ok(number) => parts.push(VersionPart::Integer(number)),
+            // too big for an integer - keep the text
+            Err(_) => parts.push(VersionPart::PEP440String(PEP440String::from(substr))),
+        },
+        Token::Text(substr) => parts.push(VersionPart::PEP440String(PEP440String::from(substr))),
+    });
 
     if !local.is_empty() {
         parts.push(VersionPart::LexicographicString(local.to_string()));
@@ -84,4 +64,9 @@ mod tests {
         let parts = conda_parser("0.4").unwrap();
         assert_eq!(parts.len(), 2);
     }
+
+    #[test]
+    fn test_non_integer_epoch_is_error() {
+        assert!(conda_parser("x!1.0").is_err());        assert!(conda_parser("-1!1.0").is_err());
+    }
 }
\ No newline at end of file
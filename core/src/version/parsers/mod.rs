@@ -1,3 +1,5 @@
 pub mod conda;
+pub mod pep440;
+pub(crate) mod tokenizer;
 // not used.  Simpler scheme.
 // pub mod default;
\ No newline at end of file
@@ -0,0 +1,226 @@
+use crate::version::VersionPart;
+use crate::version::custom_parts::pep440::PEP440String;
+use crate::version::errors::VersionParsingError;
+
+// A position in the version string.  Each `eat_*` either consumes what it looks for or leaves the
+//    position where it was, so the grammar below reads like the pattern in PEP 440, appendix B.
+struct Cursor<'a> {
+    s: &'a str,
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn eat(&mut self, byte: u8) -> bool {
+        let found = self.s.as_bytes().get(self.pos) == Some(&byte);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    // [-_.]?
+    fn eat_separator(&mut self) -> bool {
+        let found = matches!(self.s.as_bytes().get(self.pos), Some(b'-' | b'_' | b'.'));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat_digits(&mut self) -> Option<&'a str> {
+        let len = self.s.as_bytes()[self.pos..].iter().take_while(|b| b.is_ascii_digit()).count();
+        self.eat_len(len)
+    }
+
+    fn eat_alphanumeric(&mut self) -> Option<&'a str> {
+        let len = self.s.as_bytes()[self.pos..].iter().take_while(|b| b.is_ascii_alphanumeric()).count();
+        self.eat_len(len)
+    }
+
+    fn eat_len(&mut self, len: usize) -> Option<&'a str> {
+        if len == 0 {
+            return None;
+        }
+        self.pos += len;
+        Some(&self.s[self.pos - len..self.pos])
+    }
+
+    // The first of `labels` the string continues with, ignoring case; longer labels must come
+    //    before their prefixes
+    fn eat_label(&mut self, labels: &[&'static str]) -> Option<&'static str> {
+        let rest = &self.s.as_bytes()[self.pos..];
+        let label = labels.iter().find(|l| rest.get(..l.len()).is_some_and(|p| p.eq_ignore_ascii_case(l.as_bytes())))?;
+        self.pos += label.len();
+        Some(label)
+    }
+
+    // [-_.]?<label>[-_.]?<digits>?, giving the label and the number if there is one
+    fn eat_segment(&mut self, labels: &[&'static str]) -> Option<(&'static str, Option<&'a str>)> {
+        let start = self.pos;
+        self.eat_separator();
+        match self.eat_label(labels) {
+            Some(label) => {
+                self.eat_separator();
+                Some((label, self.eat_digits()))
+            },
+            None => {
+                self.pos = start;
+                None
+            }
+        }
+    }
+}
+
+/// Split a PEP 440 version string (as used by PyPI) into version parts.
+///
+/// Alternate spellings are normalized the way PEP 440 describes (`alpha` -> `a`, `c`/`pre` -> `rc`,
+/// `rev`/`r`/`-N` -> `post`), so the resulting parts compare the same way the equivalent
+/// conda version does.  Strings that aren't valid PEP 440 versions are an error.
+pub fn pep440_parser(
+    version: &str,
+) -> Result<Vec<VersionPart>, VersionParsingError> {
+    let invalid = || VersionParsingError::Message(format!("'{}' is not a valid PEP 440 version", version));
+    let number = |s: &str| s.parse::<i32>().map_err(|_| VersionParsingError::Message(
+        format!("version number '{}' is too large", s)));
+
+    let mut c = Cursor { s: version.trim(), pos: 0 };
+    if !c.eat(b'v') {
+        c.eat(b'V');
+    }
+    let mut parts = Vec::with_capacity(8);
+    let start = c.pos;
+    match c.eat_digits() {
+        Some(epoch) if c.eat(b'!') => match epoch.parse() {
+            Ok(epoch) => parts.push(VersionPart::Epoch(epoch)),
+            Err(_) => return Err(VersionParsingError::Message(format!("epoch '{}' is too large", epoch)))
+        },
+        _ => c.pos = start,
+    }
+
+    parts.push(VersionPart::Integer(number(c.eat_digits().ok_or_else(invalid)?)?));
+    loop {
+        let start = c.pos;
+        match c.eat(b'.').then(|| c.eat_digits()).flatten() {
+            Some(n) => parts.push(VersionPart::Integer(number(n)?)),
+            None => {
+                c.pos = start;
+                break;
+            }
+        }
+    }
+
+    if let Some((label, n)) = c.eat_segment(&["alpha", "beta", "preview", "pre", "rc", "a", "b", "c"]) {
+        let label = match label {
+            "alpha" | "a" => "a",
+            "beta" | "b" => "b",
+            _ => "rc",
+        };
+        parts.push(VersionPart::PEP440String(PEP440String::from(label)));
+        parts.push(VersionPart::Integer(n.map_or(Ok(0), number)?));
+    }
+    // a bare -N is a post release too
+    let start = c.pos;
+    let post = match c.eat(b'-').then(|| c.eat_digits()).flatten() {
+        Some(n) => Some(Some(n)),
+        None => {
+            c.pos = start;
+            c.eat_segment(&["post", "rev", "r"]).map(|(_, n)| n)
+        }
+    };
+    if let Some(n) = post {
+        parts.push(VersionPart::PEP440String(PEP440String::from("post")));
+        parts.push(VersionPart::Integer(n.map_or(Ok(0), number)?));
+    }
+    if let Some((_, n)) = c.eat_segment(&["dev"]) {
+        parts.push(VersionPart::PEP440String(PEP440String::from("dev")));
+        parts.push(VersionPart::Integer(n.map_or(Ok(0), number)?));
+    }
+    if c.eat(b'+') {
+        let start = c.pos;
+        c.eat_alphanumeric().ok_or_else(invalid)?;
+        loop {
+            let end = c.pos;
+            if !(c.eat_separator() && c.eat_alphanumeric().is_some()) {
+                c.pos = end;
+                break;
+            }
+        }
+        let local = c.s[start..c.pos].to_lowercase().replace(['-', '_'], ".");
+        parts.push(VersionPart::LexicographicString(local));
+    }
+    if c.pos != c.s.len() {
+        return Err(invalid());
+    }
+
+    Ok(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::pep440_parser;
+    use crate::Version;
+
+    fn pep440(v: &str) -> Version {
+        Version::parse(v, &pep440_parser).unwrap()
+    }
+
+    #[rstest(a, b,
+        case::alpha_spelling("1.0alpha1", "1.0a1"),
+        case::pre_spelling("1.0pre2", "1.0rc2"),
+        case::c_spelling("1.0c2", "1.0rc2"),
+        case::implicit_post("1.0-1", "1.0.post1"),
+        case::rev_spelling("1.0rev1", "1.0.post1"),
+        case::implicit_number("1.0.dev", "1.0.dev0"),
+        case::separators("1.0-RC_1", "1.0rc1"),
+        case::leading_v("v2.1", "2.1"),
+        case::local_separators("1.0+Ubuntu-1", "1.0+ubuntu.1"),
+        case::trailing_separator("1.0a.", "1.0a0"),
+        case::surrounding_whitespace(" 1.0.post2\n", "1.0.post2"),
+    )]
+    fn test_normalized_equal(a: &str, b: &str) {
+        assert_eq!(pep440(a), pep440(b));
+    }
+
+    #[test]
+    fn test_pep440_ordering() {
+        // the example ordering from PEP 440
+        let ordered = ["1.0.dev456", "1.0a1", "1.0a2.dev456", "1.0a12.dev456", "1.0a12",
+            "1.0b1.dev456", "1.0b2", "1.0b2.post345.dev456", "1.0b2.post345", "1.0rc1.dev456",
+            "1.0rc1", "1.0", "1.0.post456.dev34", "1.0.post456", "1.1.dev1"];
+        for pair in ordered.windows(2) {
+            assert!(pep440(pair[0]) < pep440(pair[1]), "{} < {}", pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn test_local_version_follows_public_version() {
+        // local labels are compared after all other parts, as conda does, so they only
+        //    order against versions with the same public part
+        assert!(pep440("1.0") < pep440("1.0+abc.5"));
+        assert!(pep440("1.0+abc.5") < pep440("1.0+abc.6"));
+        assert!(pep440("1.0+abc.5") < pep440("1.1"));
+    }
+
+    #[test]
+    fn test_local_segments_compare_as_numbers() {
+        assert!(pep440("1.0+abc.5") < pep440("1.0+abc.10"));
+        assert!(pep440("1.0+abc.5").sort_key() < pep440("1.0+abc.10").sort_key());
+        assert_eq!(pep440("1.0+abc.5"), pep440("1.0+abc-05"));
+        // numeric segments sort after alphanumeric ones, and more segments after fewer
+        assert!(pep440("1.0+abc") < pep440("1.0+5"));
+        assert!(pep440("1.0+abc") < pep440("1.0+abc.0"));
+    }
+
+    #[rstest(v,
+        case::empty(""),
+        case::conda_only("1.0_1_g1234"),
+        case::two_epochs("1!2!3.0"),
+        case::bad_local("1.0+"),
+        case::trailing_local_separator("1.0+abc."),
+        case::bare_separator("1.0-"),
+        case::trailing_text("1.0 beta"),
+    )]
+    fn test_invalid(v: &str) {
+        assert!(pep440_parser(v).is_err());
+    }
+}
@@ -0,0 +1,82 @@
+//! Splitting of version strings into number and text runs.
+//!
+//! Separators are found with `memchr`, which searches with SIMD instructions where the CPU has
+//! them and falls back to a word-at-a-time scalar search where it doesn't.  Digit runs are then
+//! classified byte by byte; pieces between separators are rarely more than a few bytes long.
+
+use memchr::{memchr, memchr3_iter};
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Token<'a> {
+    Number(&'a str),
+    Text(&'a str),
+}
+
+/// Position of `sep` in `input` if it occurs exactly once, `Err(())` if it occurs more than once.
+pub(crate) fn find_single(input: &str, sep: u8) -> Result<Option<usize>, ()> {
+    let bytes = input.as_bytes();
+    match memchr(sep, bytes) {
+        None => Ok(None),
+        Some(i) => match memchr(sep, &bytes[i + 1..]) {
+            None => Ok(Some(i)),
+            Some(_) => Err(()),
+        },
+    }
+}
+
+/// Call `f` for every run of ASCII digits and every run of other characters in `input`, skipping
+/// the `.`, `-` and `_` separators between them.
+pub(crate) fn tokenize<'a, F: FnMut(Token<'a>)>(input: &'a str, mut f: F) {
+    let mut start = 0;
+    for sep in memchr3_iter(b'.', b'-', b'_', input.as_bytes()) {
+        split_runs(&input[start..sep], &mut f);
+        start = sep + 1;
+    }
+    split_runs(&input[start..], &mut f);
+}
+
+fn split_runs<'a, F: FnMut(Token<'a>)>(piece: &'a str, f: &mut F) {
+    let bytes = piece.as_bytes();
+    let mut start = 0;
+    while start < bytes.len() {
+        let digits = bytes[start].is_ascii_digit();
+        // Separators and digits are ASCII, so every run boundary is also a char boundary
+        let end = bytes[start..].iter()
+            .position(|b| b.is_ascii_digit() != digits)
+            .map_or(bytes.len(), |n| start + n);
+        let run = &piece[start..end];
+        f(if digits { Token::Number(run) } else { Token::Text(run) });
+        start = end;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(input: &str) -> Vec<Token<'_>> {
+        let mut found = vec![];
+        tokenize(input, |t| found.push(t));
+        found
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(tokens("1.2.3"), vec![Token::Number("1"), Token::Number("2"), Token::Number("3")]);
+        assert_eq!(tokens("1.0rc1"), vec![Token::Number("1"), Token::Number("0"), Token::Text("rc"),
+                                          Token::Number("1")]);
+        assert_eq!(tokens("3.7.4_2-dev"), vec![Token::Number("3"), Token::Number("7"), Token::Number("4"),
+                                               Token::Number("2"), Token::Text("dev")]);
+        assert_eq!(tokens("..1__a--"), vec![Token::Number("1"), Token::Text("a")]);
+        assert_eq!(tokens("1.0ü2"), vec![Token::Number("1"), Token::Number("0"), Token::Text("ü"),
+                                         Token::Number("2")]);
+        assert!(tokens("").is_empty());
+    }
+
+    #[test]
+    fn test_find_single() {
+        assert_eq!(find_single("1!2.0", b'!'), Ok(Some(1)));
+        assert_eq!(find_single("2.0", b'!'), Ok(None));
+        assert_eq!(find_single("1!2!0", b'!'), Err(()));
+    }
+}
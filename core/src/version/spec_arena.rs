@@ -0,0 +1,115 @@
+//! Scratch storage for building spec trees.
+//!
+//! While treeify works through a spec string, groups get created, fused into their parents and
+//! re-wrapped many times.  Doing that with owned `ConstraintTree`s means moving and cloning whole
+//! subtrees at every step.  Instead, nodes live in one `Vec` and refer to each other by index:
+//! a group keeps the first and last of its members, and each member points at the next, so
+//! fusing two groups is a couple of index updates.  The owned tree is built once at the end.
+
+use std::convert::TryFrom;
+
+use crate::version::errors::VersionParsingError;
+use crate::version::spec_trees::{Combinator, ConstraintTree, VersionSpec, VersionSpecOrConstraintTree};
+
+pub(crate) type NodeId = usize;
+
+enum Node<'s> {
+    Spec(&'s str),
+    Group { combinator: Combinator, first: NodeId, last: NodeId },
+}
+
+struct Entry<'s> {
+    node: Node<'s>,
+    // next member of the group this node belongs to
+    next: Option<NodeId>,
+}
+
+pub(crate) struct SpecArena<'s> {
+    entries: Vec<Entry<'s>>,
+}
+
+impl<'s> SpecArena<'s> {
+    pub(crate) fn with_capacity(capacity: usize) -> SpecArena<'s> {
+        SpecArena { entries: Vec::with_capacity(capacity) }
+    }
+
+    fn push(&mut self, node: Node<'s>) -> NodeId {
+        self.entries.push(Entry { node, next: None });
+        self.entries.len() - 1
+    }
+
+    /// Add a single spec, e.g. ">=1.2".
+    pub(crate) fn spec(&mut self, spec: &'s str) -> NodeId {
+        self.push(Node::Spec(spec))
+    }
+
+    // (first, last) of the members `id` contributes to a `combinator` group: its own members if
+    //     it is a group with the same combinator, otherwise just itself
+    fn members(&self, id: NodeId, combinator: &Combinator) -> (NodeId, NodeId) {
+        match &self.entries[id].node {
+            Node::Group { combinator: c, first, last } if c == combinator => (*first, *last),
+            _ => (id, id),
+        }
+    }
+
+    /// Join `a` and `b` with `combinator`.  Groups that already use the same combinator are fused
+    /// rather than nested, so `(a|b)|(c|d)` becomes one group of four.
+    pub(crate) fn join(&mut self, combinator: Combinator, a: NodeId, b: NodeId) -> NodeId {
+        let (a_first, a_last) = self.members(a, &combinator);
+        let (b_first, b_last) = self.members(b, &combinator);
+        self.entries[a_last].next = Some(b_first);
+        // reuse `a` when it is already the right kind of group
+        if a_first != a {
+            if let Node::Group { last, .. } = &mut self.entries[a].node {
+                *last = b_last;
+            }
+            a
+        } else {
+            self.push(Node::Group { combinator, first: a_first, last: b_last })
+        }
+    }
+
+    /// Build the owned tree rooted at `id`.
+    pub(crate) fn build(&self, id: NodeId) -> Result<VersionSpecOrConstraintTree, VersionParsingError> {
+        match &self.entries[id].node {
+            Node::Spec(s) => match VersionSpec::try_from(*s) {
+                Ok(spec) => Ok(VersionSpecOrConstraintTree::VersionSpec(spec)),
+                Err(e) => Err(VersionParsingError::InvalidSpec(format!("'{}': {}", s, e))),
+            },
+            Node::Group { combinator, first, .. } => {
+                let mut parts = vec![];
+                let mut member = Some(*first);
+                while let Some(m) = member {
+                    parts.push(self.build(m)?);
+                    member = self.entries[m].next;
+                }
+                Ok(VersionSpecOrConstraintTree::ConstraintTree(ConstraintTree::new(combinator.clone(), parts)?))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SpecArena;
+    use crate::version::spec_trees::{untreeify, Combinator};
+
+    #[test]
+    fn test_join_fuses_same_combinator() {
+        let mut arena = SpecArena::with_capacity(8);
+        let (a, b, c, d) = (arena.spec("1"), arena.spec("2"), arena.spec("3"), arena.spec("4"));
+        let ab = arena.join(Combinator::Or, a, b);
+        let cd = arena.join(Combinator::Or, c, d);
+        let all = arena.join(Combinator::Or, ab, cd);
+        assert_eq!(untreeify(&arena.build(all).unwrap()).unwrap(), "1|2|3|4");
+    }
+
+    #[test]
+    fn test_join_nests_other_combinator() {
+        let mut arena = SpecArena::with_capacity(8);
+        let (a, b, c) = (arena.spec("1"), arena.spec("2"), arena.spec(">3"));
+        let ab = arena.join(Combinator::Or, a, b);
+        let abc = arena.join(Combinator::And, ab, c);
+        assert_eq!(untreeify(&arena.build(abc).unwrap()).unwrap(), "(1|2),>3");
+    }
+}
@@ -0,0 +1,165 @@
+//! Specs compiled to a flat program.
+//!
+//! Matching a `ConstraintTree` walks the tree, recursing and dispatching on every node for every
+//! candidate version.  A `CompiledSpec` flattens the tree once: the leaves' matchers go into one
+//! `Vec`, and the tree becomes a sequence of instructions that test a matcher or jump ahead.  The
+//! jumps keep the short-circuiting of `all`/`any` - as soon as one member of an "and" group
+//! fails, the rest of the group is skipped - so the only state while running is the last result.
+//!
+//! Each tested leaf still costs one version comparison, and for typical specs that comparison is
+//! most of the work: on the `spec_matching` benchmarks the program runs at about the same speed as
+//! the tree.  The gain is limited to the recursion and dispatch the flat form avoids.
+//!
+//! ```
+//! use std::convert::TryFrom;
+//! use ronda::{CompiledSpec, Spec, VersionSpecOrConstraintTree};
+//!
+//! let spec = VersionSpecOrConstraintTree::try_from(">=1.0,(<1.5|>=1.7,<2)").unwrap();
+//! let compiled = CompiledSpec::compile(&spec);
+//! assert!(compiled.test_match("1.7.1"));
+//! assert!(!compiled.test_match("1.6"));
+//! ```
+
+use crate::version::matching::{MatchEnum, MatchFn};
+use crate::version::spec_trees::{untreeify, Combinator, Spec, VersionSpecOrConstraintTree};
+use crate::version::Version;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    // result = matcher i tested against the version
+    Test(usize),
+    // skip to the given instruction if the result is false / true; it then is the group's result
+    JumpIfFalse(usize),
+    JumpIfTrue(usize),
+}
+
+#[derive(Clone)]
+pub struct CompiledSpec {
+    spec_str: String,
+    is_exact: bool,
+    matchers: Vec<MatchEnum>,
+    program: Vec<Op>,
+}
+
+impl CompiledSpec {
+    /// Flatten `spec` into a program.  The result matches exactly the versions `spec` matches.
+    pub fn compile(spec: &VersionSpecOrConstraintTree) -> CompiledSpec {
+        let mut compiled = CompiledSpec {
+            spec_str: untreeify(spec).unwrap_or_default(),
+            is_exact: spec.is_exact(),
+            matchers: vec![],
+            program: vec![],
+        };
+        compiled.emit(spec);
+        compiled
+    }
+
+    fn emit(&mut self, node: &VersionSpecOrConstraintTree) {
+        let tree = match node {
+            VersionSpecOrConstraintTree::VersionSpec(s) => {
+                self.matchers.push(s.matcher.clone());
+                self.program.push(Op::Test(self.matchers.len() - 1));
+                return
+            },
+            VersionSpecOrConstraintTree::ConstraintTree(t) => t,
+        };
+        let exit: fn(usize) -> Op = match tree.combinator() {
+            Combinator::And => Op::JumpIfFalse,
+            Combinator::Or => Op::JumpIfTrue,
+        };
+        let parts = tree.parts();
+        // every member but the last may end the group early; targets are filled in once the
+        //   end of the group is known
+        let mut jumps = vec![];
+        for part in &parts[..parts.len() - 1] {
+            self.emit(part);
+            jumps.push(self.program.len());
+            self.program.push(exit(0));
+        }
+        self.emit(&parts[parts.len() - 1]);
+        let end = self.program.len();
+        for j in jumps {
+            self.program[j] = exit(end);
+        }
+    }
+
+    fn run(&self, other: &Version) -> bool {
+        let mut result = false;
+        let mut pc = 0;
+        while let Some(op) = self.program.get(pc) {
+            pc += 1;
+            match *op {
+                Op::Test(i) => result = self.matchers[i].test(other),
+                Op::JumpIfFalse(target) if !result => pc = target,
+                Op::JumpIfTrue(target) if result => pc = target,
+                Op::JumpIfFalse(_) | Op::JumpIfTrue(_) => {},
+            }
+        }
+        result
+    }
+}
+
+impl From<&VersionSpecOrConstraintTree> for CompiledSpec {
+    fn from(spec: &VersionSpecOrConstraintTree) -> Self {
+        CompiledSpec::compile(spec)
+    }
+}
+
+impl Spec for CompiledSpec {
+    fn get_spec(&self) -> String {
+        self.spec_str.clone()
+    }
+    fn is_exact(&self) -> bool {
+        self.is_exact
+    }
+    fn test_match_version(&self, other: &Version) -> bool {
+        self.run(other)
+    }
+}
+
+#[cfg_attr(tarpaulin, skip)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[rstest(
+        spec,
+        case("1.7.1"),
+        case(">=1.5"),
+        case("1.7.*|1.8.*"),
+        case(">1.7,<1.8"),
+        case(">=1.0,(<1.5|>=1.7,<2)"),
+        case("((1.5|((1.6|1.7), 1.8), 1.9 |2.0))|2.1"),
+        case(">=2.7, !=3.0.*, !=3.1.*, !=3.2.*, !=3.3.*")
+    )]
+    fn test_compiled_matches_tree(spec: &str) {
+        let tree = VersionSpecOrConstraintTree::try_from(spec).unwrap();
+        let compiled = CompiledSpec::compile(&tree);
+        for v in ["1.0", "1.4", "1.5", "1.6", "1.7.1", "1.8", "1.9", "2.0", "2.1", "2.7.2", "3.3.4", "3.4"].iter() {
+            assert_eq!(compiled.test_match(v), tree.test_match(v), "{} against {}", spec, v);
+        }
+        assert_eq!(compiled.get_spec(), untreeify(&tree).unwrap());
+        assert_eq!(compiled.is_exact(), tree.is_exact());
+    }
+
+    #[test]
+    fn test_flattened_program() {
+        let tree = VersionSpecOrConstraintTree::try_from(">=1.0,(<1.5|>=1.7,<2)").unwrap();
+        let compiled = CompiledSpec::compile(&tree);
+        assert_eq!(compiled.program, vec![
+            Op::Test(0), Op::JumpIfFalse(7),
+            Op::Test(1), Op::JumpIfTrue(7),
+            Op::Test(2), Op::JumpIfFalse(7),
+            Op::Test(3)]);
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_short_circuit_skips_rest_of_group() {
+        // the second member's regex matcher panics if it is ever tested
+        let tree = VersionSpecOrConstraintTree::try_from("1.7.*|^1.*$").unwrap();
+        let compiled = CompiledSpec::compile(&tree);
+        assert!(compiled.test_match("1.7.1"));
+    }
+}
@@ -1,13 +1,13 @@
-use std::ops::Deref;
 use std::fmt;
-use regex::Regex;
-use std::borrow::Borrow;
+use std::hash::{Hash, Hasher};
+use std::borrow::{Borrow, Cow};
 
-use serde::export::TryFrom;
+use std::convert::TryFrom;
 
 use crate::version::matching::{MatchEnum, MatchFn, get_matcher};
 use crate::version::Version;
 use crate::version::errors::VersionParsingError;
+use crate::version::spec_arena::{NodeId, SpecArena};
 
 #[enum_dispatch]
 pub trait Spec {
@@ -19,64 +19,107 @@ pub trait Spec {
     // properties in Python (to be implemented by other things)
     fn get_spec(&self) -> String;
     fn is_exact(&self) -> bool;
-    // easier to use, because you can pass text
-    fn test_match(&self, other: &str) -> bool;
+    // easier to use, because you can pass text.  A version that can't be parsed matches nothing;
+    //    use try_test_match to tell that apart from a version the spec rejects
+    fn test_match(&self, other: &str) -> bool {
+        self.try_test_match(other).unwrap_or(false)
+    }
+    fn try_test_match(&self, other: &str) -> Result<bool, VersionParsingError> {
+        Ok(self.test_match_version(&other.parse()?))
+    }
     // faster, because you don't create Version objects
     fn test_match_version(&self, other: &Version) -> bool;
 }
 
 #[enum_dispatch(Spec)]
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub enum VersionSpecOrConstraintTree {
     VersionSpec(VersionSpec),
     ConstraintTree(ConstraintTree), // vec is a mix of &str or other vector(s) of str, possibly nested
 }
 
-#[derive(Clone)]
+// Two trees are equal when they have the same combinator and equal parts in the same order, so
+//    "1.2|1.3" and "1.3|1.2" are different trees even though they match the same versions
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct ConstraintTree {
-    pub combinator: Combinator,
-    pub parts: Vec<VersionSpecOrConstraintTree>,
+    combinator: Combinator,
+    // never empty, see `new`
+    parts: Vec<VersionSpecOrConstraintTree>,
 }
 
 // Not sure tw
 impl Spec for ConstraintTree{
     fn get_spec(&self) -> String {
-        untreeify(&VersionSpecOrConstraintTree::ConstraintTree(self.clone())).unwrap()
+        self.combine(false, false).unwrap_or_default()
     }
     fn is_exact(&self) -> bool {
         return false
     }
-    fn test_match(&self, other: &str) -> bool {
-        let v: Version = other.into();
-        self.test_match_version(&v)
-    }
     fn test_match_version(&self, other: &Version) -> bool {
         return match self.combinator {
             Combinator::And => self.parts.iter().all(|x| x.borrow().test_match_version(other)),
             Combinator::Or => self.parts.iter().any(|x| x.borrow().test_match_version(other)),
-            Combinator::None => false,
         }
     }
 }
 
-#[derive(PartialEq, Clone)]
+// There is deliberately no "no combinator" variant: a lone spec is a VersionSpec, never a tree,
+//    so every tree says how its members combine
+#[derive(PartialEq, Eq, Hash, Clone)]
 pub enum Combinator {
     Or,
     And,
-    None
 }
 
 impl ConstraintTree {
-    fn combine(&self, inand: bool, nested: bool) -> Result<String, String> {
+    /// A group of `parts`, combined with `combinator`.  A group needs at least one member: with
+    ///    none, it would have no spec string, and whether it matches would depend on the combinator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ronda::{Combinator, ConstraintTree, Spec, VersionSpecOrConstraintTree};
+    /// use std::convert::TryFrom;
+    ///
+    /// let parts = vec![VersionSpecOrConstraintTree::try_from("1.2").unwrap(),
+    ///                  VersionSpecOrConstraintTree::try_from("1.4").unwrap()];
+    /// let tree = ConstraintTree::new(Combinator::Or, parts).unwrap();
+    /// assert_eq!(tree.get_spec(), "1.2|1.4");
+    /// assert!(ConstraintTree::new(Combinator::And, vec![]).is_err());
+    /// ```
+    pub fn new(combinator: Combinator, parts: Vec<VersionSpecOrConstraintTree>) -> Result<ConstraintTree, VersionParsingError> {
+        if parts.is_empty() {
+            return Err(VersionParsingError::InvalidSpec("a group of specs needs at least one member".to_string()));
+        }
+        Ok(ConstraintTree { combinator, parts })
+    }
+
+    pub fn combinator(&self) -> &Combinator {
+        &self.combinator
+    }
+
+    /// The members of the group, of which there is at least one
+    pub fn parts(&self) -> &[VersionSpecOrConstraintTree] {
+        &self.parts
+    }
+
+    // Drop members equal to an earlier one; the first stays, so the group keeps a member
+    pub(crate) fn dedup(&mut self) {
+        let mut unique: Vec<VersionSpecOrConstraintTree> = Vec::with_capacity(self.parts.len());
+        for part in self.parts.drain(..) {
+            if !unique.contains(&part) {
+                unique.push(part);
+            }
+        }
+        self.parts = unique;
+    }
+
+    fn combine(&self, inand: bool, nested: bool) -> Result<String, VersionParsingError> {
         match self.parts.len() {
-            1 => {
-                if let VersionSpecOrConstraintTree::VersionSpec(s) = self.parts[0].borrow() {
-                    Ok(s.get_spec().to_string())
-                } else {
-                    Err("Can't combine (stringify) single-element ConstraintTree that isn't just a string".to_string())
-                }
+            1 => match &self.parts[0] {
+                VersionSpecOrConstraintTree::VersionSpec(s) => Ok(s.get_spec().to_string()),
+                VersionSpecOrConstraintTree::ConstraintTree(t) => t.combine(inand, nested),
             },
-            0 => Err("Can't combine (stringify) a zero-element ConstraintTree".to_string()),
             _ => {
                 let mut str_parts = vec![];
 
@@ -84,7 +127,7 @@ impl ConstraintTree {
                     str_parts.push(match item {
                         VersionSpecOrConstraintTree::VersionSpec(s) => s.get_spec().to_string(),
                         VersionSpecOrConstraintTree::ConstraintTree(cj) => {
-                            cj.combine(self.combinator == Combinator::And, true).unwrap()
+                            cj.combine(self.combinator == Combinator::And, true)?
                         }
                     });
                 }
@@ -105,12 +148,14 @@ impl ConstraintTree {
 impl TryFrom<&str> for VersionSpecOrConstraintTree {
     type Error = VersionParsingError;
     fn try_from (input: &str) -> Result<VersionSpecOrConstraintTree, Self::Error> {
-        lazy_static! { static ref REGEX_SPLIT_RE: Regex = Regex::new( r#".*[()|,^$]"# ).unwrap(); }
-        let split_input: Vec<&str> = REGEX_SPLIT_RE.split(input).collect();
-        if split_input.len() > 1 {
-            match ConstraintTree::try_from(split_input) {
-                Ok(v) => Ok(VersionSpecOrConstraintTree::ConstraintTree(v)),
-                Err(e) => Err(e)
+        let input = &*strip_spec_noise(input);
+        if input.bytes().any(|b| matches!(b, b'(' | b')' | b'|' | b',')) {
+            let mut tree = treeify(input)?;
+            // treeify wraps a lone spec (e.g. a regex containing "|") in a single-member group
+            if tree.parts.len() == 1 {
+                Ok(tree.parts.pop().unwrap())
+            } else {
+                Ok(VersionSpecOrConstraintTree::ConstraintTree(tree))
             }
         } else {
             match VersionSpec::try_from(input) {
@@ -121,28 +166,88 @@ impl TryFrom<&str> for VersionSpecOrConstraintTree {
     }
 }
 
-impl PartialEq for VersionSpecOrConstraintTree {
-    fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (VersionSpecOrConstraintTree::VersionSpec(a), VersionSpecOrConstraintTree::VersionSpec(b)) => a == b,
-            (VersionSpecOrConstraintTree::ConstraintTree(a), VersionSpecOrConstraintTree::ConstraintTree(b)) => a == b,
-            _ => false
+impl VersionSpecOrConstraintTree {
+    /// Combine two specs into one that only matches versions matched by both (the intersection).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ronda::{Spec, VersionSpecOrConstraintTree};
+    /// use std::convert::TryFrom;
+    ///
+    /// let a = VersionSpecOrConstraintTree::try_from(">=1.2").unwrap();
+    /// let b = VersionSpecOrConstraintTree::try_from("<2|3.*").unwrap();
+    /// let merged = a.merge(&b);
+    /// assert_eq!(merged.get_spec(), ">=1.2,(<2|3.*)");
+    /// assert!(merged.test_match("1.5"));
+    /// assert!(!merged.test_match("2.5"));
+    /// ```
+    pub fn merge(&self, other: &VersionSpecOrConstraintTree) -> VersionSpecOrConstraintTree {
+        let mut parts = vec![];
+        for item in [self, other].iter() {
+            match item {
+                VersionSpecOrConstraintTree::ConstraintTree(t) if t.combinator == Combinator::And => {
+                    parts.extend(t.parts.iter().cloned())
+                },
+                _ => parts.push((*item).clone())
+            }
         }
+        VersionSpecOrConstraintTree::ConstraintTree(ConstraintTree { combinator: Combinator::And, parts })
+    }
+
+    /// The spec string for the simplified form of this spec (see `simplify`), with groups
+    ///    parenthesized only where they have to be.  Parsing it gives back exactly the simplified
+    ///    tree, and normalizing that gives the same string again, so it can be used to compare or
+    ///    store specs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ronda::{simplify, VersionSpecOrConstraintTree};
+    /// use std::convert::TryFrom;
+    ///
+    /// let spec = VersionSpecOrConstraintTree::try_from("((>=1.2)),(<2|(3.*|3.*))").unwrap();
+    /// let normalized = spec.normalized_string();
+    /// assert_eq!(normalized, ">=1.2,(<2|3.*)");
+    /// assert_eq!(VersionSpecOrConstraintTree::try_from(normalized.as_str()).unwrap(), simplify(&spec));
+    /// ```
+    pub fn normalized_string(&self) -> String {
+        let mut out = String::new();
+        write_normalized(&simplify(self), false, &mut out);
+        out
     }
 }
 
-impl fmt::Debug for VersionSpecOrConstraintTree {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:#?}", self)
+// "," binds tighter than "|", so only an or-group inside an and-group needs parentheses
+fn write_normalized(spec: &VersionSpecOrConstraintTree, in_and: bool, out: &mut String) {
+    let tree = match spec {
+        VersionSpecOrConstraintTree::VersionSpec(s) => return out.push_str(&s.get_spec()),
+        VersionSpecOrConstraintTree::ConstraintTree(t) => t,
+    };
+    let (separator, parens) = match tree.combinator {
+        Combinator::And => (",", false),
+        Combinator::Or => ("|", in_and && tree.parts.len() > 1),
+    };
+    if parens {
+        out.push('(');
+    }
+    for (i, part) in tree.parts.iter().enumerate() {
+        if i > 0 {
+            out.push_str(separator);
+        }
+        write_normalized(part, tree.combinator == Combinator::And, out);
+    }
+    if parens {
+        out.push(')');
     }
 }
 
-impl PartialEq for ConstraintTree {
-    fn eq(&self, other: &Self) -> bool {
-        for (a, b) in self.parts.iter().zip(&other.parts) {
-            if a.deref() != b.deref() { return false }
+impl fmt::Debug for VersionSpecOrConstraintTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VersionSpecOrConstraintTree::VersionSpec(s) => write!(f, "{:?}", s.spec_str),
+            VersionSpecOrConstraintTree::ConstraintTree(t) => t.fmt(f),
         }
-        return true
     }
 }
 
@@ -150,16 +255,13 @@ impl TryFrom<Vec<&str>> for ConstraintTree
 {
     type Error = VersionParsingError;
     fn try_from(input: Vec<&str>) -> Result<Self, Self::Error> {
-        let combinator = match input[0].borrow() {
-            "," => Combinator::And,
-            "|" => Combinator::Or,
-            _ => return Err(VersionParsingError::Message(format!("Unknown first value in vec of str used as ConstraintTree")))
-        };
-        let tree = ConstraintTree {
-            combinator,
-            parts: input[1..].iter().map(|x| VersionSpecOrConstraintTree::try_from(x.clone()).unwrap()).collect()
+        let combinator = match input.first() {
+            Some(&",") => Combinator::And,
+            Some(&"|") => Combinator::Or,
+            _ => return Err(VersionParsingError::InvalidSpec("Unknown first value in vec of str used as ConstraintTree".to_string()))
         };
-        Ok(tree)
+        let parts = input[1..].iter().map(|x| VersionSpecOrConstraintTree::try_from(*x)).collect::<Result<_, _>>()?;
+        ConstraintTree::new(combinator, parts)
     }
 }
 
@@ -187,27 +289,28 @@ impl fmt::Debug for ConstraintTree {
 /// assert_eq!(v.unwrap(), "1.2.3".to_string());
 /// let v = untreeify(&ConstraintTree::try_from(vec![",", "1.2.3", ">4.5.6"]).unwrap().into());
 /// assert_eq!(v.unwrap(), "1.2.3,>4.5.6".to_string());
-/// let tree: VersionSpecOrConstraintTree = ConstraintTree {
-///                               combinator: Combinator::Or,
-///                               parts: vec![
+/// let tree: VersionSpecOrConstraintTree = ConstraintTree::new(
+///                               Combinator::Or,
+///                               vec![
 ///                                     VersionSpecOrConstraintTree::ConstraintTree(cj123_456),
-///                                     VersionSpecOrConstraintTree::VersionSpec(VersionSpec::try_into("<=7.8.9").unwrap())]}.into();
+///                                     VersionSpecOrConstraintTree::VersionSpec(VersionSpec::try_into("<=7.8.9").unwrap())]).unwrap().into();
 /// let v = untreeify(&tree);
 /// assert_eq!(v.unwrap(), "(1.2.3,4.5.6)|<=7.8.9".to_string());
 /// ```
-pub fn untreeify(spec: &VersionSpecOrConstraintTree) -> Result<String, String> {
+pub fn untreeify(spec: &VersionSpecOrConstraintTree) -> Result<String, VersionParsingError> {
     match spec {
         VersionSpecOrConstraintTree::ConstraintTree(s) => {s.combine(false, false)},
         VersionSpecOrConstraintTree::VersionSpec(s) => Ok(s.get_spec().to_string())
     }
 }
 
-impl From<&str> for Combinator {
-    fn from(input: &str) -> Combinator {
+impl TryFrom<&str> for Combinator {
+    type Error = VersionParsingError;
+    fn try_from(input: &str) -> Result<Combinator, Self::Error> {
         match input {
-            "," => Combinator::And,
-            "|" => Combinator::Or,
-            _ => Combinator::None
+            "," => Ok(Combinator::And),
+            "|" => Ok(Combinator::Or),
+            _ => Err(VersionParsingError::InvalidSpec(format!("'{}' is not a combinator", input)))
         }
     }
 }
@@ -217,93 +320,162 @@ impl fmt::Debug for Combinator {
         match self {
             Combinator::And => write!(f, "&"),
             Combinator::Or => write!(f, "|"),
-            Combinator::None => write!(f, ""),
         }
     }
 }
 
-fn _apply_ops(cstop: &str, output: &mut ConstraintTree, stack: &mut Vec<&str>) -> Result<(), String> {
+fn _apply_ops(cstop: &str, arena: &mut SpecArena, operands: &mut Vec<NodeId>, stack: &mut Vec<&str>)
+              -> Result<(), VersionParsingError> {
     // cstop: operators with lower precedence
     while stack.len() > 0 && ! cstop.contains(stack.last().unwrap()) {
         // Fuse expressions with the same operator; e.g.,
         //   ('|', ('|', a, b), ('|', c, d))becomes
         //   ('|', a, b, c d)
-        if output.parts.len() < 2 {
-            return Err("can't join single expression".to_string())
+        if operands.len() < 2 {
+            return Err(VersionParsingError::InvalidSpec("can't join single expression".to_string()))
         }
-        let c: Combinator = stack.pop().unwrap().into();
-        let mut condensed: Vec<VersionSpecOrConstraintTree> = vec![];
-
-        for _ in 0..2 {
-            match output.parts.pop().unwrap() {
-                VersionSpecOrConstraintTree::ConstraintTree(a) => {
-                    if a.combinator == c {
-                        condensed = a.clone().parts.into_iter().chain(condensed.into_iter()).collect();
-                    } else {
-                        condensed.insert(0,VersionSpecOrConstraintTree::ConstraintTree(a.clone()))
-                    }
-                },
-                VersionSpecOrConstraintTree::VersionSpec(a) => condensed.insert(0,
-                                                                           VersionSpecOrConstraintTree::VersionSpec(a.to_owned()))
-            }
+        let c = Combinator::try_from(stack.pop().unwrap())?;
+        let b = operands.pop().unwrap();
+        let a = operands.pop().unwrap();
+        operands.push(arena.join(c, a, b));
+    }
+    return Ok(())
+}
+
+// The end of the regex starting with the `^` at `start`: just past a `$` followed by the end or by
+//   something that ends a token
+fn regex_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut j = start + 1;
+    while j < bytes.len() {
+        match bytes[j] {
+            b'\\' => j += 1,
+            b'$' if bytes.get(j + 1).is_none_or(|&b| is_noise(b) || b")|,".contains(&b)) => return Some(j + 1),
+            _ => {}
         }
+        j += 1;
+    }
+    None
+}
 
-        let condensed_output = ConstraintTree {
-            combinator: c,
-            parts: condensed
-        };
+fn is_noise(b: u8) -> bool {
+    b.is_ascii_whitespace() || b == b'"' || b == b'\''
+}
 
-        if output.parts.len() > 0 {
-            output.parts.push(VersionSpecOrConstraintTree::ConstraintTree(condensed_output));
-        } else {
-            *output = condensed_output;
+/// Drop the whitespace and quotes that specs copied from recipes often carry, as in
+///   `">=2.7, !=3.0.*"` or `1.7.0.post123 + gabcdef9`.  Whitespace between two letters or digits
+///   is kept, so `1.7 1.8` isn't read as `1.71.8`, and regexes are left alone.
+fn strip_spec_noise(spec_str: &str) -> Cow<'_, str> {
+    if !spec_str.bytes().any(is_noise) {
+        return Cow::Borrowed(spec_str);
+    }
+    let bytes = spec_str.as_bytes();
+    let mut stripped = String::with_capacity(spec_str.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        if is_noise(bytes[i]) {
+            while i < bytes.len() && is_noise(bytes[i]) {
+                i += 1;
+            }
+            let alphanumeric = |b: Option<u8>| b.is_some_and(|b| b.is_ascii_alphanumeric());
+            if alphanumeric(stripped.bytes().last()) && alphanumeric(bytes.get(i).copied()) {
+                stripped.push(' ');
+            }
+            continue;
         }
+        let regex_start = bytes[i] == b'^' && stripped.bytes().last().is_none_or(|b| b"(|,".contains(&b));
+        match regex_end(bytes, i).filter(|_| regex_start) {
+            Some(end) => i = end,
+            // the noise and '^' are ASCII, so i always lands on a char boundary
+            None => {
+                i += 1;
+                while i < bytes.len() && !is_noise(bytes[i]) && bytes[i] != b'^' {
+                    i += 1;
+                }
+            }
+        }
+        stripped.push_str(&spec_str[start..i]);
     }
-    return Ok(())
+    Cow::Owned(stripped)
+}
+
+/// Split a spec into the tokens `(`, `)`, `|`, `,` and the specs between them.  A spec starting
+///   with `^` is a regex and runs to the `$` that ends it, so `(`, `)`, `|` and `,` inside it
+///   belong to the regex; a `$` escaped with `\` doesn't end it.
+pub(crate) fn tokenize(spec_str: &str) -> Result<Vec<&str>, VersionParsingError> {
+    let bytes = spec_str.as_bytes();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        match bytes[i] {
+            b if b.is_ascii_whitespace() => {
+                i += 1;
+                continue;
+            }
+            b'(' | b')' | b'|' | b',' => i += 1,
+            b'^' => {
+                i = regex_end(bytes, i).ok_or_else(|| VersionParsingError::InvalidSpec(
+                    format!("regex in '{}' must end with '$'", spec_str)))?;
+            }
+            _ => {
+                while i < bytes.len() && !b"()|,".contains(&bytes[i]) {
+                    i += 1;
+                }
+            }
+        }
+        tokens.push(spec_str[start..i].trim_end());
+    }
+    Ok(tokens)
 }
 
-fn _treeify(spec_str: String) -> Result<ConstraintTree, String> {
-    lazy_static! { static ref VSPEC_TOKENS: Regex = Regex::new(
-        r#"\s*\^[^$]*[$]|\s*[()|,]|\s*[^()|,]+"#
-    ).unwrap(); }
-    //let delimiters: &str = "|,()";
-    let mut output: ConstraintTree = ConstraintTree { combinator: Combinator::None, parts: vec![]};
+fn _treeify(spec_str: String) -> Result<ConstraintTree, VersionParsingError> {
     let mut stack: Vec<&str> =vec![];
 
     let spec_str_in_parens = format!("({})", spec_str);
-    let tokens: Vec<&str> = VSPEC_TOKENS.find_iter(&spec_str_in_parens).map(|x| x.as_str().trim()).collect();
+    let tokens = tokenize(&spec_str_in_parens)?;
+    // Nodes are built in the arena and only turned into a ConstraintTree once parsing is done,
+    //   so fusing groups never moves or clones subtrees
+    let mut arena = SpecArena::with_capacity(tokens.len());
+    let mut operands: Vec<NodeId> = vec![];
 
     for item in tokens {
         match item {
             "(" => { stack.push("(") },
             "|" => {
-                _apply_ops("(", &mut output, &mut stack)?;
+                _apply_ops("(", &mut arena, &mut operands, &mut stack)?;
                 stack.push("|");
             },
             "," => {
-                _apply_ops("|(", &mut output, &mut stack)?;
+                _apply_ops("|(", &mut arena, &mut operands, &mut stack)?;
                 stack.push(",");
             },
             ")" => {
-                _apply_ops("(", &mut output, &mut stack)?;
+                _apply_ops("(", &mut arena, &mut operands, &mut stack)?;
                 if stack.is_empty() || *stack.last().unwrap() != "(" {
-                    return Err("expression must start with \"(\"".to_string());
+                    return Err(VersionParsingError::InvalidSpec("expression must start with \"(\"".to_string()));
                 }
                 stack.pop();
             },
-            _ => {
-                if output.combinator != Combinator::None {
-                    output = ConstraintTree {
-                        combinator: Combinator::None,
-                        parts: vec![VersionSpecOrConstraintTree::ConstraintTree(output)]};
-                }
-                output.parts.push(VersionSpecOrConstraintTree::VersionSpec(VersionSpec::try_from(item).unwrap()))
-            }
+            _ => operands.push(arena.spec(item)),
         }
     }
 
-    if ! stack.is_empty() { return Err(format!("unable to convert ({}) to expression tree: {:#?}", spec_str, stack)); }
-    Ok(output)
+    if ! stack.is_empty() {
+        return Err(VersionParsingError::InvalidSpec(
+            format!("unable to convert ({}) to expression tree: {:#?}", spec_str, stack)));
+    }
+    let root = match operands.as_slice() {
+        [single] => *single,
+        [] => return Err(VersionParsingError::InvalidSpec("empty spec".to_string())),
+        _ => return Err(VersionParsingError::InvalidSpec(
+            format!("missing '|' or ',' between the specs in ({})", spec_str))),
+    };
+    // A lone spec comes back as a group of one, which matches exactly what the spec does
+    match arena.build(root)? {
+        VersionSpecOrConstraintTree::ConstraintTree(tree) => Ok(tree),
+        spec => Ok(ConstraintTree { combinator: Combinator::And, parts: vec![spec] }),
+    }
 }
 
 /// Examples:
@@ -311,50 +483,95 @@ fn _treeify(spec_str: String) -> Result<ConstraintTree, String> {
 /// use ronda::{treeify, ConstraintTree, VersionSpec, VersionSpecOrConstraintTree, Combinator};
 ///
 ///  let v = treeify(String::from("((1.5|((1.6|1.7), 1.8), 1.9 |2.0))|2.1").unwrap();
-///  assert_eq!(v, ConstraintTree {
-///                  combinator: Combinator::Or,
-///                  parts: vec![
+///  assert_eq!(v, ConstraintTree::new(
+///                  Combinator::Or,
+///                  vec![
 ///      VersionSpecOrConstraintTree::VersionSpec(VersionSpec::try_from("1.5").unwrap()),
-///      VersionSpecOrConstraintTree::ConstraintTree(ConstraintTree {
-///                   combinator: Combinator::And,
-///                   parts: vec![
-///           VersionSpecOrConstraintTree::ConstraintTree(ConstraintTree {
-///                       combinator: Combinator::Or,
-///                       parts: vec![
+///      VersionSpecOrConstraintTree::ConstraintTree(ConstraintTree::new(
+///                   Combinator::And,
+///                   vec![
+///           VersionSpecOrConstraintTree::ConstraintTree(ConstraintTree::new(
+///                       Combinator::Or,
+///                       vec![
 ///               VersionSpecOrConstraintTree::VersionSpec(VersionSpec::try_from("1.6").unwrap()),
 ///               VersionSpecOrConstraintTree::VersionSpec(VersionSpec::try_from("1.7").unwrap()),
-///           ]}),
+///           ]).unwrap()),
 ///           VersionSpecOrConstraintTree::VersionSpec(VersionSpec::try_from("1.8").unwrap()),
 ///           VersionSpecOrConstraintTree::VersionSpec(VersionSpec::try_from("1.9").unwrap()),
-///      ]}),
+///      ]).unwrap()),
 ///      VersionSpecOrConstraintTree::VersionSpec(VersionSpec::try_from("2.0").unwrap()),
 ///      VersionSpecOrConstraintTree::VersionSpec(VersionSpec::try_from("2.1").unwrap()),
-///  ]});
+///  ]).unwrap());
 ///  ```
-pub fn treeify(spec_str: &str) -> Result<ConstraintTree, String> {
-    _treeify(spec_str.to_string())
+pub fn treeify(spec_str: &str) -> Result<ConstraintTree, VersionParsingError> {
+    _treeify(strip_spec_noise(spec_str).into_owned())
+}
+
+/// Rewrite a spec into an equivalent, smaller form: nested groups that use the same combinator as
+///   their parent are merged into it, repeated specs within a group are dropped, and groups left
+///   with a single member are replaced by that member.
+///
+/// # Examples
+///
+/// ```
+/// use ronda::{simplify, untreeify, VersionSpecOrConstraintTree};
+/// use std::convert::TryFrom;
+///
+/// let spec = VersionSpecOrConstraintTree::try_from("(1.5|(1.6|1.5)),(>1,(>1))").unwrap();
+/// assert_eq!(untreeify(&simplify(&spec)).unwrap(), "(1.5|1.6),>1");
+/// ```
+pub fn simplify(spec: &VersionSpecOrConstraintTree) -> VersionSpecOrConstraintTree {
+    match spec {
+        VersionSpecOrConstraintTree::VersionSpec(_) => spec.clone(),
+        VersionSpecOrConstraintTree::ConstraintTree(tree) => {
+            let mut parts: Vec<VersionSpecOrConstraintTree> = vec![];
+            let mut push_unique = |part: VersionSpecOrConstraintTree| {
+                if !parts.iter().any(|p| p.get_spec() == part.get_spec()) {
+                    parts.push(part);
+                }
+            };
+            for part in tree.parts.iter().map(simplify) {
+                match part {
+                    VersionSpecOrConstraintTree::ConstraintTree(inner) if inner.combinator == tree.combinator => {
+                        inner.parts.into_iter().for_each(&mut push_unique)
+                    },
+                    _ => push_unique(part)
+                }
+            }
+            if parts.len() == 1 {
+                parts.pop().unwrap()
+            } else {
+                VersionSpecOrConstraintTree::ConstraintTree(ConstraintTree { combinator: tree.combinator.clone(), parts })
+            }
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct VersionSpec {
     spec_str: String,
-    matcher: MatchEnum,
+    pub(crate) matcher: MatchEnum,
     _is_exact: bool
 }
 
+// Specs are compared and hashed by their text; the matcher is derived from it
 impl PartialEq for VersionSpec {
     fn eq(&self, other: &Self) -> bool {
         return self.spec_str == other.spec_str
     }
 }
 
+impl Eq for VersionSpec {}
+
+impl Hash for VersionSpec {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.spec_str.hash(state)
+    }
+}
+
 impl Spec for VersionSpec {
     fn get_spec(&self) -> String { self.spec_str.clone() }
     fn is_exact(&self) -> bool { self._is_exact }
-    fn test_match(&self, other: &str) -> bool {
-        let v: Version = other.into();
-        self.test_match_version(&v)
-    }
     fn test_match_version(&self, other: &Version) -> bool {
         self.matcher.test(other)
     }
@@ -364,6 +581,7 @@ impl TryFrom<&str> for VersionSpec {
     type Error = VersionParsingError;
 
     fn try_from(input: &str) -> Result<Self, Self::Error> {
+        let input = &*strip_spec_noise(input);
         let res = get_matcher(input);
         match res {
             Ok((matcher, _is_exact)) => Ok(VersionSpec { spec_str: input.to_string(), matcher, _is_exact }),
@@ -380,6 +598,8 @@ mod tests {
     use super::*;
     use std::convert::TryInto;
     use rstest::rstest;
+    use proptest::prelude::*;
+    use crate::test_support::strategies::{version, version_spec};
 
     #[test]
     fn untreeify_single() {
@@ -441,13 +661,64 @@ mod tests {
     fn treeify_single() {
         let v = treeify("1.2.3").unwrap();
         assert_eq!(v, ConstraintTree {
-            combinator: Combinator::None,
+            combinator: Combinator::And,
             parts: vec![
                 VersionSpecOrConstraintTree::VersionSpec(VersionSpec::try_from("1.2.3").unwrap()),
             ]
         });
     }
 
+    #[rstest(spec, case(""), case("()"), case("(1.2)(1.3)"), case("1.2,(1.3)(1.4)"))]
+    fn treeify_needs_combined_specs(spec: &str) {
+        assert!(treeify(spec).is_err());
+    }
+
+    #[test]
+    fn combinator_from_str() {
+        assert_eq!(Combinator::try_from(",").unwrap(), Combinator::And);
+        assert_eq!(Combinator::try_from("|").unwrap(), Combinator::Or);
+        assert!(Combinator::try_from("(").is_err());
+    }
+
+    #[test]
+    fn malformed_input_is_an_error_not_a_panic() {
+        assert!(ConstraintTree::try_from(vec![]).is_err());
+        assert!(ConstraintTree::try_from(vec![",", "1.2", ""]).is_err());
+        assert!(ConstraintTree::try_from(vec!["|"]).is_err());
+        assert!(ConstraintTree::new(Combinator::Or, vec![]).is_err());
+        let compatible = VersionSpecOrConstraintTree::try_from("~=1.2.3").unwrap();
+        assert!(compatible.test_match("1.2.9") && !compatible.test_match("1.3") && !compatible.test_match("1.2.2"));
+    }
+
+    #[rstest(spec, expected,
+        case("1.2", "1.2"),
+        case("(1.2)", "1.2"),
+        case("1.2|(1.3|1.4),>=1", "1.2|(1.3|1.4),>=1"),
+        case("(1.2|1.3),(>=1,<2)", "(1.2|1.3),>=1,<2"),
+        case("((1.2,1.3)|1.4)", "1.2,1.3|1.4")
+    )]
+    fn test_normalized_string(spec: &str, expected: &str) {
+        assert_eq!(VersionSpecOrConstraintTree::try_from(spec).unwrap().normalized_string(), expected);
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_normalized_string_regex() {
+        let spec = "^1\\.(2|3)$|1.5";
+        assert_eq!(VersionSpecOrConstraintTree::try_from(spec).unwrap().normalized_string(), spec);
+    }
+
+    proptest! {
+        #[test]
+        fn test_normalized_string_round_trip(spec in version_spec(), v in version()) {
+            let normalized = spec.normalized_string();
+            let reparsed = VersionSpecOrConstraintTree::try_from(normalized.as_str()).unwrap();
+            prop_assert_eq!(&reparsed, &simplify(&spec), "{}", normalized);
+            prop_assert_eq!(reparsed.normalized_string(), normalized);
+            prop_assert_eq!(reparsed.test_match_version(&v), spec.test_match_version(&v));
+        }
+    }
+
     #[test]
     fn treeify_simple_and() {
         let v = treeify("1.2.3,>4.5.6").unwrap();
@@ -545,6 +816,122 @@ mod tests {
             ]}, "{:#?}", v);
     }
 
+    #[test]
+    fn version_spec_or_tree_from_compound_str() {
+        let vs = VersionSpecOrConstraintTree::try_from("1.7.*|>1.8,<2").unwrap();
+        assert_eq!(vs.get_spec(), "1.7.*|(>1.8,<2)");
+        assert!(vs.test_match("1.9"));
+        assert_eq!(vs.test_match("1.6"), false);
+    }
+
+    #[rstest(spec, tokens,
+    case("1.6.*|^0.*$|1.7.1", &["1.6.*", "|", "^0.*$", "|", "1.7.1"]),
+    case(r"(^1\.(2|3)$ ,>=1)|2", &["(", r"^1\.(2|3)$", ",", ">=1", ")", "|", "2"]),
+    case(r"^1\$$|^a$b$", &[r"^1\$$", "|", "^a$b$"]),
+    case(" >=1.2 , <2 ", &[">=1.2", ",", "<2"])
+    )]
+    fn tokenize_regex_segments(spec: &str, tokens: &[&str]) {
+        assert_eq!(tokenize(spec).unwrap(), tokens);
+    }
+
+    // run requirements as written in conda-forge recipes, and what they mean
+    #[rstest(spec, clean,
+    case(">=2.7, !=3.0.*, !=3.1.*", ">=2.7,!=3.0.*,!=3.1.*"),
+    case(">=3.6 , <4", ">=3.6,<4"),
+    case("  >=1.15  ", ">=1.15"),
+    case("'>=0.21.1'", ">=0.21.1"),
+    case("\">=3.7,<3.8.0a0\"", ">=3.7,<3.8.0a0"),
+    case(">= 1.2, < 2.0a0", ">=1.2,<2.0a0"),
+    case("'>=1.16' , '<2'", ">=1.16,<2"),
+    case("1.7.0.post123 + gabcdef9", "1.7.0.post123+gabcdef9"),
+    case("2.7.* | >=3.5", "2.7.*|>=3.5"),
+    case("( >=1.0 , <2 ) | 3.*", "(>=1.0,<2)|3.*"),
+    case("\t1.11.*\n", "1.11.*")
+    )]
+    fn recipe_spec_whitespace_and_quotes(spec: &str, clean: &str) {
+        assert_eq!(strip_spec_noise(spec), clean);
+        let tree = VersionSpecOrConstraintTree::try_from(spec).unwrap();
+        assert_eq!(tree, VersionSpecOrConstraintTree::try_from(clean).unwrap());
+        assert_eq!(tree.get_spec(), VersionSpecOrConstraintTree::try_from(clean).unwrap().get_spec());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn recipe_regex_spec_in_quotes() {
+        let (spec, clean) = ("'^1\\.7\\.[0-9]+$' | 2.0", "^1\\.7\\.[0-9]+$|2.0");
+        assert_eq!(strip_spec_noise(spec), clean);
+        let tree = VersionSpecOrConstraintTree::try_from(spec).unwrap();
+        assert_eq!(tree, VersionSpecOrConstraintTree::try_from(clean).unwrap());
+    }
+
+    #[test]
+    fn spec_noise_keeps_what_matters() {
+        // whitespace inside a regex is part of it
+        assert_eq!(strip_spec_noise("^1 2$"), "^1 2$");
+        // a space between two versions doesn't join them
+        assert_eq!(strip_spec_noise(" 1.7 1.8 "), "1.7 1.8");
+        assert!(!VersionSpecOrConstraintTree::try_from("1.7 1.8").unwrap().test_match("1.71.8"));
+        assert!(VersionSpecOrConstraintTree::try_from(" '' ").is_err());
+        assert!(matches!(strip_spec_noise(">=1.2,<2"), Cow::Borrowed(_)));
+        assert!(VersionSpec::try_from(" >= 1.2 ").unwrap().test_match("1.3"));
+    }
+
+    #[test]
+    fn tokenize_unterminated_regex() {
+        assert!(tokenize("1.2|^1.*").is_err());
+        assert!(VersionSpecOrConstraintTree::try_from("1.2|^1.*").is_err());
+    }
+
+    #[cfg(feature = "regex")]
+    #[rstest(spec,
+    case("1.6.*|^0.*$|1.7.1"),
+    case(r"^1\.(2|3)$,>=1"),
+    case(r"(^1\.[0-9]+$|2.*),!=1.5"),
+    case(r"1.6.*|^.*\.7\.1$|0.7.1"),
+    case(r"^(1|2)\.0$|3")
+    )]
+    fn regex_compound_round_trip(spec: &str) {
+        let tree = VersionSpecOrConstraintTree::try_from(spec).unwrap();
+        assert_eq!(untreeify(&tree).unwrap(), spec);
+        assert_eq!(VersionSpecOrConstraintTree::try_from(untreeify(&tree).unwrap().as_str()).unwrap(), tree);
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn regex_with_alternation_in_compound() {
+        // the '|' inside the regex doesn't split it
+        let tree = treeify(r"^1\.(2|3)$,>=1|2.0").unwrap();
+        assert_eq!(tree.combinator, Combinator::Or);
+        let and = match &tree.parts[0] {
+            VersionSpecOrConstraintTree::ConstraintTree(and) => and,
+            other => panic!("expected a group, got {:?}", other),
+        };
+        assert_eq!(and.parts[0].get_spec(), r"^1\.(2|3)$");
+        assert_eq!(tree.parts[1].get_spec(), "2.0");
+        let single = VersionSpecOrConstraintTree::try_from("^(1|2)$").unwrap();
+        assert_eq!(single.get_spec(), "^(1|2)$");
+    }
+
+    #[test]
+    fn merge_flattens_and() {
+        let a = VersionSpecOrConstraintTree::try_from(">1.0,<3").unwrap();
+        let b = VersionSpecOrConstraintTree::try_from("!=2.0").unwrap();
+        let merged = a.merge(&b);
+        assert_eq!(merged.get_spec(), ">1.0,<3,!=2.0");
+        assert!(merged.test_match("2.1"));
+        assert_eq!(merged.test_match("2.0"), false);
+    }
+
+    #[test]
+    fn simplify_flattens_and_dedupes() {
+        let spec = VersionSpecOrConstraintTree::try_from("1.5|(1.6|(1.7|1.5))|(>2,>2)").unwrap();
+        let simple = simplify(&spec);
+        assert_eq!(untreeify(&simple).unwrap(), "1.5|1.6|1.7|>2");
+        for v in ["1.5", "1.7", "2.1", "1.8"].iter() {
+            assert_eq!(simple.test_match(v), spec.test_match(v));
+        }
+    }
+
     #[test]
     fn test_ver_eval() {
         assert_eq!(VersionSpec::try_from("==1.7").unwrap().test_match("1.7.0"), true);
@@ -664,9 +1051,22 @@ mod tests {
     case("==1.7", false),
     case("==1.7.2", false),
     case("==1.7.1.0", true),
-    case("1.7.*|1.8.*", true),
     case(">1.7,<1.8", true),
     case(">1.7.1,<1.8", false),
+    case("*", true)
+    )]
+    fn test_match(vspec: &str, res: bool) {
+        let m = VersionSpec::try_from(vspec).unwrap();
+        //assert VersionSpec(m) is m
+        //assert str(m) == vspec
+        //assert repr(m) == "VersionSpec('%s')" % vspec
+        assert_eq!(m.test_match("1.7.1"), res);
+    }
+
+    // matched as one glob, with the regex engine
+    #[cfg(feature = "regex")]
+    #[rstest(vspec, res,
+    case("1.7.*|1.8.*", true),
     case("^1.7.1$", true),
     case(r"^1\.7\.1$", true),
     case(r"^1\.7\.[0-9]+$", true),
@@ -680,17 +1080,12 @@ mod tests {
     case("1.6.*|^0.*$|1.7.1", true),
     case("^0.*$|1.7.1", true),
     case(r"1.6.*|^.*\.7\.1$|0.7.1", true),
-    case("*", true),
     case("1.*.1", true),
     case("1.5.*|>1.7,<1.8", true),
     case("1.5.*|>1.7,<1.7.1", false)
     )]
-    fn test_match(vspec: &str, res: bool) {
-        let m = VersionSpec::try_from(vspec).unwrap();
-        //assert VersionSpec(m) is m
-        //assert str(m) == vspec
-        //assert repr(m) == "VersionSpec('%s')" % vspec
-        assert_eq!(m.test_match("1.7.1"), res);
+    fn test_match_regex(vspec: &str, res: bool) {
+        assert_eq!(VersionSpec::try_from(vspec).unwrap().test_match("1.7.1"), res);
     }
 
     #[rstest(vspec,
@@ -701,9 +1096,9 @@ mod tests {
     )]
     fn test_local_identifier(vspec: &'static str) {
         //"""The separator for the local identifier should be either `.` or `+`"""
-        // a valid versionstr should match itself
         let m = VersionSpec::try_from(vspec).unwrap();
-        assert!(m.test_match (vspec.into()))
+        // a valid versionstr should match itself; the spaces around `+` only belong in the spec
+        assert!(m.test_match(&vspec.replace(' ', "")))
     }
 
     #[test]
@@ -752,6 +1147,43 @@ mod tests {
         };
     }
 
+    #[test]
+    fn test_tree_equality() {
+        let parse = |s| VersionSpecOrConstraintTree::try_from(s).unwrap();
+        assert_eq!(parse("1.2|1.3"), parse("1.2|1.3"));
+        assert_ne!(parse("1.2|1.3"), parse("1.2,1.3"));
+        assert_ne!(parse("1.2|1.3"), parse("1.2|1.3|1.4"));
+        assert_ne!(parse("1.2|1.3|1.4"), parse("1.2|1.3"));
+        assert_ne!(parse("1.2"), parse("1.2|1.3"));
+
+        let hash = |s| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            parse(s).hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash("1.2|1.3,>1"), hash("1.2|1.3,>1"));
+        assert_ne!(hash("1.2|1.3"), hash("1.2,1.3"));
+    }
+
+    #[test]
+    fn test_debug() {
+        let tree = VersionSpecOrConstraintTree::try_from(">1,(<2|3)").unwrap();
+        assert_eq!(format!("{:?}", tree), "\">1,(<2|3)\"");
+        assert_eq!(format!("{:?}", VersionSpecOrConstraintTree::try_from(">1").unwrap()), "\">1\"");
+    }
+
+    #[test]
+    fn test_invalid_versions_dont_panic() {
+        // the version after an operator is parsed when the spec is, not when it's first matched
+        assert_eq!(VersionSpec::try_from(">=1!2!3").err(), Some(VersionParsingError::DuplicatedEpochCharacter));
+        assert!(VersionSpecOrConstraintTree::try_from("1.0|1+a+b").is_err());
+
+        let spec = VersionSpecOrConstraintTree::try_from(">=1.0,<2").unwrap();
+        assert_eq!(spec.try_test_match("1.5"), Ok(true));
+        assert_eq!(spec.try_test_match("1!2!3"), Err(VersionParsingError::DuplicatedEpochCharacter));
+        assert!(!spec.test_match("1!2!3"));
+    }
+
     #[test]
     fn test_compatible_release_versions() {
         assert_eq!(VersionSpec::try_from("~=1.10").unwrap().test_match("1.11.0"), true);
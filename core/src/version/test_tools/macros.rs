@@ -145,8 +145,8 @@ macro_rules! parametrize_match_evaluation {
              case::star_with_more_precision("1.0*", "1.0.0", true),
              case::star_with_less_precision("1.0.0*", "1.0", true),
              case::star_nomatch("1.0.0*", "1.0.1", false),
-             case::star_match_alpha("2013a", "2013a*", true),
-             case::star_mismatch_same_precision("2013a", "2013b*", false),
+             case::star_match_alpha("2013a*", "2013a", true),
+             case::star_mismatch_same_precision("2013b*", "2013a", false),
              case::star_match_alpha_sub("2013a*", "2013ab", true),
              case::crazy_mismatch("1.2.4*", "1.3.4", false),
              case::star_post("1.2.3*", "1.2.3+4.5.6", true),
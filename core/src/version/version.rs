@@ -5,19 +5,62 @@
 //! is made. This struct provides many methods and features for easy comparison, probing and other
 //! things.
 
+use std::borrow::Cow;
 use std::cmp::Ordering;
-use std::convert::From;
+use std::convert::{From, TryFrom};
 use std::fmt;
-use std::iter::Peekable;
-use std::slice::Iter;
 use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
 
 use serde::Deserialize;
 
 use super::comp_op::CompOp;
+use super::compact::{encode, PartRef};
+use super::conda_compat;
 use super::errors::VersionParsingError;
 use super::parsers::conda::conda_parser;
-use super::version_part::{ProvideEmptyImpl, VersionPart};
+use super::parsers::tokenizer::{tokenize, Token};
+use super::version_part::{write_sort_key_str, VersionPart};
+
+// Leading byte of each sort key entry.  A version that has run out of parts sits between parts that
+//    are less than their type's empty value (e.g. dev) and parts that are greater (e.g. 1, post).
+const SORT_KEY_BELOW_EMPTY: u8 = 1;
+pub(crate) const SORT_KEY_END: u8 = 2;
+const SORT_KEY_ABOVE_EMPTY: u8 = 3;
+// Leading byte of the local version label after SORT_KEY_END, and of each of its tokens: numbers
+//    sort after strings, as PEP 440 has it
+const SORT_KEY_LOCAL: u8 = 0;
+const SORT_KEY_LOCAL_TEXT: u8 = 1;
+const SORT_KEY_LOCAL_NUMBER: u8 = 2;
+
+/// Append the sort key entry header for a part that is less or greater (`ord`) than its type's
+///    empty value, and came right after `empties` parts equal to it.
+pub(crate) fn write_sort_key_marker(key: &mut Vec<u8>, ord: Ordering, empties: u16) {
+    // A run of empty parts before this one is compared against the other version's parts.  The
+    //     longer the run, the later this part's difference shows up.
+    if ord == Ordering::Less {
+        key.push(SORT_KEY_BELOW_EMPTY);
+        key.extend_from_slice(&empties.to_be_bytes());
+    } else {
+        key.push(SORT_KEY_ABOVE_EMPTY);
+        key.extend_from_slice(&(u16::MAX - empties).to_be_bytes());
+    }
+}
+
+/// How a `Version` is split up and compared.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+pub enum VersionMode {
+    /// Compare the parts the parser made, one after the other, then the local version labels.
+    /// `1.1.dev1` and `1.1.0dev1` are different versions, and `.`, `-` and `_` are all
+    /// separators.
+    #[default]
+    Clean,
+    /// Split and compare exactly like conda's `VersionOrder`, quirks included: components are
+    /// padded with zeros separately, so `1.1.dev1` equals `1.1.0dev1`; dashes only separate if
+    /// there are no underscores; a trailing `_` is part of the version; and a local version
+    /// sorts below the same version without one.
+    CondaCompat,
+}
 
 /// Version struct, which is a representation for a parsed version string.
 ///
@@ -29,10 +72,58 @@ use super::version_part::{ProvideEmptyImpl, VersionPart};
 /// representation, the returned value is generated.
 ///
 /// The struct provides many methods for comparison and probing.
+///
+/// Parts are kept in a compact encoding (see the `compact` module), which is what comparisons
+/// work on.  `part()` and `parts()` decode them into `VersionPart`s the first time either is
+/// called.  The data is shared rather than owned, so cloning a `Version` (or a spec holding one)
+/// only bumps reference counts.
 #[derive(Deserialize, Clone)]
+#[serde(try_from = "VersionData")]
 pub struct Version {
-    pub(crate) version: String,
+    // The version string, followed by any part text that doesn't occur in it
+    text: Arc<str>,
+    // The length of the version string, then one encoded word per part
+    words: Arc<[u64]>,
+    decoded: OnceLock<Arc<Vec<VersionPart>>>,
+    mode: VersionMode,
+}
+
+/// The biggest way in which two versions differ, as returned by `Version::diff`.  Variants are
+/// ordered from least to most significant, so deltas can be compared to find the larger change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum VersionDelta {
+    /// The versions compare equal
+    Same,
+    /// Only the local version label (after `+`) differs
+    Local,
+    /// The release numbers are the same, but what follows them differs: a pre-release, dev or
+    /// post-release tag such as `rc1`, `dev0` or `post2`
+    PreRelease,
+    /// The first difference is in the third release number or later
+    Patch,
+    /// The first difference is in the second release number
+    Minor,
+    /// The first release number differs
+    Major,
+    /// The epochs differ
+    Epoch,
+}
+
+// What `Version` deserializes from
+#[derive(Deserialize)]
+struct VersionData {
+    version: String,
     parts: Vec<VersionPart>,
+    #[serde(default)]
+    mode: VersionMode,
+}
+
+impl TryFrom<VersionData> for Version {
+    type Error = VersionParsingError;
+
+    fn try_from(data: VersionData) -> Result<Self, Self::Error> {
+        Version::from_parts(&data.version, &data.parts, data.mode)
+    }
 }
 
 impl FromStr for Version {
@@ -43,6 +134,10 @@ impl FromStr for Version {
     }
 }
 
+/// Parse a conda version string.
+///
+/// This panics if `s` isn't a valid version, so it's only meant for literals in tests and
+///    examples.  Use `s.parse::<Version>()` (`FromStr`) for input that might be invalid.
 impl From<&str> for Version {
     fn from(s: &str) -> Version {
         Version::parse(s, &conda_parser).unwrap()
@@ -56,7 +151,7 @@ impl Version {
     /// The version string should be passed to the `version` parameter.  Additional parsers
     /// are in the "parsers" module.  This is the primary means of customizing behavior.
     ///
-    /// Note that the string reference passed in here is moved with to_string().
+    /// Note that the string reference passed in here is copied into the new `Version`.
     ///
     /// # Examples
     ///
@@ -72,15 +167,114 @@ impl Version {
         parser: &dyn Fn(&str) -> Result<Vec<VersionPart>, VersionParsingError>,
     ) -> Result<Self, VersionParsingError> {
         match parser(version) {
-            Ok(parts) => Ok(Self {
-                version: version.to_string(),
-                parts,
-            }),
+            Ok(parts) => Self::from_parts(version, &parts, VersionMode::Clean),
             Err(e) => Err(e),
-            _ => unreachable!("Unknown error parsing version"),
         }
     }
 
+    /// Parse a conda version string in the given `mode`.  Versions compare by the rules of their
+    /// own mode; when two versions of different modes meet, the clean one is parsed again in
+    /// conda compatible mode if it can be.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ronda::{Version, VersionMode};
+    ///
+    /// let conda = |v| Version::with_mode(v, VersionMode::CondaCompat).unwrap();
+    /// assert!(conda("1.1.dev1") == conda("1.1.0dev1"));
+    /// assert!(Version::from("1.1.dev1") != Version::from("1.1.0dev1"));
+    ///
+    /// assert!(conda("1.0+abc") < conda("1.0"));
+    /// assert!(Version::from("1.0+abc") > Version::from("1.0"));
+    /// ```
+    pub fn with_mode(version: &str, mode: VersionMode) -> Result<Self, VersionParsingError> {
+        match mode {
+            VersionMode::Clean => Self::parse(version, &conda_parser),
+            VersionMode::CondaCompat => Self::from_parts(version, &conda_compat::conda_compat_parser(version)?, mode),
+        }
+    }
+
+    fn from_parts(version: &str, parts: &[VersionPart], mode: VersionMode) -> Result<Self, VersionParsingError> {
+        match encode(version, parts) {
+            Some((text, words)) => Ok(Self { text, words, decoded: OnceLock::new(), mode }),
+            None => Err(VersionParsingError::Message(format!("version '{}' is too long", version))),
+        }
+    }
+
+    /// Get the mode this version was parsed in.
+    pub fn mode(&self) -> VersionMode {
+        self.mode
+    }
+
+    // This version in `mode`, parsing it again if it is in the other one
+    fn in_mode(&self, mode: VersionMode) -> Option<Cow<'_, Version>> {
+        if self.mode == mode {
+            return Some(Cow::Borrowed(self));
+        }
+        Version::with_mode(self.as_str(), mode).ok().map(Cow::Owned)
+    }
+
+    // Both versions in conda compatible mode, if either is in it and the other can be parsed so
+    fn as_conda_compat<'a>(&'a self, other: &'a Version) -> Option<(Cow<'a, Version>, Cow<'a, Version>)> {
+        if self.mode == VersionMode::Clean && other.mode == VersionMode::Clean {
+            return None;
+        }
+        Some((self.in_mode(VersionMode::CondaCompat)?, other.in_mode(VersionMode::CondaCompat)?))
+    }
+
+    fn part_refs(&self) -> impl Iterator<Item = PartRef<'_>> {
+        self.words[1..].iter().map(move |w| PartRef::new(*w, &self.text))
+    }
+
+    // The parts after the epoch, if there is one
+    fn release_part_refs(&self) -> impl Iterator<Item = PartRef<'_>> {
+        let skip = self.part_refs().next().map_or(0, |p| p.as_epoch().is_some() as usize);
+        self.part_refs().skip(skip)
+    }
+
+    // The leading integers after the epoch, e.g. 1, 2 and 3 in 1!1.2.3rc1
+    fn release_numbers(&self) -> impl Iterator<Item = PartRef<'_>> {
+        self.release_part_refs().take_while(|p| p.is_integer())
+    }
+
+    // What follows the release numbers, up to any local version label
+    fn tag_part_refs(&self) -> impl Iterator<Item = PartRef<'_>> {
+        self.release_part_refs().skip_while(|p| p.is_integer()).take_while(|p| !p.is_local())
+    }
+
+    // The release parts, up to any local version label
+    fn public_part_refs(&self) -> impl Iterator<Item = PartRef<'_>> {
+        self.release_part_refs().take_while(|p| !p.is_local())
+    }
+
+    // The local version label, after the `+`
+    fn local_label(&self) -> Option<&str> {
+        self.release_part_refs().find(|p| p.is_local()).and_then(|p| p.text())
+    }
+
+    /// Get the epoch, the number before a `!` that overrides the rest of the version.  Versions
+    /// without one have epoch 0, and compare exactly like they would with `0!` in front.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ronda::Version;
+    ///
+    /// let ver: Version = "1!2.0".parse().unwrap();
+    /// assert_eq!(ver.epoch(), 1);
+    /// assert_eq!(ver.to_string(), "1!2.0");
+    ///
+    /// let plain: Version = "2.0".parse().unwrap();
+    /// assert_eq!(plain.epoch(), 0);
+    /// assert_eq!(plain, "0!2.0".parse().unwrap());
+    /// assert!(ver > "3.0".parse().unwrap());
+    /// ```
+    pub fn epoch(&self) -> u64 {
+        // parsers only accept non-negative epochs
+        self.part_refs().next().and_then(|p| p.as_epoch()).map_or(0, |e| e.max(0) as u64)
+    }
+
     /// Get the original version string.
     ///
     /// # Examples
@@ -93,11 +287,11 @@ impl Version {
     /// assert_eq!(ver.as_str(), "1.2.3");
     /// ```
     pub fn as_str(&self) -> &str {
-        &self.version
+        &self.text[..self.words[0] as usize]
     }
 
     /// Get a specific version part by it's `index`.
-    /// An error is returned if the given index is out of bound.
+    /// `None` is returned if the given index is out of bound.
     ///
     /// # Examples
     ///
@@ -106,21 +300,16 @@ impl Version {
     ///
     /// let ver: Version = "1.2.3".parse().unwrap();
     ///
-    /// assert_eq!(ver.part(0), Ok(&VersionPart::Integer(1)));
-    /// assert_eq!(ver.part(1), Ok(&VersionPart::Integer(2)));
-    /// assert_eq!(ver.part(2), Ok(&VersionPart::Integer(3)));
+    /// assert_eq!(ver.part(0), Some(&VersionPart::Integer(1)));
+    /// assert_eq!(ver.part(1), Some(&VersionPart::Integer(2)));
+    /// assert_eq!(ver.part(2), Some(&VersionPart::Integer(3)));
+    /// assert_eq!(ver.part(3), None);
     /// ```
-    pub fn part(&self, index: usize) -> Result<&VersionPart, ()> {
-        // Make sure the index is in-bound
-        if index >= self.parts.len() {
-            return Err(());
-        }
-
-        // Return the requested part
-        Ok(&self.parts[index])
+    pub fn part(&self, index: usize) -> Option<&VersionPart> {
+        self.parts().get(index)
     }
 
-    /// Get a vector of all version parts.
+    /// Get a slice of all version parts.
     ///
     /// # Examples
     ///
@@ -135,8 +324,8 @@ impl Version {
     ///     VersionPart::Integer(3)
     /// ]);
     /// ```
-    pub fn parts(&self) -> &Vec<VersionPart> {
-        &self.parts
+    pub fn parts(&self) -> &[VersionPart] {
+        self.decoded.get_or_init(|| Arc::new(self.part_refs().map(PartRef::to_part).collect()))
     }
 
     /// Get the number of parts in this version string.
@@ -153,12 +342,22 @@ impl Version {
     /// assert_eq!(ver_b.part_count(), 4);
     /// ```
     pub fn part_count(&self) -> usize {
-        self.parts.len()
+        self.words.len() - 1
     }
 
     pub fn compare_version(&self, other: &Version) -> CompOp {
-        // Compare the versions with their peekable iterators
-        Self::compare_iter(self.parts.iter().peekable(), other.parts.iter().peekable())
+        if let Some((a, b)) = self.as_conda_compat(other) {
+            return CompOp::from_ord(conda_compat::compare(&a, &b));
+        }
+        match self.epoch().cmp(&other.epoch()) {
+            Ordering::Less => CompOp::Lt,
+            Ordering::Greater => CompOp::Gt,
+            // a local version label only breaks ties, wherever it starts: 1.0.1 > 1.0+abc
+            Ordering::Equal => match Self::compare_iter(self.public_part_refs(), other.public_part_refs()) {
+                CompOp::Eq => CompOp::from_ord(compare_local(self.local_label(), other.local_label())),
+                op => op,
+            },
+        }
     }
 
     /// Compare this version to the given `other` version.
@@ -181,28 +380,129 @@ impl Version {
     /// assert_eq!(a.compare_str("1.3.2"), CompOp::Lt);
     /// assert_eq!(a.compare_str("1.2.0"), CompOp::Eq);
     /// assert_eq!(b.compare_str("1.7.3"), CompOp::Gt);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// If `other` isn't a valid version; parse it first and use `compare_version` if it might not be.
     pub fn compare_str(&self, other: &str) -> CompOp {
         self.compare_version(&other.into())
     }
 
+    /// Whether this version starts with `other`, compared part by part rather than as a string:
+    ///    `1.2.3` starts with `1.2` but `1.20` doesn't.  This is what `=1.2` and `1.2.*` specs
+    ///    match.
+    ///
+    /// * The epochs must be equal.
+    /// * Parts missing from this version count as empty, so `1.0` starts with `1.0.0`.
+    /// * A string at the end of `other` only has to start this version's string, so `2013ab`
+    ///   starts with `2013a`.
+    /// * If `other` has a local version label, everything before the `+` must be equal, and the
+    ///   label must start this version's label.  Labels are split into numbers and strings at
+    ///   `.`, `-` and `_`, so `1.2.3+4.5.6` starts with `1.2.3+4` but not with `1.2.3+45`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ronda::Version;
+    ///
+    /// let version = Version::from("1.2.3+4.5.6");
+    /// assert!(version.startswith(&Version::from("1.2")));
+    /// assert!(version.startswith(&Version::from("1.2.3+4.5")));
+    /// assert!(!version.startswith(&Version::from("1.2.3+45")));
+    /// assert!(!version.startswith(&Version::from("1.2+4")));
+    /// assert!(!Version::from("1.20").startswith(&Version::from("1.2")));
+    /// ```
     pub fn startswith(&self, other: &Version) -> bool {
-        let mut iter = self.parts.iter().peekable();
-        let mut other_iter = other.parts.iter().peekable();
-        // Iterate over the iterator, without consuming it
-        loop {
-            let i1 = &iter.next();
-            let i2 = &other_iter.next();
-            match (i1, i2) {
-                (Some(i), Some(j)) => match i == j {
-                    false => return false,
-                    _ => {}
-                },
-                // ran out of other, startswith is true
-                (Some(_), None) => return true,
-                // both versions are the same length and are equal for all values
-                (None, None) => return true,
-                _ => return false,
+        if let Some((a, b)) = self.as_conda_compat(other) {
+            return conda_compat::startswith(&a, &b);
+        }
+        if self.epoch() != other.epoch() {
+            return false;
+        }
+        let (parts, prefix): (Vec<_>, Vec<_>) = (self.public_part_refs().collect(), other.public_part_refs().collect());
+        match other.local_label() {
+            Some(prefix_local) => {
+                let part = |i: usize| (parts.get(i), prefix.get(i));
+                (0..parts.len().max(prefix.len())).all(|i| match part(i) {
+                    (Some(a), Some(b)) => a.cmp(b) == Ordering::Equal,
+                    (Some(p), None) | (None, Some(p)) => p.cmp_empty() == Ordering::Equal,
+                    (None, None) => true,
+                }) && self.local_label().is_some_and(|local| local_startswith(local, prefix_local))
+            }
+            None => prefix.iter().enumerate().all(|(i, p)| match parts.get(i) {
+                // ran out of this version, the rest of other must be empty
+                None => p.cmp_empty() == Ordering::Equal,
+                Some(part) if i + 1 == prefix.len() => {
+                    part.cmp(p) == Ordering::Equal
+                        || part.text().zip(p.text()).is_some_and(|(a, b)| starts_with_ignore_case(a, b))
+                }
+                Some(part) => part.cmp(p) == Ordering::Equal,
+            }),
+        }
+    }
+
+    /// Classify how `other` differs from this version, e.g. to report the impact of an update or
+    /// to penalize major version jumps.  The release numbers are the leading integers after the
+    /// epoch; missing ones count as 0, so `1.2` and `1.2.0` are the same release.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ronda::{Version, VersionDelta};
+    ///
+    /// let v = Version::from("1.2.3");
+    /// assert_eq!(v.diff(&"2.0".into()), VersionDelta::Major);
+    /// assert_eq!(v.diff(&"1.3".into()), VersionDelta::Minor);
+    /// assert_eq!(v.diff(&"1.2.4".into()), VersionDelta::Patch);
+    /// assert_eq!(v.diff(&"1.2.3rc1".into()), VersionDelta::PreRelease);
+    /// assert_eq!(v.diff(&"1.2.3+cuda".into()), VersionDelta::Local);
+    /// assert_eq!(v.diff(&"1.2.3.0".into()), VersionDelta::Same);
+    /// assert!(v.diff(&"2.0".into()) > v.diff(&"1.2.4".into()));
+    /// ```
+    pub fn diff(&self, other: &Version) -> VersionDelta {
+        // release numbers and tags split the same way in both modes
+        if self.mode != VersionMode::Clean || other.mode != VersionMode::Clean {
+            if let (Some(a), Some(b)) = (self.in_mode(VersionMode::Clean), other.in_mode(VersionMode::Clean)) {
+                return a.diff(&b);
+            }
+        }
+        if self.epoch() != other.epoch() {
+            return VersionDelta::Epoch;
+        }
+        let (mut iter, mut other_iter) = (self.release_numbers(), other.release_numbers());
+        for index in 0.. {
+            let differs = match (iter.next(), other_iter.next()) {
+                (None, None) => break,
+                (Some(i), Some(j)) => i.cmp(&j) != Ordering::Equal,
+                (Some(p), None) | (None, Some(p)) => p.cmp_empty() != Ordering::Equal,
             };
+            if differs {
+                return match index {
+                    0 => VersionDelta::Major,
+                    1 => VersionDelta::Minor,
+                    _ => VersionDelta::Patch,
+                };
+            }
+        }
+        if Self::compare_iter(self.tag_part_refs(), other.tag_part_refs()) != CompOp::Eq {
+            return VersionDelta::PreRelease;
+        }
+        match compare_local(self.local_label(), other.local_label()) {
+            Ordering::Equal => VersionDelta::Same,
+            _ => VersionDelta::Local,
+        }
+    }
+
+    // ~=1.2.3 is >=1.2.3 and 1.2.*: `other` is at least this version and starts with all of it
+    //    but the last component, as conda's compatible_release_operator has it
+    fn compatible_with(&self, other: &Version) -> bool {
+        if self.compare_version(other) == CompOp::Gt {
+            return false;
+        }
+        match self.as_str().rsplit_once('.') {
+            Some((prefix, _)) => Version::with_mode(prefix, self.mode).is_ok_and(|prefix| other.startswith(&prefix)),
+            None => true,
         }
     }
 
@@ -212,8 +512,8 @@ impl Version {
             //     is that a.b.c starts with the spec (up until the star)
             CompOp::StartsWith => other.startswith(self),
             CompOp::NotStartsWith => !other.startswith(self),
-            CompOp::Compatible => unimplemented!(),
-            CompOp::Incompatible => unimplemented!(),
+            CompOp::Compatible => self.compatible_with(other),
+            CompOp::Incompatible => !self.compatible_with(other),
             _ => match self.compare_version(other) {
                 CompOp::Eq => match operator {
                     &CompOp::Eq | &CompOp::Le | &CompOp::Ge => true,
@@ -248,10 +548,81 @@ impl Version {
     /// assert!(a.compare_to_str("1.2", &CompOp::Eq));
     /// assert!(a.compare_to_str("1.2", &CompOp::Le));
     /// ```
+    ///
+    /// # Panics
+    ///
+    /// If `other` isn't a valid version; parse it first and use `compare_to_version` if it might
+    /// not be.
     pub fn compare_to_str(&self, other: &str, operator: &CompOp) -> bool {
         self.compare_to_version(&other.into(), operator)
     }
 
+    /// Get a binary key for this version.  Comparing two keys byte by byte gives the same order
+    /// as comparing the versions, so the key can be used to sort versions without parsing them
+    /// again, or by code that only knows about bytes.
+    ///
+    /// A part that equals its type's empty value (0, empty string) isn't written on its own.
+    /// Instead, every other part records how many of those came right before it, so that
+    /// trailing zeros don't change the key and `1.0` sorts the same as `1.0.0`.  The local
+    /// version label comes last, as it is compared last.
+    ///
+    /// Keys of versions in different modes can't be compared with each other.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ronda::Version;
+    ///
+    /// let dev: Version = "1.0.dev1".into();
+    /// let release: Version = "1.0".into();
+    /// let post: Version = "1.0.post1".into();
+    ///
+    /// assert!(dev.sort_key() < release.sort_key());
+    /// assert!(release.sort_key() < post.sort_key());
+    /// assert_eq!(release.sort_key(), Version::from("1.0.0").sort_key());
+    /// ```
+    pub fn sort_key(&self) -> Vec<u8> {
+        if self.mode == VersionMode::CondaCompat {
+            return conda_compat::sort_key(self);
+        }
+        let mut key = Vec::with_capacity(self.part_count() * 8 + 3);
+        // epochs are at most i16::MAX, and decide the order before any other part
+        key.extend_from_slice(&(self.epoch() as u16).to_be_bytes());
+        let mut empties: u16 = 0;
+        for part in self.public_part_refs() {
+            match part.cmp_empty() {
+                Ordering::Equal => {
+                    empties = empties.saturating_add(1);
+                    continue;
+                },
+                ord => write_sort_key_marker(&mut key, ord, empties),
+            }
+            part.write_sort_key(&mut key);
+            empties = 0;
+        }
+        key.push(SORT_KEY_END);
+        // without a label the key ends here, which sorts first
+        if let Some(local) = self.local_label() {
+            key.push(SORT_KEY_LOCAL);
+            for token in local_tokens(local) {
+                match token {
+                    Token::Text(t) => {
+                        key.push(SORT_KEY_LOCAL_TEXT);
+                        write_sort_key_str(&mut key, &t.to_ascii_lowercase());
+                    },
+                    Token::Number(n) => {
+                        // a longer number is a bigger one once leading zeros are gone
+                        let n = n.trim_start_matches('0');
+                        key.push(SORT_KEY_LOCAL_NUMBER);
+                        key.extend_from_slice(&(n.len() as u16).to_be_bytes());
+                        key.extend_from_slice(n.as_bytes());
+                    },
+                }
+            }
+        }
+        key
+    }
+
     /// Compare two version numbers based on the iterators of their version parts.
     ///
     /// This method returns one of the following comparison operators:
@@ -261,49 +632,88 @@ impl Version {
     /// * `Gt`
     ///
     /// Other comparison operators can be used when comparing, but aren't returned by this method.
-    fn compare_iter(
-        mut iter: Peekable<Iter<VersionPart>>,
-        mut other_iter: Peekable<Iter<VersionPart>>,
+    fn compare_iter<'a, 'b>(
+        mut iter: impl Iterator<Item = PartRef<'a>>,
+        mut other_iter: impl Iterator<Item = PartRef<'b>>,
     ) -> CompOp {
-        // Iterate over the iterator, without consuming it
         loop {
-            let i1 = &iter.next();
-            let i2 = &other_iter.next();
-            let _cmp = match (i1, i2) {
+            let ord = match (iter.next(), other_iter.next()) {
                 // normal - both places defined
-                (Some(i), Some(j)) => match i.partial_cmp(j) {
-                    Some(Ordering::Greater) => return CompOp::Gt,
-                    Some(Ordering::Less) => return CompOp::Lt,
-                    // This is the only loop branch that continues
-                    Some(Ordering::Equal) => Ordering::Equal,
-                    _ => unreachable!("Ignoring ge, le, ne"),
-                },
+                (Some(i), Some(j)) => i.cmp_padded(&j),
 
-                // first version has more places. get_empty is a type-specific value that is "zero"
-                //     or whatever the equivalent is for a type (e.g. empty string)
-                (Some(i), None) => match i.partial_cmp(&&i.get_empty()) {
-                    Some(Ordering::Less) => return CompOp::Lt,
-                    Some(Ordering::Greater) => return CompOp::Gt,
-                    Some(Ordering::Equal) => return CompOp::Eq,
-                    _ => unreachable!("Ignoring ge, le, ne"),
-                },
-                (None, Some(j)) => match &j.get_empty().partial_cmp(j) {
-                    Some(Ordering::Less) => return CompOp::Lt,
-                    Some(Ordering::Greater) => return CompOp::Gt,
-                    Some(Ordering::Equal) => return CompOp::Eq,
-                    _ => unreachable!("Ignoring ge, le, ne"),
-                },
+                // first version has more places. The empty value is type-specific: "zero" or
+                //     whatever the equivalent is for a type (e.g. empty string)
+                //     An equal place means keep going: 1.0.5 is still greater than 1.
+                (Some(i), None) => i.cmp_empty(),
+                (None, Some(j)) => j.cmp_empty().reverse(),
 
                 // both versions are the same length and are equal for all values
                 (None, None) => return CompOp::Eq,
             };
+            match ord {
+                Ordering::Greater => return CompOp::Gt,
+                Ordering::Less => return CompOp::Lt,
+                Ordering::Equal => {},
+            }
         }
     }
 }
 
+fn starts_with_ignore_case(s: &str, prefix: &str) -> bool {
+    s.len() >= prefix.len() && s.is_char_boundary(prefix.len()) && s[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+fn local_tokens(local: &str) -> Vec<Token<'_>> {
+    let mut found = vec![];
+    tokenize(local, |t| found.push(t));
+    found
+}
+
+// Compare local version labels token by token, as PEP 440 compares their segments: numbers by
+// value and after strings, strings ignoring case, and a label that the other one continues
+// first.  No label sorts first.  `local_startswith` matches tokens the same way.
+fn compare_local(local: Option<&str>, other: Option<&str>) -> Ordering {
+    let (local, other) = match (local, other) {
+        (Some(local), Some(other)) => (local_tokens(local), local_tokens(other)),
+        (local, other) => return local.is_some().cmp(&other.is_some()),
+    };
+    for pair in local.iter().zip(&other) {
+        let ord = match pair {
+            (Token::Number(a), Token::Number(b)) => {
+                let (a, b) = (a.trim_start_matches('0'), b.trim_start_matches('0'));
+                a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+            },
+            (Token::Number(_), Token::Text(_)) => Ordering::Greater,
+            (Token::Text(_), Token::Number(_)) => Ordering::Less,
+            (Token::Text(a), Token::Text(b)) => {
+                a.bytes().map(|c| c.to_ascii_lowercase()).cmp(b.bytes().map(|c| c.to_ascii_lowercase()))
+            },
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+    local.len().cmp(&other.len())
+}
+
+// Whether local version label `local` starts with `prefix`, token by token.  Numbers compare by
+// value and strings ignore case; the last token of `prefix` may start a string in `local`, and
+// tokens missing from `local` only match zeros.
+fn local_startswith(local: &str, prefix: &str) -> bool {
+    let number = |n: &str| n.trim_start_matches('0').to_string();
+    let (local, prefix) = (local_tokens(local), local_tokens(prefix));
+    prefix.iter().enumerate().all(|(i, p)| match (local.get(i), p) {
+        (Some(Token::Number(a)), Token::Number(b)) => number(a) == number(b),
+        (Some(Token::Text(a)), Token::Text(b)) if i + 1 == prefix.len() => starts_with_ignore_case(a, b),
+        (Some(Token::Text(a)), Token::Text(b)) => a.eq_ignore_ascii_case(b),
+        (None, Token::Number(b)) => number(b).is_empty(),
+        _ => false,
+    })
+}
+
 impl fmt::Display for Version {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.version)
+        write!(f, "{}", self.as_str())
     }
 }
 
@@ -311,9 +721,9 @@ impl fmt::Display for Version {
 impl fmt::Debug for Version {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         if f.alternate() {
-            write!(f, "{:#?}", self.parts)
+            write!(f, "{:#?}", self.parts())
         } else {
-            write!(f, "{:?}", self.parts)
+            write!(f, "{:?}", self.parts())
         }
     }
 }
@@ -339,7 +749,8 @@ mod tests {
     // use crate::version_part::VersionPart;
 
     //use super::test::{black_box, Bencher};
-    use super::Version;
+    use proptest::prelude::*;
+    use super::{Version, VersionDelta, VersionMode};
     use crate::version::errors::VersionParsingError;
 
     // TODO: This doesn't really test whether this method fully works
@@ -369,11 +780,11 @@ mod tests {
 
         // Loop through each part
         for i in 0..n_parts {
-            assert_eq!(ver.part(i), Ok(&ver.parts[i]));
+            assert_eq!(ver.part(i), Some(&ver.parts()[i]));
         }
 
-        // A value outside the range must return an error
-        assert!(ver.part(n_parts).is_err());
+        // A value outside the range must return None
+        assert!(ver.part(n_parts).is_none());
     }
     parametrize_versions!(part);
 
@@ -574,6 +985,110 @@ mod tests {
         assert_eq!(c < d, true);
     }
 
+    #[test]
+    fn test_missing_epoch_is_zero() {
+        let a: Version = "0!1.2".parse().unwrap();
+        let b: Version = "1.2".parse().unwrap();
+        assert_eq!(a.epoch(), 0);
+        assert_eq!(a, b);
+        assert_eq!(a.sort_key(), b.sort_key());
+        assert!(Version::from("0!1.1") < b);
+        assert!(Version::from("0!1.2.3").startswith(&b));
+        assert!(!Version::from("1!1.2.3").startswith(&b));
+        assert_eq!(Version::from("12!1.0").epoch(), 12);
+    }
+
+    fn sort_key(a: &str, b: &str, operator: &CompOp) {
+        let key_a = Version::from(a).sort_key();
+        let key_b = Version::from(b).sort_key();
+        match operator {
+            CompOp::Eq => assert_eq!(key_a, key_b),
+            CompOp::Lt => assert!(key_a < key_b),
+            CompOp::Gt => assert!(key_a > key_b),
+            _ => {}
+        }
+    }
+    parametrize_versions_set!(sort_key);
+
+    #[test]
+    fn test_sort_key_orders_like_version() {
+        let mut versions: Vec<Version> = vec![
+            "1.1.0", "1.0", "1.1.0post1", "2!0.1", "1.1.dev1", "1.0.5", "1", "1.1.0rc1", "1.1a1",
+            "1.0+abc", "1996.07.12"]
+            .into_iter().map(Version::from).collect();
+        versions.sort_by_key(|v| v.sort_key());
+        let sorted: Vec<&str> = versions.iter().map(|v| v.as_str()).collect();
+        assert_eq!(sorted, vec![
+            "1.0", "1", "1.0+abc", "1.0.5", "1.1.dev1", "1.1a1", "1.1.0rc1", "1.1.0", "1.1.0post1",
+            "1996.07.12", "2!0.1"]);
+        for pair in versions.windows(2) {
+            assert!(pair[0] <= pair[1], "{} > {}", pair[0], pair[1]);
+        }
+    }
+
+    // Versions made of few digits and tags, so that zeros, equal parts, different lengths and
+    //    different part types in the same place are all common
+    fn dense_version_string() -> impl Strategy<Value = String> {
+        "(1!)?[01](\\.[01]){0,3}([.-]?(a|dev|post)[01]?(\\.[01])?)?(\\+(a|B)?[019]{0,2}([.-](a|10|9))?)?"
+    }
+
+    proptest! {
+        #[test]
+        fn test_sort_key_agrees_with_partial_cmp(strings in proptest::collection::vec(dense_version_string(), 2..20)) {
+            for mode in [VersionMode::Clean, VersionMode::CondaCompat] {
+                let versions: Vec<Version> = strings.iter().filter_map(|v| Version::with_mode(v, mode).ok()).collect();
+                for a in &versions {
+                    for b in &versions {
+                        prop_assert_eq!(Some(a.sort_key().cmp(&b.sort_key())), a.partial_cmp(b), "{} vs {}", a, b);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_trailing_part_after_zero_is_compared() {
+        let a: Version = "1.0.5".parse().unwrap();
+        let b: Version = "1".parse().unwrap();
+        assert_eq!(a > b, true);
+        assert_eq!(a == b, false);
+    }
+
+    #[test]
+    fn test_zero_compares_like_a_missing_part() {
+        // 0 == 0.0 and 0 < 0.post1, so 0.0 < 0.post1 too
+        assert!(Version::from("0.0") < Version::from("0.post1"));
+        assert!(Version::from("1.0.0.0a1") < Version::from("1.0.post1"));
+        assert!(Version::from("1.0.0.dev1") < Version::from("1.0"));
+        // the local version label is compared last, wherever it starts
+        assert_eq!(Version::from("1.0.0+local"), Version::from("1.0+local"));
+        assert!(Version::from("1.0.0") < Version::from("1.0+local"));
+        assert!(Version::from("1.0+local") < Version::from("1.0.0.1"));
+        assert!(Version::from("1.0.post1") > Version::from("1.0+local"));
+    }
+
+    #[rstest(a, b, operator,
+        case::number("1.0+9", "1.0+10", CompOp::Lt),
+        case::dotted_number("1.0+1.9", "1.0+1.10", CompOp::Lt),
+        case::leading_zero("1.0+abc.05", "1.0+abc.5", CompOp::Eq),
+        case::case("1.0+abc", "1.0+ABC", CompOp::Eq),
+        case::text_before_number("1.0+abc", "1.0+9", CompOp::Lt),
+        case::more_tokens("1.0+abc", "1.0+abc.0", CompOp::Lt),
+    )]
+    fn test_local_label_order(a: &str, b: &str, operator: CompOp) {
+        assert_eq!(Version::from(a).compare_version(&Version::from(b)), operator);
+        sort_key(a, b, &operator);
+    }
+
+    #[test]
+    fn test_clone_shares_parts() {
+        let a: Version = "1.2.3.post1".parse().unwrap();
+        let b = a.clone();
+        assert!(std::sync::Arc::ptr_eq(&a.words, &b.words));
+        assert_eq!(a.as_str().as_ptr(), b.as_str().as_ptr());
+        assert_eq!(a, b);
+    }
+
     #[test]
     fn test_startswith() {
         let b: Version = "0.4.1".parse().unwrap();
@@ -582,6 +1097,54 @@ mod tests {
         assert_eq!(c.startswith(&b), false);
     }
 
+    #[rstest(
+        version, prefix, expected,
+        case("1.2.3", "1.2", true),
+        case("1.20", "1.2", false),
+        case("1.0", "1.0.0", true),
+        case("1.0", "1.0.1", false),
+        case("1!1.2", "1.2", false),
+        case("2013ab", "2013a", true),
+        case("2013a", "2013b", false),
+        case("2013a1", "2013a", true),
+        case("1.2.3+4.5.6", "1.2.3", true),
+        case("1.2.3+4.5.6", "1.2.3+4", true),
+        case("1.2.3+4.5.6", "1.2.3+4.5", true),
+        case("1.2.3+4.5.6", "1.2.3+04_5", true),
+        case("1.2.3+4.5.6", "1.2.3+45", false),
+        case("1.2.3+4.5.6", "1.2.3+5", false),
+        case("1.2.3+4.5.6", "1.2+4", false),
+        case("1.2.3+4.5.6", "1.2.3.0+4", true),
+        case("1.2.3+abc", "1.2.3+ab", true),
+        case("1.2.3+ab.c", "1.2.3+abc", false),
+        case("1.2.3+4", "1.2.3+4.0", true),
+        case("1.2.3", "1.2.3+4", false)
+    )]
+    fn test_startswith_boundaries(version: &str, prefix: &str, expected: bool) {
+        let version: Version = version.parse().unwrap();
+        assert_eq!(version.startswith(&prefix.parse().unwrap()), expected);
+    }
+
+    #[rstest(
+        a, b, delta,
+        case("1.2.3", "1.2.3", VersionDelta::Same),
+        case("1.2", "1.2.0", VersionDelta::Same),
+        case("1.2.3", "1!1.2.3", VersionDelta::Epoch),
+        case("1.2.3", "2.2.3", VersionDelta::Major),
+        case("1.2.3", "1.3", VersionDelta::Minor),
+        case("1.2", "1.2.0.1", VersionDelta::Patch),
+        case("1.2.3", "1.2.3rc1", VersionDelta::PreRelease),
+        case("1.2.3.dev0", "1.2.3.post1", VersionDelta::PreRelease),
+        case("1.2.3+a", "1.2.3+b", VersionDelta::Local),
+        case("1.2.3+a", "1.2.3", VersionDelta::Local),
+        case("2021.1a", "2021.1b", VersionDelta::PreRelease)
+    )]
+    fn test_diff(a: &str, b: &str, delta: VersionDelta) {
+        let (a, b) = (Version::from(a), Version::from(b));
+        assert_eq!(a.diff(&b), delta);
+        assert_eq!(b.diff(&a), delta);
+    }
+
     // #[bench]
     // fn bench_parsing_basic(b: &mut Bencher) {
     //     b.iter(|| {
@@ -21,20 +21,16 @@ pub enum VersionPart {
     Empty,
 }
 
-pub trait ProvideEmptyImpl{
-    fn get_empty(&self) -> VersionPart;
-}
-
-impl ProvideEmptyImpl for VersionPart {
-    fn get_empty(&self) -> VersionPart {
-        match self {
-            VersionPart::Epoch(_i) => VersionPart::Epoch(0),
-            VersionPart::Integer(_i) => VersionPart::Integer(0),
-            VersionPart::LexicographicString(_i) => VersionPart::LexicographicString(String::new()),
-            VersionPart::PEP440String(_i) => VersionPart::PEP440String(PEP440String::empty()),
-            VersionPart::Empty => VersionPart::Empty
+/// Append a string so that shorter strings sort before longer ones sharing the same prefix:
+///    NUL bytes are escaped as 0x00 0xFF and the string is terminated by 0x00 0x00.
+pub(crate) fn write_sort_key_str(key: &mut Vec<u8>, s: &str) {
+    for b in s.bytes() {
+        key.push(b);
+        if b == 0 {
+            key.push(0xFF);
         }
     }
+    key.extend_from_slice(&[0, 0]);
 }
 
 impl Debug for VersionPart {
@@ -0,0 +1,5 @@
+fn main() {
+    // emits `Py_LIMITED_API` (and the other cfgs pyo3 itself is built with), so code can leave
+    //    out what the stable ABI doesn't have
+    pyo3_build_config::use_pyo3_cfgs();
+}
@@ -5,7 +5,7 @@ setup(
       name="ronda",
       version="1.0",
       rust_extensions=[RustExtension("ronda._ronda",
-                                     binding=Binding.RustCPython)],
+                                     binding=Binding.PyO3)],
       packages=["ronda"],
       # rust extensions are not zip safe, just like C-extensions.
       zip_safe=False,
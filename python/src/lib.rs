@@ -1,3 +1 @@
-#[macro_use] extern crate cpython;
-
-mod python_interface;
\ No newline at end of file
+mod python_interface;
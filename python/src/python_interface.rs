@@ -1,5 +1,33 @@
-use cpython::{PyResult, CompareOp, ToPyObject, PythonObject};
-use ronda::{Version, CompOp, read_repodata};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::OnceLock;
+
+use pyo3::basic::CompareOp;
+#[cfg(not(Py_LIMITED_API))]
+use pyo3::buffer::PyBuffer;
+use pyo3::create_exception;
+use pyo3::exceptions::{PyIOError, PyKeyError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyByteArray, PyBytes, PyDict, PyList, PyTuple};
+use ronda::{Version, CompOp, read_repodata, read_repodata_from_slice, PackageFilename, Record, RecordFilter, Repodata, RepodataReadError, Spec, VersionSpec, VersionSpecOrConstraintTree};
+use ronda::{graph_of_records, install_order, to_dot, simplify, untreeify, Combinator, ConstraintTree};
+
+create_exception!(_ronda, InvalidVersion, PyValueError, "Raised when a version string can't be parsed.");
+create_exception!(_ronda, InvalidVersionSpec, PyValueError, "Raised when a version spec string can't be parsed.");
+
+fn parse_version(input: &str) -> PyResult<Version> {
+    match input.parse::<Version>() {
+        Ok(v) => Ok(v),
+        Err(e) => Err(InvalidVersion::new_err(format!("invalid version '{}': {}", input, e)))
+    }
+}
+
+fn parse_spec(input: &str) -> PyResult<VersionSpecOrConstraintTree> {
+    match VersionSpecOrConstraintTree::try_from(input) {
+        Ok(s) => Ok(s),
+        Err(e) => Err(InvalidVersionSpec::new_err(format!("invalid version spec '{}': {}", input, e)))
+    }
+}
 
 fn py_cmp_to_ronda_cmp(other: CompareOp) -> CompOp {
     match other {
@@ -12,34 +40,365 @@ fn py_cmp_to_ronda_cmp(other: CompareOp) -> CompOp {
     }
 }
 
-py_module_initializer!(_ronda, init_ronda, PyInit__ronda, |py, m| {
-    m.add(
-            py,
-            "__doc__",
-            "I can haz rusty versions",
-        )?;
-    m.add_class::<RustyVersion>(py)?;
-    // m.add(py, "read_repodata", py_fn!(py, read_repodata<'a, P: AsRef<Path>>(path: P)))?;
+#[pymodule]
+fn _ronda(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("__doc__", "I can haz rusty versions")?;
+    m.add("InvalidVersion", m.py().get_type_bound::<InvalidVersion>())?;
+    m.add("InvalidVersionSpec", m.py().get_type_bound::<InvalidVersionSpec>())?;
+    m.add_class::<RustyVersion>()?;
+    m.add_class::<RustyVersionSpec>()?;
+    m.add_class::<RustyRepodata>()?;
+    m.add_function(wrap_pyfunction!(read_repodata_py, m)?)?;
+    m.add_function(wrap_pyfunction!(read_repodata_bytes, m)?)?;
+    m.add_function(wrap_pyfunction!(compare_versions, m)?)?;
+    m.add_function(wrap_pyfunction!(match_versions, m)?)?;
+    m.add_function(wrap_pyfunction!(version_sort_key, m)?)?;
+    m.add_function(wrap_pyfunction!(treeify_py, m)?)?;
+    m.add_function(wrap_pyfunction!(untreeify_py, m)?)?;
+    m.add_function(wrap_pyfunction!(simplify_py, m)?)?;
     Ok(())
-});
+}
+
+#[pyclass]
+struct RustyVersion {
+    rust_version: Version,
+}
+
+#[pymethods]
+impl RustyVersion {
+    #[new]
+    fn new(arg: &str) -> PyResult<Self> {
+        Ok(RustyVersion { rust_version: parse_version(arg)? })
+    }
+    fn __richcmp__(&self, other: PyRef<RustyVersion>, op: CompareOp) -> bool {
+        // compare_to_version evaluates `argument op receiver`, so python's `self op other` is
+        //    asked of `other`
+        other.rust_version.compare_to_version(&self.rust_version, &py_cmp_to_ronda_cmp(op))
+    }
+    fn __repr__(&self) -> String {
+        self.rust_version.as_str().to_string()
+    }
+    /// Whether this version starts with `other` part by part, respecting `.` and `+` boundaries
+    ///    like `=1.2` specs do (see `Version::startswith`).
+    fn startswith(&self, other: PyRef<RustyVersion>) -> bool {
+        self.rust_version.startswith(&other.rust_version)
+    }
+}
+
+#[pyclass]
+struct RustyVersionSpec {
+    rust_spec: VersionSpecOrConstraintTree,
+}
+
+#[pymethods]
+impl RustyVersionSpec {
+    #[new]
+    fn new(arg: &str) -> PyResult<Self> {
+        Ok(RustyVersionSpec { rust_spec: parse_spec(arg)? })
+    }
+    fn __repr__(&self) -> String {
+        format!("RustyVersionSpec('{}')", self.rust_spec.get_spec())
+    }
+    fn __str__(&self) -> String {
+        self.rust_spec.get_spec()
+    }
+    #[pyo3(name = "match")]
+    fn test_match(&self, version: &str) -> PyResult<bool> {
+        Ok(self.rust_spec.test_match_version(&parse_version(version)?))
+    }
+    fn test_match_version(&self, version: PyRef<RustyVersion>) -> bool {
+        self.rust_spec.test_match_version(&version.rust_version)
+    }
+    fn is_exact(&self) -> bool {
+        self.rust_spec.is_exact()
+    }
+    fn merge(&self, other: PyRef<RustyVersionSpec>) -> RustyVersionSpec {
+        RustyVersionSpec { rust_spec: self.rust_spec.merge(&other.rust_spec) }
+    }
+}
+
+fn record_to_dict<'py>(py: Python<'py>, record: &Record) -> PyResult<Bound<'py, PyDict>> {
+    let d = PyDict::new_bound(py);
+    d.set_item("build", record.build.as_str())?;
+    d.set_item("build_number", record.build_number)?;
+    d.set_item("depends", &record.depends)?;
+    d.set_item("md5", &record.md5)?;
+    d.set_item("name", record.name.as_str())?;
+    d.set_item("sha256", &record.sha256)?;
+    d.set_item("size", record.size)?;
+    d.set_item("timestamp", record.timestamp)?;
+    d.set_item("version", record.version.as_str())?;
+    Ok(d)
+}
+
+// Records stay in rust until asked for - converting a whole channel to dicts up front costs more
+//    than parsing it.
+#[pyclass]
+struct RustyRepodata {
+    repodata: Repodata,
+    // `whoneeds`'s index, built on the first call; the repodata never changes after loading
+    dependents: OnceLock<HashMap<String, Vec<String>>>,
+}
+
+#[pymethods]
+impl RustyRepodata {
+    fn info<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let d = PyDict::new_bound(py);
+        d.set_item("subdir", &self.repodata.info.subdir)?;
+        Ok(d)
+    }
+    fn __len__(&self) -> usize {
+        self.repodata.packages.len() + self.repodata.packages_conda.len()
+    }
+    fn __contains__(&self, filename: &str) -> bool {
+        self.repodata.record(filename).is_some()
+    }
+    fn __getitem__<'py>(&self, py: Python<'py>, filename: &str) -> PyResult<Bound<'py, PyDict>> {
+        match self.repodata.record(filename) {
+            Some(record) => record_to_dict(py, record),
+            None => Err(PyKeyError::new_err(filename.to_string()))
+        }
+    }
+    fn filenames(&self) -> Vec<String> {
+        self.repodata.packages.keys().chain(self.repodata.packages_conda.keys()).map(|f| f.to_string()).collect()
+    }
+    fn query<'py>(&self, py: Python<'py>, name: &str, spec: &str) -> PyResult<Bound<'py, PyList>> {
+        let spec = parse_spec(spec)?;
+        // the scan over every record doesn't touch python objects, so let other threads run
+        let matches = py.allow_threads(|| self.repodata.query(name, &spec));
+        let found = PyList::empty_bound(py);
+        for (filename, record) in matches {
+            let d = record_to_dict(py, record)?;
+            d.set_item("fn", filename)?;
+            found.append(d)?;
+        }
+        Ok(found)
+    }
+    fn select<'py>(&self, py: Python<'py>, filter: &str) -> PyResult<Bound<'py, PyList>> {
+        let filter = RecordFilter::parse(filter).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let matches = py.allow_threads(|| self.repodata.select_with(&filter));
+        let found = PyList::empty_bound(py);
+        for (filename, record) in matches {
+            let d = record_to_dict(py, record)?;
+            d.set_item("fn", filename.as_str())?;
+            found.append(d)?;
+        }
+        Ok(found)
+    }
+    /// The filenames of the records that depend on package `name`, like `conda repoquery whoneeds`.
+    fn whoneeds(&self, py: Python, name: &str) -> Vec<String> {
+        let index = py.allow_threads(|| self.dependents.get_or_init(|| {
+            self.repodata.reverse_depends_index().into_iter()
+                .map(|(name, filenames)| (name.to_string(), filenames.iter().map(|f| f.to_string()).collect()))
+                .collect()
+        }));
+        index.get(name).cloned().unwrap_or_default()
+    }
+    /// Every record `specs` need, directly or through their dependencies, like `conda repoquery
+    ///    depends --recursive`.  `platform` decides which virtual packages are there; it defaults
+    ///    to this repodata's subdir.
+    #[pyo3(signature = (specs, platform=None))]
+    fn depends<'py>(&self, py: Python<'py>, specs: Vec<String>, platform: Option<&str>) -> PyResult<Bound<'py, PyList>> {
+        let closure = py.allow_threads(|| self.closure(&specs, platform));
+        let found = PyList::empty_bound(py);
+        for (filename, record) in closure {
+            let d = record_to_dict(py, record)?;
+            d.set_item("fn", filename.as_str())?;
+            found.append(d)?;
+        }
+        Ok(found)
+    }
+    /// The filenames `depends` finds, each after the packages it depends on.
+    #[pyo3(signature = (specs, platform=None))]
+    fn install_order(&self, py: Python, specs: Vec<String>, platform: Option<&str>) -> Vec<String> {
+        py.allow_threads(|| {
+            let closure = self.closure(&specs, platform);
+            let graph = graph_of_records(&closure, &self.repodata.info.subdir);
+            install_order(&graph).into_iter().map(|i| graph[i].filename.to_string()).collect()
+        })
+    }
+    /// The dependency graph of what `depends` finds, in graphviz DOT format.
+    #[pyo3(signature = (specs, platform=None))]
+    fn to_dot(&self, py: Python, specs: Vec<String>, platform: Option<&str>) -> String {
+        py.allow_threads(|| to_dot(&graph_of_records(&self.closure(&specs, platform), &self.repodata.info.subdir)))
+    }
+}
+
+impl RustyRepodata {
+    fn new(repodata: Repodata) -> RustyRepodata {
+        RustyRepodata { repodata, dependents: OnceLock::new() }
+    }
 
-py_class!(class RustyVersion |py| {
-    data rust_version: Version;
-    def __new__(_cls, arg: &str) -> PyResult<RustyVersion> {
-        RustyVersion::create_instance(py, arg.into())
+    fn closure(&self, specs: &[String], platform: Option<&str>) -> Vec<(&PackageFilename, &Record)> {
+        let specs: Vec<&str> = specs.iter().map(|s| s.as_str()).collect();
+        self.repodata.dependency_closure(&specs, platform.unwrap_or(&self.repodata.info.subdir))
     }
-    def __richcmp__(&self, other: &RustyVersion, op: CompareOp) -> PyResult<bool> {
-        Ok(self.rust_version(py).compare_to_version(other.rust_version(py), &py_cmp_to_ronda_cmp(op)))
+}
+
+#[pyfunction]
+#[pyo3(name = "read_repodata")]
+fn read_repodata_py(py: Python, path: &str) -> PyResult<RustyRepodata> {
+    // Reading and parsing a channel can take seconds - don't hold the GIL for it
+    match py.allow_threads(|| read_repodata(path)) {
+        Ok(repodata) => Ok(RustyRepodata::new(repodata)),
+        Err(RepodataReadError::Io(e)) => Err(PyIOError::new_err(format!("unable to read repodata from {}: {}", path, e))),
+        Err(RepodataReadError::Json(e)) => Err(PyValueError::new_err(format!("unable to parse repodata from {}: {}", path, e))),
+        Err(e) => Err(PyValueError::new_err(e.to_string())),
     }
-    def __repr__(&self) -> PyResult<String> {
-        Ok(self.rust_version(py).as_str().to_string())
+}
+
+/// Parse repodata.json content from any object supporting the buffer protocol (bytes,
+/// bytearray, memoryview, mmap) without copying it first.
+#[cfg(not(Py_LIMITED_API))]
+#[pyfunction]
+fn read_repodata_bytes(py: Python, data: &Bound<'_, PyAny>) -> PyResult<RustyRepodata> {
+    let buffer = PyBuffer::<u8>::get_bound(data)?;
+    if !buffer.is_c_contiguous() {
+        return Err(PyValueError::new_err("repodata buffer must be contiguous"));
     }
-    def startswith(&self, other: &RustyVersion) -> PyResult<bool> {
-        Ok(self.rust_version(py).startswith(&other.rust_version(py)))
+    // The buffer holds its exporter alive and keeps the memory from being reallocated until
+    //    it is released, so the slice is valid for as long as `buffer` is in scope.
+    let bytes = unsafe { std::slice::from_raw_parts(buffer.buf_ptr() as *const u8, buffer.len_bytes()) };
+    // Writable buffers could change under us once other threads get the GIL, so only let go of
+    //    it when the buffer is read-only.
+    let parsed = if buffer.readonly() {
+        py.allow_threads(|| read_repodata_from_slice(bytes))
+    } else {
+        read_repodata_from_slice(bytes)
+    };
+    repodata_from_parsed(parsed)
+}
+
+/// Parse repodata.json content from bytes, or from any other object `bytes()` accepts
+/// (bytearray, memoryview, mmap), which is copied first.
+#[cfg(Py_LIMITED_API)]
+#[pyfunction]
+fn read_repodata_bytes(py: Python, data: &Bound<'_, PyAny>) -> PyResult<RustyRepodata> {
+    // The stable ABI has no buffer protocol.  bytes are immutable, so parsing can borrow them
+    //    without the GIL; anything else goes through a copy that nothing else can change.
+    let copied = match data.downcast::<PyBytes>() {
+        Ok(bytes) => bytes.clone(),
+        Err(_) => py.get_type_bound::<PyBytes>().call1((data,))?.downcast_into::<PyBytes>()?,
+    };
+    let bytes = copied.as_bytes();
+    repodata_from_parsed(py.allow_threads(|| read_repodata_from_slice(bytes)))
+}
+
+fn repodata_from_parsed<E: std::fmt::Display>(parsed: Result<Repodata, E>) -> PyResult<RustyRepodata> {
+    match parsed {
+        Ok(repodata) => Ok(RustyRepodata::new(repodata)),
+        Err(e) => Err(PyValueError::new_err(format!("unable to parse repodata: {}", e)))
     }
-});
+}
 
-//fn read_repodata_py<'a, P: AsRef<Path>>(_: Python, path: P) -> PyResult<PyObject> {
-//    let out = read_repodata(P);
-//    Ok(out)
-//}
\ No newline at end of file
+fn parse_versions(versions: &[String]) -> PyResult<Vec<Version>> {
+    versions.iter().map(|v| parse_version(v)).collect()
+}
+
+/// Compare each of `versions` against `other` using `op` (`==`, `!=`, `<`, `<=`, `>`, `>=`).
+/// Returns a bytearray with 1 where `version op other` holds and 0 where it doesn't.
+#[pyfunction]
+fn compare_versions<'py>(py: Python<'py>, versions: Vec<String>, op: &str, other: &str) -> PyResult<Bound<'py, PyByteArray>> {
+    let operator = match CompOp::from_sign(op) {
+        Ok(o @ CompOp::Eq) | Ok(o @ CompOp::Ne) | Ok(o @ CompOp::Lt) |
+        Ok(o @ CompOp::Le) | Ok(o @ CompOp::Gt) | Ok(o @ CompOp::Ge) => o,
+        _ => return Err(PyValueError::new_err(format!("unsupported comparison operator '{}'", op)))
+    };
+    let other = parse_version(other)?;
+    let results = py.allow_threads(|| -> PyResult<Vec<u8>> {
+        Ok(parse_versions(&versions)?.iter()
+            .map(|v| other.compare_to_version(v, &operator) as u8)
+            .collect())
+    })?;
+    Ok(PyByteArray::new_bound(py, &results))
+}
+
+/// Test each of `versions` against the version spec `spec`.
+/// Returns a bytearray with 1 for each version that matches and 0 for each that doesn't.
+#[pyfunction]
+fn match_versions<'py>(py: Python<'py>, versions: Vec<String>, spec: &str) -> PyResult<Bound<'py, PyByteArray>> {
+    let spec = parse_spec(spec)?;
+    let results = py.allow_threads(|| -> PyResult<Vec<u8>> {
+        Ok(parse_versions(&versions)?.iter()
+            .map(|v| spec.test_match_version(v) as u8)
+            .collect())
+    })?;
+    Ok(PyByteArray::new_bound(py, &results))
+}
+
+/// Binary sort key for a version string, e.g. `sorted(versions, key=version_sort_key)`.
+/// Keys compare in the same order as the versions they were made from.
+#[pyfunction]
+fn version_sort_key<'py>(py: Python<'py>, version: &str) -> PyResult<Bound<'py, PyBytes>> {
+    Ok(PyBytes::new_bound(py, &parse_version(version)?.sort_key()))
+}
+
+// Spec trees cross the boundary the same way conda's treeify represents them: a plain string for a
+//    single spec, or a tuple of (combinator, *members) where combinator is "," (and) or "|" (or).
+fn tree_to_py(py: Python, spec: &VersionSpecOrConstraintTree) -> PyObject {
+    match spec {
+        VersionSpecOrConstraintTree::VersionSpec(s) => s.get_spec().into_py(py),
+        VersionSpecOrConstraintTree::ConstraintTree(t) => {
+            let combinator = match t.combinator() {
+                Combinator::And => ",",
+                Combinator::Or => "|",
+            };
+            let mut items: Vec<PyObject> = vec![combinator.into_py(py)];
+            items.extend(t.parts().iter().map(|p| tree_to_py(py, p)));
+            PyTuple::new_bound(py, items).into_py(py)
+        }
+    }
+}
+
+fn py_to_tree(obj: &Bound<'_, PyAny>) -> PyResult<VersionSpecOrConstraintTree> {
+    if let Ok(s) = obj.extract::<String>() {
+        return match VersionSpec::try_from(s.as_str()) {
+            Ok(v) => Ok(VersionSpecOrConstraintTree::VersionSpec(v)),
+            Err(e) => Err(InvalidVersionSpec::new_err(format!("invalid version spec '{}': {}", s, e)))
+        };
+    }
+    let tuple = obj.downcast::<PyTuple>()?;
+    if tuple.len() < 2 {
+        return Err(InvalidVersionSpec::new_err(format!("spec tree {} needs a combinator and at least one member", tuple)));
+    }
+    let combinator = match tuple.get_item(0)?.extract::<String>()?.as_str() {
+        "," => Combinator::And,
+        "|" => Combinator::Or,
+        other => return Err(InvalidVersionSpec::new_err(format!("unknown combinator '{}' in spec tree {}", other, tuple)))
+    };
+    let parts = tuple.iter().skip(1).map(|p| py_to_tree(&p)).collect::<PyResult<Vec<_>>>()?;
+    ConstraintTree::new(combinator, parts)
+        .map(VersionSpecOrConstraintTree::ConstraintTree)
+        .map_err(|e| InvalidVersionSpec::new_err(e.to_string()))
+}
+
+/// Parse a spec string into nested (combinator, *members) tuples, like conda's `treeify`.
+#[pyfunction]
+#[pyo3(name = "treeify")]
+fn treeify_py(py: Python, spec: &str) -> PyResult<PyObject> {
+    Ok(tree_to_py(py, &parse_spec(spec)?))
+}
+
+/// Join a spec tree from `treeify` back into a spec string, like conda's `untreeify`.
+#[pyfunction]
+#[pyo3(name = "untreeify")]
+fn untreeify_py(spec: &Bound<'_, PyAny>) -> PyResult<String> {
+    match untreeify(&py_to_tree(spec)?) {
+        Ok(s) => Ok(s),
+        Err(e) => Err(InvalidVersionSpec::new_err(e.to_string()))
+    }
+}
+
+/// Normalize a spec string or tree, returning the simplified spec string.
+#[pyfunction]
+#[pyo3(name = "simplify")]
+fn simplify_py(spec: &Bound<'_, PyAny>) -> PyResult<String> {
+    let tree = match spec.extract::<String>() {
+        Ok(s) => parse_spec(&s)?,
+        Err(_) => py_to_tree(spec)?
+    };
+    match untreeify(&simplify(&tree)) {
+        Ok(s) => Ok(s),
+        Err(e) => Err(InvalidVersionSpec::new_err(e.to_string()))
+    }
+}
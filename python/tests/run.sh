@@ -0,0 +1,12 @@
+#!/bin/sh
+# Build the extension with the given cargo arguments (e.g. --features abi3) and run the tests
+#    against it, without installing anything.  Needs pytest; the library name assumes Linux.
+set -e
+cd "$(dirname "$0")/../.."
+cargo build -p ronda-py "$@"
+site=$(mktemp -d)
+trap 'rm -rf "$site"' EXIT
+cp -r python/ronda "$site/ronda"
+suffix=$(python3 -c 'import sysconfig; print(sysconfig.get_config_var("EXT_SUFFIX"))')
+cp target/debug/libronda.so "$site/ronda/_ronda$suffix"
+PYTHONPATH="$site" python3 -m pytest python/tests
@@ -0,0 +1,186 @@
+"""Tests for the python bindings in src/python_interface.rs.
+
+Run against both builds of the extension: the default one, and the stable ABI one (`--features
+abi3`), which reads repodata buffers through a copy instead of the buffer protocol.
+"""
+import mmap
+from pathlib import Path
+
+import pytest
+
+import ronda
+from ronda import (InvalidVersion, InvalidVersionSpec, RustyVersion, RustyVersionSpec, compare_versions,
+                   match_versions, read_repodata, read_repodata_bytes, simplify, treeify, untreeify,
+                   version_sort_key)
+
+REPODATA = Path(__file__).resolve().parents[2] / "core" / "tests" / "data" / "current_repodata.json"
+
+
+@pytest.fixture(scope="module")
+def repodata():
+    return read_repodata(str(REPODATA))
+
+
+def test_exceptions_are_value_errors():
+    assert issubclass(InvalidVersion, ValueError)
+    assert issubclass(InvalidVersionSpec, ValueError)
+    assert ronda.InvalidVersion is InvalidVersion
+
+
+def test_version_compare():
+    assert RustyVersion("1.2") < RustyVersion("1.10")
+    assert RustyVersion("1.0") == RustyVersion("1.0.0")
+    assert RustyVersion("1.0.post1") > RustyVersion("1.0")
+    assert RustyVersion("1.0.dev1") <= RustyVersion("1.0")
+    assert RustyVersion("1.1") >= RustyVersion("1.1")
+    assert RustyVersion("1.1") != RustyVersion("1.2")
+
+
+def test_version_repr_and_startswith():
+    assert repr(RustyVersion("1.2.3")) == "1.2.3"
+    assert RustyVersion("1.2.3").startswith(RustyVersion("1.2"))
+    assert not RustyVersion("1.20").startswith(RustyVersion("1.2"))
+
+
+def test_invalid_version():
+    with pytest.raises(InvalidVersion, match="invalid version '1!2!3'"):
+        RustyVersion("1!2!3")
+
+
+def test_version_spec():
+    spec = RustyVersionSpec(">=1.0,<2")
+    assert spec.match("1.5")
+    assert not spec.match("2.0")
+    assert spec.test_match_version(RustyVersion("1.0"))
+    assert not spec.is_exact()
+    assert RustyVersionSpec("1.2.3").is_exact()
+    assert str(spec) == ">=1.0,<2"
+    assert repr(spec) == "RustyVersionSpec('>=1.0,<2')"
+
+
+def test_version_spec_merge():
+    merged = RustyVersionSpec(">=1.2").merge(RustyVersionSpec("<2|3.*"))
+    assert str(merged) == ">=1.2,(<2|3.*)"
+    assert merged.match("1.5")
+    assert not merged.match("2.5")
+
+
+def test_invalid_version_spec():
+    with pytest.raises(InvalidVersionSpec, match="invalid version spec"):
+        RustyVersionSpec("(1.2")
+    with pytest.raises(InvalidVersion):
+        RustyVersionSpec("1.2").match("1!2!3")
+
+
+def test_repodata(repodata):
+    assert repodata.info() == {"subdir": "win-64"}
+    # .tar.bz2 and .conda records
+    assert len(repodata) == len(repodata.filenames()) == 237 + 3377
+    assert "openssl-1.1.1d-he774522_0.tar.bz2" in repodata
+    assert "openssl-9.9.9-0.tar.bz2" not in repodata
+    record = repodata["django-2.2.5-py37_1.tar.bz2"]
+    assert (record["name"], record["version"], record["build"]) == ("django", "2.2.5", "py37_1")
+    assert record["depends"] == ["python >=3.7,<3.8.0a0", "pytz", "sqlparse"]
+    with pytest.raises(KeyError):
+        repodata["openssl-9.9.9-0.tar.bz2"]
+
+
+def test_repodata_query(repodata):
+    found = sorted(r["fn"] for r in repodata.query("openssl", ">=1.1.1d"))
+    assert found == ["openssl-1.1.1d-h0c8e037_0.tar.bz2", "openssl-1.1.1d-he774522_0.tar.bz2"]
+    assert repodata.query("openssl", ">=3") == []
+    with pytest.raises(InvalidVersionSpec):
+        repodata.query("openssl", "(1.1")
+
+
+def test_repodata_select(repodata):
+    found = [r["fn"] for r in repodata.select("django build=py37* newest=1")]
+    assert found and all(fn.startswith("django-2.2.5-py37") for fn in found)
+    with pytest.raises(ValueError):
+        repodata.select("django newest=zero")
+
+
+def test_repodata_dependencies(repodata):
+    assert "django-2.2.5-py37_1.tar.bz2" in repodata.whoneeds("sqlparse")
+    assert repodata.whoneeds("no-such-package") == []
+    names = {r["name"] for r in repodata.depends(["django 2.2.5 py37_1"])}
+    assert {"django", "pytz", "sqlparse", "python"} <= names
+    order = repodata.install_order(["django 2.2.5 py37_1"])
+    django = next(i for i, fn in enumerate(order) if fn.startswith("django-"))
+    assert any(fn.startswith("sqlparse-") for fn in order[:django])
+    assert repodata.to_dot(["django 2.2.5 py37_1"]).startswith("digraph")
+
+
+def test_read_repodata_errors(tmp_path):
+    with pytest.raises(OSError):
+        read_repodata(str(tmp_path / "missing.json"))
+    broken = tmp_path / "repodata.json"
+    broken.write_text("{")
+    with pytest.raises(ValueError):
+        read_repodata(str(broken))
+
+
+@pytest.mark.parametrize("wrap", [bytes, bytearray, memoryview], ids=lambda w: w.__name__)
+def test_read_repodata_bytes(repodata, wrap):
+    # the abi3 build copies anything but bytes through bytes() first
+    loaded = read_repodata_bytes(wrap(REPODATA.read_bytes()))
+    assert sorted(loaded.filenames()) == sorted(repodata.filenames())
+
+
+def test_read_repodata_bytes_from_mmap(repodata):
+    with open(REPODATA, "rb") as f, mmap.mmap(f.fileno(), 0, access=mmap.ACCESS_READ) as mapped:
+        assert len(read_repodata_bytes(mapped)) == len(repodata)
+
+
+def test_read_repodata_bytes_errors():
+    with pytest.raises(ValueError, match="unable to parse repodata"):
+        read_repodata_bytes(b"{")
+    # not contiguous: rejected by the buffer protocol build, garbage once copied by the abi3 one
+    with pytest.raises(ValueError):
+        read_repodata_bytes(memoryview(REPODATA.read_bytes())[::2])
+
+
+def test_compare_versions():
+    versions = ["1.0", "1.5", "2.0"]
+    assert compare_versions(versions, "<", "1.5") == bytearray([1, 0, 0])
+    assert compare_versions(versions, ">=", "1.5") == bytearray([0, 1, 1])
+    assert compare_versions(versions, "==", "1.5.0") == bytearray([0, 1, 0])
+    with pytest.raises(ValueError, match="unsupported comparison operator"):
+        compare_versions(versions, "~=", "1.5")
+    with pytest.raises(InvalidVersion):
+        compare_versions(["1!2!3"], "<", "1.5")
+
+
+def test_match_versions():
+    assert match_versions(["1.0", "1.5", "2.0"], ">=1.0,<2") == bytearray([1, 1, 0])
+    with pytest.raises(InvalidVersionSpec):
+        match_versions(["1.0"], "(1.0")
+
+
+def test_version_sort_key():
+    versions = ["1.1.0", "1.0", "1.1.0post1", "1.1.dev1", "1.0.5"]
+    assert sorted(versions, key=version_sort_key) == ["1.0", "1.0.5", "1.1.dev1", "1.1.0", "1.1.0post1"]
+    assert version_sort_key("1.0") == version_sort_key("1.0.0")
+    with pytest.raises(InvalidVersion):
+        version_sort_key("1!2!3")
+
+
+def test_treeify_round_trip():
+    tree = treeify(">=1.0,(<2|3.0.*)")
+    assert tree == (",", ">=1.0", ("|", "<2", "3.0.*"))
+    assert untreeify(tree) == ">=1.0,(<2|3.0.*)"
+    assert treeify("1.2") == "1.2"
+    assert untreeify("1.2") == "1.2"
+
+
+@pytest.mark.parametrize("tree", [(",",), ("&", "1.0", "2.0"), (",", "1.0", "(2.0")])
+def test_untreeify_invalid(tree):
+    with pytest.raises(InvalidVersionSpec):
+        untreeify(tree)
+
+
+def test_simplify():
+    assert simplify("(1.5|(1.6|1.5)),(>1,(>1))") == "(1.5|1.6),>1"
+    assert simplify(("|", "1.5", ("|", "1.6", "1.5"))) == "1.5|1.6"
+    with pytest.raises(InvalidVersionSpec):
+        simplify("(1.5")
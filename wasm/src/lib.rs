@@ -0,0 +1 @@
+mod wasm_interface;
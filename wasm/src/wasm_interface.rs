@@ -0,0 +1,102 @@
+use std::convert::TryFrom;
+
+use wasm_bindgen::prelude::*;
+use ronda::{CompOp, Spec, Version, VersionSpecOrConstraintTree};
+
+// Errors cross into javascript as thrown strings
+fn parse_version(input: &str) -> Result<Version, JsValue> {
+    match input.parse::<Version>() {
+        Ok(v) => Ok(v),
+        Err(e) => Err(JsValue::from_str(&format!("invalid version '{}': {}", input, e)))
+    }
+}
+
+fn parse_spec(input: &str) -> Result<VersionSpecOrConstraintTree, JsValue> {
+    match VersionSpecOrConstraintTree::try_from(input) {
+        Ok(s) => Ok(s),
+        Err(e) => Err(JsValue::from_str(&format!("invalid version spec '{}': {}", input, e)))
+    }
+}
+
+#[wasm_bindgen(js_name = Version)]
+pub struct WasmVersion {
+    rust_version: Version,
+}
+
+#[wasm_bindgen(js_class = Version)]
+impl WasmVersion {
+    #[wasm_bindgen(constructor)]
+    pub fn new(version: &str) -> Result<WasmVersion, JsValue> {
+        Ok(WasmVersion { rust_version: parse_version(version)? })
+    }
+
+    /// -1, 0 or 1 as this version is less than, equal to or greater than `other`.
+    pub fn compare(&self, other: &WasmVersion) -> i32 {
+        match self.rust_version.compare_version(&other.rust_version) {
+            CompOp::Lt => -1,
+            CompOp::Gt => 1,
+            _ => 0,
+        }
+    }
+
+    pub fn startswith(&self, other: &WasmVersion) -> bool {
+        self.rust_version.startswith(&other.rust_version)
+    }
+
+    #[wasm_bindgen(js_name = toString)]
+    pub fn to_js_string(&self) -> String {
+        self.rust_version.as_str().to_string()
+    }
+}
+
+#[wasm_bindgen(js_name = VersionSpec)]
+pub struct WasmVersionSpec {
+    rust_spec: VersionSpecOrConstraintTree,
+}
+
+#[wasm_bindgen(js_class = VersionSpec)]
+impl WasmVersionSpec {
+    #[wasm_bindgen(constructor)]
+    pub fn new(spec: &str) -> Result<WasmVersionSpec, JsValue> {
+        Ok(WasmVersionSpec { rust_spec: parse_spec(spec)? })
+    }
+
+    /// Whether the version string `version` satisfies this spec.
+    #[wasm_bindgen(js_name = match)]
+    pub fn test_match(&self, version: &str) -> Result<bool, JsValue> {
+        Ok(self.rust_spec.test_match_version(&parse_version(version)?))
+    }
+
+    #[wasm_bindgen(js_name = isExact)]
+    pub fn is_exact(&self) -> bool {
+        self.rust_spec.is_exact()
+    }
+
+    #[wasm_bindgen(js_name = toString)]
+    pub fn to_js_string(&self) -> String {
+        self.rust_spec.get_spec()
+    }
+}
+
+/// -1, 0 or 1 as version `a` is less than, equal to or greater than version `b`.  Usable
+/// directly as an `Array.prototype.sort` comparator.
+#[wasm_bindgen(js_name = compareVersions)]
+pub fn compare_versions(a: &str, b: &str) -> Result<i32, JsValue> {
+    Ok(WasmVersion::new(a)?.compare(&WasmVersion::new(b)?))
+}
+
+/// Whether version string `version` satisfies version spec `spec`.
+#[wasm_bindgen(js_name = matchVersion)]
+pub fn match_version(spec: &str, version: &str) -> Result<bool, JsValue> {
+    WasmVersionSpec::new(spec)?.test_match(version)
+}
+
+#[wasm_bindgen(js_name = isValidVersion)]
+pub fn is_valid_version(version: &str) -> bool {
+    version.parse::<Version>().is_ok()
+}
+
+#[wasm_bindgen(js_name = isValidSpec)]
+pub fn is_valid_spec(spec: &str) -> bool {
+    VersionSpecOrConstraintTree::try_from(spec).is_ok()
+}